@@ -1,3 +1,25 @@
 fn main() {
+    // Short commit hash reported at /version; left unset outside a git checkout
+    if let Some(hash) = git(&["rev-parse", "--short", "HEAD"]) {
+        println!("cargo:rustc-env=PRIVACYRPC_BUILD_HASH={}", hash);
+        // Rebuild when HEAD moves to another branch or the branch gets a new commit
+        if let Some(head) = git(&["rev-parse", "--git-path", "HEAD"]) {
+            println!("cargo:rerun-if-changed={}", head);
+        }
+        if let Some(branch) = git(&["symbolic-ref", "-q", "HEAD"]) {
+            if let Some(path) = git(&["rev-parse", "--git-path", &branch]) {
+                println!("cargo:rerun-if-changed={}", path);
+            }
+        }
+    }
     tauri_build::build()
 }
+
+/// Trimmed stdout of a successful `git` command
+fn git(args: &[&str]) -> Option<String> {
+    let output = std::process::Command::new("git").args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
@@ -3,20 +3,23 @@
     windows_subsystem = "windows"
 )]
 
+mod audit;
 mod native_host;
 mod native_messaging;
+mod profiles;
 mod proxy;
+mod simulation;
 mod tor;
-mod transaction_decoder;
 mod websocket;
+mod ws_relay;
 
-pub use transaction_decoder::{decode_transaction, DecodedTransaction};
+pub use privacyrpc_sdk::transaction::{decode_transaction, DecodedTransaction};
 
 use parking_lot::Mutex;
-use std::net::TcpListener;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener};
 use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::Instant;
 use tauri::{
     menu::{Menu, MenuItem},
     tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent},
@@ -30,9 +33,10 @@ pub struct AppState {
     pub tor_enabled: Mutex<bool>,
     pub tor_connected: Mutex<bool>,
     pub stats: Mutex<ProxyStats>,
-    pub started_at: Mutex<Option<Instant>>,
     pub rpc_endpoint: Mutex<Option<String>>,
     pub resource_dir: Mutex<Option<PathBuf>>,
+    pub unix_socket: Mutex<Option<PathBuf>>,
+    pub bind_address: Mutex<IpAddr>,
 }
 
 #[derive(Default, Clone, serde::Serialize)]
@@ -50,9 +54,10 @@ impl Default for AppState {
             tor_enabled: Mutex::new(false),
             tor_connected: Mutex::new(false),
             stats: Mutex::new(ProxyStats::default()),
-            started_at: Mutex::new(None),
             rpc_endpoint: Mutex::new(None),
             resource_dir: Mutex::new(None),
+            unix_socket: Mutex::new(None),
+            bind_address: Mutex::new(IpAddr::V4(Ipv4Addr::LOCALHOST)),
         }
     }
 }
@@ -142,6 +147,7 @@ fn kill_old_instances(port: u16) -> Result<(), String> {
 #[tauri::command]
 async fn start_proxy(state: State<'_, Arc<AppState>>, app: AppHandle) -> Result<bool, String> {
     let port = *state.proxy_port.lock();
+    let addr = SocketAddr::new(*state.bind_address.lock(), port);
 
     // Helper to emit proxy status
     let emit_status = |running: bool, port: u16| {
@@ -153,12 +159,26 @@ async fn start_proxy(state: State<'_, Arc<AppState>>, app: AppHandle) -> Result<
         websocket::broadcast_current_state();
     };
 
+    // Listen on a Unix domain socket instead of TCP when configured
+    #[cfg(unix)]
+    {
+        let unix_socket = state.unix_socket.lock().clone();
+        if let Some(path) = unix_socket {
+            proxy::start_proxy_server_unix(&path)
+                .await
+                .map_err(|e| e.to_string())?;
+            *state.proxy_running.lock() = true;
+            log::info!("Proxy server started on unix:{}", path.display());
+            emit_status(true, port);
+            return Ok(true);
+        }
+    }
+
     // First attempt to start
-    match proxy::start_proxy_server(port).await {
+    match proxy::start_proxy_server(addr).await {
         Ok(_) => {
             *state.proxy_running.lock() = true;
-            *state.started_at.lock() = Some(Instant::now());
-            log::info!("Proxy server started on port {}", port);
+            log::info!("Proxy server started on {}", addr);
             emit_status(true, port);
             Ok(true)
         }
@@ -179,10 +199,9 @@ async fn start_proxy(state: State<'_, Arc<AppState>>, app: AppHandle) -> Result<
                 }
 
                 // Retry starting the proxy
-                match proxy::start_proxy_server(port).await {
+                match proxy::start_proxy_server(addr).await {
                     Ok(_) => {
                         *state.proxy_running.lock() = true;
-                        *state.started_at.lock() = Some(Instant::now());
                         log::info!(
                             "Proxy server started on port {} (after killing old instance)",
                             port
@@ -210,7 +229,6 @@ async fn start_proxy(state: State<'_, Arc<AppState>>, app: AppHandle) -> Result<
 async fn stop_proxy(state: State<'_, Arc<AppState>>, app: AppHandle) -> Result<bool, String> {
     proxy::stop_proxy_server().await;
     *state.proxy_running.lock() = false;
-    *state.started_at.lock() = None;
     log::info!("Proxy server stopped");
 
     // Emit event to Tauri frontend
@@ -235,10 +253,7 @@ fn get_status(state: State<'_, Arc<AppState>>) -> serde_json::Value {
     // Read live stats from proxy counters
     let requests = proxy::REQUESTS_PROXIED.load(std::sync::atomic::Ordering::Relaxed);
     let bytes = proxy::BYTES_TRANSFERRED.load(std::sync::atomic::Ordering::Relaxed);
-    let mut uptime = 0u64;
-    if let Some(started) = *state.started_at.lock() {
-        uptime = started.elapsed().as_secs();
-    }
+    let uptime = proxy::uptime_seconds();
 
     serde_json::json!({
         "running": running,
@@ -246,6 +261,7 @@ fn get_status(state: State<'_, Arc<AppState>>) -> serde_json::Value {
         "torEnabled": tor_enabled,
         "torConnected": tor_connected,
         "rpcEndpoint": rpc_endpoint,
+        "wsPort": websocket::websocket_port(),
         "stats": {
             "requests_proxied": requests,
             "bytes_transferred": bytes,
@@ -263,6 +279,48 @@ fn set_port(port: u16, state: State<'_, Arc<AppState>>) -> Result<(), String> {
     Ok(())
 }
 
+/// Listen on a Unix domain socket at `path` (empty to use the TCP port).
+/// Takes effect the next time the proxy starts.
+#[tauri::command]
+fn set_unix_socket(path: String, state: State<'_, Arc<AppState>>) -> Result<(), String> {
+    let path = path.trim();
+    if path.is_empty() {
+        *state.unix_socket.lock() = None;
+        return Ok(());
+    }
+    if !cfg!(unix) {
+        return Err("Unix domain sockets are not supported on this platform".to_string());
+    }
+    *state.unix_socket.lock() = Some(PathBuf::from(path));
+    Ok(())
+}
+
+/// Serve the proxy over HTTPS with the given PEM certificate and key (both
+/// empty for plain HTTP). Takes effect the next time the proxy starts.
+#[tauri::command]
+fn set_proxy_tls(cert_path: String, key_path: String) -> Result<(), String> {
+    let (cert_path, key_path) = (cert_path.trim(), key_path.trim());
+    if cert_path.is_empty() && key_path.is_empty() {
+        return proxy::set_tls_files(None);
+    }
+    if cert_path.is_empty() || key_path.is_empty() {
+        return Err("TLS needs both a certificate and a key".to_string());
+    }
+    proxy::set_tls_files(Some((PathBuf::from(cert_path), PathBuf::from(key_path))))
+}
+
+/// Set the proxy listen address, e.g. `127.0.0.1`, `::1`, or `::` for dual-stack.
+/// Remote clients of a non-loopback address need the auth token for `/control/*`.
+#[tauri::command]
+fn set_bind_address(address: String, state: State<'_, Arc<AppState>>) -> Result<(), String> {
+    let address: IpAddr = address
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid IP address: {}", address))?;
+    *state.bind_address.lock() = address;
+    Ok(())
+}
+
 #[tauri::command]
 fn set_rpc_endpoint(
     endpoint: String,
@@ -297,18 +355,16 @@ fn set_rpc_endpoint(
     Ok(())
 }
 
-/// Save config to file for persistence and sharing with proxy
+/// Save config to file for persistence and sharing with proxy. Setting the
+/// endpoint by hand leaves any active profile; saved profiles are kept.
 fn save_config_file(endpoint: Option<&str>) {
-    if let Some(config_dir) = directories::ProjectDirs::from("com", "privacyrpc", "PrivacyRPC") {
-        let config_path = config_dir.config_dir().join("config.json");
-        if let Some(parent) = config_path.parent() {
-            let _ = std::fs::create_dir_all(parent);
-        }
-        let config = serde_json::json!({
-            "rpcEndpoint": endpoint
-        });
-        let _ = std::fs::write(&config_path, serde_json::to_string_pretty(&config).unwrap_or_default());
-        log::info!("Config saved to {:?}", config_path);
+    let result = profiles::update_config(|config| {
+        config.insert("rpcEndpoint".to_string(), serde_json::json!(endpoint));
+        config.remove("activeProfile");
+    });
+    match result {
+        Ok(()) => log::info!("Config saved"),
+        Err(e) => log::warn!("Failed to save config: {}", e),
     }
 }
 
@@ -327,6 +383,32 @@ fn load_config_file() -> Option<String> {
     None
 }
 
+/// Save a named RPC profile (URL plus auth headers) without switching to it
+#[tauri::command]
+fn save_rpc_profile(name: String, url: String, headers: Option<HashMap<String, String>>) -> Result<(), String> {
+    profiles::save_profile(&name, &url, headers.unwrap_or_default())
+}
+
+/// Saved profiles (header names only) and which one is active
+#[tauri::command]
+fn list_rpc_profiles() -> serde_json::Value {
+    let profiles: Vec<serde_json::Value> = profiles::list_profiles().iter().map(|p| p.summary()).collect();
+    serde_json::json!({ "profiles": profiles, "activeProfile": profiles::active_profile() })
+}
+
+#[tauri::command]
+fn switch_rpc_profile(
+    name: String,
+    state: State<'_, Arc<AppState>>,
+    app: AppHandle,
+) -> Result<serde_json::Value, String> {
+    let profile = profiles::switch_profile(&name)?;
+    *state.rpc_endpoint.lock() = Some(profile.url.clone());
+    let _ = app.emit("rpc-changed", serde_json::json!({ "rpcEndpoint": profile.url, "profile": profile.name }));
+    websocket::broadcast_current_state();
+    Ok(profile.summary())
+}
+
 #[tauri::command]
 fn get_rpc_endpoint(state: State<'_, Arc<AppState>>) -> Option<String> {
     state.rpc_endpoint.lock().clone()
@@ -337,7 +419,7 @@ async fn enable_tor(state: State<'_, Arc<AppState>>, app: AppHandle) -> Result<s
     log::info!("Starting Tor...");
 
     // Start Tor FIRST - only set state after successful start
-    let status = tor::global_enable_tor().await?;
+    let status = tor::global_enable_tor().await.map_err(|e| e.to_string())?;
 
     // Only update state after Tor successfully started
     *state.tor_enabled.lock() = true;
@@ -400,9 +482,78 @@ async fn new_circuit(_state: State<'_, Arc<AppState>>) -> Result<serde_json::Val
     }))
 }
 
+/// Rotate the Tor circuit every `interval_secs` seconds (0 turns rotation off)
+#[tauri::command]
+async fn set_tor_rotation(interval_secs: u64) -> Result<(), String> {
+    let interval = (interval_secs > 0).then(|| std::time::Duration::from_secs(interval_secs));
+    tor::global_set_rotation(interval).await;
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_tor_persistent_data(enabled: bool) -> Result<(), String> {
+    tor::set_persistent_data_dir(enabled).await
+}
+
+/// Pause upstream forwarding without stopping the proxy
+#[tauri::command]
+fn pause_proxy() {
+    proxy::set_forwarding_paused(true);
+}
+
+#[tauri::command]
+fn resume_proxy() {
+    proxy::set_forwarding_paused(false);
+}
+
+/// Set the IP echo services used to detect the exit IP (empty restores defaults)
+#[tauri::command]
+fn set_ip_check_urls(urls: Vec<String>) {
+    tor::set_ip_check_urls(urls);
+}
+
+/// Set the service that reports whether the exit IP is Tor (empty restores the default)
+#[tauri::command]
+fn set_tor_check_url(url: Option<String>) {
+    tor::set_tor_check_url(url);
+}
+
+#[tauri::command]
+fn set_transaction_simulation(enabled: bool, threshold_pct: f64) {
+    proxy::set_transaction_simulation(enabled, threshold_pct);
+}
+
+/// Opt in to `_privacyrpc` decode info in transaction responses
+#[tauri::command]
+fn set_decode_injection(enabled: bool) {
+    proxy::set_decode_injection(enabled);
+}
+
+/// Route upstream traffic through an HTTP(S) proxy (`None` disables)
+#[tauri::command]
+fn set_upstream_proxy(url: Option<String>) -> Result<(), String> {
+    proxy::set_upstream_proxy(url)
+}
+
+/// CONNECT tunnel timeouts in seconds (0 disables either)
+#[tauri::command]
+fn set_tunnel_timeouts(connect_secs: u64, idle_secs: u64) {
+    proxy::set_tunnel_timeouts(
+        std::time::Duration::from_secs(connect_secs),
+        std::time::Duration::from_secs(idle_secs),
+    );
+}
+
+/// Walk the routing path (proxy, RPC endpoint, Tor) and test the exit IP and
+/// RPC reachability; same report as `GET /test-routing`
+#[tauri::command]
+async fn run_diagnostics() -> serde_json::Value {
+    proxy::run_diagnostics().await
+}
+
 #[tauri::command]
 fn decode_tx(encoded_tx: String) -> Result<serde_json::Value, String> {
-    match transaction_decoder::decode_transaction(&encoded_tx) {
+    match decode_transaction(&encoded_tx) {
         Ok(decoded) => serde_json::to_value(decoded).map_err(|e| e.to_string()),
         Err(e) => Err(e),
     }
@@ -443,8 +594,19 @@ fn main() {
 
     let state = Arc::new(AppState::default());
 
-    // Load saved config on startup
-    if let Some(endpoint) = load_config_file() {
+    tor::restore_persistent_data_dir();
+
+    if let Some(files) = profiles::saved_tls_files() {
+        if let Err(e) = proxy::set_tls_files(Some(files)) {
+            log::warn!("Saved proxy TLS files unusable: {}", e);
+        }
+    }
+
+    // Load saved config on startup; an active profile brings its headers back too
+    if let Some(profile) = profiles::restore_active_profile() {
+        log::info!("Restored RPC profile: {}", profile.name);
+        *state.rpc_endpoint.lock() = Some(profile.url);
+    } else if let Some(endpoint) = load_config_file() {
         log::info!("Loaded saved RPC endpoint: {}", endpoint);
         *state.rpc_endpoint.lock() = Some(endpoint.clone());
         proxy::set_rpc_endpoint(Some(endpoint));
@@ -463,8 +625,12 @@ fn main() {
             }
 
             // Start WebSocket server for extension communication
+            if let Err(e) = websocket::write_auth_token_file() {
+                log::error!("Failed to write WebSocket auth token: {}", e);
+            }
             tauri::async_runtime::spawn(async {
-                websocket::start_websocket_server().await;
+                let (host, port) = websocket::bind_config_from_env();
+                websocket::start_websocket_server(&host, port).await;
             });
 
             // Auto-start proxy if launched with --autostart flag
@@ -473,10 +639,10 @@ fn main() {
                 tauri::async_runtime::spawn(async move {
                     log::info!("Auto-starting proxy (launched from extension)");
                     let port = *state.proxy_port.lock();
-                    match proxy::start_proxy_server(port).await {
+                    let addr = SocketAddr::new(*state.bind_address.lock(), port);
+                    match proxy::start_proxy_server(addr).await {
                         Ok(_) => {
                             *state.proxy_running.lock() = true;
-                            *state.started_at.lock() = Some(Instant::now());
                             log::info!("Proxy auto-started on port {}", port);
                             // Notify extension via WebSocket
                             websocket::broadcast_current_state();
@@ -545,12 +711,29 @@ fn main() {
             stop_proxy,
             get_status,
             set_port,
+            set_unix_socket,
             set_rpc_endpoint,
             get_rpc_endpoint,
+            save_rpc_profile,
+            list_rpc_profiles,
+            switch_rpc_profile,
             enable_tor,
             disable_tor,
             new_circuit,
+            set_tor_rotation,
+            set_bind_address,
+            set_proxy_tls,
+            set_tor_persistent_data,
+            set_ip_check_urls,
+            set_tor_check_url,
+            pause_proxy,
+            resume_proxy,
+            set_transaction_simulation,
+            set_decode_injection,
+            set_upstream_proxy,
+            set_tunnel_timeouts,
             decode_tx,
+            run_diagnostics,
             install_native_host,
             uninstall_native_host,
         ])
@@ -1,11 +1,22 @@
 use serde::{Deserialize, Serialize};
+use std::future::Future;
 use std::io::{self, Read, Write};
+use std::sync::Arc;
+use tokio::runtime::Handle;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinHandle;
 
 #[derive(Deserialize, Debug)]
 pub struct NativeMessage {
     pub action: String,
     #[serde(default)]
     pub rpc_url: Option<String>,
+    /// RPC profile name, for the profile actions
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Auth headers for `save_profile`
+    #[serde(default)]
+    pub headers: Option<std::collections::HashMap<String, String>>,
 }
 
 #[derive(Serialize)]
@@ -27,6 +38,28 @@ pub struct NativeResponse {
     pub bootstrap_progress: Option<u8>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub rpc_provider: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ws_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ws_port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reachable: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tor_used: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profiles: Option<Vec<serde_json::Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active_profile: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub endpoints: Option<Vec<serde_json::Value>>,
 }
 
 impl NativeResponse {
@@ -41,6 +74,17 @@ impl NativeResponse {
             tor_ip: None,
             bootstrap_progress: None,
             rpc_provider: None,
+            ws_token: None,
+            ws_port: None,
+            reachable: None,
+            latency_ms: None,
+            version: None,
+            tor_used: None,
+            build: None,
+            capabilities: None,
+            profiles: None,
+            active_profile: None,
+            endpoints: None,
         }
     }
 
@@ -55,78 +99,183 @@ impl NativeResponse {
             tor_ip: None,
             bootstrap_progress: None,
             rpc_provider: None,
+            ws_token: None,
+            ws_port: None,
+            reachable: None,
+            latency_ms: None,
+            version: None,
+            tor_used: None,
+            build: None,
+            capabilities: None,
+            profiles: None,
+            active_profile: None,
+            endpoints: None,
         }
     }
 }
 
-/// Read a native messaging message from stdin
-fn read_message() -> io::Result<Option<NativeMessage>> {
-    let mut stdin = io::stdin().lock();
+/// Default cap on an incoming message body. Chrome allows up to 4 GiB towards
+/// the host; large transactions and batches can exceed the old 1 MiB limit.
+const DEFAULT_MAX_MESSAGE_BYTES: usize = 64 * 1024 * 1024;
+
+/// Incoming size cap, overridable with `PRIVACYRPC_NATIVE_MAX_MESSAGE_BYTES`
+/// (Chrome launches the host, so there are no arguments to configure it with)
+fn max_message_bytes() -> usize {
+    std::env::var("PRIVACYRPC_NATIVE_MAX_MESSAGE_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(DEFAULT_MAX_MESSAGE_BYTES)
+}
+
+/// Messages handled at once by default
+const DEFAULT_WORKERS: usize = 4;
 
+/// Concurrent message handlers, overridable with `PRIVACYRPC_NATIVE_WORKERS`
+fn worker_count() -> usize {
+    std::env::var("PRIVACYRPC_NATIVE_WORKERS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(DEFAULT_WORKERS)
+}
+
+/// One framed message read from the extension
+enum Incoming {
+    Message(NativeMessage),
+    /// The body exceeded the size limit and was discarded
+    TooLarge(usize),
+    /// The body wasn't a valid message; the stream is still usable
+    Malformed(String),
+    /// The stream closed (the extension disconnected)
+    Eof,
+}
+
+/// Read a native messaging message (4-byte little-endian length, then JSON)
+fn read_message<R: Read>(reader: &mut R, max_len: usize) -> io::Result<Incoming> {
     // Read 4-byte length header (little-endian)
     let mut len_bytes = [0u8; 4];
-    match stdin.read_exact(&mut len_bytes) {
+    match reader.read_exact(&mut len_bytes) {
         Ok(_) => {}
-        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(Incoming::Eof),
         Err(e) => return Err(e),
     }
 
     let len = u32::from_le_bytes(len_bytes) as usize;
-    if len == 0 || len > 1024 * 1024 {
-        return Ok(None);
+    if len == 0 {
+        return Ok(Incoming::Malformed("Empty message".to_string()));
+    }
+    if len > max_len {
+        // Skip the body so the next message's header is read from the right place
+        let skipped = io::copy(&mut reader.take(len as u64), &mut io::sink())?;
+        if skipped < len as u64 {
+            return Ok(Incoming::Eof);
+        }
+        return Ok(Incoming::TooLarge(len));
     }
 
     // Read message body
     let mut body = vec![0u8; len];
-    stdin.read_exact(&mut body)?;
+    match reader.read_exact(&mut body) {
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(Incoming::Eof),
+        Err(e) => return Err(e),
+    }
 
     // Parse JSON
     match serde_json::from_slice(&body) {
-        Ok(msg) => Ok(Some(msg)),
+        Ok(msg) => Ok(Incoming::Message(msg)),
         Err(e) => {
             eprintln!("Failed to parse message: {}", e);
-            Ok(None)
+            Ok(Incoming::Malformed(format!("Invalid message: {}", e)))
         }
     }
 }
 
-/// Write a native messaging response to stdout
-fn write_response(response: &NativeResponse) -> io::Result<()> {
+/// Write a native messaging response
+fn write_response<W: Write>(writer: &mut W, response: &NativeResponse) -> io::Result<()> {
     let json = serde_json::to_vec(response).unwrap();
     let len = json.len() as u32;
 
-    let mut stdout = io::stdout().lock();
-    stdout.write_all(&len.to_le_bytes())?;
-    stdout.write_all(&json)?;
-    stdout.flush()?;
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(&json)?;
+    writer.flush()?;
 
     Ok(())
 }
 
-/// Run the native messaging host loop
-pub fn run_native_host() {
-    // Create a tokio runtime for async operations
-    let rt = tokio::runtime::Runtime::new().unwrap();
+/// Answer messages from `reader` with `handle` until the stream closes.
+/// Up to `workers` messages are outstanding at once, each from when it is
+/// read until its response is written; past that the next message waits
+/// unread in the pipe. Handlers run on `rt`. Responses are written in the
+/// order the messages arrived, since the protocol has no ids to match them up by,
+/// so a slow message holds back the ones behind it and the reading with them.
+fn run_host_loop<R, W, F, Fut>(reader: &mut R, mut writer: W, max_len: usize, workers: usize, rt: &Handle, handle: F)
+where
+    R: Read,
+    W: Write + Send + 'static,
+    F: Fn(NativeMessage) -> Fut,
+    Fut: Future<Output = NativeResponse> + Send + 'static,
+{
+    let permits = Arc::new(Semaphore::new(workers.max(1)));
+    let (pending_tx, pending_rx) =
+        std::sync::mpsc::channel::<(JoinHandle<NativeResponse>, OwnedSemaphorePermit)>();
+    let writer_rt = rt.clone();
+    let writer_thread = std::thread::spawn(move || {
+        for (pending, permit) in pending_rx {
+            let response = writer_rt
+                .block_on(pending)
+                .unwrap_or_else(|e| NativeResponse::error(format!("Message handler failed: {}", e)));
+            if let Err(e) = write_response(&mut writer, &response) {
+                eprintln!("Failed to write response: {}", e);
+                break;
+            }
+            drop(permit);
+        }
+    });
 
     loop {
-        match read_message() {
-            Ok(Some(msg)) => {
-                let response = rt.block_on(handle_message(msg));
-                if let Err(e) = write_response(&response) {
-                    eprintln!("Failed to write response: {}", e);
-                    break;
-                }
-            }
-            Ok(None) => {
-                // EOF or invalid message, exit
-                break;
+        let Ok(permit) = rt.block_on(permits.clone().acquire_owned()) else {
+            break;
+        };
+        let pending = match read_message(reader, max_len) {
+            Ok(Incoming::Message(msg)) => rt.spawn(handle(msg)),
+            Ok(Incoming::TooLarge(len)) => {
+                let response = NativeResponse::error(format!(
+                    "Message of {} bytes exceeds the {} byte limit",
+                    len, max_len
+                ));
+                rt.spawn(async move { response })
             }
+            Ok(Incoming::Malformed(error)) => rt.spawn(async move { NativeResponse::error(error) }),
+            Ok(Incoming::Eof) => break,
             Err(e) => {
                 eprintln!("Failed to read message: {}", e);
                 break;
             }
+        };
+        // The writer is gone: stdout closed
+        if pending_tx.send((pending, permit)).is_err() {
+            break;
         }
     }
+    drop(pending_tx);
+    let _ = writer_thread.join();
+}
+
+/// Run the native messaging host loop
+pub fn run_native_host() {
+    // Create a tokio runtime for async operations
+    let rt = tokio::runtime::Runtime::new().unwrap();
+
+    run_host_loop(
+        &mut io::stdin().lock(),
+        io::stdout(),
+        max_message_bytes(),
+        worker_count(),
+        rt.handle(),
+        handle_message,
+    );
 }
 
 async fn handle_message(msg: NativeMessage) -> NativeResponse {
@@ -136,7 +285,7 @@ async fn handle_message(msg: NativeMessage) -> NativeResponse {
             if check_proxy_running().await {
                 return NativeResponse {
                     status: "started".to_string(),
-                    port: Some(8899),
+                    port: crate::profiles::proxy_listener().port(),
                     running: Some(true),
                     ..NativeResponse::ok()
                 };
@@ -154,7 +303,7 @@ async fn handle_message(msg: NativeMessage) -> NativeResponse {
                     let running = check_proxy_running().await;
                     NativeResponse {
                         status: if running { "started" } else { "starting" }.to_string(),
-                        port: Some(8899),
+                        port: crate::profiles::proxy_listener().port(),
                         running: Some(running),
                         ..NativeResponse::ok()
                     }
@@ -167,7 +316,7 @@ async fn handle_message(msg: NativeMessage) -> NativeResponse {
             let running = check_proxy_running().await;
             NativeResponse {
                 status: "ok".to_string(),
-                port: Some(8899),
+                port: crate::profiles::proxy_listener().port(),
                 running: Some(running),
                 ..NativeResponse::ok()
             }
@@ -180,7 +329,7 @@ async fn handle_message(msg: NativeMessage) -> NativeResponse {
                     let running = check_proxy_running().await;
                     NativeResponse {
                         status: "ok".to_string(),
-                        port: Some(8899),
+                        port: crate::profiles::proxy_listener().port(),
                         running: Some(running),
                         ..NativeResponse::ok()
                     }
@@ -268,13 +417,121 @@ async fn handle_message(msg: NativeMessage) -> NativeResponse {
                 Err(e) => NativeResponse::error(format!("Failed to clear RPC: {}", e)),
             }
         }
+        "reset_stats" => {
+            match proxy_control_post("/control/reset_stats", None).await {
+                Ok(_) => NativeResponse::ok(),
+                Err(e) => NativeResponse::error(format!("Failed to reset stats: {}", e)),
+            }
+        }
+        "test_endpoint" => {
+            // Check a candidate RPC (`rpc_url`) through the proxy's current routing
+            let Some(rpc_url) = msg.rpc_url else {
+                return NativeResponse::error("test_endpoint requires rpc_url".to_string());
+            };
+            let body = serde_json::json!({"url": rpc_url});
+            match proxy_control_post("/control/test_endpoint", Some(body)).await {
+                Ok(json) => NativeResponse {
+                    error: json.get("error").and_then(|v| v.as_str()).map(|s| s.to_string()),
+                    reachable: json.get("reachable").and_then(|v| v.as_bool()),
+                    latency_ms: json.get("latency_ms").and_then(|v| v.as_u64()),
+                    version: json.get("version").and_then(|v| v.as_str()).map(|s| s.to_string()),
+                    tor_used: json.get("tor_used").and_then(|v| v.as_bool()),
+                    ..NativeResponse::ok()
+                },
+                Err(e) => NativeResponse::error(format!("Failed to test endpoint: {}", e)),
+            }
+        }
+        "healthcheck_all" => {
+            // Probe the primary RPC and every fallback through the proxy's current routing
+            match proxy_control_post("/control/healthcheck_all", None).await {
+                Ok(json) => NativeResponse {
+                    endpoints: json.get("endpoints").and_then(|v| v.as_array()).cloned(),
+                    ..NativeResponse::ok()
+                },
+                Err(e) => NativeResponse::error(format!("Failed to check endpoints: {}", e)),
+            }
+        }
+        "version" => {
+            // The host is the installed app binary, so it can answer without the proxy
+            NativeResponse {
+                version: Some(env!("CARGO_PKG_VERSION").to_string()),
+                build: Some(crate::proxy::BUILD_HASH.to_string()),
+                capabilities: Some(crate::proxy::CAPABILITIES.iter().map(|c| c.to_string()).collect()),
+                ..NativeResponse::ok()
+            }
+        }
+        "list_profiles" => {
+            // Profiles live in the shared config file, so no proxy round trip is needed
+            NativeResponse {
+                profiles: Some(crate::profiles::list_profiles().iter().map(|p| p.summary()).collect()),
+                active_profile: crate::profiles::active_profile(),
+                ..NativeResponse::ok()
+            }
+        }
+        "save_profile" => {
+            let (Some(name), Some(rpc_url)) = (msg.name, msg.rpc_url) else {
+                return NativeResponse::error("save_profile requires name and rpc_url".to_string());
+            };
+            match crate::profiles::save_profile(&name, &rpc_url, msg.headers.unwrap_or_default()) {
+                Ok(()) => NativeResponse::ok(),
+                Err(e) => NativeResponse::error(format!("Failed to save profile: {}", e)),
+            }
+        }
+        "switch_profile" => {
+            // The running proxy has to apply it, so this goes through the control endpoint
+            let Some(name) = msg.name else {
+                return NativeResponse::error("switch_profile requires name".to_string());
+            };
+            match proxy_control_post("/control/switch_profile", Some(serde_json::json!({ "name": name }))).await {
+                Ok(_) => NativeResponse {
+                    active_profile: Some(name),
+                    ..NativeResponse::ok()
+                },
+                Err(e) => NativeResponse::error(format!("Failed to switch profile: {}", e)),
+            }
+        }
+        "ws_auth" => {
+            // Hand the extension the token it needs to connect to the WebSocket server
+            match crate::websocket::read_auth_token_file() {
+                Some(token) => {
+                    let ws_port = get_full_status().await.and_then(|s| s.ws_port);
+                    NativeResponse {
+                        ws_token: Some(token),
+                        ws_port,
+                        ..NativeResponse::ok()
+                    }
+                }
+                None => NativeResponse::error("WebSocket auth token not available - is the app running?".to_string()),
+            }
+        }
         _ => NativeResponse::error(format!("Unknown action: {}", msg.action)),
     }
 }
 
+/// Where the running app's proxy listens, and a client for it. Over HTTPS the
+/// client trusts only the proxy's own certificate, which is usually
+/// self-signed and doesn't name the loopback address.
+fn proxy_client() -> Result<(String, reqwest::Client), String> {
+    let listener = crate::profiles::proxy_listener();
+    let mut builder = reqwest::Client::builder();
+    if let Some(cert) = &listener.tls_cert {
+        let pem = std::fs::read(cert).map_err(|e| format!("{}: {}", cert.display(), e))?;
+        let cert = reqwest::Certificate::from_pem(&pem).map_err(|e| e.to_string())?;
+        builder = builder
+            .tls_built_in_root_certs(false)
+            .add_root_certificate(cert)
+            .danger_accept_invalid_hostnames(true);
+    }
+    let client = builder.build().map_err(|e| e.to_string())?;
+    Ok((listener.url, client))
+}
+
 async fn check_proxy_running() -> bool {
-    match reqwest::Client::new()
-        .get("http://127.0.0.1:8899/health")
+    let Ok((url, client)) = proxy_client() else {
+        return false;
+    };
+    match client
+        .get(format!("{}/health", url))
         .timeout(std::time::Duration::from_secs(2))
         .send()
         .await
@@ -286,9 +543,9 @@ async fn check_proxy_running() -> bool {
 
 /// Get full status from the proxy's /status endpoint
 async fn get_full_status() -> Option<NativeResponse> {
-    let client = reqwest::Client::new();
+    let (url, client) = proxy_client().ok()?;
     let resp = client
-        .get("http://127.0.0.1:8899/status")
+        .get(format!("{}/status", url))
         .timeout(std::time::Duration::from_secs(2))
         .send()
         .await
@@ -298,7 +555,7 @@ async fn get_full_status() -> Option<NativeResponse> {
 
     Some(NativeResponse {
         status: "ok".to_string(),
-        port: Some(8899),
+        port: crate::profiles::proxy_listener().port(),
         error: None,
         running: json.get("running").and_then(|v| v.as_bool()),
         tor_enabled: json.get("tor_enabled").and_then(|v| v.as_bool()),
@@ -315,6 +572,12 @@ async fn get_full_status() -> Option<NativeResponse> {
             .get("rpc_provider")
             .and_then(|v| v.as_str())
             .map(|s| s.to_string()),
+        ws_token: None,
+        ws_port: json
+            .get("ws_port")
+            .and_then(|v| v.as_u64())
+            .map(|v| v as u16),
+        ..NativeResponse::ok()
     })
 }
 
@@ -323,8 +586,8 @@ async fn proxy_control_post(
     path: &str,
     body: Option<serde_json::Value>,
 ) -> Result<serde_json::Value, String> {
-    let client = reqwest::Client::new();
-    let url = format!("http://127.0.0.1:8899{}", path);
+    let (base, client) = proxy_client()?;
+    let url = format!("{}{}", base, path);
 
     let mut req = client
         .post(&url)
@@ -346,3 +609,196 @@ async fn proxy_control_post(
         .await
         .map_err(|e| e.to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(body: &[u8]) -> Vec<u8> {
+        let mut framed = (body.len() as u32).to_le_bytes().to_vec();
+        framed.extend_from_slice(body);
+        framed
+    }
+
+    /// Run the host over `input` with `handle`, returning what it wrote
+    fn run_host<F, Fut>(input: &[u8], max_len: usize, workers: usize, handle: F) -> Vec<u8>
+    where
+        F: Fn(NativeMessage) -> Fut,
+        Fut: Future<Output = NativeResponse> + Send + 'static,
+    {
+        /// Output the test can read back once the host's writer thread is done
+        struct Shared(Arc<std::sync::Mutex<Vec<u8>>>);
+
+        impl Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let out = Arc::new(std::sync::Mutex::new(Vec::new()));
+        run_host_loop(&mut &input[..], Shared(out.clone()), max_len, workers, rt.handle(), handle);
+        let out = out.lock().unwrap().clone();
+        out
+    }
+
+    /// Split the host's output back into JSON responses
+    fn responses(mut out: &[u8]) -> Vec<serde_json::Value> {
+        let mut responses = Vec::new();
+        while out.len() >= 4 {
+            let len = u32::from_le_bytes(out[..4].try_into().unwrap()) as usize;
+            responses.push(serde_json::from_slice(&out[4..4 + len]).unwrap());
+            out = &out[4 + len..];
+        }
+        responses
+    }
+
+    #[test]
+    fn test_oversized_message_gets_error_response() {
+        let oversized = format!(r#"{{"action":"set_rpc","rpc_url":"{}"}}"#, "x".repeat(200));
+        let mut input = frame(oversized.as_bytes());
+        input.extend(frame(br#"{"action":"status"}"#));
+
+        let handled = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let out = run_host(&input, 64, 1, |msg| {
+            handled.lock().unwrap().push(msg.action);
+            async { NativeResponse::ok() }
+        });
+
+        let responses = responses(&out);
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0]["status"], "error");
+        assert!(responses[0]["error"].as_str().unwrap().contains("exceeds the 64 byte limit"));
+        assert_eq!(responses[1]["status"], "ok");
+        assert_eq!(*handled.lock().unwrap(), vec!["status"]);
+    }
+
+    #[test]
+    fn test_malformed_message_does_not_end_host() {
+        let mut input = frame(b"{not json");
+        input.extend(frame(br#"{"rpc_url":"missing action"}"#));
+        input.extend(frame(br#"{"action":"status"}"#));
+
+        let handled = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let out = run_host(&input, 1024, 1, |msg| {
+            handled.lock().unwrap().push(msg.action);
+            async { NativeResponse::ok() }
+        });
+
+        let responses = responses(&out);
+        assert_eq!(responses.len(), 3);
+        assert_eq!(responses[0]["status"], "error");
+        assert!(responses[0]["error"].as_str().unwrap().starts_with("Invalid message"));
+        assert_eq!(responses[1]["status"], "error");
+        assert_eq!(responses[2]["status"], "ok");
+        assert_eq!(*handled.lock().unwrap(), vec!["status"]);
+    }
+
+    #[test]
+    fn test_burst_never_exceeds_worker_count() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let mut input = Vec::new();
+        for i in 0..20 {
+            input.extend(frame(format!(r#"{{"action":"status","name":"{}"}}"#, i).as_bytes()));
+        }
+        let active = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let out = run_host(&input, 1024, 3, |msg| {
+            let (active, peak) = (active.clone(), peak.clone());
+            async move {
+                let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                // Later messages finish first, to check the responses stay in order
+                let index: u64 = msg.name.as_deref().unwrap().parse().unwrap();
+                tokio::time::sleep(std::time::Duration::from_millis(40 - index)).await;
+                active.fetch_sub(1, Ordering::SeqCst);
+                NativeResponse {
+                    status: msg.name.unwrap(),
+                    ..NativeResponse::ok()
+                }
+            }
+        });
+
+        assert_eq!(peak.load(Ordering::SeqCst), 3);
+        let statuses: Vec<_> = responses(&out).iter().map(|r| r["status"].as_str().unwrap().to_string()).collect();
+        assert_eq!(statuses, (0..20).map(|i| i.to_string()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_slow_head_stops_reading() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let mut input = Vec::new();
+        for i in 0..10 {
+            input.extend(frame(format!(r#"{{"action":"status","name":"{}"}}"#, i).as_bytes()));
+        }
+        let started = Arc::new(AtomicUsize::new(0));
+        let seen_by_head = Arc::new(AtomicUsize::new(0));
+        let out = run_host(&input, 1024, 2, |msg| {
+            let (started, seen_by_head) = (started.clone(), seen_by_head.clone());
+            async move {
+                started.fetch_add(1, Ordering::SeqCst);
+                if msg.name.as_deref() == Some("0") {
+                    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                    seen_by_head.store(started.load(Ordering::SeqCst), Ordering::SeqCst);
+                }
+                NativeResponse::ok()
+            }
+        });
+
+        // Finished messages behind the head keep their slots until written
+        assert_eq!(seen_by_head.load(Ordering::SeqCst), 2);
+        assert_eq!(responses(&out).len(), 10);
+    }
+
+    #[test]
+    fn test_truncated_body_is_eof() {
+        let mut input = frame(br#"{"action":"status"}"#);
+        input.truncate(10);
+        assert!(matches!(read_message(&mut input.as_slice(), 1024), Ok(Incoming::Eof)));
+    }
+
+    #[tokio::test]
+    async fn test_version_action_answers_locally() {
+        let msg: NativeMessage = serde_json::from_str(r#"{"action":"version"}"#).unwrap();
+        let response = serde_json::to_value(handle_message(msg).await).unwrap();
+        assert_eq!(response["version"], env!("CARGO_PKG_VERSION"));
+        assert!(response["capabilities"].as_array().is_some_and(|c| !c.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn test_reaches_tls_proxy() {
+        let _guard = crate::proxy::tests::SERVER_TEST_LOCK.lock().await;
+        let dir = std::env::temp_dir().join(format!("privacyrpc-native-tls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let (cert, key) = (dir.join("cert.pem"), dir.join("key.pem"));
+        std::fs::write(&cert, certified.cert.pem()).unwrap();
+        std::fs::write(&key, certified.key_pair.serialize_pem()).unwrap();
+        crate::proxy::set_tls_files(Some((cert.clone(), key.clone()))).unwrap();
+        // Saved for the next launch
+        assert_eq!(crate::profiles::saved_tls_files(), Some((cert, key)));
+
+        let port = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port();
+        crate::proxy::start_proxy_server(std::net::SocketAddr::from(([127, 0, 0, 1], port)))
+            .await
+            .unwrap();
+        assert_eq!(crate::profiles::proxy_listener().url, format!("https://127.0.0.1:{}", port));
+
+        // The handshake succeeds against the self-signed certificate
+        assert!(check_proxy_running().await);
+        let status = get_full_status().await.unwrap();
+        assert_eq!(status.port, Some(port));
+        assert_eq!(status.running, Some(true));
+
+        crate::proxy::stop_proxy_server().await;
+        crate::proxy::set_tls_files(None).unwrap();
+        assert_eq!(crate::profiles::saved_tls_files(), None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,258 @@
+//! Saved RPC Profiles
+//! Named endpoints (URL plus auth headers) kept in `config.json` alongside the
+//! active endpoint, so users can switch providers without re-pasting URLs.
+//! The active profile's name is saved too and re-applied on launch.
+//! The same file records the proxy's TLS files and where it last listened,
+//! which the native messaging host (a separate process) reads to reach it.
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Serializes read-modify-write cycles on the config file
+static CONFIG_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcProfile {
+    pub name: String,
+    pub url: String,
+    /// Sent on every request to `url`; values are usually API keys
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+}
+
+impl RpcProfile {
+    /// For display: header names only, since their values are secrets
+    pub fn summary(&self) -> serde_json::Value {
+        let mut header_names: Vec<&String> = self.headers.keys().collect();
+        header_names.sort();
+        serde_json::json!({ "name": self.name, "url": self.url, "headerNames": header_names })
+    }
+}
+
+/// Where the proxy listens, recorded each time it starts
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ProxyListener {
+    /// Loopback URL of the listener, e.g. `https://127.0.0.1:8899`
+    pub url: String,
+    /// Certificate served over HTTPS, which clients should trust
+    #[serde(default)]
+    pub tls_cert: Option<PathBuf>,
+}
+
+impl Default for ProxyListener {
+    fn default() -> Self {
+        Self {
+            url: "http://127.0.0.1:8899".to_string(),
+            tls_cert: None,
+        }
+    }
+}
+
+impl ProxyListener {
+    pub fn port(&self) -> Option<u16> {
+        reqwest::Url::parse(&self.url).ok()?.port_or_known_default()
+    }
+}
+
+fn config_path() -> Option<PathBuf> {
+    if cfg!(test) {
+        return Some(std::env::temp_dir().join(format!("privacyrpc-config-{}.json", std::process::id())));
+    }
+    directories::ProjectDirs::from("com", "privacyrpc", "PrivacyRPC").map(|dirs| dirs.config_dir().join("config.json"))
+}
+
+fn read_config() -> serde_json::Map<String, serde_json::Value> {
+    config_path()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Apply `update` to the saved config, keeping every key it doesn't touch.
+/// The file holds API keys, so only the current user may read it.
+pub fn update_config(update: impl FnOnce(&mut serde_json::Map<String, serde_json::Value>)) -> Result<(), String> {
+    let _guard = CONFIG_LOCK.lock();
+    let path = config_path().ok_or("No config directory")?;
+    let mut config = read_config();
+    update(&mut config);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create config dir: {}", e))?;
+    }
+    let content = serde_json::to_string_pretty(&config).map_err(|e| e.to_string())?;
+    let write_error = |e: std::io::Error| format!("Failed to write {:?}: {}", path, e);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&path).map_err(write_error)?;
+    // The mode only applies on creation; tighten a file saved by an older version
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600)).map_err(write_error)?;
+    }
+    std::io::Write::write_all(&mut file, content.as_bytes()).map_err(write_error)
+}
+
+/// Record where the proxy now listens
+pub fn save_proxy_listener(listener: &ProxyListener) -> Result<(), String> {
+    update_config(|config| {
+        config.insert("proxyListener".to_string(), serde_json::json!(listener));
+    })
+}
+
+/// Where the proxy last listened; the default port over plain HTTP if it never started
+pub fn proxy_listener() -> ProxyListener {
+    read_config()
+        .get("proxyListener")
+        .and_then(|listener| serde_json::from_value(listener.clone()).ok())
+        .unwrap_or_default()
+}
+
+/// Save the proxy's TLS certificate and key paths (`None` for plain HTTP)
+pub fn save_tls_files(files: Option<&(PathBuf, PathBuf)>) -> Result<(), String> {
+    let saved = files.map(|(cert, key)| serde_json::json!({ "cert": cert, "key": key }));
+    update_config(|config| {
+        config.insert("proxyTls".to_string(), serde_json::json!(saved));
+    })
+}
+
+/// TLS certificate and key paths saved with [`save_tls_files`]
+pub fn saved_tls_files() -> Option<(PathBuf, PathBuf)> {
+    let config = read_config();
+    let tls = config.get("proxyTls")?;
+    Some((PathBuf::from(tls["cert"].as_str()?), PathBuf::from(tls["key"].as_str()?)))
+}
+
+/// Save whether Tor keeps its data directory between runs
+pub fn save_tor_persistent_data(enabled: bool) -> Result<(), String> {
+    update_config(|config| {
+        config.insert("torPersistentData".to_string(), serde_json::json!(enabled));
+    })
+}
+
+/// Whether Tor keeps its data directory between runs, as saved with [`save_tor_persistent_data`]
+pub fn tor_persistent_data() -> bool {
+    read_config().get("torPersistentData").and_then(|v| v.as_bool()).unwrap_or(false)
+}
+
+/// Saved profiles, in the order they were first saved
+pub fn list_profiles() -> Vec<RpcProfile> {
+    read_config()
+        .get("profiles")
+        .and_then(|profiles| serde_json::from_value(profiles.clone()).ok())
+        .unwrap_or_default()
+}
+
+/// Name of the profile last switched to, unless the endpoint has been set by hand since
+pub fn active_profile() -> Option<String> {
+    read_config().get("activeProfile")?.as_str().map(String::from)
+}
+
+/// Save a profile, replacing any with the same name. Doesn't switch to it.
+pub fn save_profile(name: &str, url: &str, headers: HashMap<String, String>) -> Result<(), String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Profile name must not be empty".to_string());
+    }
+    let url = url.trim();
+    crate::proxy::validate_rpc_url(url)?;
+    crate::proxy::validate_rpc_headers(&headers)?;
+
+    let mut profiles = list_profiles();
+    let profile = RpcProfile {
+        name: name.to_string(),
+        url: url.to_string(),
+        headers,
+    };
+    match profiles.iter_mut().find(|p| p.name == name) {
+        Some(existing) => *existing = profile,
+        None => profiles.push(profile),
+    }
+    update_config(|config| {
+        config.insert("profiles".to_string(), serde_json::json!(profiles));
+    })?;
+    log::info!("Saved RPC profile '{}'", name);
+    Ok(())
+}
+
+/// Make the named profile the active RPC endpoint and remember it for next launch
+pub fn switch_profile(name: &str) -> Result<RpcProfile, String> {
+    let profile = list_profiles()
+        .into_iter()
+        .find(|p| p.name == name)
+        .ok_or_else(|| format!("No RPC profile named '{}'", name))?;
+    crate::proxy::set_rpc_endpoint_with_headers(Some(profile.url.clone()), profile.headers.clone())?;
+    update_config(|config| {
+        config.insert("rpcEndpoint".to_string(), serde_json::json!(profile.url));
+        config.insert("activeProfile".to_string(), serde_json::json!(profile.name));
+    })?;
+    log::info!("Switched to RPC profile '{}'", name);
+    Ok(profile)
+}
+
+/// Re-apply the active profile saved by a previous run, headers included
+pub fn restore_active_profile() -> Option<RpcProfile> {
+    let name = active_profile()?;
+    match switch_profile(&name) {
+        Ok(profile) => Some(profile),
+        Err(e) => {
+            log::warn!("Failed to restore RPC profile '{}': {}", name, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_save_list_and_switch_profiles() {
+        let _guard = crate::proxy::tests::SERVER_TEST_LOCK.lock().await;
+        // Written by an older version, readable by anyone
+        std::fs::write(config_path().unwrap(), "{}").unwrap();
+
+        let headers = HashMap::from([("x-api-key".to_string(), "secret".to_string())]);
+        save_profile("Helius", "https://mainnet.helius-rpc.com/", headers.clone()).unwrap();
+        save_profile("Triton", "https://triton.example.com/", HashMap::new()).unwrap();
+        // Same name replaces rather than duplicates
+        save_profile("Triton", " https://triton2.example.com/ ", HashMap::new()).unwrap();
+        assert!(save_profile("", "https://a.example.com/", HashMap::new()).is_err());
+        assert!(save_profile("Bad", "ftp://a.example.com/", HashMap::new()).is_err());
+
+        let profiles = list_profiles();
+        let names: Vec<&str> = profiles.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["Helius", "Triton"]);
+        assert_eq!(profiles[1].url, "https://triton2.example.com/");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(config_path().unwrap()).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let profile = switch_profile("Helius").unwrap();
+        assert_eq!(profile.headers, headers);
+        assert_eq!(crate::proxy::get_rpc_endpoint().as_deref(), Some("https://mainnet.helius-rpc.com/"));
+        assert_eq!(crate::proxy::PROXY_CONFIG.lock().rpc_headers, headers);
+        assert!(switch_profile("Missing").is_err());
+
+        // What a restart reads back
+        assert_eq!(active_profile().as_deref(), Some("Helius"));
+        assert_eq!(read_config()["rpcEndpoint"], "https://mainnet.helius-rpc.com/");
+        crate::proxy::set_rpc_endpoint(None);
+        assert_eq!(restore_active_profile().map(|p| p.name).as_deref(), Some("Helius"));
+        assert_eq!(crate::proxy::get_rpc_endpoint().as_deref(), Some("https://mainnet.helius-rpc.com/"));
+
+        crate::proxy::set_rpc_endpoint(None);
+        let _ = std::fs::remove_file(config_path().unwrap());
+    }
+}
@@ -1,11 +1,19 @@
-use crate::transaction_decoder;
+use crate::simulation;
+use crate::websocket::{broadcast_event, WsEvent};
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
+use privacyrpc_sdk::telemetry::RequestSpan;
+use privacyrpc_sdk::transaction;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use std::collections::{HashMap, VecDeque};
 use std::net::SocketAddr;
-use std::sync::atomic::{AtomicU64, Ordering};
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
 use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::oneshot;
+use tokio::sync::{oneshot, OwnedSemaphorePermit, Semaphore};
 
 
 // Proxy server state
@@ -14,6 +22,63 @@ static SHUTDOWN_TX: Lazy<Mutex<Option<oneshot::Sender<()>>>> = Lazy::new(|| Mute
 // Shared stats counters
 pub static REQUESTS_PROXIED: AtomicU64 = AtomicU64::new(0);
 pub static BYTES_TRANSFERRED: AtomicU64 = AtomicU64::new(0);
+// How long an idle keep-alive connection is held open waiting for the next request
+const KEEP_ALIVE_IDLE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+// CONNECT tunnels: cap on reaching the target, and on time with no traffic
+// either way before the tunnel is torn down (zero disables either)
+static TUNNEL_CONNECT_TIMEOUT_MS: AtomicU64 = AtomicU64::new(30_000);
+static TUNNEL_IDLE_TIMEOUT_MS: AtomicU64 = AtomicU64::new(300_000);
+pub static ACTIVE_TUNNELS: AtomicU64 = AtomicU64::new(0);
+
+// CONNECT targets allowed besides the configured RPC hosts and Jito. With no
+// list set, tunnels are only restricted while the proxy listens beyond loopback.
+static CONNECT_ALLOWLIST: Lazy<Mutex<Option<Vec<String>>>> = Lazy::new(|| Mutex::new(None));
+static LISTENING_BEYOND_LOOPBACK: AtomicBool = AtomicBool::new(false);
+
+// Jito's JSON-RPC endpoint for all MEV/bundle operations
+const JITO_MAINNET_URL: &str = "https://mainnet.block-engine.jito.wtf/api/v1/bundles";
+
+// Request head limits; past either the client gets 431 and the connection closes
+static MAX_HEADER_COUNT: AtomicUsize = AtomicUsize::new(100);
+static MAX_HEADER_LINE_BYTES: AtomicUsize = AtomicUsize::new(8 * 1024);
+
+// Upstream clients reused across requests, keyed by the route and default
+// headers they were built for (at most one plain and one keyed client)
+static UPSTREAM_CLIENTS: Lazy<Mutex<Vec<(UpstreamRoute, HeaderMap, reqwest::Client)>>> =
+    Lazy::new(|| Mutex::new(Vec::new()));
+
+// Bounds simultaneous upstream forwards; replaced (not resized) when the limit changes
+const DEFAULT_MAX_CONCURRENCY: usize = 64;
+static UPSTREAM_PERMITS: Lazy<Mutex<(usize, Arc<Semaphore>)>> =
+    Lazy::new(|| Mutex::new((DEFAULT_MAX_CONCURRENCY, Arc::new(Semaphore::new(DEFAULT_MAX_CONCURRENCY)))));
+pub static IN_FLIGHT_REQUESTS: AtomicU64 = AtomicU64::new(0);
+
+// Bounds open client connections; past it new ones get 503 and are closed.
+// Replaced like UPSTREAM_PERMITS, so open connections keep their old slots.
+const DEFAULT_MAX_CONNECTIONS: usize = 256;
+static CONNECTION_PERMITS: Lazy<Mutex<(usize, Arc<Semaphore>)>> =
+    Lazy::new(|| Mutex::new((DEFAULT_MAX_CONNECTIONS, Arc::new(Semaphore::new(DEFAULT_MAX_CONNECTIONS)))));
+const BUSY_RESPONSE: &[u8] = b"HTTP/1.1 503 Service Unavailable\r\nRetry-After: 1\r\nConnection: close\r\nContent-Length: 0\r\n\r\n";
+// How long, and how much, a refused connection's request is read before closing
+const REFUSED_DRAIN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+const REFUSED_DRAIN_BYTES: usize = 64 * 1024;
+
+// While set, JSON-RPC requests get a "service paused" error instead of being forwarded
+static FORWARDING_PAUSED: AtomicBool = AtomicBool::new(false);
+
+// Requests wait this long for Tor to bootstrap before being refused
+const TOR_READY_WAIT: std::time::Duration = std::time::Duration::from_secs(2);
+// Set once TorNotReady is broadcast, until Tor is seen ready again
+static TOR_NOT_READY_REPORTED: AtomicBool = AtomicBool::new(false);
+
+// Last decoded transactions for the activity view, newest last, with the cap
+const DEFAULT_RECENT_DECODES: usize = 50;
+static RECENT_DECODES: Lazy<Mutex<(usize, VecDeque<RecentDecode>)>> =
+    Lazy::new(|| Mutex::new((DEFAULT_RECENT_DECODES, VecDeque::new())));
+
+// When the proxy started (or stats were last reset), for uptime
+static STATS_SINCE: Lazy<Mutex<Option<Instant>>> = Lazy::new(|| Mutex::new(None));
 
 // Shared proxy configuration (Tor routing + RPC endpoint)
 pub struct ProxyConfig {
@@ -21,6 +86,28 @@ pub struct ProxyConfig {
     pub tor_enabled: bool,
     pub tor_socks_port: u16,
     pub rpc_endpoint: Option<String>,
+    /// Headers sent with every request to `rpc_endpoint` (e.g. an API key).
+    /// Values are secrets and never logged.
+    pub rpc_headers: HashMap<String, String>,
+    /// Tried in order when `rpc_endpoint` fails or answers 5xx/429
+    pub fallback_endpoints: Vec<String>,
+    /// Origins allowed by CORS; `"*"` allows any origin
+    pub cors_allow_origins: Vec<String>,
+    /// Simulate sign/send requests upstream before forwarding them (adds latency)
+    pub simulate_transactions: bool,
+    /// Balance drop (percent) in simulation that raises a Critical warning
+    pub simulation_drain_threshold_pct: f64,
+    /// Add a `_privacyrpc` field with the decoded transaction to transaction
+    /// responses. Off by default since strict JSON-RPC clients may reject it;
+    /// a request can override this with `X-PrivacyRPC-Decode` (see [`DecodeMode`]).
+    pub inject_decoded_transactions: bool,
+    /// Decoded transactions below this risk level are neither logged nor
+    /// broadcast. Alerts are only ever sent for High and above.
+    pub min_log_risk_level: transaction::RiskLevel,
+    /// HTTP(S) proxy for upstream traffic when Tor is off (e.g. a corporate proxy)
+    pub upstream_proxy: Option<String>,
+    /// Certificate and key (PEM) to serve HTTPS with; plain HTTP when `None`
+    pub tls: Option<(PathBuf, PathBuf)>,
 }
 
 pub static PROXY_CONFIG: Lazy<Mutex<ProxyConfig>> = Lazy::new(|| {
@@ -29,14 +116,309 @@ pub static PROXY_CONFIG: Lazy<Mutex<ProxyConfig>> = Lazy::new(|| {
         tor_enabled: false,
         tor_socks_port: 0,
         rpc_endpoint: None,
+        rpc_headers: HashMap::new(),
+        fallback_endpoints: Vec::new(),
+        cors_allow_origins: vec!["*".to_string()],
+        simulate_transactions: false,
+        simulation_drain_threshold_pct: 50.0,
+        inject_decoded_transactions: false,
+        min_log_risk_level: transaction::RiskLevel::Low,
+        upstream_proxy: None,
+        tls: None,
     })
 });
 
+/// Limit how many requests are forwarded upstream at once (minimum 1).
+/// Requests already holding a permit finish under the previous limit.
+pub fn set_max_concurrency(limit: usize) {
+    let limit = limit.max(1);
+    *UPSTREAM_PERMITS.lock() = (limit, Arc::new(Semaphore::new(limit)));
+    log::info!("Max upstream concurrency set to {}", limit);
+}
+
+pub fn max_concurrency() -> usize {
+    UPSTREAM_PERMITS.lock().0
+}
+
+/// Limit how many client connections may be open at once (minimum 1).
+/// Connections already open stay open.
+pub fn set_max_connections(limit: usize) {
+    let limit = limit.max(1);
+    *CONNECTION_PERMITS.lock() = (limit, Arc::new(Semaphore::new(limit)));
+    log::info!("Max client connections set to {}", limit);
+}
+
+pub fn max_connections() -> usize {
+    CONNECTION_PERMITS.lock().0
+}
+
+/// A slot for a newly accepted connection, held until it closes; `None`
+/// when the limit is reached
+pub(crate) fn connection_slot() -> Option<OwnedSemaphorePermit> {
+    CONNECTION_PERMITS.lock().1.clone().try_acquire_owned().ok()
+}
+
+/// A transaction the proxy decoded on its way upstream
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct RecentDecode {
+    /// Unix seconds
+    pub timestamp: u64,
+    pub summary: String,
+    pub risk_level: transaction::RiskLevel,
+}
+
+/// Keep the last `limit` decoded transactions (minimum 1), dropping the
+/// oldest if there are already more
+pub fn set_recent_decodes_limit(limit: usize) {
+    let limit = limit.max(1);
+    let mut recent = RECENT_DECODES.lock();
+    recent.0 = limit;
+    while recent.1.len() > limit {
+        recent.1.pop_front();
+    }
+    log::info!("Keeping the last {} decoded transactions", limit);
+}
+
+pub fn recent_decodes_limit() -> usize {
+    RECENT_DECODES.lock().0
+}
+
+/// Decoded transactions kept for the activity view, newest first
+pub fn recent_decodes() -> Vec<RecentDecode> {
+    RECENT_DECODES.lock().1.iter().rev().cloned().collect()
+}
+
+fn record_recent_decode(info: &transaction::DecodedTransaction) {
+    let entry = RecentDecode {
+        timestamp: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        summary: info.summary.clone(),
+        risk_level: info.risk_level.clone(),
+    };
+    let mut recent = RECENT_DECODES.lock();
+    if recent.1.len() >= recent.0 {
+        recent.1.pop_front();
+    }
+    recent.1.push_back(entry);
+}
+
+/// Pause or resume upstream forwarding. The proxy stays bound and `/status`
+/// stays live, so clients get an error instead of connection refused.
+pub fn set_forwarding_paused(paused: bool) {
+    if FORWARDING_PAUSED.swap(paused, Ordering::SeqCst) == paused {
+        return;
+    }
+    if paused {
+        log::info!("Upstream forwarding paused");
+        broadcast_event(WsEvent::ProxyPaused);
+    } else {
+        log::info!("Upstream forwarding resumed");
+        broadcast_event(WsEvent::ProxyResumed);
+    }
+}
+
+pub fn is_forwarding_paused() -> bool {
+    FORWARDING_PAUSED.load(Ordering::SeqCst)
+}
+
+/// Set the CONNECT tunnel connect and idle timeouts (zero disables either)
+pub fn set_tunnel_timeouts(connect: std::time::Duration, idle: std::time::Duration) {
+    TUNNEL_CONNECT_TIMEOUT_MS.store(connect.as_millis() as u64, Ordering::Relaxed);
+    TUNNEL_IDLE_TIMEOUT_MS.store(idle.as_millis() as u64, Ordering::Relaxed);
+    log::info!("CONNECT tunnel timeouts set: connect {:?}, idle {:?}", connect, idle);
+}
+
+/// Restrict CONNECT tunnels to the configured RPC hosts, Jito and `hosts`
+/// (`host` or `host:port`). `None` restores the default: unrestricted while
+/// the proxy only listens on loopback, RPC hosts and Jito otherwise.
+pub fn set_connect_allowlist(hosts: Option<Vec<String>>) {
+    let hosts = hosts.map(|hosts| {
+        hosts
+            .iter()
+            .map(|h| h.trim().to_ascii_lowercase())
+            .filter(|h| !h.is_empty())
+            .collect::<Vec<_>>()
+    });
+    log::info!("CONNECT allowlist: {:?}", hosts);
+    *CONNECT_ALLOWLIST.lock() = hosts;
+}
+
+/// Whether a CONNECT to `target` (`host:port`) may be tunneled. RPC endpoints
+/// and Jito are only reachable on the port of their URL (443/80 by default);
+/// allowlist entries match a bare host on any port, or `host:port` exactly.
+fn connect_target_allowed(target: &str) -> bool {
+    let allowlist = CONNECT_ALLOWLIST.lock().clone();
+    if allowlist.is_none() && !LISTENING_BEYOND_LOOPBACK.load(Ordering::Relaxed) {
+        return true;
+    }
+    let target = target.to_ascii_lowercase();
+    let (host, port) = match target.rsplit_once(':') {
+        Some((host, port)) if port.parse::<u16>().is_ok() => (host, port.parse::<u16>().ok()),
+        _ => (target.as_str(), None),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    // CONNECT is for TLS; a target without a port means 443
+    let port = port.unwrap_or(443);
+
+    let rpc_targets: Vec<(String, u16)> = {
+        let config = PROXY_CONFIG.lock();
+        config
+            .rpc_endpoint
+            .iter()
+            .chain(&config.fallback_endpoints)
+            .map(String::as_str)
+            .chain([JITO_MAINNET_URL])
+            .filter_map(|url| {
+                let url = reqwest::Url::parse(url).ok()?;
+                Some((url.host_str()?.to_ascii_lowercase(), url.port_or_known_default()?))
+            })
+            .collect()
+    };
+    rpc_targets
+        .iter()
+        .any(|(h, p)| h.trim_start_matches('[').trim_end_matches(']') == host && *p == port)
+        || allowlist
+            .unwrap_or_default()
+            .iter()
+            .any(|allowed| *allowed == target || allowed == host)
+}
+
+/// Current `(connect, idle)` CONNECT tunnel timeouts
+pub fn tunnel_timeouts() -> (std::time::Duration, std::time::Duration) {
+    (
+        std::time::Duration::from_millis(TUNNEL_CONNECT_TIMEOUT_MS.load(Ordering::Relaxed)),
+        std::time::Duration::from_millis(TUNNEL_IDLE_TIMEOUT_MS.load(Ordering::Relaxed)),
+    )
+}
+
+/// Limit how many header lines a request may send, and how long any line
+/// of its head (request line included) may be, in bytes. Minimum 1 each.
+pub fn set_header_limits(max_headers: usize, max_line_bytes: usize) {
+    MAX_HEADER_COUNT.store(max_headers.max(1), Ordering::Relaxed);
+    MAX_HEADER_LINE_BYTES.store(max_line_bytes.max(1), Ordering::Relaxed);
+    log::info!("Header limits set: {} headers, {} bytes per line", max_headers, max_line_bytes);
+}
+
+/// Current `(max_headers, max_line_bytes)`
+pub fn header_limits() -> (usize, usize) {
+    (MAX_HEADER_COUNT.load(Ordering::Relaxed), MAX_HEADER_LINE_BYTES.load(Ordering::Relaxed))
+}
+
+/// Read a request line and its header lines, up to the blank line. `None`
+/// means a header limit was exceeded; the rest of the head is left unread.
+async fn read_request_head<R>(buf_reader: &mut BufReader<R>) -> std::io::Result<Option<(String, Vec<String>)>>
+where
+    R: AsyncRead + Unpin,
+{
+    let (max_headers, max_line_bytes) = header_limits();
+    let read_line = async |buf_reader: &mut BufReader<R>| -> std::io::Result<Option<String>> {
+        let mut line = String::new();
+        // One byte over the limit is enough to know it was exceeded
+        (&mut *buf_reader).take(max_line_bytes as u64 + 1).read_line(&mut line).await?;
+        Ok((line.len() <= max_line_bytes).then_some(line))
+    };
+
+    let Some(request_line) = read_line(buf_reader).await? else {
+        return Ok(None);
+    };
+    let mut headers = Vec::new();
+    if request_line.is_empty() {
+        return Ok(Some((request_line, headers)));
+    }
+    loop {
+        let Some(line) = read_line(buf_reader).await? else {
+            return Ok(None);
+        };
+        if line == "\r\n" || line.is_empty() {
+            return Ok(Some((request_line, headers)));
+        }
+        if headers.len() == max_headers {
+            return Ok(None);
+        }
+        headers.push(line);
+    }
+}
+
+/// Split a request line into `(method, path, version)`, tolerating runs of
+/// whitespace between the parts. The query string is dropped from the path.
+fn parse_request_line(line: &str) -> Option<(&str, &str, &str)> {
+    let mut parts = line.split_whitespace();
+    let (method, target, version) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() || !version.starts_with("HTTP/") {
+        return None;
+    }
+    let path = target.split_once('?').map_or(target, |(path, _)| path);
+    Some((method, path, version))
+}
+
+const HEADERS_TOO_LARGE: &[u8] =
+    b"HTTP/1.1 431 Request Header Fields Too Large\r\nConnection: close\r\nContent-Length: 0\r\n\r\n";
+
+/// Counts a CONNECT tunnel as active until dropped
+struct ActiveTunnel;
+
+impl ActiveTunnel {
+    fn open() -> Self {
+        ACTIVE_TUNNELS.fetch_add(1, Ordering::Relaxed);
+        ActiveTunnel
+    }
+}
+
+impl Drop for ActiveTunnel {
+    fn drop(&mut self) {
+        ACTIVE_TUNNELS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Counts a request as in flight until dropped
+struct InFlight {
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT_REQUESTS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Wait for an upstream slot under the current concurrency limit
+async fn acquire_upstream_slot() -> Result<InFlight, tokio::sync::AcquireError> {
+    let permits = UPSTREAM_PERMITS.lock().1.clone();
+    let permit = permits.acquire_owned().await?;
+    IN_FLIGHT_REQUESTS.fetch_add(1, Ordering::Relaxed);
+    Ok(InFlight { _permit: permit })
+}
+
+/// Whether upstream traffic can go out: always true unless Tor routing is on,
+/// in which case wait up to `TOR_READY_WAIT` for Tor to bootstrap. Requests
+/// must never fall back to going out directly.
+async fn wait_for_tor() -> bool {
+    let deadline = Instant::now() + TOR_READY_WAIT;
+    loop {
+        if !PROXY_CONFIG.lock().tor_enabled || crate::tor::get_tor_status().0 {
+            TOR_NOT_READY_REPORTED.store(false, Ordering::Relaxed);
+            return true;
+        }
+        if Instant::now() >= deadline {
+            if !TOR_NOT_READY_REPORTED.swap(true, Ordering::Relaxed) {
+                log::warn!("Tor routing is enabled but Tor is not bootstrapped; refusing requests");
+                broadcast_event(WsEvent::TorNotReady);
+            }
+            return false;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+}
+
 /// Enable or disable Tor SOCKS5 routing for the proxy
 pub fn set_tor_routing(enabled: bool, socks_port: u16) {
     let mut config = PROXY_CONFIG.lock();
     config.tor_enabled = enabled;
     config.tor_socks_port = socks_port;
+    drop(config);
+    reset_upstream_clients();
     log::info!(
         "Tor routing {}: SOCKS port {}",
         if enabled { "enabled" } else { "disabled" },
@@ -44,14 +426,225 @@ pub fn set_tor_routing(enabled: bool, socks_port: u16) {
     );
 }
 
+/// Drop the pooled upstream clients so the next request opens new connections.
+/// Called whenever the route or the Tor circuit changes: a kept-alive
+/// connection would otherwise stay on the old circuit.
+pub fn reset_upstream_clients() {
+    UPSTREAM_CLIENTS.lock().clear();
+}
+
 /// Set the RPC endpoint (called from main.rs)
 pub fn set_rpc_endpoint(endpoint: Option<String>) {
+    set_rpc_endpoint_with_headers(endpoint, HashMap::new())
+        .expect("no headers to validate");
+}
+
+/// Set the RPC endpoint along with headers to send it, e.g. `x-api-key`.
+/// The headers replace any set for the previous endpoint.
+pub fn set_rpc_endpoint_with_headers(
+    endpoint: Option<String>,
+    headers: HashMap<String, String>,
+) -> Result<(), String> {
+    validate_rpc_headers(&headers)?;
+
     let mut config = PROXY_CONFIG.lock();
     log::info!(
         "RPC endpoint set to: {}",
         endpoint.as_deref().unwrap_or("default (api.mainnet-beta.solana.com)")
     );
-    config.rpc_endpoint = endpoint;
+    if !headers.is_empty() {
+        let redacted: Vec<String> = headers.keys().map(|name| format!("{}: <redacted>", name)).collect();
+        log::info!("RPC endpoint headers: {}", redacted.join(", "));
+    }
+    config.rpc_endpoint = endpoint.clone();
+    config.rpc_headers = headers;
+    drop(config);
+
+    broadcast_event(WsEvent::RpcChanged { rpc_endpoint: endpoint });
+    Ok(())
+}
+
+/// Check that every header name and value can be sent
+pub fn validate_rpc_headers(headers: &HashMap<String, String>) -> Result<(), String> {
+    for (name, value) in headers {
+        HeaderName::from_bytes(name.as_bytes()).map_err(|_| format!("Invalid header name: {}", name))?;
+        HeaderValue::from_str(value).map_err(|_| format!("Invalid value for header {}", name))?;
+    }
+    Ok(())
+}
+
+/// Check that `url` is an absolute http(s) URL with a host
+pub fn validate_rpc_url(url: &str) -> Result<(), String> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| format!("Invalid URL {}: {}", url, e))?;
+    if !matches!(parsed.scheme(), "http" | "https") || parsed.host_str().is_none() {
+        return Err(format!("RPC URL must be http:// or https://: {}", url));
+    }
+    Ok(())
+}
+
+/// Set the endpoints tried after the private RPC endpoint fails; empty clears them.
+/// Fallbacks never get the private endpoint's headers.
+pub fn set_fallback_endpoints(urls: Vec<String>) -> Result<(), String> {
+    let urls: Vec<String> = urls.into_iter().map(|u| u.trim().to_string()).collect();
+    for url in &urls {
+        validate_rpc_url(url)?;
+    }
+    log::info!("RPC fallback endpoints set to: {:?}", urls);
+    PROXY_CONFIG.lock().fallback_endpoints = urls;
+    Ok(())
+}
+
+/// Headers configured for the private RPC endpoint
+pub(crate) fn rpc_auth_headers() -> HeaderMap {
+    // Validated in set_rpc_endpoint_with_headers
+    PROXY_CONFIG
+        .lock()
+        .rpc_headers
+        .iter()
+        .filter_map(|(name, value)| {
+            Some((HeaderName::from_bytes(name.as_bytes()).ok()?, HeaderValue::from_str(value).ok()?))
+        })
+        .collect()
+}
+
+/// Set the CORS origin allowlist (`"*"` allows any origin)
+pub fn set_cors_allow_origins(origins: Vec<String>) {
+    log::info!("CORS allowed origins set to: {:?}", origins);
+    PROXY_CONFIG.lock().cors_allow_origins = origins;
+}
+
+/// Enable or disable pre-sign simulation of sign/send requests
+pub fn set_transaction_simulation(enabled: bool, threshold_pct: f64) {
+    let mut config = PROXY_CONFIG.lock();
+    config.simulate_transactions = enabled;
+    config.simulation_drain_threshold_pct = threshold_pct;
+    log::info!(
+        "Transaction simulation {} (drain threshold {}%)",
+        if enabled { "enabled" } else { "disabled" },
+        threshold_pct
+    );
+}
+
+/// A request's `X-PrivacyRPC-Decode` header. Transactions are decoded, logged
+/// and broadcast in every mode; the header only decides whether the response
+/// carries the `_privacyrpc` field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DecodeMode {
+    /// No header (or an unknown value): follow `inject_decoded_transactions`
+    Default,
+    /// `true` or `inject`: add `_privacyrpc` to the response
+    Inject,
+    /// `log-only` or `false`: return the upstream body untouched
+    LogOnly,
+}
+
+impl DecodeMode {
+    fn from_header(value: &str) -> Self {
+        match value.to_ascii_lowercase().as_str() {
+            "true" | "inject" => DecodeMode::Inject,
+            "log-only" | "false" => DecodeMode::LogOnly,
+            _ => DecodeMode::Default,
+        }
+    }
+
+    fn injects(self) -> bool {
+        match self {
+            DecodeMode::Default => PROXY_CONFIG.lock().inject_decoded_transactions,
+            DecodeMode::Inject => true,
+            DecodeMode::LogOnly => false,
+        }
+    }
+}
+
+/// Enable or disable adding `_privacyrpc` decode info to transaction responses
+pub fn set_decode_injection(enabled: bool) {
+    PROXY_CONFIG.lock().inject_decoded_transactions = enabled;
+    log::info!("Decoded transaction injection {}", if enabled { "enabled" } else { "disabled" });
+}
+
+/// Only log decoded transactions at or above `level`
+pub fn set_min_log_risk_level(level: transaction::RiskLevel) {
+    log::info!("Logging decoded transactions at {:?} risk and above", level);
+    PROXY_CONFIG.lock().min_log_risk_level = level;
+}
+
+/// Route upstream traffic through an HTTP(S) proxy, or directly with `None`.
+/// Tor takes precedence while enabled; the two aren't chained.
+pub fn set_upstream_proxy(url: Option<String>) -> Result<(), String> {
+    let url = url.map(|u| u.trim().to_string()).filter(|u| !u.is_empty());
+    if let Some(ref url) = url {
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            return Err("Upstream proxy must be an http:// or https:// URL".to_string());
+        }
+        reqwest::Proxy::all(url).map_err(|e| format!("Invalid upstream proxy: {}", e))?;
+    }
+    log::info!("Upstream proxy {}", url.as_deref().unwrap_or("disabled"));
+    PROXY_CONFIG.lock().upstream_proxy = url;
+    reset_upstream_clients();
+    Ok(())
+}
+
+/// Serve the TCP listener over TLS with `cert` and `key` (PEM files), or
+/// plain HTTP with `None`. Takes effect the next time the proxy starts, and
+/// is saved to be restored on launch.
+pub fn set_tls_files(files: Option<(PathBuf, PathBuf)>) -> Result<(), String> {
+    if let Some((cert, key)) = &files {
+        // Fail now rather than when the proxy next starts
+        privacyrpc_sdk::tls::acceptor(cert, key).map_err(|e| e.to_string())?;
+    }
+    crate::profiles::save_tls_files(files.as_ref())?;
+    log::info!("Proxy TLS {}", if files.is_some() { "enabled" } else { "disabled" });
+    PROXY_CONFIG.lock().tls = files;
+    Ok(())
+}
+
+/// CORS response header lines for a request with the given `Origin`
+fn cors_headers(origin: Option<&str>) -> String {
+    let allowed = PROXY_CONFIG.lock().cors_allow_origins.clone();
+    match privacyrpc_sdk::cors_allow_origin(&allowed, origin) {
+        Some(o) if o == "*" => "Access-Control-Allow-Origin: *\r\n".to_string(),
+        Some(o) => format!("Access-Control-Allow-Origin: {}\r\nVary: Origin\r\n", o),
+        None => String::new(),
+    }
+}
+
+/// Features this build supports, so the extension can feature-detect
+pub const CAPABILITIES: &[&str] = &["tor", "tor_rotation", "decode", "simulation", "jito", "ws", "upstream_proxy"];
+
+/// Short commit hash set by build.rs, if built from a git checkout
+pub const BUILD_HASH: &str = match option_env!("PRIVACYRPC_BUILD_HASH") {
+    Some(hash) => hash,
+    None => "unknown",
+};
+
+/// Body of `GET /version`
+pub fn version_info() -> serde_json::Value {
+    serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "build": BUILD_HASH,
+        "capabilities": CAPABILITIES,
+    })
+}
+
+/// Seconds since the proxy started or stats were last reset
+pub fn uptime_seconds() -> u64 {
+    STATS_SINCE
+        .lock()
+        .map(|since| since.elapsed().as_secs())
+        .unwrap_or(0)
+}
+
+/// Zero the traffic counters and restart the uptime clock.
+/// Returns the prior `(requests_proxied, bytes_transferred, uptime_seconds)`.
+pub fn reset_stats() -> (u64, u64, u64) {
+    let uptime = uptime_seconds();
+    let requests = REQUESTS_PROXIED.swap(0, Ordering::Relaxed);
+    let bytes = BYTES_TRANSFERRED.swap(0, Ordering::Relaxed);
+    let mut since = STATS_SINCE.lock();
+    if since.is_some() {
+        *since = Some(Instant::now());
+    }
+    (requests, bytes, uptime)
 }
 
 /// Get the current RPC endpoint
@@ -59,17 +652,40 @@ pub fn get_rpc_endpoint() -> Option<String> {
     PROXY_CONFIG.lock().rpc_endpoint.clone()
 }
 
-pub async fn start_proxy_server(port: u16) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let addr = SocketAddr::from(([127, 0, 0, 1], port));
-
-    let listener = TcpListener::bind(addr).await?;
-    log::info!("Proxy server listening on {}", addr);
+/// Start the proxy on `addr`. Binding `[::]` listens dual-stack on IPv4 and IPv6.
+pub async fn start_proxy_server(addr: SocketAddr) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let tls = PROXY_CONFIG.lock().tls.clone();
+    let acceptor = match tls.as_ref().map(|(cert, key)| privacyrpc_sdk::tls::acceptor(cert, key)).transpose() {
+        Ok(acceptor) => acceptor,
+        Err(e) => {
+            let message = format!("Proxy TLS setup failed: {}", e);
+            log::error!("{}", message);
+            broadcast_event(WsEvent::ProxyError { message: message.clone() });
+            return Err(message.into());
+        }
+    };
+    let listener = match privacyrpc_sdk::bind_tcp_listener(addr).and_then(TcpListener::from_std) {
+        Ok(listener) => listener,
+        Err(e) => {
+            broadcast_bind_error(&format!("port {}", addr.port()), &e);
+            return Err(e.into());
+        }
+    };
+    LISTENING_BEYOND_LOOPBACK.store(!addr.ip().is_loopback(), Ordering::Relaxed);
+    record_listener(addr, tls.as_ref().map(|(cert, _)| cert.clone()));
+    log::info!(
+        "Proxy server listening on {}{}",
+        addr,
+        if acceptor.is_some() { " (TLS)" } else { "" }
+    );
 
-    // Mark as running
-    PROXY_CONFIG.lock().running = true;
+    let mut shutdown_rx = mark_running(Some(addr.port()));
 
-    let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<()>();
-    *SHUTDOWN_TX.lock() = Some(shutdown_tx);
+    if let Some(relay_port) = crate::ws_relay::relay_port_for(addr.port()) {
+        if let Err(e) = crate::ws_relay::start_ws_relay(SocketAddr::new(addr.ip(), relay_port)).await {
+            log::warn!("WebSocket relay failed to bind port {}: {}", relay_port, e);
+        }
+    }
 
     // Spawn the server in a background task
     tokio::spawn(async move {
@@ -77,13 +693,82 @@ pub async fn start_proxy_server(port: u16) -> Result<(), Box<dyn std::error::Err
             tokio::select! {
                 result = listener.accept() => {
                     match result {
-                        Ok((stream, _)) => {
-                            tokio::spawn(async move {
-                                if let Err(e) = handle_connection(stream).await {
-                                    log::error!("Connection error: {}", e);
-                                }
-                            });
+                        Ok((stream, peer)) => match (connection_slot(), &acceptor) {
+                            (None, None) => refuse_connection(stream),
+                            // Over TLS there is no one to tell before the handshake
+                            (None, Some(_)) => log::warn!("Connection limit reached, dropping connection"),
+                            // Handshake off the accept loop so a slow client can't stall it
+                            (Some(slot), Some(acceptor)) => {
+                                let acceptor = acceptor.clone();
+                                tokio::spawn(async move {
+                                    let handshake = tokio::time::timeout(
+                                        privacyrpc_sdk::tls::HANDSHAKE_TIMEOUT,
+                                        acceptor.accept(stream),
+                                    );
+                                    match handshake.await {
+                                        Ok(Ok(stream)) => spawn_connection(stream, slot, is_local_peer(peer)),
+                                        Ok(Err(e)) => log::debug!("TLS handshake failed: {}", e),
+                                        Err(_) => log::debug!("TLS handshake timed out"),
+                                    }
+                                });
+                            }
+                            (Some(slot), None) => spawn_connection(stream, slot, is_local_peer(peer)),
+                        },
+                        Err(e) => {
+                            log::error!("Accept error: {}", e);
                         }
+                    }
+                }
+                _ = &mut shutdown_rx => {
+                    log::info!("Proxy server shutting down");
+                    PROXY_CONFIG.lock().running = false;
+                    break;
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Start the proxy on a Unix domain socket instead of a TCP port.
+/// The socket file is removed when the server shuts down.
+#[cfg(unix)]
+pub async fn start_proxy_server_unix(
+    path: &std::path::Path,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    use std::os::unix::fs::FileTypeExt;
+
+    // Clear a socket file left behind by an unclean shutdown
+    if let Ok(meta) = std::fs::symlink_metadata(path) {
+        if meta.file_type().is_socket() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    let listener = match tokio::net::UnixListener::bind(path) {
+        Ok(listener) => listener,
+        Err(e) => {
+            broadcast_bind_error(&path.display().to_string(), &e);
+            return Err(e.into());
+        }
+    };
+    log::info!("Proxy server listening on unix:{}", path.display());
+    LISTENING_BEYOND_LOOPBACK.store(false, Ordering::Relaxed);
+
+    let mut shutdown_rx = mark_running(None);
+    let path = path.to_path_buf();
+
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                result = listener.accept() => {
+                    match result {
+                        Ok((stream, _)) => match connection_slot() {
+                            // Only this machine can reach a Unix socket
+                            Some(slot) => spawn_connection(stream, slot, true),
+                            None => refuse_connection(stream),
+                        },
                         Err(e) => {
                             log::error!("Accept error: {}", e);
                         }
@@ -92,6 +777,7 @@ pub async fn start_proxy_server(port: u16) -> Result<(), Box<dyn std::error::Err
                 _ = &mut shutdown_rx => {
                     log::info!("Proxy server shutting down");
                     PROXY_CONFIG.lock().running = false;
+                    let _ = std::fs::remove_file(&path);
                     break;
                 }
             }
@@ -101,12 +787,113 @@ pub async fn start_proxy_server(port: u16) -> Result<(), Box<dyn std::error::Err
     Ok(())
 }
 
+/// Tell connected clients the proxy couldn't listen on `address`
+fn broadcast_bind_error(address: &str, error: &std::io::Error) {
+    let message = format!("Proxy failed to bind {}: {}", address, error);
+    log::error!("{}", message);
+    broadcast_event(WsEvent::ProxyError { message });
+}
+
+/// Mark the proxy as running and install a fresh shutdown channel
+/// Save where the proxy listens, so the native messaging host can reach it
+fn record_listener(addr: SocketAddr, tls_cert: Option<PathBuf>) {
+    // Clients can't connect to a wildcard address; use loopback of the same family
+    let ip = match addr.ip() {
+        std::net::IpAddr::V4(ip) if ip.is_unspecified() => std::net::Ipv4Addr::LOCALHOST.into(),
+        std::net::IpAddr::V6(ip) if ip.is_unspecified() => std::net::Ipv6Addr::LOCALHOST.into(),
+        ip => ip,
+    };
+    let scheme = if tls_cert.is_some() { "https" } else { "http" };
+    let listener = crate::profiles::ProxyListener {
+        url: format!("{}://{}", scheme, SocketAddr::new(ip, addr.port())),
+        tls_cert,
+    };
+    if let Err(e) = crate::profiles::save_proxy_listener(&listener) {
+        log::warn!("Failed to record the proxy listener: {}", e);
+    }
+}
+
+fn mark_running(port: Option<u16>) -> oneshot::Receiver<()> {
+    PROXY_CONFIG.lock().running = true;
+    *STATS_SINCE.lock() = Some(Instant::now());
+
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+    *SHUTDOWN_TX.lock() = Some(shutdown_tx);
+
+    broadcast_event(WsEvent::ProxyStarted { port });
+    shutdown_rx
+}
+
+/// Answer a connection over the limit with 503 and close it, off the accept
+/// loop. Closing with the request still unread would reset the connection
+/// and could discard the 503, so the write side is shut down first and the
+/// request drained (briefly) before the socket is dropped.
+pub(crate) fn refuse_connection<S>(mut stream: S)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    log::warn!("Connection limit reached, refusing connection");
+    tokio::spawn(async move {
+        if stream.write_all(BUSY_RESPONSE).await.is_err() || stream.shutdown().await.is_err() {
+            return;
+        }
+        let drain = async {
+            let mut buf = [0u8; 4096];
+            let mut drained = 0;
+            while drained < REFUSED_DRAIN_BYTES {
+                match stream.read(&mut buf).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => drained += n,
+                }
+            }
+        };
+        let _ = tokio::time::timeout(REFUSED_DRAIN_TIMEOUT, drain).await;
+    });
+}
+
+/// Whether a peer at `addr` is on this machine (IPv4-mapped addresses included)
+fn is_local_peer(addr: SocketAddr) -> bool {
+    addr.ip().to_canonical().is_loopback()
+}
+
+/// Whether a request from this peer may use `/control/*` and `/status`.
+/// Local clients always may; remote ones need the app's auth token in
+/// `X-PrivacyRPC-Token`, as with the WebSocket relay.
+fn control_allowed(local: bool, token: Option<&str>) -> bool {
+    local || token.is_some_and(crate::websocket::token_matches)
+}
+
+/// Handle an accepted connection in its own task, releasing `slot` when it closes.
+/// `local` is whether the peer is on this machine.
+fn spawn_connection<S>(stream: S, slot: OwnedSemaphorePermit, local: bool)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        if let Err(e) = handle_connection(stream, local).await {
+            log::error!("Connection error: {}", e);
+        }
+        drop(slot);
+    });
+}
+
 pub async fn stop_proxy_server() {
-    if let Some(tx) = SHUTDOWN_TX.lock().take() {
+    let shutdown_tx = SHUTDOWN_TX.lock().take();
+    if let Some(tx) = shutdown_tx {
         let _ = tx.send(());
+        broadcast_event(WsEvent::ProxyStopped);
     }
+    crate::ws_relay::stop_ws_relay();
     // Also mark as not running immediately
     PROXY_CONFIG.lock().running = false;
+    *STATS_SINCE.lock() = None;
+    reset_upstream_clients();
+}
+
+/// Routing report behind the GUI's `run_diagnostics` command, the same one
+/// `GET /test-routing` serves
+pub async fn run_diagnostics() -> serde_json::Value {
+    test_routing_path().await
 }
 
 /// Test the full routing path for diagnostics
@@ -160,54 +947,41 @@ async fn test_routing_path() -> serde_json::Value {
 
     // Step 5: Actually test the connection by getting our exit IP
     let mut exit_ip = "unknown".to_string();
-    let mut ip_test_status = "skipped";
-    let mut ip_test_error: Option<String> = None;
-
-    // Build client (with or without Tor)
-    let client_result = if tor_enabled && tor_socks_port > 0 {
-        let proxy_url = format!("socks5h://127.0.0.1:{}", tor_socks_port);
-        reqwest::Client::builder()
-            .proxy(reqwest::Proxy::all(&proxy_url).unwrap())
+
+    // Build client (through Tor or the upstream proxy, if configured)
+    let client_result = match UpstreamRoute::current().proxy() {
+        Ok(Some(proxy)) => reqwest::Client::builder()
+            .proxy(proxy)
             .timeout(std::time::Duration::from_secs(15))
-            .build()
-    } else {
-        reqwest::Client::builder()
+            .build(),
+        Ok(None) => reqwest::Client::builder()
             .timeout(std::time::Duration::from_secs(10))
-            .build()
+            .build(),
+        Err(e) => Err(e),
     };
 
     if let Ok(client) = client_result {
-        // Test 1: Get exit IP from ip-api.com
-        match client.get("http://ip-api.com/json").send().await {
-            Ok(resp) => {
-                if let Ok(json) = resp.json::<serde_json::Value>().await {
-                    exit_ip = json.get("query")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("unknown")
-                        .to_string();
-                    ip_test_status = "ok";
+        // Test 1: Get exit IP from the configured echo services
+        let (ip_test_status, ip_test_error) =
+            match crate::tor::detect_ip(&client, &crate::tor::ip_check_urls()).await {
+                Ok((ip, _)) => {
+                    exit_ip = ip;
+                    ("ok", None)
                 }
-            }
-            Err(e) => {
-                ip_test_status = "error";
-                ip_test_error = Some(e.to_string());
-            }
-        }
+                Err(e) => ("error", Some(e)),
+            };
 
         // Test 2: Check if it's a Tor exit (only if Tor enabled)
-        let is_tor_exit = if tor_enabled {
-            match client.get("https://check.torproject.org/api/ip").send().await {
-                Ok(resp) => {
-                    if let Ok(json) = resp.json::<serde_json::Value>().await {
-                        json.get("IsTor").and_then(|v| v.as_bool()).unwrap_or(false)
-                    } else {
-                        false
-                    }
+        let (is_tor_exit, tor_check_error) = if tor_enabled {
+            match crate::tor::check_is_tor(&client, &crate::tor::tor_check_url()).await {
+                Ok(is_tor) => (is_tor, None),
+                Err(e) => {
+                    log::warn!("Tor check: {}", e);
+                    (false, Some(e))
                 }
-                Err(_) => false,
             }
         } else {
-            false
+            (false, None)
         };
 
         routing_steps.push(serde_json::json!({
@@ -215,6 +989,7 @@ async fn test_routing_path() -> serde_json::Value {
             "component": "Exit IP Test",
             "action": format!("Your requests appear from: {}", exit_ip),
             "is_tor_exit": is_tor_exit,
+            "tor_check_error": tor_check_error,
             "status": ip_test_status,
             "error": ip_test_error
         }));
@@ -222,6 +997,7 @@ async fn test_routing_path() -> serde_json::Value {
         // Test 3: Actually hit the RPC endpoint with getHealth
         let rpc_test_result = client
             .post(&final_rpc)
+            .headers(rpc_auth_headers())
             .header("Content-Type", "application/json")
             .body(r#"{"jsonrpc":"2.0","id":1,"method":"getHealth"}"#)
             .send()
@@ -272,37 +1048,71 @@ async fn test_routing_path() -> serde_json::Value {
     })
 }
 
-async fn handle_connection(
-    mut stream: TcpStream,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    // Peek at the first line to determine request type
-    let mut peek_buf = [0u8; 8];
-    let n = stream.peek(&mut peek_buf).await?;
+async fn handle_connection<S>(stream: S, local: bool) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut buf_reader = BufReader::new(reader);
 
-    // Check if this is a CONNECT request
-    if n >= 7 && &peek_buf[..7] == b"CONNECT" {
-        return handle_connect(stream).await;
+    // Peek at the first bytes to determine request type
+    if buf_reader.fill_buf().await?.starts_with(b"CONNECT") {
+        return handle_connect(buf_reader, writer).await;
     }
 
-    // For other requests, use buffered reading
-    let (reader, mut writer) = stream.split();
-    let mut buf_reader = BufReader::new(reader);
+    loop {
+        // Wait for the next request, closing idle keep-alive connections
+        match tokio::time::timeout(KEEP_ALIVE_IDLE_TIMEOUT, buf_reader.fill_buf()).await {
+            Ok(Ok([])) => return Ok(()),
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => return Err(e.into()),
+            Err(_) => {
+                log::debug!("Closing idle keep-alive connection");
+                return Ok(());
+            }
+        }
+
+        if !handle_request(&mut buf_reader, &mut writer, local).await? {
+            return Ok(());
+        }
+    }
+}
 
+/// Handle a single HTTP request on a connection.
+/// Returns whether the connection should be kept open for another request.
+async fn handle_request<R, W>(
+    buf_reader: &mut BufReader<R>,
+    writer: &mut W,
+    local: bool,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>>
+where
+    R: AsyncRead + Unpin + Send,
+    W: AsyncWrite + Unpin + Send,
+{
     // Read the HTTP request
-    let mut request_line = String::new();
-    buf_reader.read_line(&mut request_line).await?;
+    let Some((request_line, header_lines)) = read_request_head(buf_reader).await? else {
+        log::warn!("Refusing request: header limits exceeded");
+        writer.write_all(HEADERS_TOO_LARGE).await?;
+        return Ok(false);
+    };
+    let Some((method, path, version)) = parse_request_line(&request_line) else {
+        log::warn!("Refusing malformed request line: {:?}", request_line.trim_end());
+        writer
+            .write_all(b"HTTP/1.1 400 Bad Request\r\nConnection: close\r\nContent-Length: 0\r\n\r\n")
+            .await?;
+        return Ok(false);
+    };
 
     // Read headers
     let mut content_length = 0usize;
     let mut target_url_header: Option<String> = None;
+    let mut decode_mode = DecodeMode::Default;
+    let mut origin_header: Option<String> = None;
+    let mut connection_header: Option<String> = None;
+    let mut content_type_header: Option<String> = None;
+    let mut token_header: Option<String> = None;
 
-    loop {
-        let mut line = String::new();
-        buf_reader.read_line(&mut line).await?;
-        if line == "\r\n" || line.is_empty() {
-            break;
-        }
-
+    for line in header_lines {
         // Parse headers
         if let Some((key, value)) = line.split_once(':') {
             let key = key.trim().to_lowercase();
@@ -312,43 +1122,100 @@ async fn handle_connection(
                 content_length = value.parse().unwrap_or(0);
             } else if key == "x-target-url" {
                 target_url_header = Some(value.to_string());
+            } else if key == "x-privacyrpc-decode" {
+                decode_mode = DecodeMode::from_header(value);
+            } else if key == "origin" {
+                origin_header = Some(value.to_string());
+            } else if key == "connection" {
+                connection_header = Some(value.to_lowercase());
+            } else if key == "content-type" {
+                content_type_header = Some(value.to_string());
+            } else if key == "x-privacyrpc-token" {
+                token_header = Some(value.to_string());
             }
         }
     }
 
     // Note: target_url logic moved to final_target below for clarity
 
+    // HTTP/1.1 defaults to keep-alive, HTTP/1.0 must ask for it
+    let keep_alive = match connection_header.as_deref() {
+        Some(c) if c.contains("close") => false,
+        Some(c) if c.contains("keep-alive") => true,
+        _ => version != "HTTP/1.0",
+    };
+
+    let cors = cors_headers(origin_header.as_deref());
+
+    // Always consume the body so the next request on this connection starts cleanly
+    let mut body = vec![0u8; content_length];
+    if content_length > 0 {
+        buf_reader.read_exact(&mut body).await?;
+    }
+
+    // Every POST body is parsed as JSON; refuse anything declared otherwise
+    if method == "POST"
+        && !privacyrpc_sdk::is_json_content_type(content_type_header.as_deref())
+    {
+        let body = r#"{"error":"Unsupported Media Type: expected application/json"}"#;
+        let response = format!(
+            "HTTP/1.1 415 Unsupported Media Type\r\nContent-Type: application/json\r\n{cors}Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        writer.write_all(response.as_bytes()).await?;
+        return Ok(keep_alive);
+    }
+
     // Handle control endpoints
-    if request_line.starts_with("POST /control/") || request_line.starts_with("GET /status") {
-        // Read body for POST requests
-        let mut body = vec![0u8; content_length];
-        if content_length > 0 {
-            buf_reader.read_exact(&mut body).await?;
+    if matches!(method, "GET" | "POST") && path.starts_with("/control/") || (method, path) == ("GET", "/status") {
+        if !control_allowed(local, token_header.as_deref()) {
+            log::warn!("Refusing {} {} from a remote client without the auth token", method, path);
+            let body = r#"{"error":"Control endpoints need the auth token from a remote client"}"#;
+            let response = format!(
+                "HTTP/1.1 403 Forbidden\r\nContent-Type: application/json\r\n{cors}Content-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            writer.write_all(response.as_bytes()).await?;
+            return Ok(keep_alive);
         }
-        return handle_control_endpoint(&request_line, &body, &mut writer).await;
+        handle_control_endpoint(&request_line, &body, &cors, writer).await?;
+        return Ok(keep_alive);
     }
 
     // Handle different request types
-    if request_line.starts_with("GET /health") {
-        let response = "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nAccess-Control-Allow-Origin: *\r\nContent-Length: 35\r\n\r\n{\"status\":\"ok\",\"proxy\":\"running\"}";
+    if (method, path) == ("GET", "/health") {
+        let response = format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n{cors}Content-Length: 35\r\n\r\n{{\"status\":\"ok\",\"proxy\":\"running\"}}");
+        writer.write_all(response.as_bytes()).await?;
+        return Ok(keep_alive);
+    }
+
+    if (method, path) == ("GET", "/version") {
+        let body = version_info().to_string();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n{cors}Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
         writer.write_all(response.as_bytes()).await?;
-        return Ok(());
+        return Ok(keep_alive);
     }
 
     // Diagnostic endpoint to verify routing configuration
-    if request_line.starts_with("GET /test-routing") {
+    if (method, path) == ("GET", "/test-routing") {
         let test_result = test_routing_path().await;
         let body = serde_json::to_string(&test_result).unwrap_or_default();
         let response = format!(
-            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nAccess-Control-Allow-Origin: *\r\nContent-Length: {}\r\n\r\n{}",
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n{cors}Content-Length: {}\r\n\r\n{}",
             body.len(),
             body
         );
         writer.write_all(response.as_bytes()).await?;
-        return Ok(());
+        return Ok(keep_alive);
     }
 
-    if request_line.starts_with("GET /config") {
+    if (method, path) == ("GET", "/config") {
         // Get config values without holding lock across await
         let (endpoint, tor_enabled, tor_socks_port) = {
             let proxy_cfg = PROXY_CONFIG.lock();
@@ -365,35 +1232,39 @@ async fn handle_connection(
             "torEnabled": tor_enabled,
             "torConnected": tor_connected,
             "torIp": tor_ip,
-            "torSocksPort": tor_socks_port
+            "torSocksPort": tor_socks_port,
+            "upstreamProxy": PROXY_CONFIG.lock().upstream_proxy.clone(),
+            "fallbackEndpoints": PROXY_CONFIG.lock().fallback_endpoints.clone()
         });
         let body = serde_json::to_string(&config_json).unwrap_or_default();
         let response = format!(
-            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nAccess-Control-Allow-Origin: *\r\nContent-Length: {}\r\n\r\n{}",
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n{cors}Content-Length: {}\r\n\r\n{}",
             body.len(),
             body
         );
         writer.write_all(response.as_bytes()).await?;
-        return Ok(());
+        return Ok(keep_alive);
     }
 
-    if request_line.starts_with("OPTIONS") {
-        let response = "HTTP/1.1 200 OK\r\nAccess-Control-Allow-Origin: *\r\nAccess-Control-Allow-Methods: POST, GET, OPTIONS\r\nAccess-Control-Allow-Headers: Content-Type, X-Target-URL\r\nAccess-Control-Max-Age: 86400\r\nContent-Length: 0\r\n\r\n";
+    if method == "OPTIONS" {
+        let response = format!("HTTP/1.1 200 OK\r\n{cors}Access-Control-Allow-Methods: POST, GET, OPTIONS\r\nAccess-Control-Allow-Headers: Content-Type, X-Target-URL, X-PrivacyRPC-Decode\r\nAccess-Control-Max-Age: 86400\r\nContent-Length: 0\r\n\r\n");
         writer.write_all(response.as_bytes()).await?;
-        return Ok(());
+        return Ok(keep_alive);
     }
 
-    // Handle transaction decode endpoint
-    if request_line.starts_with("POST /decode") {
-        // Read body
-        let mut body = vec![0u8; content_length];
-        if content_length > 0 {
-            buf_reader.read_exact(&mut body).await?;
-        }
+    if !matches!(method, "GET" | "POST") {
+        let response = format!(
+            "HTTP/1.1 405 Method Not Allowed\r\nAllow: POST, GET, OPTIONS\r\n{cors}Content-Length: 0\r\n\r\n"
+        );
+        writer.write_all(response.as_bytes()).await?;
+        return Ok(keep_alive);
+    }
 
+    // Handle transaction decode endpoint
+    if (method, path) == ("POST", "/decode") {
         let result = if let Ok(json) = serde_json::from_slice::<serde_json::Value>(&body) {
             if let Some(tx) = json.get("transaction").and_then(|v| v.as_str()) {
-                match transaction_decoder::decode_transaction(tx) {
+                match transaction::decode_transaction(tx) {
                     Ok(decoded) => serde_json::json!({
                         "success": true,
                         "decoded": decoded
@@ -418,31 +1289,73 @@ async fn handle_connection(
 
         let body = serde_json::to_string(&result).unwrap_or_default();
         let response = format!(
-            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nAccess-Control-Allow-Origin: *\r\nContent-Length: {}\r\n\r\n{}",
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n{cors}Content-Length: {}\r\n\r\n{}",
             body.len(),
             body
         );
         writer.write_all(response.as_bytes()).await?;
-        return Ok(());
+        return Ok(keep_alive);
     }
 
-    // Read body for POST requests
-    let mut body = vec![0u8; content_length];
-    if content_length > 0 {
-        buf_reader.read_exact(&mut body).await?;
+    // Dry run: decode, price and optionally simulate, but never forward
+    if (method, path) == ("POST", "/inspect") {
+        let result = inspect_transaction(&body, target_url_header.as_deref()).await;
+        let body = serde_json::to_string(&result).unwrap_or_default();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n{cors}Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        writer.write_all(response.as_bytes()).await?;
+        return Ok(keep_alive);
     }
 
-    // Check if this is a transaction-related RPC call and decode it
-    let decoded_tx_info = decode_rpc_transaction(&body);
-    if let Some(ref info) = decoded_tx_info {
-        log::info!("Decoded transaction: {}", info.summary);
-        if !info.warnings.is_empty() {
-            for warning in &info.warnings {
-                log::warn!("TX Warning: {} - {}", warning.title, warning.message);
-            }
-        }
+    if is_forwarding_paused() {
+        let id = serde_json::from_slice::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|json| json.get("id").cloned())
+            .unwrap_or(serde_json::Value::Null);
+        let error_body = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": -32000, "message": "service paused" },
+        })
+        .to_string();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n{cors}Content-Length: {}\r\n\r\n{}",
+            error_body.len(),
+            error_body
+        );
+        writer.write_all(response.as_bytes()).await?;
+        return Ok(keep_alive);
+    }
+
+    if !wait_for_tor().await {
+        let id = serde_json::from_slice::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|json| json.get("id").cloned())
+            .unwrap_or(serde_json::Value::Null);
+        let error_body = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": {
+                "code": -32000,
+                "message": "Tor is enabled but not connected yet; retry once it has bootstrapped",
+            },
+        })
+        .to_string();
+        let response = format!(
+            "HTTP/1.1 503 Service Unavailable\r\nContent-Type: application/json\r\n{cors}Content-Length: {}\r\n\r\n{}",
+            error_body.len(),
+            error_body
+        );
+        writer.write_all(response.as_bytes()).await?;
+        return Ok(keep_alive);
     }
 
+    // Check if this is a transaction-related RPC call and decode it
+    let mut decoded_tx_info = decode_rpc_transaction(&body);
+
     // Check if this is a Jito-specific RPC method
     // Jito methods must go to Jito's endpoint - they're not supported by standard RPCs like Helius
     const JITO_METHODS: &[&str] = &[
@@ -452,14 +1365,14 @@ async fn handle_connection(
         "simulateBundle",
         "getInflightBundleStatuses",
     ];
-    // Jito's JSON-RPC endpoint for all MEV/bundle operations
-    const JITO_MAINNET_URL: &str = "https://mainnet.block-engine.jito.wtf/api/v1/bundles";
-
-    // Extract method from JSON-RPC body
-    let rpc_method = if let Ok(json) = serde_json::from_slice::<serde_json::Value>(&body) {
-        json.get("method").and_then(|m| m.as_str()).map(|s| s.to_string())
+    // Extract method and id from JSON-RPC body
+    let (rpc_method, rpc_id) = if let Ok(json) = serde_json::from_slice::<serde_json::Value>(&body) {
+        (
+            json.get("method").and_then(|m| m.as_str()).map(|s| s.to_string()),
+            json.get("id").cloned().unwrap_or(serde_json::Value::Null),
+        )
     } else {
-        None
+        (None, serde_json::Value::Null)
     };
 
     let is_jito_method = rpc_method.as_ref()
@@ -467,12 +1380,16 @@ async fn handle_connection(
         .unwrap_or(false);
 
     // Smart routing: Jito methods -> Jito block engine, everything else -> private RPC
+    let mut target_headers = HeaderMap::new();
+    let mut fallbacks = Vec::new();
     let final_target = if is_jito_method {
         log::info!("Routing Jito method '{}' to Jito block engine", rpc_method.as_deref().unwrap_or("unknown"));
         JITO_MAINNET_URL.to_string()
     } else if let Some(private_endpoint) = get_rpc_endpoint() {
         // Standard RPC methods go to user's private endpoint
         log::info!("Routing '{}' to private endpoint", rpc_method.as_deref().unwrap_or("unknown"));
+        target_headers = rpc_auth_headers();
+        fallbacks = PROXY_CONFIG.lock().fallback_endpoints.clone();
         private_endpoint
     } else if let Some(ref header_url) = target_url_header {
         // No private endpoint, use the original target from extension
@@ -484,75 +1401,150 @@ async fn handle_connection(
         "https://api.mainnet-beta.solana.com".to_string()
     };
 
-    // Build HTTP client — with or without Tor SOCKS5 proxy
-    let client = {
-        let config = PROXY_CONFIG.lock();
-        if config.tor_enabled && config.tor_socks_port > 0 {
-            let proxy_url = format!("socks5h://127.0.0.1:{}", config.tor_socks_port);
-            let proxy = reqwest::Proxy::all(&proxy_url).map_err(|e| -> Box<dyn std::error::Error + Send + Sync> {
-                Box::new(e)
-            })?;
-            reqwest::Client::builder()
-                .proxy(proxy)
-                .timeout(std::time::Duration::from_secs(30))
-                .build()
-                .map_err(|e| -> Box<dyn std::error::Error + Send + Sync> { Box::new(e) })?
-        } else {
-            reqwest::Client::new()
-        }
+    // Get HTTP client — direct, through Tor SOCKS5, or through the upstream proxy
+    // The private endpoint's auth headers go on every call to it, simulation included
+    let client = upstream_client(&UpstreamRoute::current(), &target_headers)?;
+
+    // Queue here when the upstream concurrency limit is reached
+    let _in_flight = acquire_upstream_slot().await?;
+
+    // Token-2022 extensions (permanent delegate, transfer fee) live on the mint account
+    if let Some(ref mut decoded) = decoded_tx_info {
+        let resolver = simulation::UpstreamMintResolver::new(&client, &final_target);
+        transaction::check_token_2022_mints(decoded, &resolver, &transaction::DecoderConfig::default()).await;
+    }
+
+    // Optionally simulate first to catch drains the static decoder misses
+    let simulation = match decoded_tx_info {
+        Some(ref mut decoded) => run_presign_simulation(&client, &final_target, &body, decoded).await,
+        None => None,
     };
+    if let Some(ref info) = decoded_tx_info {
+        report_decoded_transaction(info);
+    }
 
-    // Forward to target RPC
-    let response = client
-        .post(&final_target)
-        .header("Content-Type", "application/json")
-        .body(body)
-        .send()
-        .await;
+    // Forward to target RPC, tagged so the request can be found in provider logs
+    let request_id = next_request_id();
+    log::info!("Forwarding request {} to {}", request_id, crate::audit::redact_url(final_target.clone()));
+    let span = RequestSpan::new(rpc_method.as_deref().unwrap_or("unknown"), &request_id);
+    let mut targets = vec![(final_target, client)];
+    if !fallbacks.is_empty() {
+        let plain = upstream_client(&UpstreamRoute::current(), &HeaderMap::new())?;
+        targets.extend(fallbacks.into_iter().map(|url| (url, plain.clone())));
+    }
+    let send = async {
+        let mut i = 0;
+        loop {
+            let (target, client) = &targets[i];
+            let response = client
+                .post(target)
+                .header("Content-Type", "application/json")
+                .body(body.clone())
+                .send()
+                .await
+                // The URL may carry an API key; events and logs name endpoints redacted
+                .map_err(reqwest::Error::without_url);
+            let reason = match &response {
+                Err(e) => Some(e.to_string()),
+                Ok(resp) if resp.status().is_server_error() || resp.status() == reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                    Some(format!("HTTP {}", resp.status().as_u16()))
+                }
+                Ok(_) => None,
+            };
+            match (reason, targets.get(i + 1)) {
+                (Some(reason), Some((next, _))) => {
+                    let (from, to) = (crate::audit::redact_url(target.clone()), crate::audit::redact_url(next.clone()));
+                    log::warn!("Request {}: {} failed ({}), trying fallback {}", request_id, from, reason, i + 1);
+                    broadcast_event(WsEvent::RpcFailover {
+                        from,
+                        to,
+                        fallback: i + 1,
+                        reason,
+                    });
+                    i += 1;
+                }
+                _ => return (i, response),
+            }
+        }
+    };
+    let (used, response) = span.instrument(send).await;
+    let final_target = targets.swap_remove(used).0;
+    let status = response.as_ref().ok().map(|resp| resp.status());
+    span.finish(
+        Some(&final_target),
+        status.map(|s| s.as_u16()),
+        status.is_some_and(|s| s.is_success()),
+    );
 
     match response {
-        Ok(resp) => {
+        Ok(mut resp) => {
             let status = resp.status();
-            let response_body = resp.bytes().await.unwrap_or_default();
+            let content_type = resp
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .map(|s| s.to_string());
+            let content_encoding = resp
+                .headers()
+                .get(reqwest::header::CONTENT_ENCODING)
+                .and_then(|v| v.to_str().ok())
+                .filter(|e| !e.eq_ignore_ascii_case("identity"))
+                .map(|s| s.to_string());
 
             log::info!("=== PROXY RESPONSE ===");
-            log::info!("Upstream status: {}", status);
-            log::info!("Response body (first 300 chars): {}", String::from_utf8_lossy(&response_body[..std::cmp::min(300, response_body.len())]));
-
-            // Update stats
+            log::info!("Upstream status for {}: {}", request_id, status);
             REQUESTS_PROXIED.fetch_add(1, Ordering::Relaxed);
+
+            // If we decoded a transaction and the caller opted in, enrich the
+            // response. Only that needs the whole body; everything else streams.
+            let Some(decoded) = decoded_tx_info.as_ref().filter(|_| decode_mode.injects()) else {
+                let content_type = content_type.unwrap_or_else(|| "application/json".to_string());
+                let content_length = resp.content_length();
+                let head = response_head(status, &content_type, content_encoding.as_deref(), &cors, &request_id, content_length);
+                writer.write_all(head.as_bytes()).await?;
+                stream_body(&mut resp, content_length.is_none(), writer).await?;
+                return Ok(keep_alive);
+            };
+
+            let response_body = resp.bytes().await.unwrap_or_default();
+            log::info!("Response body (first 300 chars): {}", String::from_utf8_lossy(&response_body[..std::cmp::min(300, response_body.len())]));
             BYTES_TRANSFERRED.fetch_add(response_body.len() as u64, Ordering::Relaxed);
 
-            // If we decoded a transaction, enrich the response with the decoded info
-            let final_body = if let Some(ref decoded) = decoded_tx_info {
-                // Parse the original response and add decoded info
-                if let Ok(mut json) = serde_json::from_slice::<serde_json::Value>(&response_body) {
-                    // Add decoded transaction info to the response
-                    json["_privacyrpc"] = serde_json::json!({
-                        "decoded": decoded,
-                        "intercepted": true
-                    });
-                    serde_json::to_vec(&json).unwrap_or_else(|_| response_body.to_vec())
-                } else {
-                    response_body.to_vec()
-                }
-            } else {
-                response_body.to_vec()
+            let info = serde_json::json!({
+                "decoded": decoded,
+                "simulation": simulation,
+                "request_id": request_id,
+                "intercepted": true
+            });
+            let enriched =
+                enrich_response(&response_body, content_encoding.is_some(), status.as_u16(), rpc_id, info);
+            // Anything passed through untouched keeps the upstream's headers,
+            // so compressed or binary bodies reach the client intact
+            let (final_body, content_type, content_encoding) = match enriched {
+                Some(body) => (body, "application/json".to_string(), None),
+                None => (
+                    response_body.to_vec(),
+                    content_type.unwrap_or_else(|| "application/json".to_string()),
+                    content_encoding,
+                ),
             };
 
-            let http_response = format!(
-                "HTTP/1.1 {} OK\r\nContent-Type: application/json\r\nAccess-Control-Allow-Origin: *\r\nAccess-Control-Allow-Methods: POST, OPTIONS\r\nAccess-Control-Allow-Headers: Content-Type, X-Target-URL\r\nContent-Length: {}\r\n\r\n",
-                status.as_u16(),
-                final_body.len()
+            let head = response_head(
+                status,
+                &content_type,
+                content_encoding.as_deref(),
+                &cors,
+                &request_id,
+                Some(final_body.len() as u64),
             );
-
-            writer.write_all(http_response.as_bytes()).await?;
+            writer.write_all(head.as_bytes()).await?;
             writer.write_all(&final_body).await?;
         }
         Err(e) => {
-            let error_body = format!(r#"{{"error":"Proxy error: {}"}}"#, e);
+            log::warn!("Request {} failed: {}", request_id, e);
+            let error_body = format!(r#"{{"error":"Proxy error: {}","request_id":"{}"}}"#, e, request_id);
             let response = format!(
-                "HTTP/1.1 502 Bad Gateway\r\nContent-Type: application/json\r\nAccess-Control-Allow-Origin: *\r\nContent-Length: {}\r\n\r\n{}",
+                "HTTP/1.1 502 Bad Gateway\r\nContent-Type: application/json\r\n{cors}Content-Length: {}\r\n\r\n{}",
                 error_body.len(),
                 error_body
             );
@@ -560,11 +1552,457 @@ async fn handle_connection(
         }
     }
 
+    Ok(keep_alive)
+}
+
+/// Status line and headers for a forwarded response. Without a known
+/// `content_length` the body is sent chunked.
+fn response_head(
+    status: reqwest::StatusCode,
+    content_type: &str,
+    content_encoding: Option<&str>,
+    cors: &str,
+    request_id: &str,
+    content_length: Option<u64>,
+) -> String {
+    let content_encoding = content_encoding
+        .map(|e| format!("Content-Encoding: {}\r\n", e))
+        .unwrap_or_default();
+    let framing = match content_length {
+        Some(len) => format!("Content-Length: {}", len),
+        None => "Transfer-Encoding: chunked".to_string(),
+    };
+    format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\n{}{cors}Access-Control-Allow-Methods: POST, OPTIONS\r\nAccess-Control-Allow-Headers: Content-Type, X-Target-URL, X-PrivacyRPC-Decode\r\nX-Request-Id: {}\r\n{}\r\n\r\n",
+        status.as_u16(),
+        status.canonical_reason().unwrap_or(""),
+        content_type,
+        content_encoding,
+        request_id,
+        framing
+    )
+}
+
+/// Copy an upstream body to the client as it arrives, so large responses
+/// (`getProgramAccounts`, `getBlock`) are never held in memory whole
+async fn stream_body<W: AsyncWriteExt + Unpin>(
+    resp: &mut reqwest::Response,
+    chunked: bool,
+    writer: &mut W,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut first = true;
+    // An upstream failure mid-body is returned as an error so the connection
+    // is dropped; the client can't mistake a truncated body for a whole one
+    while let Some(chunk) = resp.chunk().await? {
+        if first {
+            log::info!("Response body (first 300 chars): {}", String::from_utf8_lossy(&chunk[..chunk.len().min(300)]));
+            first = false;
+        }
+        BYTES_TRANSFERRED.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+        if chunked {
+            if chunk.is_empty() {
+                continue;
+            }
+            writer.write_all(format!("{:x}\r\n", chunk.len()).as_bytes()).await?;
+            writer.write_all(&chunk).await?;
+            writer.write_all(b"\r\n").await?;
+        } else {
+            writer.write_all(&chunk).await?;
+        }
+    }
+    if chunked {
+        writer.write_all(b"0\r\n\r\n").await?;
+    }
     Ok(())
 }
 
+/// Add `_privacyrpc` decode `info` to an upstream response. A JSON object gets
+/// the field added; a text body that isn't JSON (e.g. an HTML error page) is
+/// wrapped in a JSON-RPC error carrying the raw text. Returns `None` to pass
+/// the body through untouched: compressed, binary, or non-object JSON.
+fn enrich_response(
+    body: &[u8],
+    compressed: bool,
+    status: u16,
+    rpc_id: serde_json::Value,
+    info: serde_json::Value,
+) -> Option<Vec<u8>> {
+    if compressed {
+        return None;
+    }
+    let json = match serde_json::from_slice::<serde_json::Value>(body) {
+        Ok(serde_json::Value::Object(mut json)) => {
+            json.insert("_privacyrpc".to_string(), info);
+            serde_json::Value::Object(json)
+        }
+        Ok(_) => return None,
+        Err(_) => {
+            let text = std::str::from_utf8(body).ok()?;
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": rpc_id,
+                "error": {
+                    "code": -32000,
+                    "message": "Upstream returned a non-JSON response",
+                    "data": { "status": status, "body": text },
+                },
+                "_privacyrpc": info,
+            })
+        }
+    };
+    serde_json::to_vec(&json).ok()
+}
+
+/// Random id echoed back to the caller as `X-Request-Id` and used in logs.
+/// It isn't sent upstream, where it would only help correlate requests.
+fn next_request_id() -> String {
+    use rand::RngCore;
+    let mut bytes = [0u8; 8];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// How upstream traffic leaves this machine. Tor takes precedence over an
+/// HTTP proxy; the two aren't chained.
+#[derive(Clone, Debug, PartialEq)]
+enum UpstreamRoute {
+    Direct,
+    Tor(u16),
+    HttpProxy(String),
+}
+
+impl UpstreamRoute {
+    /// Route for the current config
+    fn current() -> Self {
+        let config = PROXY_CONFIG.lock();
+        if config.tor_enabled && config.tor_socks_port > 0 {
+            UpstreamRoute::Tor(config.tor_socks_port)
+        } else if let Some(ref url) = config.upstream_proxy {
+            UpstreamRoute::HttpProxy(url.clone())
+        } else {
+            UpstreamRoute::Direct
+        }
+    }
+
+    /// reqwest proxy for this route (`None` = direct)
+    fn proxy(&self) -> Result<Option<reqwest::Proxy>, reqwest::Error> {
+        match self {
+            UpstreamRoute::Direct => Ok(None),
+            UpstreamRoute::Tor(port) => reqwest::Proxy::all(format!("socks5h://127.0.0.1:{}", port)).map(Some),
+            UpstreamRoute::HttpProxy(url) => reqwest::Proxy::all(url).map(Some),
+        }
+    }
+}
+
+/// Get a pooled upstream client for `route` that sends `headers` on every request.
+/// Reusing the client keeps upstream connections (and Tor circuits) alive across requests.
+fn upstream_client(route: &UpstreamRoute, headers: &HeaderMap) -> Result<reqwest::Client, reqwest::Error> {
+    let mut cached = UPSTREAM_CLIENTS.lock();
+    if let Some((_, _, client)) = cached.iter().find(|(r, h, _)| r == route && h == headers) {
+        return Ok(client.clone());
+    }
+
+    // Redirects aren't followed, so endpoint credentials can't be carried to another host
+    let builder = reqwest::Client::builder()
+        .default_headers(headers.clone())
+        .redirect(reqwest::redirect::Policy::none());
+    let client = match route.proxy()? {
+        Some(proxy) => builder.proxy(proxy).timeout(std::time::Duration::from_secs(30)).build()?,
+        None => builder.build()?,
+    };
+    // Drop clients for a stale route, and the old keyed client when the headers change
+    cached.retain(|(r, h, _)| r == route && h.is_empty() != headers.is_empty());
+    cached.push((route.clone(), headers.clone(), client.clone()));
+    Ok(client)
+}
+
+/// Open a TCP connection to `host:port` over the current route, for traffic
+/// that doesn't go through reqwest
+pub(crate) async fn connect_upstream(
+    host: &str,
+    port: u16,
+) -> Result<TcpStream, Box<dyn std::error::Error + Send + Sync>> {
+    match UpstreamRoute::current() {
+        UpstreamRoute::Tor(tor_socks_port) => {
+            let stream =
+                tokio_socks::tcp::Socks5Stream::connect(format!("127.0.0.1:{}", tor_socks_port).as_str(), (host, port))
+                    .await?;
+            Ok(stream.into_inner())
+        }
+        UpstreamRoute::HttpProxy(proxy) => {
+            let target = if host.contains(':') {
+                format!("[{}]:{}", host, port)
+            } else {
+                format!("{}:{}", host, port)
+            };
+            connect_via_http_proxy(&proxy, &target).await
+        }
+        UpstreamRoute::Direct => Ok(TcpStream::connect((host, port)).await?),
+    }
+}
+
+/// Open a tunnel to `target` (host:port) through an HTTP proxy with `CONNECT`
+async fn connect_via_http_proxy(
+    proxy: &str,
+    target: &str,
+) -> Result<TcpStream, Box<dyn std::error::Error + Send + Sync>> {
+    use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
+    let url = reqwest::Url::parse(proxy)?;
+    if url.scheme() != "http" {
+        return Err("CONNECT tunnels need an http:// upstream proxy".into());
+    }
+    let host = url.host_str().ok_or("Upstream proxy URL has no host")?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    let mut stream = TcpStream::connect((host, port)).await?;
+
+    let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", target);
+    if !url.username().is_empty() {
+        let credentials = format!("{}:{}", url.username(), url.password().unwrap_or_default());
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", BASE64.encode(credentials)));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // Read the reply head a byte at a time so no tunnelled data is consumed
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() > 8192 {
+            return Err("Upstream proxy sent an oversized CONNECT reply".into());
+        }
+        let mut byte = [0u8; 1];
+        if stream.read(&mut byte).await? == 0 {
+            return Err("Upstream proxy closed the connection".into());
+        }
+        head.push(byte[0]);
+    }
+    let status_line = String::from_utf8_lossy(&head);
+    let status_line = status_line.lines().next().unwrap_or_default();
+    if status_line.split_whitespace().nth(1) != Some("200") {
+        return Err(format!("Upstream proxy refused CONNECT: {}", status_line).into());
+    }
+    Ok(stream)
+}
+
+/// Whether a decoded transaction at `risk_level` gets logged
+fn is_logged_risk(risk_level: &transaction::RiskLevel) -> bool {
+    *risk_level >= PROXY_CONFIG.lock().min_log_risk_level
+}
+
+/// Log a decoded transaction and alert the extension if it is high risk
+fn report_decoded_transaction(info: &transaction::DecodedTransaction) {
+    // The activity view and live feed show every decode; the risk threshold only quiets logs and alerts
+    record_recent_decode(info);
+    crate::websocket::broadcast_transaction_decoded(info);
+    if !is_logged_risk(&info.risk_level) {
+        return;
+    }
+    log::info!("Decoded transaction: {}", info.summary);
+    if !info.warnings.is_empty() {
+        for warning in &info.warnings {
+            log::warn!("TX Warning: {} - {}", warning.title, warning.message);
+        }
+    }
+
+    if info.risk_level >= transaction::RiskLevel::High {
+        crate::websocket::broadcast_transaction_alert(info);
+    }
+}
+
+/// Simulate a signTransaction/sendTransaction request if enabled in config,
+/// merging any simulation warnings into `decoded`
+async fn run_presign_simulation(
+    client: &reqwest::Client,
+    target: &str,
+    body: &[u8],
+    decoded: &mut transaction::DecodedTransaction,
+) -> Option<simulation::SimulationReport> {
+    let threshold_pct = {
+        let config = PROXY_CONFIG.lock();
+        if !config.simulate_transactions {
+            return None;
+        }
+        config.simulation_drain_threshold_pct
+    };
+
+    let (method, tx_encoded) = rpc_transaction_param(body)?;
+    if method != "signTransaction" && method != "sendTransaction" {
+        return None;
+    }
+    let encoding = rpc_transaction_encoding(body);
+    // The fee payer is the wallet being protected
+    let wallet = decoded.accounts_involved.first()?.clone();
+
+    let accounts = &decoded.accounts_involved;
+    match simulation::simulate_transaction(client, target, &tx_encoded, &encoding, &wallet, accounts).await {
+        Ok(report) => {
+            let warnings = simulation::simulation_warnings(&report, threshold_pct);
+            if warnings.iter().any(|w| w.level == transaction::WarningLevel::Danger) {
+                decoded.risk_level = transaction::RiskLevel::Critical;
+            }
+            decoded.warnings.extend(warnings);
+            Some(report)
+        }
+        Err(e) => {
+            log::warn!("Transaction simulation failed: {}", e);
+            None
+        }
+    }
+}
+
+/// Check a candidate RPC endpoint through the current routing (Tor if enabled):
+/// `getHealth` for reachability and latency, then `getVersion`
+async fn test_endpoint(url: &str) -> serde_json::Value {
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        return serde_json::json!({ "reachable": false, "error": "URL must be http:// or https://" });
+    }
+    let route = UpstreamRoute::current();
+    let tor_used = matches!(route, UpstreamRoute::Tor(_));
+    let client = match upstream_client(&route, &HeaderMap::new()) {
+        Ok(client) => client,
+        Err(e) => return serde_json::json!({ "reachable": false, "tor_used": tor_used, "error": e.to_string() }),
+    };
+
+    // A node that is behind answers getHealth with an error: reachable but unhealthy
+    let started = Instant::now();
+    let health = client
+        .post(url)
+        .json(&serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": "getHealth" }))
+        .send()
+        .await;
+    let health: serde_json::Value = match health {
+        Ok(resp) => match resp.json().await {
+            Ok(json) => json,
+            Err(e) => {
+                return serde_json::json!({ "reachable": false, "tor_used": tor_used, "error": format!("Not a JSON-RPC endpoint: {}", e) })
+            }
+        },
+        Err(e) => return serde_json::json!({ "reachable": false, "tor_used": tor_used, "error": e.to_string() }),
+    };
+    let latency_ms = started.elapsed().as_millis() as u64;
+    let version = simulation::rpc_call(&client, url, "getVersion", serde_json::json!([]))
+        .await
+        .ok()
+        .and_then(|result| result.get("solana-core")?.as_str().map(String::from));
+
+    serde_json::json!({
+        "reachable": true,
+        "healthy": health.get("result").and_then(|r| r.as_str()) == Some("ok"),
+        "latency_ms": latency_ms,
+        "version": version,
+        "tor_used": tor_used,
+    })
+}
+
+/// Run [`test_endpoint`] against the primary endpoint and every fallback at
+/// once, in that order
+async fn healthcheck_all() -> serde_json::Value {
+    let urls: Vec<String> = {
+        let config = PROXY_CONFIG.lock();
+        config.rpc_endpoint.iter().chain(&config.fallback_endpoints).cloned().collect()
+    };
+    let checks = futures_util::future::join_all(urls.iter().map(|url| test_endpoint(url))).await;
+    urls.iter()
+        .zip(checks)
+        .map(|(url, check)| {
+            serde_json::json!({
+                "url": url,
+                "reachable": check["reachable"],
+                "latency_ms": check["latency_ms"],
+                "tor_used": check["tor_used"],
+            })
+        })
+        .collect()
+}
+
+/// Handle an `/inspect` body: `{"transaction": "...", "encoding": "base58", "simulate": false}`.
+/// The transaction is never sent; `simulate` only runs `simulateTransaction`.
+/// `encoding` defaults to `base58`, as for `sendTransaction`.
+async fn inspect_transaction(body: &[u8], target_url: Option<&str>) -> serde_json::Value {
+    let Ok(json) = serde_json::from_slice::<serde_json::Value>(body) else {
+        return serde_json::json!({ "success": false, "error": "Invalid JSON" });
+    };
+    let Some(tx) = json.get("transaction").and_then(|v| v.as_str()) else {
+        return serde_json::json!({ "success": false, "error": "Missing 'transaction' field" });
+    };
+    let mut decoded = match transaction::decode_transaction(tx) {
+        Ok(decoded) => decoded,
+        Err(e) => return serde_json::json!({ "success": false, "error": e }),
+    };
+
+    let mut simulation = None;
+    if json.get("simulate").and_then(|v| v.as_bool()).unwrap_or(false) {
+        let (target, headers) = match get_rpc_endpoint() {
+            Some(endpoint) => (endpoint, rpc_auth_headers()),
+            None => (
+                target_url.unwrap_or("https://api.mainnet-beta.solana.com").to_string(),
+                HeaderMap::new(),
+            ),
+        };
+        let threshold_pct = PROXY_CONFIG.lock().simulation_drain_threshold_pct;
+        let report = match (upstream_client(&UpstreamRoute::current(), &headers), decoded.accounts_involved.first()) {
+            (Ok(client), Some(wallet)) => {
+                let encoding = json.get("encoding").and_then(|v| v.as_str()).unwrap_or("base58");
+                simulation::simulate_transaction(&client, &target, tx, encoding, wallet, &decoded.accounts_involved).await
+            }
+            (Err(e), _) => Err(e.to_string()),
+            (_, None) => Err("Transaction has no fee payer".to_string()),
+        };
+        match report {
+            Ok(report) => {
+                let warnings = simulation::simulation_warnings(&report, threshold_pct);
+                if warnings.iter().any(|w| w.level == transaction::WarningLevel::Danger) {
+                    decoded.risk_level = transaction::RiskLevel::Critical;
+                }
+                decoded.warnings.extend(warnings);
+                simulation = Some(report);
+            }
+            Err(e) => log::warn!("Inspect simulation failed: {}", e),
+        }
+    }
+
+    let fee_lamports = decoded.fee_lamports();
+    serde_json::json!({
+        "success": true,
+        "decoded": decoded,
+        "fee_lamports": fee_lamports,
+        "fee_sol": fee_lamports as f64 / 1_000_000_000.0,
+        "simulation": simulation,
+        "forwarded": false,
+    })
+}
+
 /// Decode transaction from RPC request body if it's a transaction-related method
-fn decode_rpc_transaction(body: &[u8]) -> Option<transaction_decoder::DecodedTransaction> {
+fn decode_rpc_transaction(body: &[u8]) -> Option<transaction::DecodedTransaction> {
+    let (method, tx_encoded) = rpc_transaction_param(body)?;
+
+    match transaction::decode_transaction(&tx_encoded) {
+        Ok(mut decoded) => {
+            if method == "sendTransaction" {
+                decoded.flag_unsigned_send();
+            }
+            Some(decoded)
+        }
+        Err(e) => {
+            log::debug!("Failed to decode transaction: {}", e);
+            None
+        }
+    }
+}
+
+/// The encoding a transaction-related RPC body gives for its transaction, in
+/// its options (`params[1]`) or a `{transaction, encoding}` object. Defaults to
+/// `base58`, as in the RPC spec: base58 text is often valid base64 too, so the
+/// encoding can't be told from the transaction itself.
+fn rpc_transaction_encoding(body: &[u8]) -> String {
+    let json: serde_json::Value = serde_json::from_slice(body).unwrap_or_default();
+    let params = &json["params"];
+    let options = if params.is_array() { &params[1] } else { params };
+    options["encoding"].as_str().unwrap_or("base58").to_string()
+}
+
+/// Extract the method and encoded transaction from a transaction-related RPC body
+fn rpc_transaction_param(body: &[u8]) -> Option<(String, String)> {
     let json: serde_json::Value = serde_json::from_slice(body).ok()?;
 
     let method = json.get("method")?.as_str()?;
@@ -587,13 +2025,7 @@ fn decode_rpc_transaction(body: &[u8]) -> Option<transaction_decoder::DecodedTra
                 params.as_str()
             }?;
 
-            match transaction_decoder::decode_transaction(tx_encoded) {
-                Ok(decoded) => Some(decoded),
-                Err(e) => {
-                    log::debug!("Failed to decode transaction: {}", e);
-                    None
-                }
-            }
+            Some((method.to_string(), tx_encoded.to_string()))
         }
         _ => None,
     }
@@ -603,9 +2035,12 @@ fn decode_rpc_transaction(body: &[u8]) -> Option<transaction_decoder::DecodedTra
 async fn handle_control_endpoint<W: AsyncWriteExt + Unpin>(
     request_line: &str,
     body: &[u8],
+    cors: &str,
     writer: &mut W,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let (status_code, response_body) = if request_line.starts_with("GET /status") {
+    let (method, path, _) = parse_request_line(request_line).unwrap_or_default();
+    let route = (method, path);
+    let (status_code, response_body) = if route == ("GET", "/status") {
         // Enhanced status endpoint with live Tor status
         let (tor_enabled, tor_socks_port, rpc_endpoint) = {
             let config = PROXY_CONFIG.lock();
@@ -614,18 +2049,57 @@ async fn handle_control_endpoint<W: AsyncWriteExt + Unpin>(
         let tor_status = crate::tor::global_get_status().await;
         let body = serde_json::json!({
             "running": true,
-            "version": "1.0.0",
+            "version": env!("CARGO_PKG_VERSION"),
             "tor_enabled": tor_enabled,
             "tor_socks_port": tor_socks_port,
             "tor_connected": tor_status.is_bootstrapped,
             "tor_ip": tor_status.exit_ip,
             "bootstrap_progress": tor_status.bootstrap_progress,
             "rpc_endpoint": rpc_endpoint,
+            "upstream_proxy": PROXY_CONFIG.lock().upstream_proxy.clone(),
             "requests_proxied": REQUESTS_PROXIED.load(Ordering::Relaxed),
             "bytes_transferred": BYTES_TRANSFERRED.load(Ordering::Relaxed),
+            "uptime_seconds": uptime_seconds(),
+            "in_flight_requests": IN_FLIGHT_REQUESTS.load(Ordering::Relaxed),
+            "active_tunnels": ACTIVE_TUNNELS.load(Ordering::Relaxed),
+            "max_concurrency": max_concurrency(),
+            "max_connections": max_connections(),
+            "paused": is_forwarding_paused(),
+            "ws_port": crate::websocket::websocket_port(),
+            "ws_relay_port": crate::ws_relay::relay_port(),
         });
         (200, body.to_string())
-    } else if request_line.starts_with("POST /control/enable_tor") {
+    } else if route == ("GET", "/control/audit") {
+        let resp = serde_json::json!({ "entries": crate::audit::recent_entries() });
+        (200, resp.to_string())
+    } else if route == ("GET", "/control/recent_decodes") {
+        let resp = serde_json::json!({ "limit": recent_decodes_limit(), "entries": recent_decodes() });
+        (200, resp.to_string())
+    } else if route == ("POST", "/control/set_recent_decodes") {
+        let limit = serde_json::from_slice::<serde_json::Value>(body)
+            .ok()
+            .and_then(|json| json.get("limit")?.as_u64())
+            .filter(|limit| *limit > 0);
+        match limit {
+            Some(limit) => {
+                set_recent_decodes_limit(limit as usize);
+                let resp = serde_json::json!({"status": "ok", "limit": recent_decodes_limit()});
+                (200, resp.to_string())
+            }
+            None => (400, r#"{"error":"Expected {\"limit\": <positive integer>}"}"#.to_string()),
+        }
+    } else if route == ("POST", "/control/reset_stats") {
+        let (requests, bytes, uptime) = reset_stats();
+        let resp = serde_json::json!({
+            "status": "ok",
+            "previous": {
+                "requests_proxied": requests,
+                "bytes_transferred": bytes,
+                "uptime_seconds": uptime,
+            }
+        });
+        (200, resp.to_string())
+    } else if route == ("POST", "/control/enable_tor") {
         // Start Tor globally (manages process + proxy routing)
         match crate::tor::global_enable_tor().await {
             Ok(status) => {
@@ -639,14 +2113,14 @@ async fn handle_control_endpoint<W: AsyncWriteExt + Unpin>(
                 });
                 (200, resp.to_string())
             }
-            Err(e) => (500, format!(r#"{{"error":"{}"}}"#, e)),
+            Err(e) => (500, e.to_json().to_string()),
         }
-    } else if request_line.starts_with("POST /control/disable_tor") {
+    } else if route == ("POST", "/control/disable_tor") {
         match crate::tor::global_disable_tor().await {
             Ok(_) => (200, r#"{"status":"ok","tor_enabled":false}"#.to_string()),
             Err(e) => (500, format!(r#"{{"error":"{}"}}"#, e)),
         }
-    } else if request_line.starts_with("POST /control/new_circuit") {
+    } else if route == ("POST", "/control/new_circuit") {
         match crate::tor::global_new_circuit().await {
             Ok(ip) => {
                 let resp = serde_json::json!({"status": "ok", "exitIp": ip});
@@ -654,44 +2128,267 @@ async fn handle_control_endpoint<W: AsyncWriteExt + Unpin>(
             }
             Err(e) => (500, format!(r#"{{"error":"{}"}}"#, e)),
         }
-    } else if request_line.starts_with("POST /control/set_rpc") {
+    } else if route == ("POST", "/control/set_rotation") {
+        // {"interval_secs": 600} rotates every 10 minutes; 0 turns rotation off
+        let secs = serde_json::from_slice::<serde_json::Value>(body)
+            .ok()
+            .and_then(|json| json.get("interval_secs")?.as_u64());
+        match secs {
+            Some(secs) => {
+                let interval = (secs > 0).then(|| std::time::Duration::from_secs(secs));
+                crate::tor::global_set_rotation(interval).await;
+                let resp = serde_json::json!({"status": "ok", "interval_secs": secs});
+                (200, resp.to_string())
+            }
+            None => (400, r#"{"error":"Expected {\"interval_secs\": <seconds, 0 = off>}"}"#.to_string()),
+        }
+    } else if route == ("POST", "/control/set_rpc") {
         if let Ok(json) = serde_json::from_slice::<serde_json::Value>(body) {
             let url = json
                 .get("url")
                 .and_then(|v| v.as_str())
                 .map(|s| s.to_string());
-            set_rpc_endpoint(url.clone());
-            let resp = serde_json::json!({"status": "ok", "rpc_endpoint": url});
-            (200, resp.to_string())
+            // {"url": ..., "headers": {"x-api-key": "..."}}; values must be strings
+            let headers = match json.get("headers") {
+                None | Some(serde_json::Value::Null) => Some(HashMap::new()),
+                Some(headers) => serde_json::from_value::<HashMap<String, String>>(headers.clone()).ok(),
+            };
+            match headers.map(|headers| set_rpc_endpoint_with_headers(url.clone(), headers)) {
+                Some(Ok(())) => {
+                    // Header names only; values are secrets
+                    let names: Vec<String> = PROXY_CONFIG.lock().rpc_headers.keys().cloned().collect();
+                    let resp = serde_json::json!({"status": "ok", "rpc_endpoint": url, "rpc_headers": names});
+                    (200, resp.to_string())
+                }
+                Some(Err(e)) => (400, serde_json::json!({ "error": e }).to_string()),
+                None => (400, r#"{"error":"Expected \"headers\" to be an object of strings"}"#.to_string()),
+            }
         } else {
             (400, r#"{"error":"Invalid JSON body"}"#.to_string())
         }
-    } else if request_line.starts_with("POST /control/clear_rpc") {
+    } else if route == ("POST", "/control/switch_profile") {
+        let name = serde_json::from_slice::<serde_json::Value>(body)
+            .ok()
+            .and_then(|json| json.get("name")?.as_str().map(String::from));
+        match name.map(|name| crate::profiles::switch_profile(&name)) {
+            Some(Ok(profile)) => {
+                let resp = serde_json::json!({"status": "ok", "profile": profile.summary()});
+                (200, resp.to_string())
+            }
+            Some(Err(e)) => (400, serde_json::json!({ "error": e }).to_string()),
+            None => (400, r#"{"error":"Expected {\"name\": \"...\"}"}"#.to_string()),
+        }
+    } else if route == ("POST", "/control/clear_rpc") {
         set_rpc_endpoint(None);
         (200, r#"{"status":"ok","rpc_endpoint":null}"#.to_string())
-    } else {
-        (404, r#"{"error":"Unknown control endpoint"}"#.to_string())
-    };
-
-    let http_response = format!(
-        "HTTP/1.1 {} OK\r\nContent-Type: application/json\r\nAccess-Control-Allow-Origin: *\r\nContent-Length: {}\r\n\r\n{}",
-        status_code,
-        response_body.len(),
-        response_body
-    );
-    writer.write_all(http_response.as_bytes()).await?;
-    Ok(())
-}
+    } else if route == ("POST", "/control/set_max_concurrency") {
+        let limit = serde_json::from_slice::<serde_json::Value>(body)
+            .ok()
+            .and_then(|json| json.get("limit")?.as_u64())
+            .filter(|limit| *limit > 0);
+        match limit {
+            Some(limit) => {
+                set_max_concurrency(limit as usize);
+                let resp = serde_json::json!({"status": "ok", "max_concurrency": max_concurrency()});
+                (200, resp.to_string())
+            }
+            None => (400, r#"{"error":"Expected {\"limit\": <positive integer>}"}"#.to_string()),
+        }
+    } else if route == ("POST", "/control/set_max_connections") {
+        let limit = serde_json::from_slice::<serde_json::Value>(body)
+            .ok()
+            .and_then(|json| json.get("limit")?.as_u64())
+            .filter(|limit| *limit > 0);
+        match limit {
+            Some(limit) => {
+                set_max_connections(limit as usize);
+                let resp = serde_json::json!({"status": "ok", "max_connections": max_connections()});
+                (200, resp.to_string())
+            }
+            None => (400, r#"{"error":"Expected {\"limit\": <positive integer>}"}"#.to_string()),
+        }
+    } else if route == ("POST", "/control/set_tunnel_timeouts") {
+        // Either field may be left out to keep its current value
+        let json = serde_json::from_slice::<serde_json::Value>(body).ok();
+        let connect = json.as_ref().and_then(|j| j.get("connect_secs")?.as_u64());
+        let idle = json.as_ref().and_then(|j| j.get("idle_secs")?.as_u64());
+        if connect.is_none() && idle.is_none() {
+            (400, r#"{"error":"Expected {\"connect_secs\": N, \"idle_secs\": N}"}"#.to_string())
+        } else {
+            let (current_connect, current_idle) = tunnel_timeouts();
+            let connect = connect.map(std::time::Duration::from_secs).unwrap_or(current_connect);
+            let idle = idle.map(std::time::Duration::from_secs).unwrap_or(current_idle);
+            set_tunnel_timeouts(connect, idle);
+            let resp = serde_json::json!({
+                "status": "ok",
+                "connect_secs": connect.as_secs(),
+                "idle_secs": idle.as_secs(),
+            });
+            (200, resp.to_string())
+        }
+    } else if route == ("POST", "/control/set_header_limits") {
+        // Either field may be left out to keep its current value
+        let json = serde_json::from_slice::<serde_json::Value>(body).ok();
+        let max_headers = json.as_ref().and_then(|j| j.get("max_headers")?.as_u64());
+        let max_line_bytes = json.as_ref().and_then(|j| j.get("max_line_bytes")?.as_u64());
+        if max_headers.is_none() && max_line_bytes.is_none() {
+            (400, r#"{"error":"Expected {\"max_headers\": N, \"max_line_bytes\": N}"}"#.to_string())
+        } else {
+            let (current_headers, current_line_bytes) = header_limits();
+            set_header_limits(
+                max_headers.map_or(current_headers, |n| n as usize),
+                max_line_bytes.map_or(current_line_bytes, |n| n as usize),
+            );
+            let (max_headers, max_line_bytes) = header_limits();
+            let resp = serde_json::json!({
+                "status": "ok",
+                "max_headers": max_headers,
+                "max_line_bytes": max_line_bytes,
+            });
+            (200, resp.to_string())
+        }
+    } else if route == ("POST", "/control/test_endpoint") {
+        let url = serde_json::from_slice::<serde_json::Value>(body)
+            .ok()
+            .and_then(|json| json.get("url")?.as_str().map(String::from));
+        match url {
+            Some(url) => (200, test_endpoint(&url).await.to_string()),
+            None => (400, r#"{"error":"Expected {\"url\": \"...\"}"}"#.to_string()),
+        }
+    } else if route == ("POST", "/control/healthcheck_all") {
+        let resp = serde_json::json!({ "status": "ok", "endpoints": healthcheck_all().await });
+        (200, resp.to_string())
+    } else if route == ("POST", "/control/pause") {
+        set_forwarding_paused(true);
+        (200, r#"{"status":"ok","paused":true}"#.to_string())
+    } else if route == ("POST", "/control/resume") {
+        set_forwarding_paused(false);
+        (200, r#"{"status":"ok","paused":false}"#.to_string())
+    } else if route == ("POST", "/control/set_ip_check_urls") {
+        let urls = serde_json::from_slice::<serde_json::Value>(body)
+            .ok()
+            .and_then(|json| serde_json::from_value::<Vec<String>>(json.get("urls")?.clone()).ok());
+        match urls {
+            Some(urls) => {
+                crate::tor::set_ip_check_urls(urls);
+                let resp = serde_json::json!({"status": "ok", "ip_check_urls": crate::tor::ip_check_urls()});
+                (200, resp.to_string())
+            }
+            None => (400, r#"{"error":"Expected {\"urls\": [..]}"}"#.to_string()),
+        }
+    } else if route == ("POST", "/control/set_upstream_proxy") {
+        // {"url": "http://proxy:3128"} enables, {"url": null} disables
+        let url = serde_json::from_slice::<serde_json::Value>(body)
+            .ok()
+            .and_then(|json| json.get("url").map(|u| u.as_str().map(String::from)));
+        match url.map(set_upstream_proxy) {
+            Some(Ok(())) => {
+                let resp = serde_json::json!({"status": "ok", "upstream_proxy": PROXY_CONFIG.lock().upstream_proxy.clone()});
+                (200, resp.to_string())
+            }
+            Some(Err(e)) => (400, serde_json::json!({ "error": e }).to_string()),
+            None => (400, r#"{"error":"Expected {\"url\": \"http://...\" | null}"}"#.to_string()),
+        }
+    } else if route == ("POST", "/control/set_min_log_risk") {
+        // {"level": "High"}; one of Low, Medium, High, Critical
+        let level = serde_json::from_slice::<serde_json::Value>(body)
+            .ok()
+            .and_then(|json| serde_json::from_value::<transaction::RiskLevel>(json.get("level")?.clone()).ok());
+        match level {
+            Some(level) => {
+                set_min_log_risk_level(level.clone());
+                let resp = serde_json::json!({"status": "ok", "min_log_risk_level": level});
+                (200, resp.to_string())
+            }
+            None => (400, r#"{"error":"Expected {\"level\": \"Low\" | \"Medium\" | \"High\" | \"Critical\"}"}"#.to_string()),
+        }
+    } else if route == ("POST", "/control/set_fallbacks") {
+        // {"urls": ["https://...", ...]}; [] or null clears
+        let urls = serde_json::from_slice::<serde_json::Value>(body).ok().and_then(|json| match json.get("urls") {
+            Some(serde_json::Value::Null) => Some(Vec::new()),
+            Some(urls) => serde_json::from_value::<Vec<String>>(urls.clone()).ok(),
+            None => None,
+        });
+        match urls.map(set_fallback_endpoints) {
+            Some(Ok(())) => {
+                let urls = PROXY_CONFIG.lock().fallback_endpoints.clone();
+                let resp = serde_json::json!({"status": "ok", "fallback_endpoints": urls});
+                (200, resp.to_string())
+            }
+            Some(Err(e)) => (400, serde_json::json!({ "error": e }).to_string()),
+            None => (400, r#"{"error":"Expected {\"urls\": [\"https://...\"]}"}"#.to_string()),
+        }
+    } else if route == ("POST", "/control/set_cors_origins") {
+        let origins = serde_json::from_slice::<serde_json::Value>(body)
+            .ok()
+            .and_then(|json| serde_json::from_value::<Vec<String>>(json.get("origins")?.clone()).ok());
+        match origins {
+            Some(origins) => {
+                set_cors_allow_origins(origins.clone());
+                let resp = serde_json::json!({"status": "ok", "cors_allow_origins": origins});
+                (200, resp.to_string())
+            }
+            None => (400, r#"{"error":"Expected {\"origins\": [..]}"}"#.to_string()),
+        }
+    } else if route == ("POST", "/control/set_connect_allowlist") {
+        // {"hosts": ["example.com", "example.org:443"]}; null restores the default
+        let hosts = serde_json::from_slice::<serde_json::Value>(body).ok().and_then(|json| match json.get("hosts") {
+            Some(serde_json::Value::Null) => Some(None),
+            Some(hosts) => serde_json::from_value::<Vec<String>>(hosts.clone()).ok().map(Some),
+            None => None,
+        });
+        match hosts {
+            Some(hosts) => {
+                set_connect_allowlist(hosts);
+                let resp = serde_json::json!({"status": "ok", "connect_allowlist": *CONNECT_ALLOWLIST.lock()});
+                (200, resp.to_string())
+            }
+            None => (400, r#"{"error":"Expected {\"hosts\": [..] | null}"}"#.to_string()),
+        }
+    } else {
+        (404, r#"{"error":"Unknown control endpoint"}"#.to_string())
+    };
 
-/// Handle CONNECT requests for HTTPS tunneling
-async fn handle_connect(
-    mut stream: TcpStream,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let mut buf_reader = BufReader::new(&mut stream);
+    // Audit everything that can change state (the endpoint checks only read)
+    if let Some(action) = path.strip_prefix("/control/").filter(|_| method == "POST") {
+        if status_code != 404 && !matches!(action, "test_endpoint" | "healthcheck_all") {
+            crate::audit::record(action, body, status_code);
+        }
+    }
 
-    // Read the CONNECT request line
-    let mut request_line = String::new();
-    buf_reader.read_line(&mut request_line).await?;
+    let http_response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\n{cors}Content-Length: {}\r\n\r\n{}",
+        status_code,
+        reason_phrase(status_code),
+        response_body.len(),
+        response_body
+    );
+    writer.write_all(http_response.as_bytes()).await?;
+    Ok(())
+}
+
+/// Standard reason phrase for an HTTP status code (empty if unknown)
+fn reason_phrase(status_code: u16) -> &'static str {
+    reqwest::StatusCode::from_u16(status_code)
+        .ok()
+        .and_then(|status| status.canonical_reason())
+        .unwrap_or("")
+}
+
+/// Handle CONNECT requests for HTTPS tunneling
+async fn handle_connect<R, W>(
+    mut buf_reader: BufReader<R>,
+    mut writer: W,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+where
+    R: AsyncRead + Unpin + Send,
+    W: AsyncWrite + Unpin + Send,
+{
+    // Read the CONNECT request line; its headers are discarded
+    let Some((request_line, _)) = read_request_head(&mut buf_reader).await? else {
+        writer.write_all(HEADERS_TOO_LARGE).await?;
+        return Err("CONNECT request exceeded header limits".into());
+    };
 
     // Parse: CONNECT host:port HTTP/1.1
     let parts: Vec<&str> = request_line.split_whitespace().collect();
@@ -700,73 +2397,93 @@ async fn handle_connect(
     }
     let target = parts[1].to_string();
     log::info!("CONNECT tunnel requested to: {}", target);
-
-    // Read and discard headers until empty line
-    loop {
-        let mut line = String::new();
-        buf_reader.read_line(&mut line).await?;
-        if line == "\r\n" || line.is_empty() {
-            break;
-        }
+    if !connect_target_allowed(&target) {
+        log::warn!("Refusing CONNECT to {}: not in the allowlist", target);
+        writer
+            .write_all(b"HTTP/1.1 403 Forbidden\r\nConnection: close\r\nContent-Length: 0\r\n\r\n")
+            .await?;
+        return Err(format!("CONNECT target {} not allowed", target).into());
     }
 
-    // Drop the buf_reader to release the borrow
-    drop(buf_reader);
-
-    // Check if Tor routing is enabled
-    let (tor_enabled, tor_socks_port) = {
-        let config = PROXY_CONFIG.lock();
-        (config.tor_enabled, config.tor_socks_port)
-    };
-
-    // Connect to target — either directly or via Tor SOCKS5
-    let connect_result = if tor_enabled && tor_socks_port > 0 {
-        // Parse host:port for SOCKS5 connection
-        let parts: Vec<&str> = target.splitn(2, ':').collect();
-        if parts.len() != 2 {
-            stream
-                .write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n")
-                .await?;
-            return Err("Invalid CONNECT target format".into());
+    // Connect to target — directly, via Tor SOCKS5, or through the upstream proxy
+    let (connect_timeout, idle_timeout) = tunnel_timeouts();
+    let route = UpstreamRoute::current();
+    // SOCKS5 needs the host and port separately
+    let socks_target = target
+        .split_once(':')
+        .map(|(host, port)| (host.to_string(), port.parse::<u16>().unwrap_or(443)));
+    if matches!(route, UpstreamRoute::Tor(_)) && socks_target.is_none() {
+        writer
+            .write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n")
+            .await?;
+        return Err("Invalid CONNECT target format".into());
+    }
+    let connect = async {
+        match route {
+            UpstreamRoute::Tor(tor_socks_port) => {
+                let (host, port) = socks_target.unwrap_or_default();
+                log::info!("CONNECT via Tor SOCKS5 to {}:{}", host, port);
+                tokio_socks::tcp::Socks5Stream::connect(
+                    format!("127.0.0.1:{}", tor_socks_port).as_str(),
+                    (host.as_str(), port),
+                )
+                .await
+                .map(|s| s.into_inner())
+                .map_err(|e| -> Box<dyn std::error::Error + Send + Sync> { Box::new(e) })
+            }
+            UpstreamRoute::HttpProxy(proxy) => {
+                log::info!("CONNECT via upstream proxy to {}", target);
+                connect_via_http_proxy(&proxy, &target).await
+            }
+            UpstreamRoute::Direct => TcpStream::connect(&target)
+                .await
+                .map_err(|e| -> Box<dyn std::error::Error + Send + Sync> { Box::new(e) }),
         }
-        let host = parts[0];
-        let port: u16 = parts[1].parse().unwrap_or(443);
-
-        log::info!("CONNECT via Tor SOCKS5 to {}:{}", host, port);
-        tokio_socks::tcp::Socks5Stream::connect(
-            format!("127.0.0.1:{}", tor_socks_port).as_str(),
-            (host, port),
-        )
-        .await
-        .map(|s| s.into_inner())
-        .map_err(|e| -> Box<dyn std::error::Error + Send + Sync> { Box::new(e) })
+    };
+    let connect_result = if connect_timeout.is_zero() {
+        connect.await
     } else {
-        TcpStream::connect(&target)
-            .await
-            .map_err(|e| -> Box<dyn std::error::Error + Send + Sync> { Box::new(e) })
+        match tokio::time::timeout(connect_timeout, connect).await {
+            Ok(result) => result,
+            Err(_) => {
+                log::warn!("CONNECT to {} timed out after {:?}", target, connect_timeout);
+                writer
+                    .write_all(b"HTTP/1.1 504 Gateway Timeout\r\nContent-Length: 0\r\n\r\n")
+                    .await?;
+                return Err(format!("CONNECT to {} timed out", target).into());
+            }
+        }
     };
 
     match connect_result {
         Ok(target_stream) => {
+            let _tunnel = ActiveTunnel::open();
+
             // Send 200 Connection established
-            stream
+            writer
                 .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
                 .await?;
-            stream.flush().await?;
+            writer.flush().await?;
 
             // Update stats
             REQUESTS_PROXIED.fetch_add(1, Ordering::Relaxed);
 
-            // Tunnel: copy data bidirectionally
-            let (mut client_read, mut client_write) = stream.into_split();
+            // Tunnel: copy data bidirectionally (keeping anything already buffered)
+            let (mut client_read, mut client_write) = (buf_reader, writer);
             let (mut target_read, mut target_write) = target_stream.into_split();
 
+            // Milliseconds since `opened` when bytes last moved in either direction
+            let opened = Instant::now();
+            let last_activity = AtomicU64::new(0);
+            let touch = || last_activity.store(opened.elapsed().as_millis() as u64, Ordering::Relaxed);
+
             let client_to_target = async {
                 let mut buf = [0u8; 8192];
                 loop {
                     match client_read.read(&mut buf).await {
                         Ok(0) => break,
                         Ok(n) => {
+                            touch();
                             BYTES_TRANSFERRED.fetch_add(n as u64, Ordering::Relaxed);
                             if target_write.write_all(&buf[..n]).await.is_err() {
                                 break;
@@ -784,6 +2501,7 @@ async fn handle_connect(
                     match target_read.read(&mut buf).await {
                         Ok(0) => break,
                         Ok(n) => {
+                            touch();
                             BYTES_TRANSFERRED.fetch_add(n as u64, Ordering::Relaxed);
                             if client_write.write_all(&buf[..n]).await.is_err() {
                                 break;
@@ -795,20 +2513,1391 @@ async fn handle_connect(
                 }
             };
 
-            // Run both directions concurrently until one ends
+            // Ends once the tunnel has seen no traffic for `idle_timeout`
+            let idle = async {
+                if idle_timeout.is_zero() {
+                    return std::future::pending().await;
+                }
+                loop {
+                    let last = std::time::Duration::from_millis(last_activity.load(Ordering::Relaxed));
+                    let quiet = opened.elapsed().saturating_sub(last);
+                    if quiet >= idle_timeout {
+                        break;
+                    }
+                    tokio::time::sleep(idle_timeout - quiet).await;
+                }
+            };
+
+            // Run both directions concurrently until one ends or the tunnel idles out
             tokio::select! {
                 _ = client_to_target => {}
                 _ = target_to_client => {}
+                _ = idle => log::info!("Closing CONNECT tunnel to {} after {:?} idle", target, idle_timeout),
             }
 
             Ok(())
         }
         Err(e) => {
             log::error!("Failed to connect to {}: {}", target, e);
-            stream
+            writer
                 .write_all(b"HTTP/1.1 502 Bad Gateway\r\nContent-Length: 0\r\n\r\n")
                 .await?;
             Err(e)
         }
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Build (or reuse) the pooled direct client, returning how many are pooled
+    pub(crate) fn pool_direct_client() -> usize {
+        upstream_client(&UpstreamRoute::Direct, &HeaderMap::new()).unwrap();
+        UPSTREAM_CLIENTS.lock().len()
+    }
+
+    pub(crate) fn pooled_client_count() -> usize {
+        UPSTREAM_CLIENTS.lock().len()
+    }
+
+    // Tests that start a server share the global shutdown channel
+    pub(crate) static SERVER_TEST_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    /// Drain a test client, returning the `type` of every frame received
+    fn received_types(rx: &mut tokio::sync::mpsc::UnboundedReceiver<String>) -> Vec<String> {
+        let mut types = Vec::new();
+        while let Ok(msg) = rx.try_recv() {
+            let json: serde_json::Value = serde_json::from_str(&msg).unwrap();
+            types.push(json["type"].as_str().unwrap_or_default().to_string());
+        }
+        types
+    }
+
+    fn decoded_with_risk(
+        risk_level: transaction::RiskLevel,
+    ) -> transaction::DecodedTransaction {
+        transaction::DecodedTransaction {
+            summary: format!("{:?} risk transaction", risk_level),
+            instructions: vec![],
+            warnings: vec![],
+            accounts_involved: vec![],
+            estimated_cost: None,
+            risk_level,
+            required_signatures: 1,
+            unsigned: false,
+        }
+    }
+
+    #[test]
+    fn test_decode_rpc_transaction() {
+        use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
+        // One System transfer of 20 SOL, with an all-zero (unsigned) signature
+        let mut tx = privacyrpc_sdk::fixtures::sol_transfer_bytes(20_000_000_000);
+        tx[1..65].fill(0);
+
+        let body = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "sendTransaction",
+            "params": [BASE64.encode(&tx), { "encoding": "base64" }],
+        });
+        let decoded = decode_rpc_transaction(body.to_string().as_bytes()).unwrap();
+        assert_eq!(decoded.risk_level, transaction::RiskLevel::High);
+        // Its signature is a placeholder: worth noting when sent, not when signed
+        let unsigned = |d: &transaction::DecodedTransaction| d.warnings.iter().any(|w| w.title == "Unsigned Transaction");
+        assert!(unsigned(&decoded));
+        let body = serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": "signTransaction", "params": [BASE64.encode(&tx)] });
+        assert!(!unsigned(&decode_rpc_transaction(body.to_string().as_bytes()).unwrap()));
+
+        let body = serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": "getSlot" });
+        assert!(decode_rpc_transaction(body.to_string().as_bytes()).is_none());
+    }
+
+    #[test]
+    fn test_rpc_transaction_encoding() {
+        let encoding = |body: serde_json::Value| rpc_transaction_encoding(body.to_string().as_bytes());
+        // Valid base64 as well, but without an encoding it's base58
+        assert_eq!(encoding(serde_json::json!({ "method": "sendTransaction", "params": ["AQID"] })), "base58");
+        assert_eq!(
+            encoding(serde_json::json!({ "method": "sendTransaction", "params": ["AQID", { "encoding": "base64" }] })),
+            "base64"
+        );
+        assert_eq!(
+            encoding(serde_json::json!({ "method": "signTransaction", "params": { "transaction": "AQID", "encoding": "base64" } })),
+            "base64"
+        );
+    }
+
+    #[test]
+    fn test_cors_headers() {
+        let _guard = SERVER_TEST_LOCK.blocking_lock();
+        set_cors_allow_origins(vec!["https://app.example.com".to_string()]);
+        assert_eq!(
+            cors_headers(Some("https://app.example.com")),
+            "Access-Control-Allow-Origin: https://app.example.com\r\nVary: Origin\r\n"
+        );
+        assert_eq!(cors_headers(Some("https://evil.example.com")), "");
+
+        set_cors_allow_origins(vec!["*".to_string()]);
+        assert_eq!(cors_headers(Some("https://evil.example.com")), "Access-Control-Allow-Origin: *\r\n");
+    }
+
+    #[test]
+    fn test_high_risk_decode_broadcasts_alert() {
+        let _guard = SERVER_TEST_LOCK.blocking_lock();
+        let mut rx = crate::websocket::test_client();
+
+        report_decoded_transaction(&decoded_with_risk(transaction::RiskLevel::Low));
+        report_decoded_transaction(&decoded_with_risk(transaction::RiskLevel::Critical));
+
+        let mut alerts = Vec::new();
+        while let Ok(msg) = rx.try_recv() {
+            let json: serde_json::Value = serde_json::from_str(&msg).unwrap();
+            if json["type"] == "TRANSACTION_ALERT" {
+                alerts.push(json);
+            }
+        }
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0]["riskLevel"], "Critical");
+    }
+
+    #[tokio::test]
+    async fn test_min_log_risk_level_silences_lower_risk() {
+        let _guard = SERVER_TEST_LOCK.lock().await;
+        let mut out = Vec::new();
+        handle_control_endpoint("POST /control/set_min_log_risk HTTP/1.1\r\n", br#"{"level":"High"}"#, "", &mut out)
+            .await
+            .unwrap();
+        assert!(String::from_utf8(out).unwrap().starts_with("HTTP/1.1 200 OK"));
+        let mut rx = crate::websocket::test_client();
+
+        assert!(!is_logged_risk(&transaction::RiskLevel::Low));
+        assert!(is_logged_risk(&transaction::RiskLevel::Critical));
+        report_decoded_transaction(&decoded_with_risk(transaction::RiskLevel::Low));
+        report_decoded_transaction(&decoded_with_risk(transaction::RiskLevel::Critical));
+        let alerts: Vec<serde_json::Value> = std::iter::from_fn(|| rx.try_recv().ok())
+            .map(|msg| serde_json::from_str(&msg).unwrap())
+            .filter(|json: &serde_json::Value| json["type"] == "TRANSACTION_ALERT")
+            .collect();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0]["riskLevel"], "Critical");
+
+        let mut out = Vec::new();
+        handle_control_endpoint("POST /control/set_min_log_risk HTTP/1.1\r\n", br#"{"level":"Severe"}"#, "", &mut out)
+            .await
+            .unwrap();
+        assert!(String::from_utf8(out).unwrap().starts_with("HTTP/1.1 400"));
+        set_min_log_risk_level(transaction::RiskLevel::Low);
+        assert!(is_logged_risk(&transaction::RiskLevel::Low));
+    }
+
+    #[tokio::test]
+    async fn test_keep_alive_serves_pipelined_requests() {
+        let (mut client, server) = tokio::io::duplex(8192);
+        let handle = tokio::spawn(handle_connection(server, true));
+
+        client
+            .write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\n\r\nGET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert_eq!(response.matches("HTTP/1.1 200 OK").count(), 2);
+        assert_eq!(response.matches(r#"{"status":"ok","proxy":"running"}"#).count(), 2);
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_text_plain_body_rejected_with_415() {
+        let (mut client, server) = tokio::io::duplex(8192);
+        let handle = tokio::spawn(handle_connection(server, true));
+
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"getSlot"}"#;
+        let request = format!(
+            "POST / HTTP/1.1\r\nContent-Type: text/plain\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        client.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 415 Unsupported Media Type"));
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_version_endpoint_reports_package_version() {
+        let (mut client, server) = tokio::io::duplex(8192);
+        let handle = tokio::spawn(handle_connection(server, true));
+        client
+            .write_all(b"GET /version HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        let body: serde_json::Value =
+            serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+        assert!(body["capabilities"].as_array().unwrap().iter().any(|c| c == "tor"));
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_remote_control_requests_need_token() {
+        async fn get(path: &str, token: Option<&str>) -> String {
+            let (mut client, server) = tokio::io::duplex(8192);
+            let handle = tokio::spawn(handle_connection(server, false));
+            let token = token.map(|t| format!("X-PrivacyRPC-Token: {}\r\n", t)).unwrap_or_default();
+            let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n{}Connection: close\r\n\r\n", path, token);
+            client.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            client.read_to_string(&mut response).await.unwrap();
+            handle.await.unwrap().unwrap();
+            response
+        }
+
+        assert!(get("/status", None).await.starts_with("HTTP/1.1 403"));
+        assert!(get("/control/audit", None).await.starts_with("HTTP/1.1 403"));
+        assert!(get("/control/audit", Some("wrong")).await.starts_with("HTTP/1.1 403"));
+        let token = crate::websocket::auth_token();
+        assert!(get("/control/audit", Some(token)).await.starts_with("HTTP/1.1 200"));
+        // Plain proxy routes stay open to remote clients
+        assert!(get("/health", None).await.starts_with("HTTP/1.1 200"));
+        assert!(is_local_peer("[::ffff:127.0.0.1]:1".parse().unwrap()));
+        assert!(!is_local_peer("192.0.2.1:1".parse().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_control_endpoint_status_line() {
+        let mut out = Vec::new();
+        handle_control_endpoint("POST /control/nope HTTP/1.1\r\n", b"", "", &mut out)
+            .await
+            .unwrap();
+        let response = String::from_utf8(out).unwrap();
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+
+    #[test]
+    fn test_reason_phrase() {
+        assert_eq!(reason_phrase(200), "OK");
+        assert_eq!(reason_phrase(400), "Bad Request");
+        assert_eq!(reason_phrase(500), "Internal Server Error");
+    }
+
+    #[tokio::test]
+    async fn test_reset_stats_zeroes_status_counters() {
+        let _guard = SERVER_TEST_LOCK.lock().await;
+        REQUESTS_PROXIED.fetch_add(3, Ordering::Relaxed);
+        BYTES_TRANSFERRED.fetch_add(1024, Ordering::Relaxed);
+
+        let mut out = Vec::new();
+        handle_control_endpoint("POST /control/reset_stats HTTP/1.1\r\n", b"", "", &mut out)
+            .await
+            .unwrap();
+        let response = String::from_utf8(out).unwrap();
+        let body: serde_json::Value =
+            serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert!(body["previous"]["requests_proxied"].as_u64().unwrap() >= 3);
+
+        let mut out = Vec::new();
+        handle_control_endpoint("GET /status HTTP/1.1\r\n", b"", "", &mut out)
+            .await
+            .unwrap();
+        let response = String::from_utf8(out).unwrap();
+        let status: serde_json::Value =
+            serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(status["requests_proxied"], 0);
+        assert_eq!(status["bytes_transferred"], 0);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_socket_health() {
+        let _guard = SERVER_TEST_LOCK.lock().await;
+        let path = std::env::temp_dir().join(format!("privacyrpc-proxy-{}.sock", std::process::id()));
+        start_proxy_server_unix(&path).await.unwrap();
+
+        let mut stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+        stream
+            .write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.contains(r#""status":"ok""#));
+
+        stop_proxy_server().await;
+        for _ in 0..50 {
+            if !path.exists() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_proxy_lifecycle_emits_events() {
+        let _guard = SERVER_TEST_LOCK.lock().await;
+        let mut rx = crate::websocket::test_client();
+
+        let port = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port();
+        start_proxy_server(SocketAddr::from(([127, 0, 0, 1], port))).await.unwrap();
+        assert!(received_types(&mut rx).contains(&"PROXY_STARTED".to_string()));
+
+        stop_proxy_server().await;
+        assert!(received_types(&mut rx).contains(&"PROXY_STOPPED".to_string()));
+    }
+
+    #[test]
+    fn test_set_rpc_endpoint_emits_event() {
+        let mut rx = crate::websocket::test_client();
+        let previous = get_rpc_endpoint();
+
+        set_rpc_endpoint(previous.clone());
+        assert!(received_types(&mut rx).contains(&"RPC_CHANGED".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_ipv6_loopback_health() {
+        let _guard = SERVER_TEST_LOCK.lock().await;
+        let addr = privacyrpc_sdk::bind_tcp_listener("[::1]:0".parse().unwrap())
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        start_proxy_server(addr).await.unwrap();
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /health HTTP/1.1\r\nHost: [::1]\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+
+        stop_proxy_server().await;
+    }
+
+    #[tokio::test]
+    async fn test_set_max_concurrency_queues_excess() {
+        let _guard = SERVER_TEST_LOCK.lock().await;
+        let mut out = Vec::new();
+        handle_control_endpoint(
+            "POST /control/set_max_concurrency HTTP/1.1\r\n",
+            br#"{"limit":2}"#,
+            "",
+            &mut out,
+        )
+        .await
+        .unwrap();
+        assert!(String::from_utf8(out).unwrap().starts_with("HTTP/1.1 200 OK"));
+        assert_eq!(max_concurrency(), 2);
+
+        let first = acquire_upstream_slot().await.unwrap();
+        let _second = acquire_upstream_slot().await.unwrap();
+        assert_eq!(IN_FLIGHT_REQUESTS.load(Ordering::Relaxed), 2);
+
+        // A third request waits until a slot frees up
+        let third = tokio::spawn(acquire_upstream_slot());
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(!third.is_finished());
+        drop(first);
+        let _third = third.await.unwrap().unwrap();
+        assert_eq!(IN_FLIGHT_REQUESTS.load(Ordering::Relaxed), 2);
+
+        set_max_concurrency(DEFAULT_MAX_CONCURRENCY);
+    }
+
+    #[tokio::test]
+    async fn test_inspect_never_forwards() {
+        use std::sync::atomic::AtomicUsize;
+        use std::sync::Arc;
+
+        // Upstream that only counts requests
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let upstream_url = spawn_mock_http(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            json_response(r#"{"jsonrpc":"2.0","id":1,"result":"5sig"}"#)
+        })
+        .await;
+
+        let tx = privacyrpc_sdk::fixtures::sol_transfer_base64(20_000_000_000);
+        let body = serde_json::json!({ "transaction": tx }).to_string();
+
+        let (mut client, server) = tokio::io::duplex(8192);
+        let handle = tokio::spawn(handle_connection(server, true));
+        let request = format!(
+            "POST /inspect HTTP/1.1\r\nX-Target-URL: {}\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+            upstream_url,
+            body.len(),
+            body
+        );
+        client.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        handle.await.unwrap().unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(json["success"], true);
+        assert_eq!(json["decoded"]["risk_level"], "High");
+        assert_eq!(json["fee_lamports"], 5_000);
+        assert_eq!(json["forwarded"], false);
+        assert_eq!(requests.load(Ordering::SeqCst), 0);
+    }
+
+    /// Send one JSON-RPC request through the proxy to `upstream_url`, returning the response body
+    async fn proxy_rpc(upstream_url: &str, body: &str) -> serde_json::Value {
+        let (mut client, server) = tokio::io::duplex(8192);
+        let handle = tokio::spawn(handle_connection(server, true));
+        let request = format!(
+            "POST / HTTP/1.1\r\nX-Target-URL: {}\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+            upstream_url,
+            body.len(),
+            body
+        );
+        client.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        handle.await.unwrap().unwrap();
+        serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_endpoint_reports_version_and_latency() {
+        let _guard = SERVER_TEST_LOCK.lock().await;
+
+        // Mock RPC answering getHealth and getVersion
+        let rpc_url = spawn_mock_http(|request| {
+            let result = if request.contains("getVersion") {
+                serde_json::json!({ "solana-core": "1.18.22", "feature-set": 3580551090u32 })
+            } else {
+                serde_json::json!("ok")
+            };
+            json_response(&serde_json::json!({ "jsonrpc": "2.0", "id": 1, "result": result }).to_string())
+        })
+        .await;
+
+        let mut out = Vec::new();
+        let body = serde_json::json!({ "url": rpc_url }).to_string();
+        handle_control_endpoint("POST /control/test_endpoint HTTP/1.1\r\n", body.as_bytes(), "", &mut out)
+            .await
+            .unwrap();
+        let response = String::from_utf8(out).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(json["reachable"], true);
+        assert_eq!(json["healthy"], true);
+        assert_eq!(json["version"], "1.18.22");
+        assert_eq!(json["tor_used"], false);
+        assert!(json["latency_ms"].is_u64());
+
+        // Nothing listening: unreachable rather than an error status
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed_url = format!("http://{}", closed.local_addr().unwrap());
+        drop(closed);
+        let json = test_endpoint(&closed_url).await;
+        assert_eq!(json["reachable"], false);
+        assert!(json["error"].is_string());
+    }
+
+    #[tokio::test]
+    async fn test_healthcheck_all_covers_primary_and_fallbacks() {
+        let _guard = SERVER_TEST_LOCK.lock().await;
+        let previous = get_rpc_endpoint();
+
+        let healthy_url = spawn_mock_rpc(r#"{"jsonrpc":"2.0","id":1,"result":"ok"}"#).await;
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let dead_url = format!("http://{}", closed.local_addr().unwrap());
+        drop(closed);
+        set_rpc_endpoint(Some(healthy_url.clone()));
+        set_fallback_endpoints(vec![dead_url.clone()]).unwrap();
+
+        let mut out = Vec::new();
+        handle_control_endpoint("POST /control/healthcheck_all HTTP/1.1\r\n", b"", "", &mut out)
+            .await
+            .unwrap();
+        set_fallback_endpoints(Vec::new()).unwrap();
+        set_rpc_endpoint(previous);
+        let response = String::from_utf8(out).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        let json: serde_json::Value =
+            serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        let endpoints = json["endpoints"].as_array().unwrap();
+        assert_eq!(endpoints.len(), 2);
+
+        assert_eq!(endpoints[0]["url"], healthy_url);
+        assert_eq!(endpoints[0]["reachable"], true);
+        assert!(endpoints[0]["latency_ms"].is_u64());
+        assert_eq!(endpoints[0]["tor_used"], false);
+
+        assert_eq!(endpoints[1]["url"], dead_url);
+        assert_eq!(endpoints[1]["reachable"], false);
+        assert!(endpoints[1]["latency_ms"].is_null());
+        assert_eq!(endpoints[1]["tor_used"], false);
+    }
+
+    /// Mock RPC answering every request on its own connection with `body`
+    async fn spawn_mock_rpc(body: &'static str) -> String {
+        spawn_mock_http(move |_| json_response(body)).await
+    }
+
+    /// Spawn an upstream answering each request with the raw HTTP response
+    /// `respond` builds from it, one request per connection
+    pub(crate) async fn spawn_mock_http<F>(respond: F) -> String
+    where
+        F: Fn(&str) -> String + Send + Sync + 'static,
+    {
+        let rpc = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", rpc.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = rpc.accept().await {
+                let request = read_request(&mut stream).await;
+                let response = respond(&request);
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        url
+    }
+
+    /// Read one HTTP request: its head, then as much body as `Content-Length` says
+    async fn read_request(stream: &mut tokio::net::TcpStream) -> String {
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = stream.read(&mut buf).await.unwrap_or(0);
+            if n == 0 {
+                break;
+            }
+            request.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&request);
+            if let Some(end) = text.find("\r\n\r\n") {
+                let length = text[..end]
+                    .lines()
+                    .filter_map(|line| line.split_once(':'))
+                    .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+                    .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+                    .unwrap_or(0);
+                if request.len() >= end + 4 + length {
+                    break;
+                }
+            }
+        }
+        String::from_utf8_lossy(&request).into_owned()
+    }
+
+    /// Spawn a CONNECT target that accepts connections and never sends anything
+    async fn spawn_idle_target() -> std::net::SocketAddr {
+        let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = target.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((stream, _)) = target.accept().await {
+                held.push(stream);
+            }
+        });
+        addr
+    }
+
+    /// `200 OK` carrying `body` as JSON
+    pub(crate) fn json_response(body: &str) -> String {
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    }
+
+    /// `sendTransaction` body for one System transfer of 20 SOL (High risk)
+    fn high_value_send_body() -> String {
+        let tx = privacyrpc_sdk::fixtures::sol_transfer_base64(20_000_000_000);
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "sendTransaction",
+            "params": [tx, { "encoding": "base64" }],
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_decode_injection_is_opt_in() {
+        let _guard = SERVER_TEST_LOCK.lock().await;
+        let upstream_url = spawn_mock_rpc(r#"{"jsonrpc":"2.0","id":1,"result":"5sig"}"#).await;
+        let body = high_value_send_body();
+
+        let send = |extra_header: &'static str| {
+            let request = format!(
+                "POST / HTTP/1.1\r\nX-Target-URL: {}\r\n{}Connection: close\r\nContent-Length: {}\r\n\r\n{}",
+                upstream_url,
+                extra_header,
+                body.len(),
+                body
+            );
+            async move {
+                let (mut client, server) = tokio::io::duplex(16384);
+                let handle = tokio::spawn(handle_connection(server, true));
+                client.write_all(request.as_bytes()).await.unwrap();
+                let mut response = String::new();
+                client.read_to_string(&mut response).await.unwrap();
+                handle.await.unwrap().unwrap();
+                serde_json::from_str::<serde_json::Value>(response.split("\r\n\r\n").nth(1).unwrap()).unwrap()
+            }
+        };
+
+        // Plain clients get the upstream response untouched, but the alert still fires
+        let mut rx = crate::websocket::test_client();
+        let json = send("").await;
+        assert_eq!(json, serde_json::json!({ "jsonrpc": "2.0", "id": 1, "result": "5sig" }));
+        let mut alerted = false;
+        while let Ok(msg) = rx.try_recv() {
+            alerted |= msg.contains("TRANSACTION_ALERT");
+        }
+        assert!(alerted);
+
+        let json = send("X-PrivacyRPC-Decode: true\r\n").await;
+        assert_eq!(json["result"], "5sig");
+        assert_eq!(json["_privacyrpc"]["decoded"]["risk_level"], "High");
+
+        // Log-only still decodes and alerts, but keeps the body pristine
+        let json = send("X-PrivacyRPC-Decode: log-only\r\n").await;
+        assert_eq!(json, serde_json::json!({ "jsonrpc": "2.0", "id": 1, "result": "5sig" }));
+        assert!(std::iter::from_fn(|| rx.try_recv().ok()).any(|msg| msg.contains("TRANSACTION_ALERT")));
+
+        set_decode_injection(true);
+        let json = send("").await;
+        assert_eq!(json["_privacyrpc"]["intercepted"], true);
+        // ...even when injection is on for everyone else
+        let json = send("X-PrivacyRPC-Decode: log-only\r\n").await;
+        assert!(json.get("_privacyrpc").is_none());
+        set_decode_injection(false);
+    }
+
+    #[test]
+    fn test_decode_mode_header_values() {
+        assert_eq!(DecodeMode::from_header("true"), DecodeMode::Inject);
+        assert_eq!(DecodeMode::from_header("Inject"), DecodeMode::Inject);
+        assert_eq!(DecodeMode::from_header("log-only"), DecodeMode::LogOnly);
+        assert_eq!(DecodeMode::from_header("false"), DecodeMode::LogOnly);
+        assert_eq!(DecodeMode::from_header("maybe"), DecodeMode::Default);
+    }
+
+    #[tokio::test]
+    async fn test_decoded_transaction_broadcasts_tx_decoded() {
+        let _guard = SERVER_TEST_LOCK.lock().await;
+        let upstream_url = spawn_mock_rpc(r#"{"jsonrpc":"2.0","id":1,"result":"5sig"}"#).await;
+        let body = high_value_send_body();
+        let request = format!(
+            "POST / HTTP/1.1\r\nX-Target-URL: {}\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+            upstream_url,
+            body.len(),
+            body
+        );
+
+        let mut rx = crate::websocket::test_client();
+        let (mut client, server) = tokio::io::duplex(16384);
+        let handle = tokio::spawn(handle_connection(server, true));
+        client.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        handle.await.unwrap().unwrap();
+
+        let frames: Vec<serde_json::Value> = std::iter::from_fn(|| rx.try_recv().ok())
+            .map(|msg| serde_json::from_str(&msg).unwrap())
+            .filter(|json: &serde_json::Value| json["type"] == "TX_DECODED")
+            .collect();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0]["riskLevel"], "High");
+        assert!(frames[0]["summary"].as_str().unwrap().contains("20"));
+        assert!(frames[0]["warnings"].as_array().is_some_and(|w| !w.is_empty()));
+        // One signature, no priority fee
+        assert_eq!(frames[0]["feeLamports"], 5000);
+    }
+
+    #[tokio::test]
+    async fn test_decode_info_survives_non_json_upstream() {
+        let _guard = SERVER_TEST_LOCK.lock().await;
+        let upstream_url = spawn_mock_rpc("<html>502 Bad Gateway</html>").await;
+        let body = high_value_send_body();
+        let request = format!(
+            "POST / HTTP/1.1\r\nX-Target-URL: {}\r\nX-PrivacyRPC-Decode: true\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+            upstream_url,
+            body.len(),
+            body
+        );
+
+        let (mut client, server) = tokio::io::duplex(16384);
+        let handle = tokio::spawn(handle_connection(server, true));
+        client.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        handle.await.unwrap().unwrap();
+
+        let json: serde_json::Value = serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(json["id"], 1);
+        assert_eq!(json["error"]["data"]["body"], "<html>502 Bad Gateway</html>");
+        assert_eq!(json["_privacyrpc"]["decoded"]["risk_level"], "High");
+    }
+
+    #[test]
+    fn test_enrich_response_leaves_binary_and_compressed_bodies() {
+        let info = serde_json::json!({ "intercepted": true });
+        let gzip = [0x1f, 0x8b, 0x08, 0x00, 0xff];
+        assert!(enrich_response(&gzip, false, 200, serde_json::json!(1), info.clone()).is_none());
+        assert!(enrich_response(br#"{"result":1}"#, true, 200, serde_json::json!(1), info.clone()).is_none());
+        assert!(enrich_response(b"[1,2]", false, 200, serde_json::json!(1), info.clone()).is_none());
+
+        let body = enrich_response(br#"{"result":1}"#, false, 200, serde_json::json!(1), info).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["_privacyrpc"]["intercepted"], true);
+    }
+
+    #[tokio::test]
+    async fn test_idle_tunnel_is_closed() {
+        let _guard = SERVER_TEST_LOCK.lock().await;
+        let target_addr = spawn_idle_target().await;
+
+        let previous = tunnel_timeouts();
+        set_tunnel_timeouts(std::time::Duration::from_secs(5), std::time::Duration::from_millis(200));
+        let (mut client, server) = tokio::io::duplex(8192);
+        let handle = tokio::spawn(handle_connection(server, true));
+        client
+            .write_all(format!("CONNECT {} HTTP/1.1\r\n\r\n", target_addr).as_bytes())
+            .await
+            .unwrap();
+
+        let mut established = [0u8; 39];
+        client.read_exact(&mut established).await.unwrap();
+        assert!(established.starts_with(b"HTTP/1.1 200"));
+        assert_eq!(ACTIVE_TUNNELS.load(Ordering::Relaxed), 1);
+
+        // The proxy hangs up once nothing has moved for the idle timeout
+        let mut rest = Vec::new();
+        tokio::time::timeout(std::time::Duration::from_secs(2), client.read_to_end(&mut rest))
+            .await
+            .expect("idle tunnel was not closed")
+            .unwrap();
+        handle.await.unwrap().unwrap();
+        assert_eq!(ACTIVE_TUNNELS.load(Ordering::Relaxed), 0);
+        set_tunnel_timeouts(previous.0, previous.1);
+    }
+
+    #[tokio::test]
+    async fn test_connect_allowlist() {
+        let _guard = SERVER_TEST_LOCK.lock().await;
+        let target_addr = spawn_idle_target().await;
+        let connect = |target: String| async move {
+            let (mut client, server) = tokio::io::duplex(8192);
+            let handle = tokio::spawn(handle_connection(server, true));
+            client
+                .write_all(format!("CONNECT {} HTTP/1.1\r\n\r\n", target).as_bytes())
+                .await
+                .unwrap();
+            let mut status = [0u8; 12];
+            client.read_exact(&mut status).await.unwrap();
+            drop(client);
+            let _ = handle.await;
+            String::from_utf8_lossy(&status).to_string()
+        };
+
+        // Listening beyond loopback, only RPC hosts and Jito are reachable by default
+        LISTENING_BEYOND_LOOPBACK.store(true, Ordering::Relaxed);
+        assert_eq!(connect(target_addr.to_string()).await, "HTTP/1.1 403");
+        assert!(connect_target_allowed("mainnet.block-engine.jito.wtf:443"));
+        assert!(!connect_target_allowed("mainnet.block-engine.jito.wtf:22"));
+        set_rpc_endpoint(Some(format!("http://{}/", target_addr)));
+        assert_eq!(connect(target_addr.to_string()).await, "HTTP/1.1 200");
+        assert!(!connect_target_allowed(&format!("127.0.0.1:{}", target_addr.port() ^ 1)));
+        set_rpc_endpoint(Some("http://rpc.example.com/".to_string()));
+        assert!(connect_target_allowed("rpc.example.com:80"));
+        assert!(!connect_target_allowed("rpc.example.com:443"));
+        set_rpc_endpoint(None);
+
+        // An explicit list applies even on loopback, host alone or with port
+        LISTENING_BEYOND_LOOPBACK.store(false, Ordering::Relaxed);
+        assert_eq!(connect(target_addr.to_string()).await, "HTTP/1.1 200");
+        let mut out = Vec::new();
+        handle_control_endpoint(
+            "POST /control/set_connect_allowlist HTTP/1.1\r\n",
+            br#"{"hosts":["api.example.com"]}"#,
+            "",
+            &mut out,
+        )
+        .await
+        .unwrap();
+        assert!(String::from_utf8(out).unwrap().starts_with("HTTP/1.1 200"));
+        assert_eq!(connect(target_addr.to_string()).await, "HTTP/1.1 403");
+        assert!(connect_target_allowed("API.example.com:443"));
+        set_connect_allowlist(Some(vec![target_addr.to_string()]));
+        assert_eq!(connect(target_addr.to_string()).await, "HTTP/1.1 200");
+        assert!(!connect_target_allowed("127.0.0.1:1"));
+
+        set_connect_allowlist(None);
+    }
+
+    #[tokio::test]
+    async fn test_requests_go_through_upstream_proxy() {
+        let _guard = SERVER_TEST_LOCK.lock().await;
+
+        // Mock HTTP proxy recording the request line it was sent
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorder = seen.clone();
+        let proxy_url = spawn_mock_http(move |request| {
+            recorder.lock().push(request.lines().next().unwrap_or_default().to_string());
+            json_response(r#"{"jsonrpc":"2.0","id":1,"result":"via proxy"}"#)
+        })
+        .await;
+
+        let mut out = Vec::new();
+        let body = serde_json::json!({ "url": proxy_url }).to_string();
+        handle_control_endpoint("POST /control/set_upstream_proxy HTTP/1.1\r\n", body.as_bytes(), "", &mut out)
+            .await
+            .unwrap();
+        assert!(String::from_utf8(out).unwrap().starts_with("HTTP/1.1 200 OK"));
+
+        // The upstream host doesn't resolve, so only the proxy can answer
+        let response = proxy_rpc("http://rpc.invalid", r#"{"jsonrpc":"2.0","id":1,"method":"getSlot"}"#).await;
+        assert_eq!(response["result"], "via proxy");
+        assert_eq!(seen.lock().as_slice(), ["POST http://rpc.invalid/ HTTP/1.1"]);
+
+        let mut out = Vec::new();
+        handle_control_endpoint("POST /control/set_upstream_proxy HTTP/1.1\r\n", br#"{"url":null}"#, "", &mut out)
+            .await
+            .unwrap();
+        assert!(PROXY_CONFIG.lock().upstream_proxy.is_none());
+        assert!(set_upstream_proxy(Some("ftp://proxy".to_string())).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pause_returns_error_until_resumed() {
+        use std::sync::atomic::AtomicUsize;
+
+        let _guard = SERVER_TEST_LOCK.lock().await;
+        let mut rx = crate::websocket::test_client();
+
+        // Upstream answering every request with the same result
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let upstream_url = spawn_mock_http(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            json_response(r#"{"jsonrpc":"2.0","id":1,"result":42}"#)
+        })
+        .await;
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"getSlot"}"#;
+
+        let mut out = Vec::new();
+        handle_control_endpoint("POST /control/pause HTTP/1.1\r\n", b"", "", &mut out)
+            .await
+            .unwrap();
+        let json = proxy_rpc(&upstream_url, body).await;
+        assert_eq!(json["error"]["code"], -32000);
+        assert_eq!(json["error"]["message"], "service paused");
+        assert_eq!(json["id"], 1);
+        assert_eq!(requests.load(Ordering::SeqCst), 0);
+
+        let mut out = Vec::new();
+        handle_control_endpoint("POST /control/resume HTTP/1.1\r\n", b"", "", &mut out)
+            .await
+            .unwrap();
+        let json = proxy_rpc(&upstream_url, body).await;
+        assert_eq!(json["result"], 42);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        let types = received_types(&mut rx);
+        assert!(types.contains(&"PROXY_PAUSED".to_string()));
+        assert!(types.contains(&"PROXY_RESUMED".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_rpc_headers_sent_to_private_endpoint_only() {
+        let _guard = SERVER_TEST_LOCK.lock().await;
+        let previous = get_rpc_endpoint();
+
+        // Mock RPC answering with the x-api-key header it received, if any
+        let rpc_url = spawn_mock_http(|request| {
+            let key = request
+                .lines()
+                .find_map(|line| line.strip_prefix("x-api-key: "))
+                .map(|key| serde_json::json!(key))
+                .unwrap_or(serde_json::Value::Null);
+            json_response(&serde_json::json!({ "jsonrpc": "2.0", "id": 1, "result": key }).to_string())
+        })
+        .await;
+
+        let mut out = Vec::new();
+        let body = serde_json::json!({ "url": rpc_url, "headers": { "x-api-key": "secret" } }).to_string();
+        handle_control_endpoint("POST /control/set_rpc HTTP/1.1\r\n", body.as_bytes(), "", &mut out)
+            .await
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("HTTP/1.1 200 OK"));
+        // The response names the header but never echoes its value
+        assert!(out.contains("x-api-key") && !out.contains("secret"));
+
+        let request = r#"{"jsonrpc":"2.0","id":1,"method":"getSlot"}"#;
+        assert_eq!(proxy_rpc("http://rpc.invalid", request).await["result"], "secret");
+
+        // Without a private endpoint the same upstream is reached via X-Target-URL, keyless
+        set_rpc_endpoint(None);
+        assert!(PROXY_CONFIG.lock().rpc_headers.is_empty());
+        assert!(proxy_rpc(&rpc_url, request).await["result"].is_null());
+
+        let mut out = Vec::new();
+        let body = serde_json::json!({ "url": rpc_url, "headers": { "bad header": "x" } }).to_string();
+        handle_control_endpoint("POST /control/set_rpc HTTP/1.1\r\n", body.as_bytes(), "", &mut out)
+            .await
+            .unwrap();
+        assert!(String::from_utf8(out).unwrap().starts_with("HTTP/1.1 400"));
+
+        set_rpc_endpoint(previous);
+    }
+
+    #[tokio::test]
+    async fn test_upstream_redirects_not_followed() {
+        let _guard = SERVER_TEST_LOCK.lock().await;
+        let elsewhere = spawn_mock_rpc(r#"{"jsonrpc":"2.0","id":1,"result":"followed"}"#).await;
+        let rpc_url = spawn_mock_http(move |_| {
+            let body = r#"{"jsonrpc":"2.0","id":1,"result":"redirect"}"#;
+            format!(
+                "HTTP/1.1 307 Temporary Redirect\r\nLocation: {}\r\nContent-Type: application/json\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+                elsewhere,
+                body.len(),
+                body
+            )
+        })
+        .await;
+
+        let request = r#"{"jsonrpc":"2.0","id":1,"method":"getSlot"}"#;
+        assert_eq!(proxy_rpc(&rpc_url, request).await["result"], "redirect");
+    }
+
+    #[tokio::test]
+    async fn test_tor_enabled_but_not_bootstrapped_is_refused() {
+        let _guard = SERVER_TEST_LOCK.lock().await;
+        let mut rx = crate::websocket::test_client();
+        assert!(!crate::tor::get_tor_status().0);
+        set_tor_routing(true, 9050);
+
+        let (mut client, server) = tokio::io::duplex(8192);
+        let handle = tokio::spawn(handle_connection(server, true));
+        let body = r#"{"jsonrpc":"2.0","id":4,"method":"getSlot"}"#;
+        let request = format!(
+            "POST / HTTP/1.1\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        client.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        handle.await.unwrap().unwrap();
+        set_tor_routing(false, 0);
+
+        assert!(response.starts_with("HTTP/1.1 503"));
+        let json: serde_json::Value =
+            serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(json["id"], 4);
+        assert!(json["error"]["message"].as_str().unwrap().starts_with("Tor is enabled but not connected"));
+        assert!(received_types(&mut rx).contains(&"TOR_NOT_READY".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_toggling_tor_writes_audit_entry() {
+        let _guard = SERVER_TEST_LOCK.lock().await;
+        let disables = || {
+            crate::audit::recent_entries()
+                .iter()
+                .filter(|entry| entry.action == "disable_tor")
+                .count()
+        };
+        let before = disables();
+
+        let mut out = Vec::new();
+        handle_control_endpoint("POST /control/disable_tor HTTP/1.1\r\n", b"", "", &mut out)
+            .await
+            .unwrap();
+        assert_eq!(disables(), before + 1);
+
+        // Reads aren't audited
+        let mut out = Vec::new();
+        handle_control_endpoint("GET /control/audit HTTP/1.1\r\n", b"", "", &mut out)
+            .await
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let json: serde_json::Value = serde_json::from_str(out.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        let entries = json["entries"].as_array().unwrap();
+        assert_eq!(entries.iter().filter(|e| e["action"] == "disable_tor").count(), before + 1);
+        assert_eq!(disables(), before + 1);
+    }
+
+    #[tokio::test]
+    async fn test_large_response_is_streamed() {
+        let _guard = SERVER_TEST_LOCK.lock().await;
+
+        // Upstream sending a chunked body, holding the rest back until told to
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream_url = format!("http://{}", upstream.local_addr().unwrap());
+        let (release_tx, release_rx) = tokio::sync::oneshot::channel::<()>();
+        let part = "a".repeat(64 * 1024);
+        let head = r#"{"jsonrpc":"2.0","id":1,"result":""#;
+        let half = part.len();
+        tokio::spawn(async move {
+            let (mut stream, _) = upstream.accept().await.unwrap();
+            let mut buf = [0u8; 4096];
+            let _ = stream.read(&mut buf).await;
+            let first = format!("{}{}", head, part);
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{}\r\n",
+                first.len(),
+                first
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+            release_rx.await.unwrap();
+            let rest = format!("{}\"}}", part);
+            let response = format!("{:x}\r\n{}\r\n0\r\n\r\n", rest.len(), rest);
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let (mut client, server) = tokio::io::duplex(8192);
+        let handle = tokio::spawn(handle_connection(server, true));
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"getProgramAccounts"}"#;
+        let request = format!(
+            "POST / HTTP/1.1\r\nX-Target-URL: {}\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+            upstream_url,
+            body.len(),
+            body
+        );
+        client.write_all(request.as_bytes()).await.unwrap();
+
+        // The first half reaches the client while the upstream is still holding the rest
+        let mut received = Vec::new();
+        let mut buf = [0u8; 8192];
+        while received.len() < half {
+            let n = tokio::time::timeout(std::time::Duration::from_secs(5), client.read(&mut buf))
+                .await
+                .expect("first half was not streamed")
+                .unwrap();
+            assert!(n > 0);
+            received.extend_from_slice(&buf[..n]);
+        }
+        release_tx.send(()).unwrap();
+        client.read_to_end(&mut received).await.unwrap();
+        handle.await.unwrap().unwrap();
+
+        let response = String::from_utf8(received).unwrap();
+        let (head, mut chunked) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.contains("Transfer-Encoding: chunked"));
+        let mut body = String::new();
+        loop {
+            let (size, rest) = chunked.split_once("\r\n").unwrap();
+            let size = usize::from_str_radix(size, 16).unwrap();
+            if size == 0 {
+                break;
+            }
+            body.push_str(&rest[..size]);
+            chunked = &rest[size + 2..];
+        }
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["result"].as_str().unwrap().len(), 2 * half);
+    }
+
+    #[tokio::test]
+    async fn test_set_and_clear_fallbacks() {
+        let _guard = SERVER_TEST_LOCK.lock().await;
+        let set = |body: &'static str| async move {
+            let mut out = Vec::new();
+            handle_control_endpoint("POST /control/set_fallbacks HTTP/1.1\r\n", body.as_bytes(), "", &mut out)
+                .await
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert!(set(r#"{"urls":["https://rpc-a.example.com"," https://rpc-b.example.com "]}"#)
+            .await
+            .starts_with("HTTP/1.1 200 OK"));
+        assert_eq!(
+            PROXY_CONFIG.lock().fallback_endpoints,
+            ["https://rpc-a.example.com", "https://rpc-b.example.com"]
+        );
+
+        // Listed by /config
+        let (mut client, server) = tokio::io::duplex(8192);
+        let handle = tokio::spawn(handle_connection(server, true));
+        client.write_all(b"GET /config HTTP/1.1\r\nConnection: close\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        handle.await.unwrap().unwrap();
+        let json: serde_json::Value = serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(json["fallbackEndpoints"][1], "https://rpc-b.example.com");
+
+        // Invalid URLs are refused without touching the list
+        assert!(set(r#"{"urls":["ftp://rpc.example.com"]}"#).await.starts_with("HTTP/1.1 400"));
+        assert!(set(r#"{"urls":["not a url"]}"#).await.starts_with("HTTP/1.1 400"));
+        assert_eq!(PROXY_CONFIG.lock().fallback_endpoints.len(), 2);
+
+        assert!(set(r#"{"urls":[]}"#).await.starts_with("HTTP/1.1 200 OK"));
+        assert!(PROXY_CONFIG.lock().fallback_endpoints.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failover_to_fallback_is_broadcast() {
+        let _guard = SERVER_TEST_LOCK.lock().await;
+        let previous = get_rpc_endpoint();
+
+        // Primary answering every request with 503, its API key in the path
+        let primary = spawn_mock_http(|_| {
+            "HTTP/1.1 503 Service Unavailable\r\nConnection: close\r\nContent-Length: 0\r\n\r\n".to_string()
+        })
+        .await;
+        let primary_url = format!("{}/v2/primary-secret", primary);
+        let fallback = spawn_mock_rpc(r#"{"jsonrpc":"2.0","id":1,"result":"from fallback"}"#).await;
+        let fallback_url = format!("{}/?api-key=fallback-secret", fallback);
+
+        set_rpc_endpoint(Some(primary_url.clone()));
+        set_fallback_endpoints(vec![fallback_url.clone()]).unwrap();
+        let mut rx = crate::websocket::test_client();
+
+        let response = proxy_rpc("http://rpc.invalid", r#"{"jsonrpc":"2.0","id":1,"method":"getSlot"}"#).await;
+        set_fallback_endpoints(Vec::new()).unwrap();
+        set_rpc_endpoint(previous);
+        assert_eq!(response["result"], "from fallback");
+
+        let failover = std::iter::from_fn(|| rx.try_recv().ok())
+            .map(|msg| serde_json::from_str::<serde_json::Value>(&msg).unwrap())
+            .find(|json| json["type"] == "RPC_FAILOVER")
+            .expect("no failover event");
+        assert_eq!(failover["from"], format!("{}/v2/redacted", primary));
+        assert_eq!(failover["to"], format!("{}/?redacted", fallback));
+        assert_eq!(failover["fallback"], 1);
+        assert_eq!(failover["reason"], "HTTP 503");
+    }
+
+    #[tokio::test]
+    async fn test_too_many_headers_rejected_with_431() {
+        let (mut client, server) = tokio::io::duplex(65536);
+        let handle = tokio::spawn(handle_connection(server, true));
+
+        let mut request = String::from("GET /health HTTP/1.1\r\n");
+        for i in 0..header_limits().0 + 1 {
+            request.push_str(&format!("X-Filler-{}: {}\r\n", i, i));
+        }
+        request.push_str("\r\n");
+        client.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 431 Request Header Fields Too Large"), "{}", response);
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_overlong_header_line_rejected_with_431() {
+        let (mut client, server) = tokio::io::duplex(65536);
+        let handle = tokio::spawn(handle_connection(server, true));
+
+        let request = format!(
+            "GET /health HTTP/1.1\r\nX-Filler: {}\r\n\r\n",
+            "a".repeat(header_limits().1 + 1)
+        );
+        client.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 431 Request Header Fields Too Large"), "{}", response);
+        handle.await.unwrap().unwrap();
+
+        // Right at the limits is still served
+        let (mut client, server) = tokio::io::duplex(65536);
+        let handle = tokio::spawn(handle_connection(server, true));
+        let filler = "a".repeat(header_limits().1 - "X-Filler: \r\n".len());
+        let request = format!("GET /health HTTP/1.1\r\nConnection: close\r\nX-Filler: {}\r\n\r\n", filler);
+        client.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        handle.await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_parse_request_line() {
+        assert_eq!(parse_request_line("GET /health HTTP/1.1\r\n"), Some(("GET", "/health", "HTTP/1.1")));
+        assert_eq!(parse_request_line("GET  \t/health   HTTP/1.1 \r\n"), Some(("GET", "/health", "HTTP/1.1")));
+        assert_eq!(parse_request_line("GET /status?foo=1&bar HTTP/1.0\r\n"), Some(("GET", "/status", "HTTP/1.0")));
+        assert_eq!(parse_request_line("BREW /pot HTTP/1.1\r\n"), Some(("BREW", "/pot", "HTTP/1.1")));
+        assert_eq!(parse_request_line("GET /health\r\n"), None);
+        assert_eq!(parse_request_line("GET /health HTTP/1.1 extra\r\n"), None);
+        assert_eq!(parse_request_line("GET /health FTP/1.1\r\n"), None);
+        assert_eq!(parse_request_line(""), None);
+    }
+
+    #[tokio::test]
+    async fn test_routing_uses_parsed_request_line() {
+        let (mut client, server) = tokio::io::duplex(8192);
+        let handle = tokio::spawn(handle_connection(server, true));
+
+        client
+            .write_all(b"GET   /health?probe=1  HTTP/1.1\r\n\r\nPUT / HTTP/1.1\r\n\r\nGET /control/status HTTP/1.1\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        let statuses: Vec<&str> = response
+            .split("HTTP/1.1 ")
+            .skip(1)
+            .filter_map(|response| response.lines().next())
+            .collect();
+        assert_eq!(statuses, ["200 OK", "405 Method Not Allowed", "404 Not Found"]);
+        handle.await.unwrap().unwrap();
+
+        let mut out = Vec::new();
+        handle_control_endpoint("GET /status?verbose HTTP/1.1\r\n", b"", "", &mut out)
+            .await
+            .unwrap();
+        assert!(String::from_utf8(out).unwrap().starts_with("HTTP/1.1 200 OK"));
+
+        let (mut client, server) = tokio::io::duplex(8192);
+        let handle = tokio::spawn(handle_connection(server, true));
+        client.write_all(b"GET/health HTTP/1.1\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request"), "{}", response);
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_recent_decodes_newest_first() {
+        let _guard = SERVER_TEST_LOCK.lock().await;
+        let decoded = |summary: &str| transaction::DecodedTransaction {
+            summary: summary.to_string(),
+            ..decoded_with_risk(transaction::RiskLevel::Low)
+        };
+        report_decoded_transaction(&decoded("first"));
+        report_decoded_transaction(&decoded("second"));
+
+        let mut out = Vec::new();
+        handle_control_endpoint("GET /control/recent_decodes HTTP/1.1\r\n", b"", "", &mut out)
+            .await
+            .unwrap();
+        let response = String::from_utf8(out).unwrap();
+        let json: serde_json::Value = serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        let entries = json["entries"].as_array().unwrap();
+        assert_eq!(entries[0]["summary"], "second");
+        assert_eq!(entries[1]["summary"], "first");
+        assert_eq!(entries[0]["risk_level"], "Low");
+
+        // Shrinking the cap drops the oldest
+        set_recent_decodes_limit(1);
+        let recent = recent_decodes();
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].summary, "second");
+        set_recent_decodes_limit(DEFAULT_RECENT_DECODES);
+    }
+
+    #[tokio::test]
+    async fn test_connection_limit_rejects_excess() {
+        let _guard = SERVER_TEST_LOCK.lock().await;
+        let mut out = Vec::new();
+        handle_control_endpoint(
+            "POST /control/set_max_connections HTTP/1.1\r\n",
+            br#"{"limit":2}"#,
+            "",
+            &mut out,
+        )
+        .await
+        .unwrap();
+        assert!(String::from_utf8(out).unwrap().starts_with("HTTP/1.1 200 OK"));
+        assert_eq!(max_connections(), 2);
+
+        let port = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port();
+        start_proxy_server(SocketAddr::from(([127, 0, 0, 1], port))).await.unwrap();
+
+        // Two keep-alive connections, each served once and then left open
+        let health = |stream: TcpStream| async move {
+            let mut reader = BufReader::new(stream);
+            reader
+                .get_mut()
+                .write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                .await
+                .unwrap();
+            let mut status = String::new();
+            reader.read_line(&mut status).await.unwrap();
+            (status, reader)
+        };
+        let (status, first) = health(TcpStream::connect(("127.0.0.1", port)).await.unwrap()).await;
+        assert!(status.starts_with("HTTP/1.1 200"), "{}", status);
+        let (status, _second) = health(TcpStream::connect(("127.0.0.1", port)).await.unwrap()).await;
+        assert!(status.starts_with("HTTP/1.1 200"), "{}", status);
+
+        // A third is turned away; its request is drained so the 503 isn't lost to a reset
+        let mut third = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        third
+            .write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        third.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 503"), "{}", response);
+
+        // Closing one frees its slot
+        drop(first);
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let (status, _) = health(TcpStream::connect(("127.0.0.1", port)).await.unwrap()).await;
+        assert!(status.starts_with("HTTP/1.1 200"), "{}", status);
+
+        stop_proxy_server().await;
+        set_max_connections(DEFAULT_MAX_CONNECTIONS);
+    }
+
+    #[tokio::test]
+    async fn test_run_diagnostics_reports_each_step() {
+        let _guard = SERVER_TEST_LOCK.lock().await;
+        let previous = get_rpc_endpoint();
+        let rpc = spawn_mock_rpc(r#"{"jsonrpc":"2.0","id":1,"result":"ok"}"#).await;
+        let echo = spawn_mock_rpc(r#"{"ip":"203.0.113.7"}"#).await;
+        set_rpc_endpoint(Some(rpc.clone()));
+        crate::tor::set_ip_check_urls(vec![echo]);
+
+        let report = run_diagnostics().await;
+        assert_eq!(report["test"], "routing_path");
+        assert_eq!(report["exit_ip"], "203.0.113.7");
+        assert_eq!(report["config"]["final_rpc"], rpc);
+        let steps = report["routing_path"].as_array().unwrap();
+        let components: Vec<_> = steps.iter().map(|step| step["component"].as_str().unwrap()).collect();
+        assert_eq!(
+            components,
+            ["Browser/Extension", "PrivacyRPC Proxy", "RPC Endpoint", "Exit IP Test", "RPC Connectivity Test"]
+        );
+        assert!(steps.iter().all(|step| step["status"] == "ok"), "{}", report);
+        assert_eq!(steps[2]["mode"], "private_rpc");
+
+        crate::tor::set_ip_check_urls(Vec::new());
+        set_rpc_endpoint(previous);
+    }
+
+    #[tokio::test]
+    async fn test_route_changes_reset_client_pool() {
+        let _guard = SERVER_TEST_LOCK.lock().await;
+        let (tor_enabled, tor_socks_port, upstream_proxy) = {
+            let config = PROXY_CONFIG.lock();
+            (config.tor_enabled, config.tor_socks_port, config.upstream_proxy.clone())
+        };
+
+        assert!(pool_direct_client() >= 1);
+        set_tor_routing(tor_enabled, tor_socks_port);
+        assert_eq!(pooled_client_count(), 0);
+
+        assert!(pool_direct_client() >= 1);
+        set_upstream_proxy(upstream_proxy).unwrap();
+        assert_eq!(pooled_client_count(), 0);
+    }
+}
@@ -0,0 +1,348 @@
+//! Pre-sign Transaction Simulation
+//! Runs simulateTransaction against the upstream RPC and flags balance drains
+//! that static decoding can miss (e.g. transfers made through CPI)
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use privacyrpc_sdk::transaction::{MintDataFuture, MintResolver, TransactionWarning, WarningLevel};
+use serde::Serialize;
+
+const TOKEN_PROGRAM: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+const TOKEN_2022_PROGRAM: &str = "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb";
+
+/// Result of simulating a transaction before it is signed or sent
+#[derive(Debug, Serialize, Clone)]
+pub struct SimulationReport {
+    pub err: Option<serde_json::Value>,
+    pub logs: Vec<String>,
+    pub balance_changes: Vec<BalanceChange>,
+}
+
+/// Balance change of the wallet (SOL) or one of its token accounts
+#[derive(Debug, Serialize, Clone)]
+pub struct BalanceChange {
+    pub account: String,
+    /// Token mint, or `None` for the wallet's SOL balance
+    pub mint: Option<String>,
+    pub pre: u64,
+    pub post: u64,
+}
+
+impl BalanceChange {
+    /// Percentage of the pre-balance lost (0 if the balance did not drop)
+    fn drop_pct(&self) -> f64 {
+        if self.pre == 0 || self.post >= self.pre {
+            return 0.0;
+        }
+        (self.pre - self.post) as f64 / self.pre as f64 * 100.0
+    }
+}
+
+/// Account state relevant to balance tracking
+struct AccountState {
+    lamports: u64,
+    /// (mint, owner, amount) if this is an SPL token account
+    token: Option<(String, String, u64)>,
+}
+
+/// Simulate `tx_encoded` (in `encoding`, `base58` or `base64`) and compute balance
+/// changes for `wallet` (the fee payer). `accounts` are the transaction's account
+/// keys; token accounts owned by the wallet are tracked.
+pub async fn simulate_transaction(
+    client: &reqwest::Client,
+    rpc_url: &str,
+    tx_encoded: &str,
+    encoding: &str,
+    wallet: &str,
+    accounts: &[String],
+) -> Result<SimulationReport, String> {
+    // Pre-state for every account the transaction touches
+    let pre = rpc_call(
+        client,
+        rpc_url,
+        "getMultipleAccounts",
+        serde_json::json!([accounts, { "encoding": "base64" }]),
+    )
+    .await?;
+    let pre_states: Vec<Option<AccountState>> = pre["value"]
+        .as_array()
+        .ok_or("getMultipleAccounts returned no value")?
+        .iter()
+        .map(parse_account)
+        .collect();
+
+    let sim = rpc_call(
+        client,
+        rpc_url,
+        "simulateTransaction",
+        serde_json::json!([tx_encoded, {
+            "encoding": encoding,
+            "sigVerify": false,
+            "replaceRecentBlockhash": true,
+            "accounts": { "encoding": "base64", "addresses": accounts }
+        }]),
+    )
+    .await?;
+    let value = &sim["value"];
+
+    let post_states: Vec<Option<AccountState>> = value["accounts"]
+        .as_array()
+        .map(|a| a.iter().map(parse_account).collect())
+        .unwrap_or_default();
+
+    let mut balance_changes = Vec::new();
+    for (i, account) in accounts.iter().enumerate() {
+        let Some(pre_state) = pre_states.get(i).and_then(|s| s.as_ref()) else {
+            continue;
+        };
+        // The simulation returns null for accounts it couldn't load; a
+        // missing balance is not evidence of a drain, so skip them
+        let Some(post_state) = post_states.get(i).and_then(|s| s.as_ref()) else {
+            continue;
+        };
+
+        if account == wallet {
+            balance_changes.push(BalanceChange {
+                account: account.clone(),
+                mint: None,
+                pre: pre_state.lamports,
+                post: post_state.lamports,
+            });
+        } else if let Some((mint, owner, amount)) = &pre_state.token {
+            if owner == wallet {
+                balance_changes.push(BalanceChange {
+                    account: account.clone(),
+                    mint: Some(mint.clone()),
+                    pre: *amount,
+                    post: post_state
+                        .token
+                        .as_ref()
+                        .map(|(_, _, amount)| *amount)
+                        .unwrap_or(0),
+                });
+            }
+        }
+    }
+
+    Ok(SimulationReport {
+        err: value.get("err").filter(|e| !e.is_null()).cloned(),
+        logs: value["logs"]
+            .as_array()
+            .map(|l| l.iter().filter_map(|s| s.as_str().map(String::from)).collect())
+            .unwrap_or_default(),
+        balance_changes,
+    })
+}
+
+/// Warnings raised by a simulation: Danger if any balance drops more than `threshold_pct`
+pub fn simulation_warnings(report: &SimulationReport, threshold_pct: f64) -> Vec<TransactionWarning> {
+    let mut warnings = Vec::new();
+
+    if let Some(ref err) = report.err {
+        warnings.push(TransactionWarning {
+            level: WarningLevel::Warning,
+            title: "Simulation Failed".into(),
+            message: format!("The transaction failed in simulation: {}", err),
+        });
+    }
+
+    for change in &report.balance_changes {
+        let drop = change.drop_pct();
+        if drop > threshold_pct {
+            let asset = match change.mint {
+                Some(ref mint) => format!("token {}", mint),
+                None => "SOL".to_string(),
+            };
+            warnings.push(TransactionWarning {
+                level: WarningLevel::Danger,
+                title: "Balance Drain Detected".into(),
+                message: format!(
+                    "Simulation shows your {} balance dropping {:.1}% ({} -> {})",
+                    asset, drop, change.pre, change.post
+                ),
+            });
+        }
+    }
+
+    warnings
+}
+
+/// Fetches mint accounts through the proxy's upstream client, so lookups
+/// follow the same routing (e.g. Tor) as the request being inspected
+pub struct UpstreamMintResolver<'a> {
+    client: &'a reqwest::Client,
+    rpc_url: &'a str,
+}
+
+impl<'a> UpstreamMintResolver<'a> {
+    pub fn new(client: &'a reqwest::Client, rpc_url: &'a str) -> Self {
+        Self { client, rpc_url }
+    }
+}
+
+impl MintResolver for UpstreamMintResolver<'_> {
+    fn mint_data<'b>(&'b self, mint: &'b str) -> MintDataFuture<'b> {
+        Box::pin(async move {
+            let result = rpc_call(
+                self.client,
+                self.rpc_url,
+                "getAccountInfo",
+                serde_json::json!([mint, { "encoding": "base64" }]),
+            )
+            .await?;
+            match result["value"]["data"][0].as_str() {
+                Some(data) => BASE64
+                    .decode(data)
+                    .map(Some)
+                    .map_err(|e| format!("Mint {} data is not base64: {}", mint, e)),
+                None => Ok(None),
+            }
+        })
+    }
+}
+
+/// Send a JSON-RPC request and return its `result`
+pub(crate) async fn rpc_call(
+    client: &reqwest::Client,
+    rpc_url: &str,
+    method: &str,
+    params: serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let body = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": method,
+        "params": params,
+    });
+    let resp: serde_json::Value = client
+        .post(rpc_url)
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| format!("{} failed: {}", method, e))?
+        .json()
+        .await
+        .map_err(|e| format!("{} returned invalid JSON: {}", method, e))?;
+
+    if let Some(err) = resp.get("error") {
+        return Err(format!("{} error: {}", method, err));
+    }
+    resp.get("result")
+        .cloned()
+        .ok_or_else(|| format!("{} returned no result", method))
+}
+
+/// Parse an account from a base64-encoded RPC account object
+fn parse_account(value: &serde_json::Value) -> Option<AccountState> {
+    let lamports = value.get("lamports")?.as_u64()?;
+    let owner = value.get("owner").and_then(|o| o.as_str()).unwrap_or_default();
+
+    let token = if owner == TOKEN_PROGRAM || owner == TOKEN_2022_PROGRAM {
+        value["data"][0]
+            .as_str()
+            .and_then(|d| BASE64.decode(d).ok())
+            .filter(|data| data.len() >= 72)
+            .map(|data| {
+                // SPL token account layout: mint (32) | owner (32) | amount (u64 LE)
+                let mint = bs58::encode(&data[0..32]).into_string();
+                let owner = bs58::encode(&data[32..64]).into_string();
+                let amount = u64::from_le_bytes(data[64..72].try_into().unwrap());
+                (mint, owner, amount)
+            })
+    } else {
+        None
+    };
+
+    Some(AccountState { lamports, token })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::tests::{json_response, spawn_mock_http};
+
+    /// Minimal JSON-RPC server answering each request with `handler(method)`
+    async fn mock_rpc(handler: fn(&str) -> serde_json::Value) -> String {
+        spawn_mock_http(move |request| {
+            let body = request.split("\r\n\r\n").nth(1).unwrap_or_default();
+            let req: serde_json::Value = serde_json::from_str(body).unwrap();
+            let resp = serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "result": handler(req["method"].as_str().unwrap_or_default()),
+            });
+            json_response(&resp.to_string())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_simulated_balance_drain_is_critical() {
+        const WALLET: &str = "11111111111111111111111111111112";
+        let url = mock_rpc(|method| match method {
+            "getMultipleAccounts" => serde_json::json!({
+                "value": [{ "lamports": 2_000_000_000u64, "owner": "11111111111111111111111111111111", "data": ["", "base64"] }]
+            }),
+            "simulateTransaction" => serde_json::json!({
+                "value": {
+                    "err": null,
+                    "logs": ["Program log: transfer"],
+                    "accounts": [{ "lamports": 100_000_000u64, "owner": "11111111111111111111111111111111", "data": ["", "base64"] }]
+                }
+            }),
+            _ => serde_json::Value::Null,
+        })
+        .await;
+
+        let report = simulate_transaction(
+            &reqwest::Client::new(),
+            &url,
+            "AQID",
+            "base64",
+            WALLET,
+            &[WALLET.to_string()],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(report.logs, vec!["Program log: transfer"]);
+        assert_eq!(report.balance_changes.len(), 1);
+        assert_eq!(report.balance_changes[0].pre, 2_000_000_000);
+        assert_eq!(report.balance_changes[0].post, 100_000_000);
+
+        let warnings = simulation_warnings(&report, 50.0);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].level, WarningLevel::Danger);
+
+        // A threshold above the 95% drop raises nothing
+        assert!(simulation_warnings(&report, 99.0).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_null_post_state_is_not_a_drain() {
+        const WALLET: &str = "11111111111111111111111111111112";
+        let url = mock_rpc(|method| match method {
+            "getMultipleAccounts" => serde_json::json!({
+                "value": [{ "lamports": 2_000_000_000u64, "owner": "11111111111111111111111111111111", "data": ["", "base64"] }]
+            }),
+            "simulateTransaction" => serde_json::json!({
+                "value": { "err": null, "logs": [], "accounts": [null] }
+            }),
+            _ => serde_json::Value::Null,
+        })
+        .await;
+
+        let report = simulate_transaction(
+            &reqwest::Client::new(),
+            &url,
+            "AQID",
+            "base64",
+            WALLET,
+            &[WALLET.to_string()],
+        )
+        .await
+        .unwrap();
+
+        assert!(report.balance_changes.is_empty());
+        assert!(simulation_warnings(&report, 50.0).is_empty());
+    }
+}
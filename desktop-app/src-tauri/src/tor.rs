@@ -1,11 +1,13 @@
 use once_cell::sync::Lazy;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
 use tokio::net::TcpStream;
 use tokio::process::{Child, Command};
 use tokio::sync::Mutex;
+use thiserror::Error;
 
 // Global Tor state accessible from both Tauri commands and proxy control endpoints
 static GLOBAL_TOR: Lazy<Arc<Mutex<Option<TorManager>>>> =
@@ -13,10 +15,121 @@ static GLOBAL_TOR: Lazy<Arc<Mutex<Option<TorManager>>>> =
 static RESOURCE_DIR: Lazy<parking_lot::Mutex<Option<PathBuf>>> =
     Lazy::new(|| parking_lot::Mutex::new(None));
 
+// Keep the Tor data directory across restarts (cached consensus + entry guards)
+static PERSISTENT_DATA_DIR: AtomicBool = AtomicBool::new(false);
+
+// Automatic circuit rotation interval in seconds (0 = off), applied whenever Tor starts
+static ROTATION_INTERVAL_SECS: AtomicU64 = AtomicU64::new(0);
+
 // Sync-accessible Tor status cache (updated when status changes)
 static TOR_STATUS_CACHE: Lazy<parking_lot::Mutex<(bool, Option<String>)>> =
     Lazy::new(|| parking_lot::Mutex::new((false, None)));
 
+// IP echo services tried in order when detecting the exit IP
+const DEFAULT_IP_CHECK_URLS: &[&str] = &[
+    "https://check.torproject.org/api/ip",
+    "http://ip-api.com/json",
+    "https://api.ipify.org?format=json",
+];
+static IP_CHECK_URLS: Lazy<parking_lot::Mutex<Vec<String>>> = Lazy::new(|| {
+    parking_lot::Mutex::new(DEFAULT_IP_CHECK_URLS.iter().map(|s| s.to_string()).collect())
+});
+
+/// Set the IP echo services used for exit IP detection, tried in order.
+/// An empty list restores the defaults.
+pub fn set_ip_check_urls(urls: Vec<String>) {
+    let urls = if urls.is_empty() {
+        DEFAULT_IP_CHECK_URLS.iter().map(|s| s.to_string()).collect()
+    } else {
+        urls
+    };
+    log::info!("IP check URLs set to {:?}", urls);
+    *IP_CHECK_URLS.lock() = urls;
+}
+
+pub fn ip_check_urls() -> Vec<String> {
+    IP_CHECK_URLS.lock().clone()
+}
+
+// Service answering whether the caller's IP is a Tor exit
+const DEFAULT_TOR_CHECK_URL: &str = "https://check.torproject.org/api/ip";
+static TOR_CHECK_URL: Lazy<parking_lot::Mutex<String>> =
+    Lazy::new(|| parking_lot::Mutex::new(DEFAULT_TOR_CHECK_URL.to_string()));
+
+/// Set the service asked whether the exit IP belongs to Tor. It must answer
+/// like check.torproject.org, with `{"IsTor": bool, ...}`. `None` or an empty
+/// URL restores the default.
+pub fn set_tor_check_url(url: Option<String>) {
+    let url = url
+        .filter(|u| !u.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_TOR_CHECK_URL.to_string());
+    log::info!("Tor check URL set to {}", url);
+    *TOR_CHECK_URL.lock() = url;
+}
+
+pub fn tor_check_url() -> String {
+    TOR_CHECK_URL.lock().clone()
+}
+
+/// Ask the Tor check service at `url` whether requests leave through Tor
+pub async fn check_is_tor(client: &reqwest::Client, url: &str) -> Result<bool, String> {
+    let resp = client
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| format!("{} failed: {}", url, e))?;
+    if !resp.status().is_success() {
+        return Err(format!("{} returned HTTP {}", url, resp.status()));
+    }
+    let body = resp.text().await.map_err(|e| format!("{} failed: {}", url, e))?;
+    parse_tor_check(&body).ok_or_else(|| format!("{} returned an unexpected response", url))
+}
+
+/// Read `IsTor` from a Tor check response: a JSON object whose `IsTor` is a
+/// boolean and whose `IP`, if present, is an address
+fn parse_tor_check(body: &str) -> Option<bool> {
+    let json: serde_json::Value = serde_json::from_str(body).ok()?;
+    let object = json.as_object()?;
+    if let Some(ip) = object.get("IP") {
+        ip.as_str()?.parse::<std::net::IpAddr>().ok()?;
+    }
+    object.get("IsTor")?.as_bool()
+}
+
+/// Ask each IP echo service in `urls` in turn, returning the first IP found
+/// and the service that answered
+pub async fn detect_ip(client: &reqwest::Client, urls: &[String]) -> Result<(String, String), String> {
+    let mut last_error = "No IP check URLs configured".to_string();
+    for url in urls {
+        match client.get(url).send().await {
+            Ok(resp) => match resp.text().await {
+                Ok(body) => match parse_echo_ip(&body) {
+                    Some(ip) => return Ok((ip, url.clone())),
+                    None => last_error = format!("{} returned no IP", url),
+                },
+                Err(e) => last_error = format!("{} failed: {}", url, e),
+            },
+            Err(e) => last_error = format!("{} failed: {}", url, e),
+        }
+        log::debug!("IP check: {}", last_error);
+    }
+    Err(last_error)
+}
+
+/// Extract an IP from an echo service response: a JSON object with an
+/// `ip`, `query` or `IP` key, or a bare address
+fn parse_echo_ip(body: &str) -> Option<String> {
+    let candidate = match serde_json::from_str::<serde_json::Value>(body) {
+        Ok(json) => ["ip", "query", "IP"]
+            .iter()
+            .find_map(|key| json.get(key)?.as_str())?
+            .to_string(),
+        Err(_) => body.trim().to_string(),
+    };
+    candidate.parse::<std::net::IpAddr>().ok()?;
+    Some(candidate)
+}
+
 /// Get Tor status synchronously (for proxy config endpoint)
 pub fn get_tor_status() -> (bool, Option<String>) {
     TOR_STATUS_CACHE.lock().clone()
@@ -33,8 +146,62 @@ pub fn set_resource_dir(dir: PathBuf) {
     *RESOURCE_DIR.lock() = Some(dir);
 }
 
+/// Use a persistent data directory under the app data dir instead of a temp dir.
+/// The choice is saved and takes effect the next time Tor is started. Refused
+/// while Tor runs, as the running process would be cleaned up as the other kind.
+pub async fn set_persistent_data_dir(enabled: bool) -> Result<(), String> {
+    let guard = GLOBAL_TOR.lock().await;
+    if let Some(ref manager) = *guard {
+        if *manager.is_running.lock().await {
+            return Err("Stop Tor before changing where it keeps its data".to_string());
+        }
+    }
+    crate::profiles::save_tor_persistent_data(enabled)?;
+    PERSISTENT_DATA_DIR.store(enabled, Ordering::SeqCst);
+    log::info!(
+        "Tor persistent data directory {}",
+        if enabled { "enabled" } else { "disabled" }
+    );
+    Ok(())
+}
+
+/// Apply the saved persistent data directory setting (call during startup)
+pub fn restore_persistent_data_dir() {
+    PERSISTENT_DATA_DIR.store(crate::profiles::tor_persistent_data(), Ordering::SeqCst);
+}
+
+/// Why Tor couldn't be started
+#[derive(Error, Debug)]
+pub enum TorError {
+    #[error("Tor binary not found. Bundle tor in the resources directory, install it on the system, or point PRIVACYRPC_TOR_BINARY at it.")]
+    BinaryNotFound { searched: Vec<PathBuf> },
+    #[error("{0}")]
+    Failed(String),
+}
+
+impl From<String> for TorError {
+    fn from(message: String) -> Self {
+        TorError::Failed(message)
+    }
+}
+
+impl TorError {
+    /// Control endpoint body: the message, plus a `code` and every path
+    /// checked when the binary is missing so the UI can offer a fix
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            TorError::BinaryNotFound { searched } => serde_json::json!({
+                "error": self.to_string(),
+                "code": "tor_binary_not_found",
+                "searched": searched.iter().map(|path| path.display().to_string()).collect::<Vec<_>>(),
+            }),
+            TorError::Failed(_) => serde_json::json!({ "error": self.to_string() }),
+        }
+    }
+}
+
 /// Start Tor globally. Returns TorStatus on success.
-pub async fn global_enable_tor() -> Result<TorStatus, String> {
+pub async fn global_enable_tor() -> Result<TorStatus, TorError> {
     let mut guard = GLOBAL_TOR.lock().await;
 
     // Already running?
@@ -65,6 +232,10 @@ pub async fn global_enable_tor() -> Result<TorStatus, String> {
     // Update sync cache
     update_tor_status_cache(status.is_bootstrapped, status.exit_ip.clone());
 
+    if let Some(interval) = rotation_interval() {
+        manager.enable_auto_rotation(interval).await;
+    }
+
     *guard = Some(manager);
     Ok(status)
 }
@@ -90,11 +261,41 @@ pub async fn global_new_circuit() -> Result<Option<String>, String> {
         .as_ref()
         .ok_or_else(|| "Tor is not running".to_string())?;
     let new_ip = manager.new_circuit().await?;
-    // Update cache with new IP
-    update_tor_status_cache(true, new_ip.clone());
+    circuit_rotated(new_ip.clone());
     Ok(new_ip)
 }
 
+/// Record a new circuit: NEWNYM only affects new streams, so pooled upstream
+/// connections are dropped or traffic would stay on the old circuit
+fn circuit_rotated(exit_ip: Option<String>) {
+    crate::proxy::reset_upstream_clients();
+    update_tor_status_cache(true, exit_ip.clone());
+    crate::websocket::broadcast_event(crate::websocket::WsEvent::TorCircuitRotated { exit_ip });
+}
+
+/// Rotate the circuit every `interval` while Tor runs (`None` turns rotation off).
+/// The setting is kept and applied the next time Tor starts.
+pub async fn global_set_rotation(interval: Option<std::time::Duration>) {
+    let secs = interval.map(|i| i.as_secs().max(1)).unwrap_or(0);
+    ROTATION_INTERVAL_SECS.store(secs, Ordering::SeqCst);
+
+    let guard = GLOBAL_TOR.lock().await;
+    if let Some(ref manager) = *guard {
+        match rotation_interval() {
+            Some(interval) => manager.enable_auto_rotation(interval).await,
+            None => manager.disable_auto_rotation().await,
+        }
+    }
+}
+
+/// Configured automatic rotation interval, if any
+pub fn rotation_interval() -> Option<std::time::Duration> {
+    match ROTATION_INTERVAL_SECS.load(Ordering::SeqCst) {
+        0 => None,
+        secs => Some(std::time::Duration::from_secs(secs)),
+    }
+}
+
 /// Get global Tor status.
 pub async fn global_get_status() -> TorStatus {
     let guard = GLOBAL_TOR.lock().await;
@@ -127,13 +328,15 @@ pub struct TorManager {
     bootstrap_progress: Mutex<u8>,
     exit_ip: Mutex<Option<String>>,
     cookie_auth_file: PathBuf,
+    /// Automatic circuit rotation task, if enabled
+    rotation: Mutex<Option<tokio::task::JoinHandle<()>>>,
 }
 
 impl TorManager {
     /// Create a new TorManager. `resource_dir` is the Tauri resource directory
     /// containing the bundled `tor/` folder.
     pub fn new(_resource_dir: PathBuf) -> Self {
-        let data_dir = std::env::temp_dir().join("privacyrpc-tor");
+        let data_dir = resolve_data_dir(PERSISTENT_DATA_DIR.load(Ordering::SeqCst));
         let cookie_auth_file = data_dir.join("control_auth_cookie");
 
         Self {
@@ -147,18 +350,22 @@ impl TorManager {
             bootstrap_progress: Mutex::new(0),
             exit_ip: Mutex::new(None),
             cookie_auth_file,
+            rotation: Mutex::new(None),
         }
     }
 
     /// Start the Tor process. Returns once bootstrapped or on error.
-    pub async fn start(&mut self, resource_dir: &PathBuf) -> Result<(), String> {
+    pub async fn start(&mut self, resource_dir: &Path) -> Result<(), TorError> {
         if *self.is_running.lock().await {
             return Ok(());
         }
 
-        // Clean up stale Tor data directory to prevent "No, it's still there" errors
+        // Clean up stale Tor state to prevent "No, it's still there" errors
         // This happens when Tor didn't shut down cleanly and left a lock file
-        if self.data_dir.exists() {
+        if PERSISTENT_DATA_DIR.load(Ordering::SeqCst) {
+            // Keep cached consensus and guards, only drop the stale lock
+            remove_stale_lock(&self.data_dir).await;
+        } else if self.data_dir.exists() {
             log::info!("Cleaning up stale Tor data directory...");
             let _ = tokio::fs::remove_dir_all(&self.data_dir).await;
         }
@@ -248,17 +455,19 @@ impl TorManager {
             }
             Ok(Err(e)) => {
                 self.stop().await;
-                Err(e)
+                Err(e.into())
             }
             Err(_) => {
                 self.stop().await;
-                Err("Tor bootstrap timed out after 120 seconds".to_string())
+                Err(TorError::Failed("Tor bootstrap timed out after 120 seconds".to_string()))
             }
         }
     }
 
     /// Stop the Tor process
     pub async fn stop(&mut self) {
+        self.disable_auto_rotation().await;
+
         // Try graceful shutdown via control port
         if let Some(ref mut stream) = *self.control_stream.lock().await {
             let _ = send_control_command(stream, "SIGNAL SHUTDOWN").await;
@@ -302,6 +511,36 @@ impl TorManager {
         self.detect_exit_ip().await
     }
 
+    /// Request a new circuit every `interval` until Tor stops, broadcasting each
+    /// new exit IP. Replaces any rotation already running.
+    pub async fn enable_auto_rotation(&self, interval: std::time::Duration) {
+        let task = spawn_rotation(interval, || async {
+            // The task belongs to the global manager; stop once it's gone
+            if !global_get_status().await.is_running {
+                return false;
+            }
+            match global_new_circuit().await {
+                Ok(ip) => log::info!("Rotated Tor circuit, exit IP: {:?}", ip),
+                Err(e) => log::warn!("Scheduled circuit rotation failed: {}", e),
+            }
+            true
+        });
+        self.set_rotation_task(Some(task)).await;
+        log::info!("Tor circuit rotation every {}s", interval.as_secs());
+    }
+
+    pub async fn disable_auto_rotation(&self) {
+        self.set_rotation_task(None).await;
+    }
+
+    async fn set_rotation_task(&self, task: Option<tokio::task::JoinHandle<()>>) {
+        let mut rotation = self.rotation.lock().await;
+        if let Some(previous) = rotation.take() {
+            previous.abort();
+        }
+        *rotation = task;
+    }
+
     /// Get the current Tor status
     pub async fn get_status(&self) -> TorStatus {
         TorStatus {
@@ -331,21 +570,11 @@ impl TorManager {
             .build()
             .map_err(|e| format!("Failed to build client: {}", e))?;
 
-        match client
-            .get("https://check.torproject.org/api/ip")
-            .send()
-            .await
-        {
-            Ok(resp) => {
-                if let Ok(json) = resp.json::<serde_json::Value>().await {
-                    if let Some(ip) = json.get("IP").and_then(|v| v.as_str()) {
-                        let ip_str = ip.to_string();
-                        *self.exit_ip.lock().await = Some(ip_str.clone());
-                        log::info!("Tor exit IP: {}", ip_str);
-                        return Ok(Some(ip_str));
-                    }
-                }
-                Ok(None)
+        match detect_ip(&client, &ip_check_urls()).await {
+            Ok((ip, source)) => {
+                *self.exit_ip.lock().await = Some(ip.clone());
+                log::info!("Tor exit IP: {} (via {})", ip, source);
+                Ok(Some(ip))
             }
             Err(e) => {
                 log::warn!("Failed to detect Tor exit IP: {}", e);
@@ -420,49 +649,83 @@ Log notice stdout
     }
 
     /// Find the Tor binary in bundled resources or system
-    fn find_tor_binary(&self, resource_dir: &PathBuf) -> Result<PathBuf, String> {
-        let is_windows = cfg!(target_os = "windows");
-        let tor_exe = if is_windows { "tor.exe" } else { "tor" };
-
-        let locations = vec![
-            // Bundled with desktop app (primary)
-            resource_dir.join("tor").join(tor_exe),
-            // Next to executable
-            std::env::current_exe()
-                .unwrap_or_default()
-                .parent()
-                .unwrap_or(&PathBuf::from("."))
-                .join("tor")
-                .join(tor_exe),
-            // Development: SDK binaries
-            PathBuf::from("../../../sdk/typescript/bin/win32-x64/tor").join(tor_exe),
-        ];
-
-        // System fallback paths
-        let system_paths: Vec<PathBuf> = if is_windows {
-            vec![
-                PathBuf::from(r"C:\Program Files\Tor Browser\Browser\TorBrowser\Tor\tor.exe"),
-                PathBuf::from(r"C:\Program Files\Tor\tor.exe"),
-            ]
-        } else {
-            vec![
-                PathBuf::from("/usr/bin/tor"),
-                PathBuf::from("/usr/local/bin/tor"),
-                PathBuf::from("/opt/homebrew/bin/tor"),
-            ]
-        };
-
-        for loc in locations.iter().chain(system_paths.iter()) {
-            if loc.exists() {
-                log::info!("Found Tor binary at: {}", loc.display());
-                return Ok(loc.clone());
-            }
+    fn find_tor_binary(&self, resource_dir: &Path) -> Result<PathBuf, TorError> {
+        first_existing(tor_binary_candidates(resource_dir))
+    }
+}
+
+/// Where to look for the Tor binary, in order of preference
+fn tor_binary_candidates(resource_dir: &Path) -> Vec<PathBuf> {
+    let is_windows = cfg!(target_os = "windows");
+    let tor_exe = if is_windows { "tor.exe" } else { "tor" };
+
+    let mut locations = Vec::new();
+    // Explicit override, for installs the defaults don't cover
+    if let Some(path) = std::env::var_os("PRIVACYRPC_TOR_BINARY") {
+        locations.push(PathBuf::from(path));
+    }
+    locations.extend([
+        // Bundled with desktop app (primary)
+        resource_dir.join("tor").join(tor_exe),
+        // Next to executable
+        std::env::current_exe()
+            .unwrap_or_default()
+            .parent()
+            .unwrap_or(Path::new("."))
+            .join("tor")
+            .join(tor_exe),
+        // Development: SDK binaries
+        PathBuf::from("../../../sdk/typescript/bin/win32-x64/tor").join(tor_exe),
+    ]);
+
+    // System fallback paths
+    if is_windows {
+        locations.extend([
+            PathBuf::from(r"C:\Program Files\Tor Browser\Browser\TorBrowser\Tor\tor.exe"),
+            PathBuf::from(r"C:\Program Files\Tor\tor.exe"),
+        ]);
+    } else {
+        locations.extend([
+            PathBuf::from("/usr/bin/tor"),
+            PathBuf::from("/usr/local/bin/tor"),
+            PathBuf::from("/opt/homebrew/bin/tor"),
+        ]);
+    }
+    locations
+}
+
+/// The first of `candidates` that exists, or an error listing all of them
+fn first_existing(candidates: Vec<PathBuf>) -> Result<PathBuf, TorError> {
+    match candidates.iter().find(|path| path.exists()) {
+        Some(path) => {
+            log::info!("Found Tor binary at: {}", path.display());
+            Ok(path.clone())
         }
+        None => Err(TorError::BinaryNotFound { searched: candidates }),
+    }
+}
 
-        Err(
-            "Tor binary not found. Ensure tor is bundled in the resources directory or installed on the system."
-                .to_string(),
-        )
+/// Resolve the Tor data directory. Persistent mode lives under the app data dir
+/// so later bootstraps can reuse the cached consensus and entry guards.
+fn resolve_data_dir(persistent: bool) -> PathBuf {
+    if persistent {
+        if let Some(dirs) = directories::ProjectDirs::from("com", "privacyrpc", "PrivacyRPC") {
+            return dirs.data_dir().join("tor");
+        }
+        log::warn!("No app data directory available, falling back to temp dir for Tor");
+    }
+    std::env::temp_dir().join("privacyrpc-tor")
+}
+
+/// Remove a `lock` file left behind by a Tor process that didn't exit cleanly.
+/// Only called before we spawn our own Tor, so any existing lock is stale.
+async fn remove_stale_lock(data_dir: &std::path::Path) {
+    let lock_file = data_dir.join("lock");
+    if tokio::fs::metadata(&lock_file).await.is_ok() {
+        log::info!("Removing stale Tor lock file: {}", lock_file.display());
+        if let Err(e) = tokio::fs::remove_file(&lock_file).await {
+            log::warn!("Failed to remove stale Tor lock file: {}", e);
+        }
     }
 }
 
@@ -529,3 +792,199 @@ mod hex {
         data.iter().map(|b| format!("{:02x}", b)).collect()
     }
 }
+
+/// Call `rotate` every `interval` until it returns false
+fn spawn_rotation<F, Fut>(interval: std::time::Duration, mut rotate: F) -> tokio::task::JoinHandle<()>
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: std::future::Future<Output = bool> + Send,
+{
+    tokio::spawn(async move {
+        let start = tokio::time::Instant::now() + interval;
+        let mut ticker = tokio::time::interval_at(start, interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            if !rotate().await {
+                break;
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_persistent_data_dir_under_app_data() {
+        let dir = resolve_data_dir(true);
+        if let Some(dirs) = directories::ProjectDirs::from("com", "privacyrpc", "PrivacyRPC") {
+            assert_eq!(dir, dirs.data_dir().join("tor"));
+        }
+        assert_ne!(dir, resolve_data_dir(false));
+        assert_eq!(resolve_data_dir(false), std::env::temp_dir().join("privacyrpc-tor"));
+    }
+
+    #[tokio::test]
+    async fn test_persistent_data_setting_saved_and_locked_while_running() {
+        let _guard = crate::proxy::tests::SERVER_TEST_LOCK.lock().await;
+
+        set_persistent_data_dir(true).await.unwrap();
+        PERSISTENT_DATA_DIR.store(false, Ordering::SeqCst);
+        restore_persistent_data_dir();
+        assert!(PERSISTENT_DATA_DIR.load(Ordering::SeqCst));
+
+        let manager = TorManager::new(PathBuf::new());
+        *manager.is_running.lock().await = true;
+        *GLOBAL_TOR.lock().await = Some(manager);
+        assert!(set_persistent_data_dir(false).await.is_err());
+        assert!(PERSISTENT_DATA_DIR.load(Ordering::SeqCst));
+        assert!(crate::profiles::tor_persistent_data());
+
+        *GLOBAL_TOR.lock().await = None;
+        set_persistent_data_dir(false).await.unwrap();
+        assert!(!crate::profiles::tor_persistent_data());
+    }
+
+    #[tokio::test]
+    async fn test_rotation_repeats_until_stopped() {
+        use std::sync::atomic::AtomicUsize;
+
+        let rotations = Arc::new(AtomicUsize::new(0));
+        let counter = rotations.clone();
+        let mut manager = TorManager::new(PathBuf::new());
+        let task = spawn_rotation(std::time::Duration::from_millis(10), move || {
+            let counter = counter.clone();
+            async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+                true
+            }
+        });
+        manager.set_rotation_task(Some(task)).await;
+
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert!(rotations.load(Ordering::SeqCst) >= 3);
+
+        // stop() cancels the rotation task
+        manager.stop().await;
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        let after_stop = rotations.load(Ordering::SeqCst);
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert_eq!(rotations.load(Ordering::SeqCst), after_stop);
+    }
+
+    #[test]
+    fn test_parse_echo_ip() {
+        assert_eq!(parse_echo_ip(r#"{"IsTor":true,"IP":"185.220.101.1"}"#).as_deref(), Some("185.220.101.1"));
+        assert_eq!(parse_echo_ip(r#"{"status":"success","query":"203.0.113.9"}"#).as_deref(), Some("203.0.113.9"));
+        assert_eq!(parse_echo_ip("2001:db8::1\n").as_deref(), Some("2001:db8::1"));
+        assert_eq!(parse_echo_ip(r#"{"ip":"not an ip"}"#), None);
+        assert_eq!(parse_echo_ip("<html>blocked</html>"), None);
+    }
+
+    #[test]
+    fn test_parse_tor_check() {
+        assert_eq!(parse_tor_check(r#"{"IsTor":true,"IP":"185.220.101.1"}"#), Some(true));
+        assert_eq!(parse_tor_check(r#"{"IsTor":false,"IP":"203.0.113.9"}"#), Some(false));
+        assert_eq!(parse_tor_check(r#"{"IsTor":"true","IP":"185.220.101.1"}"#), None);
+        assert_eq!(parse_tor_check(r#"{"IsTor":true,"IP":"not an ip"}"#), None);
+        assert_eq!(parse_tor_check(r#"{"ip":"185.220.101.1"}"#), None);
+        assert_eq!(parse_tor_check(r#"[true]"#), None);
+        assert_eq!(parse_tor_check("<html>blocked</html>"), None);
+    }
+
+    #[tokio::test]
+    async fn test_check_is_tor_uses_configured_url() {
+        use crate::proxy::tests::{json_response, spawn_mock_http};
+
+        let check_url = spawn_mock_http(|_| json_response(r#"{"IsTor":true,"IP":"185.220.101.1"}"#)).await;
+        set_tor_check_url(Some(check_url.clone()));
+        assert_eq!(tor_check_url(), check_url);
+        let client = reqwest::Client::new();
+        assert_eq!(check_is_tor(&client, &tor_check_url()).await, Ok(true));
+
+        let blocked = spawn_mock_http(|_| json_response(r#"{"error":"rate limited"}"#)).await;
+        assert!(check_is_tor(&client, &blocked).await.is_err());
+
+        set_tor_check_url(None);
+        assert_eq!(tor_check_url(), DEFAULT_TOR_CHECK_URL);
+    }
+
+    #[tokio::test]
+    async fn test_detect_ip_falls_back_to_custom_echo() {
+        use crate::proxy::tests::{json_response, spawn_mock_http};
+        use tokio::net::TcpListener;
+
+        // Nothing listens on the first URL's port
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed_url = format!("http://{}/", closed.local_addr().unwrap());
+        drop(closed);
+
+        let echo_url = spawn_mock_http(|_| json_response(r#"{"ip":"198.51.100.23"}"#)).await;
+
+        let client = reqwest::Client::new();
+        let (ip, source) = detect_ip(&client, &[closed_url, echo_url.clone()]).await.unwrap();
+        assert_eq!(ip, "198.51.100.23");
+        assert_eq!(source, echo_url);
+    }
+
+    #[tokio::test]
+    async fn test_remove_stale_lock_keeps_cache() {
+        let dir = std::env::temp_dir().join(format!("privacyrpc-tor-test-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        tokio::fs::write(dir.join("lock"), b"").await.unwrap();
+        tokio::fs::write(dir.join("cached-consensus"), b"consensus").await.unwrap();
+
+        remove_stale_lock(&dir).await;
+
+        assert!(!dir.join("lock").exists());
+        assert!(dir.join("cached-consensus").exists());
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+    }
+
+    #[test]
+    fn test_missing_binary_lists_searched_paths() {
+        let dir = std::env::temp_dir().join(format!("privacyrpc-no-tor-{}", std::process::id()));
+        let candidates = tor_binary_candidates(&dir);
+        let bundled = dir.join("tor").join(if cfg!(target_os = "windows") { "tor.exe" } else { "tor" });
+        assert!(candidates.contains(&bundled));
+
+        let searched = vec![bundled, dir.join("elsewhere").join("tor")];
+        let error = first_existing(searched.clone()).unwrap_err();
+        match &error {
+            TorError::BinaryNotFound { searched: listed } => assert_eq!(*listed, searched),
+            other => panic!("expected BinaryNotFound, got {:?}", other),
+        }
+        let json = error.to_json();
+        assert_eq!(json["code"], "tor_binary_not_found");
+        assert!(json["error"].as_str().unwrap().contains("Tor binary not found"));
+        let listed: Vec<&str> = json["searched"].as_array().unwrap().iter().map(|p| p.as_str().unwrap()).collect();
+        assert_eq!(listed, searched.iter().map(|p| p.to_str().unwrap()).collect::<Vec<_>>());
+
+        // Found once one of them exists
+        std::fs::create_dir_all(&dir).unwrap();
+        let present = dir.join("tor-present");
+        std::fs::write(&present, b"").unwrap();
+        assert_eq!(first_existing(vec![dir.join("missing"), present.clone()]).unwrap(), present);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_rotation_moves_traffic_to_fresh_clients() {
+        let _guard = crate::proxy::tests::SERVER_TEST_LOCK.lock().await;
+        let mut rx = crate::websocket::test_client();
+
+        assert!(crate::proxy::tests::pool_direct_client() >= 1);
+        circuit_rotated(Some("185.220.101.1".to_string()));
+        // Nothing pooled survives the rotation, so the next request builds a new client
+        assert_eq!(crate::proxy::tests::pooled_client_count(), 0);
+        assert_eq!(crate::proxy::tests::pool_direct_client(), 1);
+        assert_eq!(get_tor_status(), (true, Some("185.220.101.1".to_string())));
+        update_tor_status_cache(false, None);
+
+        let rotated = std::iter::from_fn(|| rx.try_recv().ok())
+            .any(|msg| msg.contains("TOR_CIRCUIT_ROTATED") && msg.contains("185.220.101.1"));
+        assert!(rotated);
+    }
+}
@@ -1,14 +1,43 @@
 use futures_util::{SinkExt, StreamExt};
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
+use privacyrpc_sdk::transaction::{DecodedTransaction, RiskLevel, TransactionWarning};
 use serde::Serialize;
 use std::collections::HashMap;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU64, Ordering};
 use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::mpsc;
-use tokio_tungstenite::{accept_async, tungstenite::Message};
+use tokio::sync::{mpsc, Notify};
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::{accept_hdr_async, tungstenite::Message, WebSocketStream};
 
-const WS_PORT: u16 = 8898;
+const DEFAULT_WS_HOST: &str = "127.0.0.1";
+const DEFAULT_WS_PORT: u16 = 8898;
+// How many consecutive ports to try when the requested one is taken
+const WS_PORT_ATTEMPTS: u16 = 10;
+
+// Port the server actually bound (0 = not listening)
+static BOUND_PORT: AtomicU16 = AtomicU16::new(0);
+
+// Minimum gap between state broadcasts; bursts in between collapse into the latest state
+const STATE_BROADCAST_INTERVAL: std::time::Duration = std::time::Duration::from_millis(200);
+
+// Coalescing state for broadcast_current_state
+static STATE_DIRTY: AtomicBool = AtomicBool::new(false);
+static STATE_NOTIFY: Lazy<Notify> = Lazy::new(Notify::new);
+static START_BROADCASTER: std::sync::Once = std::sync::Once::new();
+
+// How long a client without a `?token=` has to send its AUTH frame
+const AUTH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+// Per-launch secret clients must present; shared with the extension via native messaging
+static AUTH_TOKEN: Lazy<String> = Lazy::new(|| {
+    use rand::RngCore;
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+});
 
 // Client ID counter
 static CLIENT_ID: AtomicU64 = AtomicU64::new(1);
@@ -31,29 +60,191 @@ pub struct StateUpdate {
     pub tor_ip: Option<String>,
 }
 
-/// Start the WebSocket server for extension communication
-pub async fn start_websocket_server() {
-    let addr = format!("127.0.0.1:{}", WS_PORT);
+/// Alert sent to extension when a high-risk transaction is decoded
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionAlert {
+    #[serde(rename = "type")]
+    pub msg_type: String,
+    pub summary: String,
+    pub risk_level: RiskLevel,
+    pub warnings: Vec<TransactionWarning>,
+}
 
-    let listener = match TcpListener::bind(&addr).await {
-        Ok(l) => l,
-        Err(e) => {
-            log::error!("Failed to bind WebSocket server to {}: {}", addr, e);
-            return;
+/// WebSocket bind address from `PRIVACYRPC_WS_HOST` / `PRIVACYRPC_WS_PORT`,
+/// falling back to 127.0.0.1:8898
+pub fn bind_config_from_env() -> (String, u16) {
+    let host = std::env::var("PRIVACYRPC_WS_HOST").unwrap_or_else(|_| DEFAULT_WS_HOST.to_string());
+    let port = std::env::var("PRIVACYRPC_WS_PORT")
+        .ok()
+        .and_then(|p| p.parse().ok())
+        .unwrap_or(DEFAULT_WS_PORT);
+    (host, port)
+}
+
+/// Port the WebSocket server is listening on, if running
+pub fn websocket_port() -> Option<u16> {
+    match BOUND_PORT.load(Ordering::SeqCst) {
+        0 => None,
+        port => Some(port),
+    }
+}
+
+/// Bind `host:port`, advancing to the next few ports if it is in use
+async fn bind_listener(host: &str, port: u16) -> Option<(TcpListener, u16)> {
+    for candidate in port..=port.saturating_add(WS_PORT_ATTEMPTS - 1) {
+        let addr = format!("{}:{}", host, candidate);
+        match TcpListener::bind(&addr).await {
+            Ok(listener) => return Some((listener, candidate)),
+            Err(e) => log::warn!("Failed to bind WebSocket server to {}: {}", addr, e),
         }
+    }
+    None
+}
+
+/// Secret WebSocket clients must present to connect
+pub fn auth_token() -> &'static str {
+    &AUTH_TOKEN
+}
+
+/// Where the auth token is stored for the native messaging host to read
+fn auth_token_path() -> Option<std::path::PathBuf> {
+    directories::ProjectDirs::from("com", "privacyrpc", "PrivacyRPC")
+        .map(|dirs| dirs.data_dir().join("ws_token"))
+}
+
+/// Write this launch's auth token where only the current user can read it
+pub fn write_auth_token_file() -> std::io::Result<()> {
+    let path = auth_token_path()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "No app data directory"))?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    // Created private, so the token is never readable by others, even briefly
+    let mut file = options.open(&path)?;
+    // The mode only applies on creation; tighten a file left by an older version
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    }
+    std::io::Write::write_all(&mut file, auth_token().as_bytes())
+}
+
+/// Read the auth token written by the running app (used by the native messaging host)
+pub fn read_auth_token_file() -> Option<String> {
+    let token = std::fs::read_to_string(auth_token_path()?).ok()?;
+    Some(token.trim().to_string())
+}
+
+/// Compare tokens without short-circuiting on the first mismatched byte
+pub(crate) fn token_matches(candidate: &str) -> bool {
+    let expected = auth_token().as_bytes();
+    let candidate = candidate.as_bytes();
+    candidate.len() == expected.len()
+        && candidate
+            .iter()
+            .zip(expected)
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// Extract `token` from a request query string
+pub(crate) fn token_from_query(query: Option<&str>) -> Option<String> {
+    query?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("token="))
+        .map(|t| t.to_string())
+}
+
+/// Wait for a `{"type":"AUTH","token":"..."}` first frame
+async fn authenticate_first_frame(ws_stream: &mut WebSocketStream<TcpStream>) -> bool {
+    let frame = match tokio::time::timeout(AUTH_TIMEOUT, ws_stream.next()).await {
+        Ok(Some(Ok(Message::Text(text)))) => text,
+        _ => return false,
+    };
+    let Ok(json) = serde_json::from_str::<serde_json::Value>(&frame) else {
+        return false;
+    };
+    json.get("type").and_then(|t| t.as_str()) == Some("AUTH")
+        && json
+            .get("token")
+            .and_then(|t| t.as_str())
+            .map(token_matches)
+            .unwrap_or(false)
+}
+
+/// Discrete lifecycle events, so the extension can react without diffing snapshots
+#[derive(Serialize, Clone, Debug)]
+#[serde(
+    tag = "type",
+    rename_all = "SCREAMING_SNAKE_CASE",
+    rename_all_fields = "camelCase"
+)]
+pub enum WsEvent {
+    /// `port` is `None` when listening on a Unix socket
+    ProxyStarted { port: Option<u16> },
+    ProxyStopped,
+    /// Upstream forwarding paused; requests get a "service paused" error
+    ProxyPaused,
+    ProxyResumed,
+    /// The proxy failed to start, e.g. its port is already in use
+    ProxyError { message: String },
+    RpcChanged { rpc_endpoint: Option<String> },
+    TorCircuitRotated { exit_ip: Option<String> },
+    /// Tor routing is on but Tor hasn't bootstrapped, so requests are refused
+    TorNotReady,
+    /// A request moved on from `from` to fallback number `fallback` (1-based)
+    RpcFailover { from: String, to: String, fallback: usize, reason: String },
+    /// A transaction passing through the proxy was decoded, whatever its risk
+    TxDecoded {
+        summary: String,
+        risk_level: RiskLevel,
+        warnings: Vec<TransactionWarning>,
+        fee_lamports: u64,
+    },
+}
+
+/// Start the WebSocket server for extension communication
+pub async fn start_websocket_server(host: &str, port: u16) {
+    let Some((listener, bound_port)) = bind_listener(host, port).await else {
+        log::error!(
+            "Failed to bind WebSocket server on {} (ports {}-{})",
+            host,
+            port,
+            port.saturating_add(WS_PORT_ATTEMPTS - 1)
+        );
+        return;
     };
 
-    log::info!("WebSocket server listening on ws://{}", addr);
+    BOUND_PORT.store(bound_port, Ordering::SeqCst);
+    log::info!("WebSocket server listening on ws://{}:{}", host, bound_port);
 
     while let Ok((stream, peer)) = listener.accept().await {
         log::info!("New WebSocket connection from {}", peer);
         tokio::spawn(handle_connection(stream));
     }
+
+    BOUND_PORT.store(0, Ordering::SeqCst);
 }
 
 /// Handle a single WebSocket connection
 async fn handle_connection(stream: TcpStream) {
-    let ws_stream = match accept_async(stream).await {
+    let mut query_token = None;
+    // The callback signature is fixed by tungstenite
+    #[allow(clippy::result_large_err)]
+    let capture_token = |req: &Request, resp: Response| {
+        query_token = token_from_query(req.uri().query());
+        Ok(resp)
+    };
+    let mut ws_stream = match accept_hdr_async(stream, capture_token).await {
         Ok(ws) => ws,
         Err(e) => {
             log::error!("WebSocket handshake failed: {}", e);
@@ -61,6 +252,22 @@ async fn handle_connection(stream: TcpStream) {
         }
     };
 
+    // Require the shared token so other local processes can't snoop on state
+    let authenticated = match query_token {
+        Some(ref token) => token_matches(token),
+        None => authenticate_first_frame(&mut ws_stream).await,
+    };
+    if !authenticated {
+        log::warn!("Rejected unauthenticated WebSocket client");
+        let _ = ws_stream
+            .close(Some(CloseFrame {
+                code: CloseCode::Policy,
+                reason: "Unauthorized".into(),
+            }))
+            .await;
+        return;
+    }
+
     let client_id = CLIENT_ID.fetch_add(1, Ordering::SeqCst);
     let (mut ws_sender, mut ws_receiver) = ws_stream.split();
 
@@ -121,10 +328,41 @@ async fn handle_connection(stream: TcpStream) {
 
 /// Broadcast state update to all connected clients
 pub fn broadcast_state_update(update: StateUpdate) {
-    let json = match serde_json::to_string(&update) {
+    broadcast_message(&update, "state update");
+}
+
+/// Broadcast a risky decoded transaction so the extension can warn before signing
+pub fn broadcast_transaction_alert(decoded: &DecodedTransaction) {
+    let alert = TransactionAlert {
+        msg_type: "TRANSACTION_ALERT".to_string(),
+        summary: decoded.summary.clone(),
+        risk_level: decoded.risk_level.clone(),
+        warnings: decoded.warnings.clone(),
+    };
+    broadcast_message(&alert, "transaction alert");
+}
+
+/// Broadcast every decoded transaction, for a live feed or a pre-sign prompt
+pub fn broadcast_transaction_decoded(decoded: &DecodedTransaction) {
+    broadcast_event(WsEvent::TxDecoded {
+        summary: decoded.summary.clone(),
+        risk_level: decoded.risk_level.clone(),
+        warnings: decoded.warnings.clone(),
+        fee_lamports: decoded.fee_lamports(),
+    });
+}
+
+/// Broadcast a lifecycle event to all connected clients (never coalesced)
+pub fn broadcast_event(event: WsEvent) {
+    broadcast_message(&event, "event");
+}
+
+/// Serialize a message and send it to all connected clients
+fn broadcast_message<T: Serialize>(message: &T, kind: &str) {
+    let json = match serde_json::to_string(message) {
         Ok(j) => j,
         Err(e) => {
-            log::error!("Failed to serialize state update: {}", e);
+            log::error!("Failed to serialize {}: {}", kind, e);
             return;
         }
     };
@@ -137,7 +375,7 @@ pub fn broadcast_state_update(update: StateUpdate) {
     }
 
     if !clients.is_empty() {
-        log::debug!("Broadcast state update to {} clients", clients.len());
+        log::debug!("Broadcast {} to {} clients", kind, clients.len());
     }
 }
 
@@ -161,9 +399,156 @@ fn get_current_state() -> Option<StateUpdate> {
     })
 }
 
-/// Helper to broadcast current state (call after any state change)
+/// Helper to broadcast current state (call after any state change).
+/// Broadcasts are coalesced to at most one per `STATE_BROADCAST_INTERVAL`.
 pub fn broadcast_current_state() {
+    // Callers include sync Tauri commands, so the broadcaster gets its own thread and runtime
+    START_BROADCASTER.call_once(|| {
+        std::thread::spawn(|| {
+            match tokio::runtime::Builder::new_current_thread().enable_time().build() {
+                Ok(rt) => rt.block_on(state_broadcaster()),
+                Err(e) => log::error!("Failed to start state broadcaster: {}", e),
+            }
+        });
+    });
+    STATE_DIRTY.store(true, Ordering::SeqCst);
+    STATE_NOTIFY.notify_one();
+}
+
+fn send_current_state() {
     if let Some(state) = get_current_state() {
         broadcast_state_update(state);
     }
 }
+
+/// Background task that sends the latest state, then waits out the interval.
+/// Notifications during the wait leave a single permit, so a burst yields one trailing broadcast.
+async fn state_broadcaster() {
+    loop {
+        STATE_NOTIFY.notified().await;
+        if STATE_DIRTY.swap(false, Ordering::SeqCst) {
+            send_current_state();
+        }
+        tokio::time::sleep(STATE_BROADCAST_INTERVAL).await;
+    }
+}
+
+/// Register an in-process client that receives every broadcast (for tests)
+#[cfg(test)]
+pub fn test_client() -> mpsc::UnboundedReceiver<String> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    CLIENTS
+        .lock()
+        .insert(CLIENT_ID.fetch_add(1, Ordering::SeqCst), tx);
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn free_port() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    #[test]
+    fn test_ws_event_serialization() {
+        let json = serde_json::to_value(WsEvent::TorCircuitRotated {
+            exit_ip: Some("1.2.3.4".to_string()),
+        })
+        .unwrap();
+        assert_eq!(json["type"], "TOR_CIRCUIT_ROTATED");
+        assert_eq!(json["exitIp"], "1.2.3.4");
+
+        let json = serde_json::to_value(WsEvent::ProxyStopped).unwrap();
+        assert_eq!(json, serde_json::json!({ "type": "PROXY_STOPPED" }));
+    }
+
+    #[tokio::test]
+    async fn test_bind_listener_honors_requested_port() {
+        let port = free_port().await;
+        let (_listener, bound) = bind_listener("127.0.0.1", port).await.unwrap();
+        assert_eq!(bound, port);
+    }
+
+    /// Start a server on a free port and return its address
+    async fn start_test_server() -> String {
+        let port = free_port().await;
+        tokio::spawn(start_websocket_server("127.0.0.1", port));
+        for _ in 0..50 {
+            if TcpStream::connect(("127.0.0.1", port)).await.is_ok() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        format!("ws://127.0.0.1:{}", port)
+    }
+
+    #[tokio::test]
+    async fn test_valid_token_receives_state() {
+        let addr = start_test_server().await;
+        let url = format!("{}/?token={}", addr, auth_token());
+        let (mut ws, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+
+        match ws.next().await {
+            Some(Ok(Message::Text(text))) => assert!(text.contains("STATE_UPDATE")),
+            other => panic!("expected state update, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_auth_frame_receives_state() {
+        let addr = start_test_server().await;
+        let (mut ws, _) = tokio_tungstenite::connect_async(addr).await.unwrap();
+        let auth = serde_json::json!({ "type": "AUTH", "token": auth_token() });
+        ws.send(Message::Text(auth.to_string())).await.unwrap();
+
+        match ws.next().await {
+            Some(Ok(Message::Text(text))) => assert!(text.contains("STATE_UPDATE")),
+            other => panic!("expected state update, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_state_broadcasts_are_coalesced() {
+        let addr = start_test_server().await;
+        let url = format!("{}/?token={}", addr, auth_token());
+        let (mut ws, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+        // Initial state sent on connect
+        ws.next().await.unwrap().unwrap();
+
+        for _ in 0..100 {
+            broadcast_current_state();
+        }
+
+        let mut frames = 0;
+        let deadline = tokio::time::Instant::now() + std::time::Duration::from_millis(600);
+        while let Ok(Some(Ok(msg))) = tokio::time::timeout_at(deadline, ws.next()).await {
+            if msg.to_text().unwrap_or_default().contains("STATE_UPDATE") {
+                frames += 1;
+            }
+        }
+        assert!(frames >= 1, "latest state was never sent");
+        assert!(frames <= 4, "expected coalesced broadcasts, got {} frames", frames);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_token_is_closed() {
+        let addr = start_test_server().await;
+        let url = format!("{}/?token=wrong", addr);
+        let (mut ws, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+
+        match ws.next().await {
+            Some(Ok(Message::Close(Some(frame)))) => assert_eq!(frame.code, CloseCode::Policy),
+            other => panic!("expected policy close, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bind_listener_advances_on_conflict() {
+        let (_taken, port) = bind_listener("127.0.0.1", free_port().await).await.unwrap();
+        let (_listener, bound) = bind_listener("127.0.0.1", port).await.unwrap();
+        assert!(bound > port && bound < port + WS_PORT_ATTEMPTS);
+    }
+}
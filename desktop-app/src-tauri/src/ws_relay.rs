@@ -0,0 +1,438 @@
+//! WebSocket subscription relay
+//!
+//! Wallets open their `accountSubscribe`/`signatureSubscribe` streams on a
+//! separate WebSocket endpoint, which the HTTP proxy never sees. The relay
+//! listens on the port above the proxy (the validator convention, 8899 → 8900,
+//! or `PRIVACYRPC_WS_RELAY_PORT`) and gives each client its own upstream
+//! connection to the `wss://` form of the configured RPC, opened over Tor when
+//! it is enabled. Text and binary frames are passed through untouched in both
+//! directions. Clients share the proxy's connection limit, pause switch,
+//! stats and TLS certificate.
+//!
+//! Browsers don't apply CORS to WebSockets, so without a check any page could
+//! reach the user's keyed RPC and Tor circuit through the relay. Browser
+//! clients need an origin listed by name in the CORS allowlist (`"*"` doesn't
+//! count) or the extension's `?token=`, and so do clients from other machines
+//! when the proxy listens beyond loopback.
+
+use futures_util::{SinkExt, StreamExt};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+// Upstream used when no private RPC is configured, as for HTTP requests
+const DEFAULT_RPC_URL: &str = "https://api.mainnet-beta.solana.com";
+
+// Tor circuits can take a while to build
+const UPSTREAM_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Port the relay is listening on (0 = not listening)
+static BOUND_PORT: AtomicU16 = AtomicU16::new(0);
+
+static SHUTDOWN_TX: Lazy<Mutex<Option<oneshot::Sender<()>>>> = Lazy::new(|| Mutex::new(None));
+
+type UpstreamSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// PubSub URL for an HTTP(S) RPC URL: the same host over `ws(s)://`, with an
+/// explicit port moved up by one as on a validator
+pub fn upstream_ws_url(rpc_url: &str) -> Option<String> {
+    let mut url = reqwest::Url::parse(rpc_url).ok()?;
+    let scheme = match url.scheme() {
+        "https" => "wss",
+        "http" => "ws",
+        _ => return None,
+    };
+    url.set_scheme(scheme).ok()?;
+    if let Some(port) = url.port() {
+        url.set_port(Some(port.checked_add(1)?)).ok()?;
+    }
+    Some(url.to_string())
+}
+
+/// Port for the relay next to a proxy on `proxy_port`: `PRIVACYRPC_WS_RELAY_PORT`
+/// if set (0 turns the relay off), otherwise the port above the proxy. `None`
+/// as well when the extension's WebSocket server already holds that port.
+pub fn relay_port_for(proxy_port: u16) -> Option<u16> {
+    let configured = std::env::var("PRIVACYRPC_WS_RELAY_PORT").ok().and_then(|p| p.parse::<u16>().ok());
+    let port = match configured {
+        Some(port) => port,
+        None if proxy_port == 0 => return None,
+        None => proxy_port.checked_add(1)?,
+    };
+    if port == 0 {
+        return None;
+    }
+    if crate::websocket::websocket_port() == Some(port) {
+        log::warn!(
+            "WebSocket relay port {} is taken by the extension WebSocket server; set PRIVACYRPC_WS_RELAY_PORT",
+            port
+        );
+        return None;
+    }
+    Some(port)
+}
+
+/// Port the relay is listening on, if running
+pub fn relay_port() -> Option<u16> {
+    match BOUND_PORT.load(Ordering::SeqCst) {
+        0 => None,
+        port => Some(port),
+    }
+}
+
+/// Start relaying WebSocket clients on `addr`, replacing a relay that is
+/// already running. Serves `wss://` with the proxy's certificate when it has
+/// one. Returns the bound port.
+pub async fn start_ws_relay(addr: SocketAddr) -> std::io::Result<u16> {
+    let tls = crate::proxy::PROXY_CONFIG.lock().tls.clone();
+    let acceptor = tls
+        .map(|(cert, key)| privacyrpc_sdk::tls::acceptor(&cert, &key))
+        .transpose()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string()))?;
+    let listener = TcpListener::bind(addr).await?;
+    let port = listener.local_addr()?.port();
+    let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<()>();
+    // Dropping the previous sender stops the previous relay
+    *SHUTDOWN_TX.lock() = Some(shutdown_tx);
+    BOUND_PORT.store(port, Ordering::SeqCst);
+    log::info!(
+        "WebSocket relay listening on {}://{}",
+        if acceptor.is_some() { "wss" } else { "ws" },
+        SocketAddr::new(addr.ip(), port)
+    );
+
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                result = listener.accept() => match result {
+                    Ok((stream, peer)) => {
+                        let local = peer.ip().is_loopback();
+                        match (crate::proxy::connection_slot(), &acceptor) {
+                            (None, None) => crate::proxy::refuse_connection(stream),
+                            (None, Some(_)) => log::warn!("Connection limit reached, dropping relay connection"),
+                            (Some(slot), Some(acceptor)) => {
+                                let acceptor = acceptor.clone();
+                                tokio::spawn(async move {
+                                    let handshake = tokio::time::timeout(
+                                        privacyrpc_sdk::tls::HANDSHAKE_TIMEOUT,
+                                        acceptor.accept(stream),
+                                    );
+                                    if let Ok(Ok(stream)) = handshake.await {
+                                        relay_connection(stream, local).await;
+                                    }
+                                    drop(slot);
+                                });
+                            }
+                            (Some(slot), None) => {
+                                tokio::spawn(async move {
+                                    relay_connection(stream, local).await;
+                                    drop(slot);
+                                });
+                            }
+                        }
+                    }
+                    Err(e) => log::error!("WebSocket relay accept error: {}", e),
+                },
+                _ = &mut shutdown_rx => break,
+            }
+        }
+        let _ = BOUND_PORT.compare_exchange(port, 0, Ordering::SeqCst, Ordering::SeqCst);
+        log::info!("WebSocket relay shutting down");
+    });
+    Ok(port)
+}
+
+/// Stop accepting relay clients; open subscriptions run until either side closes
+pub fn stop_ws_relay() {
+    if let Some(tx) = SHUTDOWN_TX.lock().take() {
+        let _ = tx.send(());
+    }
+}
+
+/// Whether a client with this `Origin` and `?token=` may use the relay.
+/// Local clients without an `Origin` aren't browsers and are treated like any
+/// other local client of the proxy; remote ones always need the token.
+fn client_allowed(origin: Option<&str>, token: Option<&str>, local: bool) -> bool {
+    if token.is_some_and(crate::websocket::token_matches) {
+        return true;
+    }
+    if !local {
+        return false;
+    }
+    let Some(origin) = origin else {
+        return true;
+    };
+    let listed: Vec<String> = crate::proxy::PROXY_CONFIG
+        .lock()
+        .cors_allow_origins
+        .iter()
+        .filter(|o| *o != "*")
+        .cloned()
+        .collect();
+    privacyrpc_sdk::cors_allow_origin(&listed, Some(origin)).is_some()
+}
+
+/// Connect to the PubSub endpoint of `rpc_url` over the current route
+async fn connect_upstream(rpc_url: &str) -> Result<UpstreamSocket, Box<dyn std::error::Error + Send + Sync>> {
+    let ws_url = upstream_ws_url(rpc_url).ok_or("RPC endpoint has no WebSocket equivalent")?;
+    let url = reqwest::Url::parse(&ws_url)?;
+    let host = url.host_str().ok_or("RPC endpoint has no host")?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = url.port_or_known_default().ok_or("RPC endpoint has no port")?;
+
+    let stream = tokio::time::timeout(UPSTREAM_CONNECT_TIMEOUT, crate::proxy::connect_upstream(host, port))
+        .await
+        .map_err(|_| format!("Timed out connecting to {}", host))??;
+    // Same auth headers as the HTTP requests to this endpoint
+    let mut request = ws_url.as_str().into_client_request()?;
+    request.headers_mut().extend(crate::proxy::rpc_auth_headers());
+    let (socket, _) = tokio_tungstenite::client_async_tls(request, stream).await?;
+    log::info!("Relaying WebSocket client to {}", host);
+    Ok(socket)
+}
+
+/// Close `client` with `code` and `reason`
+async fn refuse<S: AsyncRead + AsyncWrite + Unpin>(client: &mut WebSocketStream<S>, code: CloseCode, reason: &str) {
+    let _ = client
+        .close(Some(CloseFrame {
+            code,
+            reason: reason.to_string().into(),
+        }))
+        .await;
+}
+
+/// Relay one client connection until either side closes. `local` is whether
+/// the client connected from a loopback address.
+async fn relay_connection<S: AsyncRead + AsyncWrite + Unpin>(stream: S, local: bool) {
+    // The callback signature is fixed by tungstenite
+    #[allow(clippy::result_large_err)]
+    let admit = |req: &Request, resp: Response| {
+        let origin = req.headers().get("origin").and_then(|v| v.to_str().ok());
+        let token = crate::websocket::token_from_query(req.uri().query());
+        if client_allowed(origin, token.as_deref(), local) {
+            return Ok(resp);
+        }
+        log::warn!("WebSocket relay refused client (origin {:?}, local {})", origin, local);
+        let mut refused = ErrorResponse::new(Some("Not allowed".to_string()));
+        *refused.status_mut() = StatusCode::FORBIDDEN;
+        Err(refused)
+    };
+    let mut client = match tokio_tungstenite::accept_hdr_async(stream, admit).await {
+        Ok(ws) => ws,
+        Err(e) => {
+            log::debug!("WebSocket relay handshake failed: {}", e);
+            return;
+        }
+    };
+    if crate::proxy::is_forwarding_paused() {
+        refuse(&mut client, CloseCode::Again, "service paused").await;
+        return;
+    }
+    let rpc_url = crate::proxy::get_rpc_endpoint().unwrap_or_else(|| DEFAULT_RPC_URL.to_string());
+    let upstream = match connect_upstream(&rpc_url).await {
+        Ok(upstream) => upstream,
+        Err(e) => {
+            log::warn!("WebSocket relay could not reach the upstream: {}", e);
+            refuse(&mut client, CloseCode::Again, "Upstream unavailable").await;
+            return;
+        }
+    };
+
+    // Control frames are answered on each hop; only data crosses over.
+    // Each client frame counts as a proxied request.
+    let (mut client_tx, mut client_rx) = client.split();
+    let (mut upstream_tx, mut upstream_rx) = upstream.split();
+    let client_to_upstream = async move {
+        while let Some(Ok(msg)) = client_rx.next().await {
+            // Pausing cuts open subscriptions too
+            if msg.is_close() || crate::proxy::is_forwarding_paused() {
+                break;
+            }
+            if msg.is_text() || msg.is_binary() {
+                crate::proxy::REQUESTS_PROXIED.fetch_add(1, Ordering::Relaxed);
+                crate::proxy::BYTES_TRANSFERRED.fetch_add(msg.len() as u64, Ordering::Relaxed);
+                if upstream_tx.send(msg).await.is_err() {
+                    break;
+                }
+            }
+        }
+        let _ = upstream_tx.close().await;
+    };
+    let upstream_to_client = async move {
+        while let Some(Ok(msg)) = upstream_rx.next().await {
+            if msg.is_close() {
+                break;
+            }
+            if msg.is_text() || msg.is_binary() {
+                crate::proxy::BYTES_TRANSFERRED.fetch_add(msg.len() as u64, Ordering::Relaxed);
+                if client_tx.send(msg).await.is_err() {
+                    break;
+                }
+            }
+        }
+        let _ = client_tx.close().await;
+    };
+    tokio::select! {
+        _ = client_to_upstream => {}
+        _ = upstream_to_client => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_tungstenite::tungstenite::Message;
+
+    #[test]
+    fn test_upstream_ws_url() {
+        assert_eq!(
+            upstream_ws_url("https://mainnet.helius-rpc.com/?api-key=abc").as_deref(),
+            Some("wss://mainnet.helius-rpc.com/?api-key=abc")
+        );
+        assert_eq!(upstream_ws_url("http://127.0.0.1:8899").as_deref(), Some("ws://127.0.0.1:8900/"));
+        assert_eq!(upstream_ws_url("ftp://example.com"), None);
+    }
+
+    async fn next_json(ws: &mut UpstreamSocket) -> serde_json::Value {
+        let msg = tokio::time::timeout(Duration::from_secs(5), ws.next()).await.unwrap();
+        serde_json::from_str(msg.unwrap().unwrap().to_text().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_subscription_round_trip_is_relayed() {
+        let _guard = crate::proxy::tests::SERVER_TEST_LOCK.lock().await;
+        let previous = crate::proxy::get_rpc_endpoint();
+
+        // Mock PubSub node: confirms the subscription, then sends one notification
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream_port = upstream.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (stream, _) = upstream.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            let Some(Ok(Message::Text(text))) = ws.next().await else {
+                return;
+            };
+            let request: serde_json::Value = serde_json::from_str(&text).unwrap();
+            assert_eq!(request["method"], "accountSubscribe");
+            let confirmed = serde_json::json!({ "jsonrpc": "2.0", "result": 42, "id": request["id"] });
+            ws.send(Message::Text(confirmed.to_string())).await.unwrap();
+            let notification = serde_json::json!({
+                "jsonrpc": "2.0",
+                "method": "accountNotification",
+                "params": { "result": { "value": { "lamports": 5000 } }, "subscription": 42 },
+            });
+            ws.send(Message::Text(notification.to_string())).await.unwrap();
+            while ws.next().await.is_some() {}
+        });
+        // The relay derives the PubSub port as the RPC port plus one
+        crate::proxy::set_rpc_endpoint(Some(format!("http://127.0.0.1:{}", upstream_port - 1)));
+
+        let port = start_ws_relay("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        assert_eq!(relay_port(), Some(port));
+        let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://127.0.0.1:{}", port))
+            .await
+            .unwrap();
+        let subscribe = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "accountSubscribe",
+            "params": ["Wallet111"],
+        });
+        ws.send(Message::Text(subscribe.to_string())).await.unwrap();
+
+        let confirmed = next_json(&mut ws).await;
+        let notification = next_json(&mut ws).await;
+        stop_ws_relay();
+        crate::proxy::set_rpc_endpoint(previous);
+
+        assert_eq!(confirmed["result"], 42);
+        assert_eq!(confirmed["id"], 1);
+        assert_eq!(notification["method"], "accountNotification");
+        assert_eq!(notification["params"]["subscription"], 42);
+        assert_eq!(notification["params"]["result"]["value"]["lamports"], 5000);
+    }
+
+    #[tokio::test]
+    async fn test_cross_site_pages_are_refused() {
+        let _guard = crate::proxy::tests::SERVER_TEST_LOCK.lock().await;
+        let previous_origins = crate::proxy::PROXY_CONFIG.lock().cors_allow_origins.clone();
+        let port = start_ws_relay("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let handshake = |origin: &str, query: String| {
+            let mut request = format!("ws://127.0.0.1:{}/{}", port, query).into_client_request().unwrap();
+            request.headers_mut().insert("origin", origin.parse().unwrap());
+            async move { tokio_tungstenite::connect_async(request).await.map(|_| ()) }
+        };
+        let refused = |result: Result<(), tokio_tungstenite::tungstenite::Error>| {
+            matches!(result, Err(tokio_tungstenite::tungstenite::Error::Http(resp)) if resp.status() == StatusCode::FORBIDDEN)
+        };
+
+        // The default wildcard allowlist doesn't admit pages
+        crate::proxy::set_cors_allow_origins(vec!["*".to_string()]);
+        assert!(refused(handshake("https://evil.example", String::new()).await));
+        // ...but the extension's token does
+        let with_token = format!("?token={}", crate::websocket::auth_token());
+        assert!(handshake("chrome-extension://abc", with_token).await.is_ok());
+
+        // Origins listed by name are admitted, others still refused
+        crate::proxy::set_cors_allow_origins(vec!["https://wallet.example".to_string()]);
+        assert!(handshake("https://wallet.example", String::new()).await.is_ok());
+        assert!(refused(handshake("https://evil.example", "?token=wrong".to_string()).await));
+
+        stop_ws_relay();
+        crate::proxy::set_cors_allow_origins(previous_origins);
+    }
+
+    #[test]
+    fn test_relay_port_and_remote_clients() {
+        if std::env::var_os("PRIVACYRPC_WS_RELAY_PORT").is_none() {
+            assert_eq!(relay_port_for(8899), Some(8900));
+            assert_eq!(relay_port_for(u16::MAX), None);
+            assert_eq!(relay_port_for(0), None);
+        }
+        // Other machines need the token even without an Origin
+        assert!(!client_allowed(None, None, false));
+        assert!(client_allowed(None, Some(crate::websocket::auth_token()), false));
+        assert!(client_allowed(None, None, true));
+    }
+
+    #[tokio::test]
+    async fn test_relay_honors_pause_and_connection_limit() {
+        use tokio::io::AsyncReadExt;
+
+        let _guard = crate::proxy::tests::SERVER_TEST_LOCK.lock().await;
+        let port = start_ws_relay("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let url = format!("ws://127.0.0.1:{}", port);
+
+        crate::proxy::set_forwarding_paused(true);
+        let (mut ws, _) = tokio_tungstenite::connect_async(url.as_str()).await.unwrap();
+        let closed = tokio::time::timeout(Duration::from_secs(5), ws.next()).await.unwrap();
+        crate::proxy::set_forwarding_paused(false);
+        match closed {
+            Some(Ok(Message::Close(Some(frame)))) => assert_eq!(frame.code, CloseCode::Again),
+            other => panic!("expected a close frame, got {:?}", other),
+        }
+
+        // The relay draws on the proxy's connection permits
+        let previous = crate::proxy::max_connections();
+        crate::proxy::set_max_connections(1);
+        let held = crate::proxy::connection_slot().unwrap();
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response).await;
+        drop(held);
+        crate::proxy::set_max_connections(previous);
+        stop_ws_relay();
+        assert!(response.starts_with("HTTP/1.1 503"), "{}", response);
+    }
+}
@@ -0,0 +1,402 @@
+//! Per-method response caching
+//!
+//! Successful responses are cached for the TTL configured for their method,
+//! keyed by method and params. Only finalized data is cached: a request asking
+//! for `processed` or `confirmed` commitment always goes upstream, since its
+//! answer can still be rolled back. A `null` result is never cached: for
+//! `getTransaction` or `getBlock` it means the node hasn't seen it yet.
+//!
+//! Account reads change with every block, so no TTL suits them. With slot
+//! tracking on, the chain tip poller reports each slot it sees and account
+//! reads at the poller's commitment are cached only until that slot advances.
+
+use crate::commitment::Commitment;
+use crate::{RpcRequest, RpcResponse};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Entries kept before new responses stop being cached
+const MAX_ENTRIES: usize = 1024;
+
+/// Account reads, cached per slot when slot tracking is on
+const ACCOUNT_METHODS: &[&str] = &["getAccountInfo", "getBalance", "getMultipleAccounts", "getTokenAccountBalance"];
+
+/// Built-in TTLs for common Solana methods. `Duration::MAX` never expires.
+pub const DEFAULT_CACHE_TABLE: &[(&str, Duration)] = &[
+    // Fixed for the life of the cluster
+    ("getGenesisHash", Duration::MAX),
+    ("getEpochSchedule", Duration::MAX),
+    // Finalized blocks and transactions are immutable; the TTL only bounds memory
+    ("getBlock", Duration::from_secs(300)),
+    ("getBlockTime", Duration::from_secs(300)),
+    ("getTransaction", Duration::from_secs(300)),
+    // Only with an explicit end slot; see `ends_at_tip`
+    ("getBlocks", Duration::from_secs(300)),
+    // Rarely changes
+    ("getVersion", Duration::from_secs(60)),
+    ("getMinimumBalanceForRentExemption", Duration::from_secs(60)),
+    ("getFirstAvailableBlock", Duration::from_secs(60)),
+    // Advances every slot (~400ms)
+    ("getSlot", Duration::from_millis(400)),
+    ("getBlockHeight", Duration::from_millis(400)),
+    ("getEpochInfo", Duration::from_millis(400)),
+    ("getLatestBlockhash", Duration::from_millis(400)),
+];
+
+/// The commitment `request` reads at. Requests without one get the node
+/// default, which is `finalized`.
+fn commitment(request: &RpcRequest) -> &str {
+    request
+        .params
+        .as_ref()
+        .and_then(|params| params.as_array())
+        .and_then(|params| params.iter().rev().find_map(|p| p.get("commitment")))
+        .and_then(|c| c.as_str())
+        .unwrap_or("finalized")
+}
+
+/// Whether `request` is a `getBlocks` without an end slot, whose result runs
+/// up to the current tip and so grows every slot
+fn ends_at_tip(request: &RpcRequest) -> bool {
+    request.method == "getBlocks"
+        && !request
+            .params
+            .as_ref()
+            .and_then(|params| params.get(1))
+            .is_some_and(|end| end.is_u64())
+}
+
+/// How long an entry stays valid
+enum Freshness {
+    Ttl(Duration),
+    /// Until the tracked slot advances
+    Slot,
+}
+
+struct Entry {
+    /// `None` never expires
+    expires_at: Option<Instant>,
+    /// For account reads: valid while the tracked slot is at most this
+    slot: Option<u64>,
+    response: RpcResponse,
+}
+
+/// Latest slot reported by the chain tip poller
+struct TrackedSlot {
+    commitment: Commitment,
+    /// Reports older than this are too stale to trust
+    max_age: Duration,
+    latest: Option<(u64, Instant)>,
+}
+
+/// Cached responses for methods with a configured TTL
+pub(crate) struct ResponseCache {
+    ttls: HashMap<String, Duration>,
+    entries: Mutex<HashMap<String, Entry>>,
+    slot: Option<Mutex<TrackedSlot>>,
+}
+
+impl ResponseCache {
+    pub(crate) fn new(ttls: HashMap<String, Duration>) -> Self {
+        Self {
+            ttls,
+            entries: Mutex::new(HashMap::new()),
+            slot: None,
+        }
+    }
+
+    /// Cache account reads at `commitment` until the slot reported through
+    /// [`observe_slot`](Self::observe_slot) advances. Nothing is served once
+    /// reports stop for `max_age`.
+    pub(crate) fn with_slot_tracking(mut self, commitment: Commitment, max_age: Duration) -> Self {
+        self.slot = Some(Mutex::new(TrackedSlot {
+            commitment,
+            max_age,
+            latest: None,
+        }));
+        self
+    }
+
+    /// The slot account reads are currently cached at, if it is known and fresh
+    fn current_slot(&self) -> Option<u64> {
+        let tracked = self.slot.as_ref()?.lock().unwrap_or_else(|e| e.into_inner());
+        let (slot, seen) = tracked.latest?;
+        (seen.elapsed() <= tracked.max_age).then_some(slot)
+    }
+
+    /// Record the slot the chain tip poller just saw, dropping account reads
+    /// cached at an earlier slot
+    pub(crate) fn observe_slot(&self, slot: u64) {
+        let Some(tracked) = &self.slot else {
+            return;
+        };
+        let advanced = {
+            let mut tracked = tracked.lock().unwrap_or_else(|e| e.into_inner());
+            let advanced = tracked.latest.is_none_or(|(latest, _)| slot > latest);
+            // A lagging endpoint's lower slot never moves the tracked slot back
+            let latest = tracked.latest.map_or(slot, |(latest, _)| latest.max(slot));
+            tracked.latest = Some((latest, Instant::now()));
+            advanced
+        };
+        if advanced {
+            let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+            entries.retain(|_, entry| entry.slot.is_none_or(|cached| cached >= slot));
+        }
+    }
+
+    /// Cache key for `request`, or `None` if it must not be cached
+    fn key(&self, request: &RpcRequest) -> Option<(String, Freshness)> {
+        if request.is_notification() {
+            return None;
+        }
+        let params = request.params.as_ref().map(|p| p.to_string()).unwrap_or_default();
+        let key = format!("{}:{}", request.method, params);
+        if ACCOUNT_METHODS.contains(&request.method.as_str()) {
+            if let Some(tracked) = &self.slot {
+                let tracked = tracked.lock().unwrap_or_else(|e| e.into_inner());
+                if commitment(request) == tracked.commitment.as_str() {
+                    return Some((key, Freshness::Slot));
+                }
+            }
+        }
+        let ttl = *self.ttls.get(&request.method)?;
+        if ttl.is_zero() || commitment(request) != "finalized" || ends_at_tip(request) {
+            return None;
+        }
+        Some((key, Freshness::Ttl(ttl)))
+    }
+
+    /// A fresh cached response to `request`, re-addressed to its id
+    pub(crate) fn get(&self, request: &RpcRequest) -> Option<RpcResponse> {
+        let (key, freshness) = self.key(request)?;
+        let current_slot = match freshness {
+            Freshness::Slot => Some(self.current_slot()?),
+            Freshness::Ttl(_) => None,
+        };
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = entries.get(&key)?;
+        let stale_slot = entry.slot.zip(current_slot).is_some_and(|(cached, current)| cached < current);
+        if stale_slot || entry.expires_at.is_some_and(|at| at <= Instant::now()) {
+            entries.remove(&key);
+            return None;
+        }
+        Some(RpcResponse {
+            id: request.id.clone(),
+            ..entry.response.clone()
+        })
+    }
+
+    /// Cache `response` to `request` if it succeeded with a non-null result
+    /// and its method is cacheable
+    pub(crate) fn insert(&self, request: &RpcRequest, response: &RpcResponse) {
+        if response.error.is_some() || response.result.as_ref().is_none_or(|r| r.is_null()) {
+            return;
+        }
+        let Some((key, freshness)) = self.key(request) else {
+            return;
+        };
+        let now = Instant::now();
+        let (expires_at, slot) = match freshness {
+            Freshness::Ttl(ttl) => (now.checked_add(ttl), None),
+            Freshness::Slot => {
+                let Some(current) = self.current_slot() else {
+                    return;
+                };
+                // The node says which slot it answered at; a node behind the
+                // tracked slot gave an answer that is already stale
+                let answered = response
+                    .result
+                    .as_ref()
+                    .and_then(|r| r.pointer("/context/slot"))
+                    .and_then(|s| s.as_u64())
+                    .unwrap_or(current);
+                if answered < current {
+                    return;
+                }
+                (None, Some(answered))
+            }
+        };
+
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.len() >= MAX_ENTRIES {
+            entries.retain(|_, entry| entry.expires_at.is_none_or(|at| at > now));
+            if entries.len() >= MAX_ENTRIES {
+                return;
+            }
+        }
+        entries.insert(
+            key,
+            Entry {
+                expires_at,
+                slot,
+                response: response.clone(),
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str, params: serde_json::Value) -> RpcRequest {
+        RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(serde_json::json!(1)),
+            method: method.to_string(),
+            params: Some(params),
+        }
+    }
+
+    fn response(result: serde_json::Value) -> RpcResponse {
+        RpcResponse::success(Some(serde_json::json!(1)), result)
+    }
+
+    #[test]
+    fn test_only_finalized_commitment_cached() {
+        let cache = ResponseCache::new(DEFAULT_CACHE_TABLE.iter().map(|(m, t)| (m.to_string(), *t)).collect());
+
+        let processed = request("getBlock", serde_json::json!([100, { "commitment": "processed" }]));
+        cache.insert(&processed, &response(serde_json::json!({ "blockhash": "abc" })));
+        assert!(cache.get(&processed).is_none());
+
+        let finalized = request("getBlock", serde_json::json!([100, { "commitment": "finalized" }]));
+        cache.insert(&finalized, &response(serde_json::json!({ "blockhash": "abc" })));
+        let mut again = finalized.clone();
+        again.id = Some(serde_json::json!(7));
+        let cached = cache.get(&again).unwrap();
+        assert_eq!(cached.id, Some(serde_json::json!(7)));
+        assert_eq!(cached.result.unwrap()["blockhash"], "abc");
+
+        // Different params are a different entry
+        let other = request("getBlock", serde_json::json!([101, { "commitment": "finalized" }]));
+        assert!(cache.get(&other).is_none());
+    }
+
+    #[test]
+    fn test_open_ended_get_blocks_not_cached() {
+        let cache = ResponseCache::new(DEFAULT_CACHE_TABLE.iter().map(|(m, t)| (m.to_string(), *t)).collect());
+
+        for params in [
+            serde_json::json!([100]),
+            serde_json::json!([100, { "commitment": "finalized" }]),
+        ] {
+            let open = request("getBlocks", params);
+            cache.insert(&open, &response(serde_json::json!([100, 101])));
+            assert!(cache.get(&open).is_none());
+        }
+
+        let bounded = request("getBlocks", serde_json::json!([100, 102]));
+        cache.insert(&bounded, &response(serde_json::json!([100, 101, 102])));
+        assert!(cache.get(&bounded).is_some());
+    }
+
+    #[test]
+    fn test_not_found_results_not_cached() {
+        let cache = ResponseCache::new(DEFAULT_CACHE_TABLE.iter().map(|(m, t)| (m.to_string(), *t)).collect());
+
+        // A transaction the node hasn't seen yet may land a moment later
+        let tx = request("getTransaction", serde_json::json!(["sig", { "commitment": "finalized" }]));
+        cache.insert(&tx, &response(serde_json::Value::Null));
+        assert!(cache.get(&tx).is_none());
+        let mut missing = response(serde_json::Value::Null);
+        missing.result = None;
+        cache.insert(&tx, &missing);
+        assert!(cache.get(&tx).is_none());
+
+        let block = request("getBlock", serde_json::json!([100, { "commitment": "finalized" }]));
+        cache.insert(&block, &response(serde_json::Value::Null));
+        assert!(cache.get(&block).is_none());
+    }
+
+    #[test]
+    fn test_uncached_methods_and_expiry() {
+        let mut ttls = HashMap::new();
+        ttls.insert("getSlot".to_string(), Duration::from_millis(20));
+        let cache = ResponseCache::new(ttls);
+
+        let balance = request("getBalance", serde_json::json!(["addr"]));
+        cache.insert(&balance, &response(serde_json::json!(5)));
+        assert!(cache.get(&balance).is_none());
+
+        let slot = request("getSlot", serde_json::json!([]));
+        cache.insert(&slot, &response(serde_json::json!(42)));
+        assert!(cache.get(&slot).is_some());
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(cache.get(&slot).is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_account_info_invalidated_when_slot_advances() {
+        use std::sync::atomic::{AtomicU64, Ordering};
+        use std::sync::Arc;
+
+        let slot = Arc::new(AtomicU64::new(100));
+        let reads = Arc::new(AtomicU64::new(0));
+        let (current, counter) = (slot.clone(), reads.clone());
+        let url = crate::tests::mock_upstream(move |_, req| {
+            let slot = current.load(Ordering::SeqCst);
+            let result = match req["method"].as_str() {
+                Some("getAccountInfo") => serde_json::json!({
+                    "context": { "slot": slot },
+                    "value": { "lamports": counter.fetch_add(1, Ordering::SeqCst) },
+                }),
+                _ => serde_json::json!(slot),
+            };
+            let body = serde_json::json!({ "jsonrpc": "2.0", "id": req["id"], "result": result });
+            hyper::Response::new(hyper::Body::from(body.to_string()))
+        })
+        .await;
+        let (privacy_rpc, proxy_url) = crate::tests::start_proxy(
+            crate::Config::builder()
+                .primary_rpc(&url)
+                .poll_chain_tip(Duration::from_millis(200))
+                .cache_account_data(),
+        )
+        .await;
+        // Time is paused: waiting for the poller advances the clock only as far
+        // as its ticks need, not in real time
+        let slot_seen = |expected: u64| {
+            let privacy_rpc = privacy_rpc.clone();
+            async move {
+                while privacy_rpc.cache.current_slot() != Some(expected) {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            }
+        };
+        slot_seen(100).await;
+
+        let client = reqwest::Client::new();
+        let get_account = || async {
+            let body = serde_json::json!({
+                "jsonrpc": "2.0", "id": 1, "method": "getAccountInfo", "params": ["Wallet111"],
+            });
+            let response: serde_json::Value =
+                client.post(&proxy_url).json(&body).send().await.unwrap().json().await.unwrap();
+            response["result"]["value"]["lamports"].as_u64().unwrap()
+        };
+
+        // Within one slot, repeated reads are served from the cache
+        assert_eq!(get_account().await, 0);
+        assert_eq!(get_account().await, 0);
+        assert_eq!(reads.load(Ordering::SeqCst), 1);
+
+        // The poller sees the next slot, so the cached read is dropped
+        slot.store(101, Ordering::SeqCst);
+        tokio::time::advance(Duration::from_millis(200)).await;
+        slot_seen(101).await;
+        assert_eq!(get_account().await, 1);
+        assert_eq!(reads.load(Ordering::SeqCst), 2);
+
+        // Other commitments always go upstream
+        let body = serde_json::json!({
+            "jsonrpc": "2.0", "id": 1, "method": "getAccountInfo",
+            "params": ["Wallet111", { "commitment": "processed" }],
+        });
+        client.post(&proxy_url).json(&body).send().await.unwrap();
+        assert_eq!(reads.load(Ordering::SeqCst), 3);
+
+        privacy_rpc.stop().await;
+    }
+}
@@ -0,0 +1,677 @@
+//! TLS certificate monitoring
+//!
+//! The leaf certificate of each pinned hostname is fetched periodically and
+//! compared with the one seen before. Routine renewals keep the same issuer.
+//! A new issuer from the same CA organization, or over the same key, is
+//! usually the CA moving to another intermediate and raises a Medium
+//! `SuspiciousCertificate` alert. A new key from a different CA means the
+//! connection is likely being intercepted and a Critical `MitmDetected` fires.
+//!
+//! A host can also be pinned to one certificate by its SHA-256 fingerprint;
+//! presenting any other raises `CertificateMismatch`, and requests to that
+//! host fail until it presents the pinned certificate again. Pins are kept in
+//! [`Config::pin_file`] when one is set, and with trust on first use the first
+//! certificate seen from a pinned hostname becomes its pin.
+
+use crate::{Alert, AlertType, Config, Error, RoutingMode, Severity};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+/// Future returned by [`CertificateSource::leaf_certificate`]
+pub type CertificateFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<u8>, String>> + Send + 'a>>;
+
+/// Fetches the DER-encoded leaf certificate a host presents
+pub trait CertificateSource: Send + Sync {
+    fn leaf_certificate<'a>(&'a self, hostname: &'a str) -> CertificateFuture<'a>;
+}
+
+/// Connects to `https://<hostname>/` and reads the certificate from the handshake
+pub struct TlsCertificateSource {
+    client: reqwest::Client,
+}
+
+impl TlsCertificateSource {
+    pub fn new() -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .tls_info(true)
+                .timeout(std::time::Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Connect the way upstream requests do, through `config`'s proxy if it has one
+    pub(crate) fn routed(config: &Config) -> Result<Self, Error> {
+        let mut client = reqwest::Client::builder()
+            .tls_info(true)
+            .timeout(std::time::Duration::from_secs(10));
+        if let Some(proxy) = RoutingMode::for_config(config).proxy()? {
+            client = client.proxy(proxy);
+        }
+        Ok(Self {
+            client: client.build().map_err(|e| Error::ConfigError(e.to_string()))?,
+        })
+    }
+}
+
+impl Default for TlsCertificateSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CertificateSource for TlsCertificateSource {
+    fn leaf_certificate<'a>(&'a self, hostname: &'a str) -> CertificateFuture<'a> {
+        Box::pin(async move {
+            let resp = self
+                .client
+                .head(format!("https://{}/", hostname))
+                .send()
+                .await
+                .map_err(|e| format!("TLS connection to {} failed: {}", hostname, e))?;
+            resp.extensions()
+                .get::<reqwest::tls::TlsInfo>()
+                .and_then(|info| info.peer_certificate())
+                .map(|der| der.to_vec())
+                .ok_or_else(|| format!("{} presented no certificate", hostname))
+        })
+    }
+}
+
+/// A host's certificate as last observed
+#[derive(Debug, Clone, Serialize)]
+pub struct ObservedCertificate {
+    pub hostname: String,
+    /// SHA-256 of the DER certificate, hex encoded
+    pub fingerprint: String,
+    /// SHA-256 of the subject public key info, hex encoded
+    pub public_key: String,
+    pub issuer: String,
+    /// Organization (`O`) of the issuer, naming the CA across its intermediates
+    pub issuer_organization: Option<String>,
+    pub subject: String,
+    /// When this certificate was first seen (ms since epoch)
+    pub first_seen: u64,
+    /// When this certificate was last seen (ms since epoch)
+    pub last_seen: u64,
+}
+
+/// `hostname` must present the certificate with this SHA-256 fingerprint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CertificatePin {
+    pub hostname: String,
+    /// Hex encoded, as in [`ObservedCertificate::fingerprint`]
+    pub sha256: String,
+}
+
+impl CertificatePin {
+    /// Pin `hostname` to `sha256`, given as hex with or without `:` separators
+    pub fn new(hostname: &str, sha256: &str) -> Result<Self, Error> {
+        let sha256 = sha256.replace(':', "").to_ascii_lowercase();
+        if sha256.len() != 64 || !sha256.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(Error::ConfigError(format!("Invalid SHA-256 fingerprint for {}", hostname)));
+        }
+        Ok(Self {
+            hostname: hostname.to_string(),
+            sha256,
+        })
+    }
+}
+
+/// Write `pins` to `path` as JSON, creating its directory if needed
+pub fn save_pins(path: &Path, pins: &[CertificatePin]) -> Result<(), Error> {
+    let write_error = |e: std::io::Error| Error::ConfigError(format!("{}: {}", path.display(), e));
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).map_err(write_error)?;
+    }
+    let json = serde_json::to_string_pretty(pins).map_err(|e| Error::ConfigError(e.to_string()))?;
+    std::fs::write(path, json).map_err(write_error)
+}
+
+/// Read the pins saved at `path`. A missing file is an empty set; an
+/// unreadable one is an error rather than silently unpinning everything.
+pub fn load_pins(path: &Path) -> Result<Vec<CertificatePin>, Error> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(Error::ConfigError(format!("{}: {}", path.display(), e))),
+    };
+    let pins: Vec<CertificatePin> =
+        serde_json::from_str(&contents).map_err(|e| Error::ConfigError(format!("{}: {}", path.display(), e)))?;
+    // Re-validate, in case the file was edited by hand
+    pins.iter().map(|pin| CertificatePin::new(&pin.hostname, &pin.sha256)).collect()
+}
+
+/// How a newly fetched certificate compares with the previous one
+#[derive(Debug)]
+pub(crate) enum Observation {
+    First,
+    Unchanged,
+    /// New certificate from the same issuer (normal renewal)
+    Rotated,
+    /// New certificate from a different issuer. `same_ca` when it kept the
+    /// key or comes from the same CA organization (an intermediate change).
+    IssuerChanged {
+        previous: ObservedCertificate,
+        same_ca: bool,
+    },
+}
+
+/// Last certificate observed for each pinned hostname, and the pin set
+#[derive(Default)]
+pub(crate) struct CertificateTracker {
+    observed: Mutex<HashMap<String, ObservedCertificate>>,
+    pins: Mutex<HashMap<String, String>>,
+}
+
+impl CertificateTracker {
+    pub(crate) fn pins(&self) -> Vec<CertificatePin> {
+        let pins = self.pins.lock().unwrap_or_else(|e| e.into_inner());
+        let mut pins: Vec<_> = pins
+            .iter()
+            .map(|(hostname, sha256)| CertificatePin {
+                hostname: hostname.clone(),
+                sha256: sha256.clone(),
+            })
+            .collect();
+        pins.sort_by(|a, b| a.hostname.cmp(&b.hostname));
+        pins
+    }
+
+    pub(crate) fn replace_pins(&self, pins: Vec<CertificatePin>) {
+        *self.pins.lock().unwrap_or_else(|e| e.into_inner()) =
+            pins.into_iter().map(|pin| (pin.hostname, pin.sha256)).collect();
+    }
+
+    /// Add or replace the pin for `pin.hostname`, saving the set to
+    /// [`Config::pin_file`] if one is configured
+    pub(crate) fn pin(&self, config: &Config, pin: CertificatePin) -> Result<(), Error> {
+        self.pins
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(pin.hostname, pin.sha256);
+        self.persist(config)
+    }
+
+    /// Remove the pin for `hostname`, returning whether there was one
+    pub(crate) fn unpin(&self, config: &Config, hostname: &str) -> Result<bool, Error> {
+        let removed = self.pins.lock().unwrap_or_else(|e| e.into_inner()).remove(hostname).is_some();
+        if removed {
+            self.persist(config)?;
+        }
+        Ok(removed)
+    }
+
+    fn persist(&self, config: &Config) -> Result<(), Error> {
+        match &config.pin_file {
+            Some(path) => save_pins(path, &self.pins()),
+            None => Ok(()),
+        }
+    }
+
+    fn pinned_fingerprint(&self, hostname: &str) -> Option<String> {
+        self.pins.lock().unwrap_or_else(|e| e.into_inner()).get(hostname).cloned()
+    }
+
+    /// Whether `hostname` last presented a certificate other than its pin
+    pub(crate) fn mismatched(&self, hostname: &str) -> bool {
+        match (self.pinned_fingerprint(hostname), self.get(hostname)) {
+            (Some(pinned), Some(current)) => pinned != current.fingerprint,
+            _ => false,
+        }
+    }
+
+    /// Record `der` as the certificate `hostname` now presents
+    pub(crate) fn observe(&self, hostname: &str, der: &[u8]) -> Result<Observation, String> {
+        let (_, cert) = x509_parser::parse_x509_certificate(der)
+            .map_err(|e| format!("Invalid certificate from {}: {}", hostname, e))?;
+        let fingerprint = hex::encode(Sha256::digest(der));
+        let now = chrono::Utc::now().timestamp_millis() as u64;
+        let current = ObservedCertificate {
+            hostname: hostname.to_string(),
+            fingerprint,
+            public_key: hex::encode(Sha256::digest(cert.public_key().raw)),
+            issuer: cert.issuer().to_string(),
+            issuer_organization: cert
+                .issuer()
+                .iter_organization()
+                .next()
+                .and_then(|o| o.as_str().ok())
+                .map(str::to_string),
+            subject: cert.subject().to_string(),
+            first_seen: now,
+            last_seen: now,
+        };
+
+        let mut observed = self.observed.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(previous) = observed.get_mut(hostname) else {
+            observed.insert(hostname.to_string(), current);
+            return Ok(Observation::First);
+        };
+        if previous.fingerprint == current.fingerprint {
+            previous.last_seen = now;
+            return Ok(Observation::Unchanged);
+        }
+
+        let issuer_changed = previous.issuer != current.issuer;
+        let same_ca = previous.public_key == current.public_key
+            || (previous.issuer_organization.is_some() && previous.issuer_organization == current.issuer_organization);
+        let previous = std::mem::replace(previous, current);
+        Ok(if issuer_changed {
+            Observation::IssuerChanged { previous, same_ca }
+        } else {
+            Observation::Rotated
+        })
+    }
+
+    pub(crate) fn snapshot(&self) -> Vec<ObservedCertificate> {
+        let observed = self.observed.lock().unwrap_or_else(|e| e.into_inner());
+        let mut certs: Vec<_> = observed.values().cloned().collect();
+        certs.sort_by(|a, b| a.hostname.cmp(&b.hostname));
+        certs
+    }
+
+    fn get(&self, hostname: &str) -> Option<ObservedCertificate> {
+        let observed = self.observed.lock().unwrap_or_else(|e| e.into_inner());
+        observed.get(hostname).cloned()
+    }
+}
+
+/// Check every pinned hostname once, alerting when a certificate doesn't
+/// match its pin or the issuer changes
+pub(crate) async fn check_pinned_certificates(config: &Config, tracker: &CertificateTracker) {
+    let source: Arc<dyn CertificateSource> = match &config.certificate_source {
+        Some(source) => source.clone(),
+        // Never connect around the configured proxy
+        None => match TlsCertificateSource::routed(config) {
+            Ok(source) => Arc::new(source),
+            Err(_) => return,
+        },
+    };
+
+    let mut hostnames = config.pinned_endpoints.clone();
+    for pin in tracker.pins() {
+        if !hostnames.contains(&pin.hostname) {
+            hostnames.push(pin.hostname);
+        }
+    }
+    for hostname in &hostnames {
+        // Connection failures are not evidence of interception; try again next interval
+        let Ok(der) = source.leaf_certificate(hostname).await else {
+            continue;
+        };
+        let Ok(observation) = tracker.observe(hostname, &der) else {
+            continue;
+        };
+        let Some(current) = tracker.get(hostname) else {
+            continue;
+        };
+
+        match tracker.pinned_fingerprint(hostname) {
+            Some(pinned) if pinned != current.fingerprint => {
+                if let Some(handler) = &config.alert_handler {
+                    let mut details = HashMap::new();
+                    details.insert("pinned_fingerprint".to_string(), pinned);
+                    details.insert("fingerprint".to_string(), current.fingerprint);
+                    details.insert("issuer".to_string(), current.issuer);
+                    handler(Alert {
+                        alert_type: AlertType::CertificateMismatch,
+                        severity: Severity::Critical,
+                        message: format!("Certificate for {} does not match its pin", hostname),
+                        hostname: Some(hostname.clone()),
+                        details: Some(details),
+                        timestamp: chrono::Utc::now().timestamp_millis() as u64,
+                    });
+                }
+                continue;
+            }
+            Some(_) => continue,
+            None if config.trust_on_first_use => {
+                let learned = CertificatePin {
+                    hostname: hostname.clone(),
+                    sha256: current.fingerprint.clone(),
+                };
+                if let (Err(e), Some(handler)) = (tracker.pin(config, learned), &config.alert_handler) {
+                    handler(Alert {
+                        alert_type: AlertType::ProxyError,
+                        severity: Severity::Medium,
+                        message: format!("Could not save the learned pin for {}: {}", hostname, e),
+                        hostname: Some(hostname.clone()),
+                        details: None,
+                        timestamp: chrono::Utc::now().timestamp_millis() as u64,
+                    });
+                }
+                continue;
+            }
+            None => {}
+        }
+
+        let Observation::IssuerChanged { previous, same_ca } = observation else {
+            continue;
+        };
+        if let Some(handler) = &config.alert_handler {
+            let mut details = HashMap::new();
+            details.insert("previous_fingerprint".to_string(), previous.fingerprint);
+            details.insert("previous_issuer".to_string(), previous.issuer);
+            details.insert("fingerprint".to_string(), current.fingerprint);
+            details.insert("issuer".to_string(), current.issuer);
+
+            let (alert_type, severity, message) = if same_ca {
+                (
+                    AlertType::SuspiciousCertificate,
+                    Severity::Medium,
+                    format!("Certificate for {} is now issued by another intermediate of the same CA", hostname),
+                )
+            } else {
+                (
+                    AlertType::MitmDetected,
+                    Severity::Critical,
+                    format!(
+                        "Certificate for {} is now issued by a different CA - connection may be intercepted",
+                        hostname
+                    ),
+                )
+            };
+            handler(Alert {
+                alert_type,
+                severity,
+                message,
+                hostname: Some(hostname.clone()),
+                details: Some(details),
+                timestamp: chrono::Utc::now().timestamp_millis() as u64,
+            });
+        }
+    }
+}
+
+/// Re-check pinned certificates every `cert_check_interval` until aborted
+pub(crate) async fn monitor(config: Config, tracker: Arc<CertificateTracker>) {
+    loop {
+        check_pinned_certificates(&config, &tracker).await;
+        tokio::time::sleep(config.cert_check_interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Self-signed certificate for `hostname` whose issuer is `ca_name`
+    fn certificate(hostname: &str, ca_name: &str) -> Vec<u8> {
+        certificate_with(hostname, ca_name, "", &rcgen::KeyPair::generate().unwrap())
+    }
+
+    /// Self-signed certificate for `hostname` over `key`, whose issuer is the
+    /// organization `ca_name` with common name `intermediate`
+    fn certificate_with(hostname: &str, ca_name: &str, intermediate: &str, key: &rcgen::KeyPair) -> Vec<u8> {
+        let mut params = rcgen::CertificateParams::new(vec![hostname.to_string()]).unwrap();
+        params
+            .distinguished_name
+            .push(rcgen::DnType::OrganizationName, ca_name);
+        params.distinguished_name.push(rcgen::DnType::CommonName, intermediate);
+        params.self_signed(key).unwrap().der().to_vec()
+    }
+
+    /// Source serving whatever certificate the test last set
+    struct StubSource(Mutex<Vec<u8>>);
+
+    impl CertificateSource for StubSource {
+        fn leaf_certificate<'a>(&'a self, _hostname: &'a str) -> CertificateFuture<'a> {
+            let der = self.0.lock().unwrap().clone();
+            Box::pin(async move { Ok(der) })
+        }
+    }
+
+    #[test]
+    fn test_rotation_keeps_issuer() {
+        let tracker = CertificateTracker::default();
+        let host = "rpc.example.com";
+
+        assert!(matches!(tracker.observe(host, &certificate(host, "Good CA")), Ok(Observation::First)));
+        let renewed = certificate(host, "Good CA");
+        assert!(matches!(tracker.observe(host, &renewed), Ok(Observation::Rotated)));
+        assert!(matches!(tracker.observe(host, &renewed), Ok(Observation::Unchanged)));
+    }
+
+    #[tokio::test]
+    async fn test_issuer_change_fires_mitm_alert() {
+        let host = "rpc.example.com";
+        let source = Arc::new(StubSource(Mutex::new(certificate(host, "Good CA"))));
+        let alerts = Arc::new(Mutex::new(Vec::new()));
+        let sink = alerts.clone();
+        let config = Config::builder()
+            .pin_endpoint(host)
+            .certificate_source(source.clone())
+            .on_alert(move |alert| sink.lock().unwrap().push(alert))
+            .build();
+        let tracker = CertificateTracker::default();
+
+        check_pinned_certificates(&config, &tracker).await;
+        let first = tracker.snapshot()[0].fingerprint.clone();
+        assert!(alerts.lock().unwrap().is_empty());
+
+        *source.0.lock().unwrap() = certificate(host, "Evil Proxy CA");
+        check_pinned_certificates(&config, &tracker).await;
+
+        let alerts = alerts.lock().unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert!(matches!(alerts[0].alert_type, AlertType::MitmDetected));
+        assert!(matches!(alerts[0].severity, Severity::Critical));
+        let details = alerts[0].details.as_ref().unwrap();
+        assert_eq!(details["previous_fingerprint"], first);
+        assert!(details["issuer"].contains("Evil Proxy CA"));
+        assert_ne!(tracker.snapshot()[0].fingerprint, first);
+    }
+
+    #[tokio::test]
+    async fn test_intermediate_change_is_a_warning() {
+        let host = "rpc.example.com";
+        let key = rcgen::KeyPair::generate().unwrap();
+        let source = Arc::new(StubSource(Mutex::new(certificate_with(host, "Good CA", "R10", &key))));
+        let alerts = Arc::new(Mutex::new(Vec::new()));
+        let sink = alerts.clone();
+        let config = Config::builder()
+            .pin_endpoint(host)
+            .certificate_source(source.clone())
+            .on_alert(move |alert| sink.lock().unwrap().push(alert))
+            .build();
+        let tracker = CertificateTracker::default();
+        check_pinned_certificates(&config, &tracker).await;
+
+        // Another intermediate of the same CA, with a new key
+        *source.0.lock().unwrap() = certificate_with(host, "Good CA", "R11", &rcgen::KeyPair::generate().unwrap());
+        check_pinned_certificates(&config, &tracker).await;
+        // A different CA name over the key already seen
+        let key = rcgen::KeyPair::generate().unwrap();
+        *source.0.lock().unwrap() = certificate_with(host, "Good CA", "R12", &key);
+        check_pinned_certificates(&config, &tracker).await;
+        *source.0.lock().unwrap() = certificate_with(host, "Renamed CA", "E1", &key);
+        check_pinned_certificates(&config, &tracker).await;
+
+        let alerts = alerts.lock().unwrap();
+        assert_eq!(alerts.len(), 3);
+        for alert in alerts.iter() {
+            assert!(matches!(alert.alert_type, AlertType::SuspiciousCertificate));
+            assert!(matches!(alert.severity, Severity::Medium));
+        }
+    }
+
+    fn temp_pin_file(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir()
+            .join(format!("privacyrpc-pins-{}-{}", name, std::process::id()))
+            .join("pins.json")
+    }
+
+    #[tokio::test]
+    async fn test_loaded_pin_is_enforced() {
+        let host = "rpc.example.com";
+        let der = certificate(host, "Good CA");
+        let path = temp_pin_file("enforced");
+        let pins = vec![CertificatePin::new(host, &hex::encode(Sha256::digest(&der)).to_uppercase()).unwrap()];
+        save_pins(&path, &pins).unwrap();
+        assert_eq!(load_pins(&path).unwrap(), pins);
+        assert!(CertificatePin::new(host, "not-a-fingerprint").is_err());
+
+        let source = Arc::new(StubSource(Mutex::new(der)));
+        let alerts = Arc::new(Mutex::new(Vec::new()));
+        let sink = alerts.clone();
+        // Pinned by the file alone, without `pin_endpoint`
+        let config = Config::builder()
+            .pin_file(&path)
+            .certificate_source(source.clone())
+            .on_alert(move |alert| sink.lock().unwrap().push(alert))
+            .build();
+        let tracker = CertificateTracker::default();
+        tracker.replace_pins(load_pins(&path).unwrap());
+
+        check_pinned_certificates(&config, &tracker).await;
+        assert!(alerts.lock().unwrap().is_empty());
+
+        // Same issuer, so only the pin catches it
+        *source.0.lock().unwrap() = certificate(host, "Good CA");
+        check_pinned_certificates(&config, &tracker).await;
+        let alerts = alerts.lock().unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert!(matches!(alerts[0].alert_type, AlertType::CertificateMismatch));
+        assert!(matches!(alerts[0].severity, Severity::Critical));
+        assert_eq!(alerts[0].details.as_ref().unwrap()["pinned_fingerprint"], pins[0].sha256);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_mismatched_pin_fails_requests() {
+        let upstream = crate::tests::mock_upstream(|_, req| {
+            let body = serde_json::json!({ "jsonrpc": "2.0", "id": req["id"], "result": 42 });
+            hyper::Response::new(hyper::Body::from(body.to_string()))
+        })
+        .await;
+        let host = "127.0.0.1";
+        let presented = certificate(host, "Good CA");
+        let privacy_rpc = crate::PrivacyRPC::new(
+            Config::builder()
+                .primary_rpc(&upstream)
+                .no_default_fallback()
+                .certificate_source(Arc::new(StubSource(Mutex::new(presented.clone()))))
+                .build(),
+        );
+        privacy_rpc.add_certificate_pin(CertificatePin::new(host, &"ab".repeat(32)).unwrap()).unwrap();
+
+        check_pinned_certificates(&privacy_rpc.config, &privacy_rpc.certificates).await;
+        let response = privacy_rpc.forward_request(crate::tests::rpc_request("getSlot")).await.unwrap();
+        assert!(response.result.is_none());
+        assert!(response.error.unwrap().message.contains("does not match its pin"));
+
+        // Pinning the certificate actually presented lets requests through again
+        let pin = CertificatePin::new(host, &hex::encode(Sha256::digest(&presented))).unwrap();
+        privacy_rpc.add_certificate_pin(pin).unwrap();
+        let response = privacy_rpc.forward_request(crate::tests::rpc_request("getSlot")).await.unwrap();
+        assert_eq!(response.result, Some(serde_json::json!(42)));
+    }
+
+    #[tokio::test]
+    async fn test_trust_on_first_use_saves_pin() {
+        let host = "rpc.example.com";
+        let der = certificate(host, "Good CA");
+        let path = temp_pin_file("tofu");
+        let config = Config::builder()
+            .pin_endpoint(host)
+            .pin_file(&path)
+            .trust_on_first_use()
+            .certificate_source(Arc::new(StubSource(Mutex::new(der.clone()))))
+            .build();
+
+        check_pinned_certificates(&config, &CertificateTracker::default()).await;
+        let saved = load_pins(&path).unwrap();
+        assert_eq!(saved, vec![CertificatePin::new(host, &hex::encode(Sha256::digest(&der))).unwrap()]);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_pins_control_endpoint() {
+        let path = temp_pin_file("control");
+        let existing = CertificatePin::new("a.example.com", &"ab".repeat(32)).unwrap();
+        save_pins(&path, std::slice::from_ref(&existing)).unwrap();
+        let (privacy_rpc, url) = crate::tests::start_proxy(
+            Config::builder().pin_file(&path).cert_check_interval(std::time::Duration::ZERO),
+        )
+        .await;
+        // Loaded on start
+        assert_eq!(privacy_rpc.certificate_pins(), vec![existing.clone()]);
+
+        let client = reqwest::Client::new();
+        let added = CertificatePin::new("b.example.com", &"cd".repeat(32)).unwrap();
+        let response = client
+            .post(format!("{}/pins", url))
+            .json(&added)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(load_pins(&path).unwrap(), vec![existing.clone(), added.clone()]);
+
+        // Browsers can read but not change them
+        let response = client
+            .delete(format!("{}/pins", url))
+            .header("Origin", "https://evil.example")
+            .json(&serde_json::json!({"hostname": "a.example.com"}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 403);
+
+        let response = client
+            .post(format!("{}/pins", url))
+            .body(vec![b' '; 64 * 1024])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 413);
+
+        // Nor from other machines
+        let remote = hyper::Request::post("/pins")
+            .body(hyper::Body::from(serde_json::json!({"hostname": "a.example.com"}).to_string()))
+            .unwrap();
+        let response = crate::handle_request(remote, privacy_rpc.server_context(), false).await.unwrap();
+        assert_eq!(response.status(), 403);
+        assert_eq!(privacy_rpc.certificate_pins().len(), 2);
+
+        let response = client
+            .delete(format!("{}/pins", url))
+            .json(&serde_json::json!({"hostname": "a.example.com"}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let listed: serde_json::Value = client.get(format!("{}/pins", url)).send().await.unwrap().json().await.unwrap();
+        assert_eq!(listed["pins"], serde_json::json!([added]));
+        assert_eq!(load_pins(&path).unwrap(), vec![added]);
+
+        privacy_rpc.stop().await;
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_certificate_fetch_goes_through_proxy() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorded = seen.clone();
+        let proxy = crate::tests::mock_upstream(move |headers, _| {
+            let host = headers.get("host").and_then(|v| v.to_str().ok()).unwrap_or_default();
+            recorded.lock().unwrap().push(host.to_string());
+            let mut resp = hyper::Response::new(hyper::Body::empty());
+            *resp.status_mut() = hyper::StatusCode::BAD_GATEWAY;
+            resp
+        })
+        .await;
+        let config = Config::builder().upstream_proxy(&proxy).build();
+
+        let source = TlsCertificateSource::routed(&config).unwrap();
+        assert!(source.leaf_certificate("rpc.example.com").await.is_err());
+        assert_eq!(*seen.lock().unwrap(), vec!["rpc.example.com:443"]);
+    }
+}
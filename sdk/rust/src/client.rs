@@ -0,0 +1,334 @@
+//! Typed helpers for common Solana RPC calls
+//!
+//! [`SolanaClient`] builds each request, sends it through [`PrivacyRPC`] so
+//! caching, failover and the other protections still apply, and parses the
+//! `result` into a plain Rust type.
+
+use crate::transaction::{self, TransactionWarning, WarningLevel};
+use crate::{Commitment, Error, PrivacyRPC, RpcRequest};
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// First wait between `getSignatureStatuses` polls; it grows by half each time
+const CONFIRM_POLL_START: Duration = Duration::from_millis(250);
+const CONFIRM_POLL_MAX: Duration = Duration::from_secs(2);
+
+/// A quoted fee more than this many times the local estimate (or less than
+/// its share) is flagged
+const FEE_DIVERGENCE_FACTOR: u64 = 2;
+
+/// The cluster's fee for a message next to the decoder's own estimate
+#[derive(Debug, Clone)]
+pub struct FeeQuote {
+    /// From `getFeeForMessage`: what the transaction will be charged
+    pub lamports: u64,
+    /// From decoding the message locally, if it could be decoded
+    pub local_estimate: Option<u64>,
+    /// Set when the two are far apart, which can mean unexpected signers or
+    /// compute usage the decoder didn't see
+    pub warning: Option<TransactionWarning>,
+}
+
+/// A transaction's status, as reported by `getSignatureStatuses`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignatureStatus {
+    pub slot: u64,
+    /// `None` once the block is rooted
+    pub confirmations: Option<u64>,
+    /// Why the transaction failed, if it did
+    pub err: Option<serde_json::Value>,
+    pub confirmation_status: Option<Commitment>,
+}
+
+impl SignatureStatus {
+    /// Commitment the transaction has reached. Nodes that leave out
+    /// `confirmationStatus` signal a rooted block with `confirmations: null`.
+    pub fn commitment(&self) -> Commitment {
+        match (self.confirmation_status, self.confirmations) {
+            (Some(commitment), _) => commitment,
+            (None, None) => Commitment::Finalized,
+            (None, Some(_)) => Commitment::Processed,
+        }
+    }
+}
+
+/// Convenience client for the most common Solana RPC methods
+pub struct SolanaClient {
+    rpc: Arc<PrivacyRPC>,
+}
+
+impl SolanaClient {
+    pub fn new(rpc: Arc<PrivacyRPC>) -> Self {
+        Self { rpc }
+    }
+
+    /// Balance of `pubkey` in lamports
+    pub async fn get_balance(&self, pubkey: &str) -> Result<u64, Error> {
+        let result = self.call("getBalance", Some(serde_json::json!([pubkey]))).await?;
+        result["value"]
+            .as_u64()
+            .ok_or_else(|| unexpected("getBalance", &result))
+    }
+
+    /// Latest blockhash, base58-encoded
+    pub async fn get_latest_blockhash(&self) -> Result<String, Error> {
+        let result = self.call("getLatestBlockhash", None).await?;
+        result["value"]["blockhash"]
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| unexpected("getLatestBlockhash", &result))
+    }
+
+    pub async fn get_slot(&self) -> Result<u64, Error> {
+        let result = self.call("getSlot", None).await?;
+        result.as_u64().ok_or_else(|| unexpected("getSlot", &result))
+    }
+
+    /// Poll `getSignatureStatuses` until `signature` reaches `commitment`,
+    /// backing off between polls, and return its status. A transaction that
+    /// failed on chain is returned as soon as it's seen, with `err` set.
+    /// Poll errors are retried until `timeout`; each poll fails over like any
+    /// other request.
+    pub async fn confirm_signature(
+        &self,
+        signature: &str,
+        commitment: Commitment,
+        timeout: Duration,
+    ) -> Result<SignatureStatus, Error> {
+        let deadline = Instant::now() + timeout;
+        let mut wait = CONFIRM_POLL_START;
+        let mut last_error = None;
+        loop {
+            let params = serde_json::json!([[signature], { "searchTransactionHistory": false }]);
+            match self.call("getSignatureStatuses", Some(params)).await {
+                Ok(result) => {
+                    let status = serde_json::from_value::<Option<SignatureStatus>>(result["value"][0].clone())
+                        .map_err(|_| unexpected("getSignatureStatuses", &result))?;
+                    if let Some(status) = status.filter(|s| s.err.is_some() || s.commitment() >= commitment) {
+                        return Ok(status);
+                    }
+                }
+                Err(e) => last_error = Some(e),
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(last_error.unwrap_or_else(|| {
+                    Error::RpcError(format!(
+                        "Signature {} not {} after {:?}",
+                        signature,
+                        commitment.as_str(),
+                        timeout
+                    ))
+                }));
+            }
+            tokio::time::sleep(wait.min(remaining)).await;
+            wait = (wait * 3 / 2).min(CONFIRM_POLL_MAX);
+        }
+    }
+
+    /// Fee in lamports the cluster would charge for `message_base64` (a
+    /// serialized, unsigned message), from `getFeeForMessage`. Fails if the
+    /// message's blockhash has expired.
+    pub async fn estimate_fee(&self, message_base64: &str) -> Result<u64, Error> {
+        let result = self
+            .call("getFeeForMessage", Some(serde_json::json!([message_base64])))
+            .await?;
+        match &result["value"] {
+            serde_json::Value::Null => Err(Error::RpcError(
+                "getFeeForMessage returned no fee; the blockhash may have expired".to_string(),
+            )),
+            value => value.as_u64().ok_or_else(|| unexpected("getFeeForMessage", &result)),
+        }
+    }
+
+    /// [`estimate_fee`](Self::estimate_fee), cross-checked against the fee
+    /// the decoder works out locally from the message's signers and Compute
+    /// Budget instructions
+    pub async fn quote_fee(&self, message_base64: &str) -> Result<FeeQuote, Error> {
+        let lamports = self.estimate_fee(message_base64).await?;
+        let local_estimate = transaction::decode_message(message_base64)
+            .ok()
+            .map(|decoded| decoded.fee_lamports());
+        let warning = local_estimate
+            .filter(|&local| {
+                lamports > local.saturating_mul(FEE_DIVERGENCE_FACTOR)
+                    || local > lamports.saturating_mul(FEE_DIVERGENCE_FACTOR)
+            })
+            .map(|local| TransactionWarning {
+                level: WarningLevel::Warning,
+                title: "Unexpected fee".to_string(),
+                message: format!(
+                    "The network quotes {} lamports but the transaction decodes to about {}; \
+                     it may have signers or compute usage that isn't visible",
+                    lamports, local
+                ),
+            });
+        Ok(FeeQuote {
+            lamports,
+            local_estimate,
+            warning,
+        })
+    }
+
+    /// Send `method` and return its `result`, turning a JSON-RPC error into [`Error::RpcError`]
+    pub async fn call(&self, method: &str, params: Option<serde_json::Value>) -> Result<serde_json::Value, Error> {
+        let response = self
+            .rpc
+            .forward_request(RpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: Some(serde_json::json!(1)),
+                method: method.to_string(),
+                params,
+            })
+            .await?;
+        if let Some(error) = response.error {
+            return Err(Error::RpcError(format!("{} failed ({}): {}", method, error.code, error.message)));
+        }
+        response
+            .result
+            .ok_or_else(|| Error::RpcError(format!("{} returned no result", method)))
+    }
+}
+
+fn unexpected(method: &str, result: &serde_json::Value) -> Error {
+    Error::RpcError(format!("{} returned an unexpected result: {}", method, result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::mock_upstream;
+    use crate::Config;
+
+    /// Client whose upstream answers each method with `result(method)`
+    async fn client(result: fn(&str) -> serde_json::Value) -> SolanaClient {
+        let url = mock_upstream(move |_, req| {
+            let body = serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": req["id"],
+                "result": result(req["method"].as_str().unwrap_or_default()),
+            });
+            hyper::Response::new(hyper::Body::from(body.to_string()))
+        })
+        .await;
+        SolanaClient::new(Arc::new(PrivacyRPC::new(Config::builder().primary_rpc(&url).build())))
+    }
+
+    #[tokio::test]
+    async fn test_typed_helpers_parse_results() {
+        let client = client(|method| match method {
+            "getBalance" => serde_json::json!({ "context": { "slot": 1 }, "value": 5_000_000_000u64 }),
+            "getLatestBlockhash" => serde_json::json!({
+                "context": { "slot": 1 },
+                "value": { "blockhash": "EkSnNWid2cvwEVnVx9aBqawnmiCNiDgp3gUdkDPTKN1N", "lastValidBlockHeight": 3090 }
+            }),
+            "getSlot" => serde_json::json!(287_654_321u64),
+            _ => serde_json::Value::Null,
+        })
+        .await;
+
+        assert_eq!(client.get_balance("11111111111111111111111111111112").await.unwrap(), 5_000_000_000);
+        assert_eq!(
+            client.get_latest_blockhash().await.unwrap(),
+            "EkSnNWid2cvwEVnVx9aBqawnmiCNiDgp3gUdkDPTKN1N"
+        );
+        assert_eq!(client.get_slot().await.unwrap(), 287_654_321);
+    }
+
+    #[tokio::test]
+    async fn test_rpc_error_and_bad_result_are_errors() {
+        let url = mock_upstream(|_, req| {
+            let body = serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": req["id"],
+                "error": { "code": -32602, "message": "Invalid param: WrongSize" },
+            });
+            hyper::Response::new(hyper::Body::from(body.to_string()))
+        })
+        .await;
+        let failing = SolanaClient::new(Arc::new(PrivacyRPC::new(Config::builder().primary_rpc(&url).build())));
+        match failing.get_balance("bad").await {
+            Err(Error::RpcError(message)) => assert!(message.contains("WrongSize")),
+            other => panic!("expected an RPC error, got {:?}", other.map_err(|e| e.to_string())),
+        }
+
+        let wrong_shape = client(|_| serde_json::json!("not a number")).await;
+        assert!(matches!(wrong_shape.get_slot().await, Err(Error::RpcError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_confirm_signature_waits_for_commitment() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // "processed" on the first poll, "confirmed" after
+        let polls = Arc::new(AtomicUsize::new(0));
+        let counter = polls.clone();
+        let url = mock_upstream(move |_, req| {
+            let status = match counter.fetch_add(1, Ordering::SeqCst) {
+                0 => "processed",
+                _ => "confirmed",
+            };
+            let body = serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": req["id"],
+                "result": {
+                    "context": { "slot": 100 },
+                    "value": [{ "slot": 99, "confirmations": 1, "err": null, "confirmationStatus": status }],
+                },
+            });
+            hyper::Response::new(hyper::Body::from(body.to_string()))
+        })
+        .await;
+        let client = SolanaClient::new(Arc::new(PrivacyRPC::new(Config::builder().primary_rpc(&url).build())));
+
+        let status = client
+            .confirm_signature("5sig", Commitment::Confirmed, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(status.confirmation_status, Some(Commitment::Confirmed));
+        assert_eq!(status.slot, 99);
+        assert_eq!(polls.load(Ordering::SeqCst), 2);
+
+        // Never finalized within the timeout
+        let err = client
+            .confirm_signature("5sig", Commitment::Finalized, Duration::from_millis(300))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("not finalized"), "{}", err);
+    }
+
+    /// Message with one signer and no instructions, so a local fee of 5000 lamports
+    fn fee_payer_message() -> String {
+        use base64::Engine;
+        let mut message = vec![1u8, 0, 0, 1];
+        message.extend([7u8; 32]);
+        message.extend([9u8; 32]);
+        message.push(0);
+        base64::engine::general_purpose::STANDARD.encode(message)
+    }
+
+    #[tokio::test]
+    async fn test_fee_quote_from_get_fee_for_message() {
+        let matching = client(|method| match method {
+            "getFeeForMessage" => serde_json::json!({ "context": { "slot": 1 }, "value": 5000 }),
+            _ => serde_json::Value::Null,
+        })
+        .await;
+        assert_eq!(matching.estimate_fee(&fee_payer_message()).await.unwrap(), 5000);
+        let quote = matching.quote_fee(&fee_payer_message()).await.unwrap();
+        assert_eq!(quote.local_estimate, Some(5000));
+        assert!(quote.warning.is_none());
+
+        // Far more than the one visible signature accounts for
+        let diverging = client(|_| serde_json::json!({ "context": { "slot": 1 }, "value": 45000 })).await;
+        let quote = diverging.quote_fee(&fee_payer_message()).await.unwrap();
+        assert_eq!(quote.lamports, 45000);
+        assert!(quote.warning.unwrap().message.contains("45000"));
+
+        let expired = client(|_| serde_json::json!({ "context": { "slot": 1 }, "value": null })).await;
+        assert!(matches!(expired.estimate_fee(&fee_payer_message()).await, Err(Error::RpcError(_))));
+    }
+}
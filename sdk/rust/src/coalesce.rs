@@ -0,0 +1,159 @@
+//! `getAccountInfo` coalescing
+//!
+//! dApps often fire a burst of individual `getAccountInfo` calls. Calls with
+//! the same options (commitment, encoding, ...) arriving within the configured
+//! window are sent upstream as one `getMultipleAccounts`, and each caller gets
+//! its own slice of the result.
+
+use crate::{forward_unscrubbed, next_request_id, RpcError, RpcRequest, RpcResponse, ServerContext, UpstreamAttempt};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tokio::sync::oneshot;
+
+/// Most accounts `getMultipleAccounts` accepts in one call
+const MAX_ACCOUNTS: usize = 100;
+
+/// What each caller receives: its response, and the upstream attempt it shared
+type Outcome = (RpcResponse, UpstreamAttempt);
+
+struct Waiter {
+    id: Option<serde_json::Value>,
+    pubkey: String,
+    reply: oneshot::Sender<Outcome>,
+}
+
+/// Callers waiting for the next upstream call with the same options
+struct Group {
+    generation: u64,
+    waiters: Vec<Waiter>,
+}
+
+#[derive(Default)]
+pub(crate) struct AccountInfoBatcher {
+    /// Keyed by the serialized options object
+    pending: Mutex<HashMap<String, Group>>,
+    next_generation: AtomicU64,
+}
+
+impl AccountInfoBatcher {
+    /// Queue a `getAccountInfo` request to be sent with others arriving within
+    /// `account_info_window`. Returns `None` if the request can't be coalesced
+    /// and must be sent as is, including when its pubkey is malformed, so an
+    /// upstream "Invalid param" error only reaches the caller who caused it.
+    pub(crate) fn submit(&self, ctx: &ServerContext, request: &RpcRequest) -> Option<oneshot::Receiver<Outcome>> {
+        let params = request.params.as_ref()?.as_array()?;
+        let pubkey = params.first()?.as_str().filter(|key| is_pubkey(key))?.to_string();
+        let options = match params.get(1) {
+            Some(options) if options.is_object() => options.clone(),
+            None => serde_json::Value::Null,
+            Some(_) => return None,
+        };
+        if params.len() > 2 {
+            return None;
+        }
+
+        let (reply, receiver) = oneshot::channel();
+        let waiter = Waiter { id: request.id.clone(), pubkey, reply };
+        let key = options.to_string();
+
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        match pending.get_mut(&key) {
+            Some(group) => {
+                group.waiters.push(waiter);
+                // Full: send now rather than waiting out the window
+                if group.waiters.len() >= MAX_ACCOUNTS {
+                    if let Some(group) = pending.remove(&key) {
+                        tokio::spawn(flush(ctx.clone(), options, group.waiters));
+                    }
+                }
+            }
+            None => {
+                let generation = self.next_generation.fetch_add(1, Ordering::Relaxed);
+                pending.insert(key.clone(), Group { generation, waiters: vec![waiter] });
+
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(ctx.config.account_info_window).await;
+                    let group = {
+                        let mut pending = ctx.coalescer.pending.lock().unwrap_or_else(|e| e.into_inner());
+                        // The group may have been flushed early for being full
+                        match pending.get(&key) {
+                            Some(group) if group.generation == generation => pending.remove(&key),
+                            _ => None,
+                        }
+                    };
+                    if let Some(group) = group {
+                        flush(ctx, options, group.waiters).await;
+                    }
+                });
+            }
+        }
+        Some(receiver)
+    }
+}
+
+/// Send one `getMultipleAccounts` for `waiters` and hand each its account
+async fn flush(ctx: ServerContext, options: serde_json::Value, waiters: Vec<Waiter>) {
+    let pubkeys: Vec<&str> = waiters.iter().map(|w| w.pubkey.as_str()).collect();
+    let mut params = vec![serde_json::json!(pubkeys)];
+    if !options.is_null() {
+        params.push(options);
+    }
+    let request = RpcRequest {
+        jsonrpc: "2.0".to_string(),
+        id: Some(serde_json::json!(1)),
+        method: "getMultipleAccounts".to_string(),
+        params: Some(serde_json::Value::Array(params)),
+    };
+
+    let mut attempt = UpstreamAttempt::default();
+    let result = forward_unscrubbed(
+        &ctx.config,
+        &ctx.endpoints,
+        &ctx.upstream,
+        &request,
+        &next_request_id(),
+        &mut attempt,
+    )
+    .await;
+
+    let count = waiters.len();
+    for (i, waiter) in waiters.into_iter().enumerate() {
+        let mut response = match &result {
+            Ok(RpcResponse { error: Some(error), .. }) => RpcResponse::error(waiter.id, error.clone()),
+            // A missing entry would read as "account does not exist"
+            Ok(RpcResponse { result: Some(result), .. }) => match result["value"].as_array() {
+                Some(values) if values.len() == count => RpcResponse::success(
+                    waiter.id,
+                    serde_json::json!({
+                        "context": result["context"],
+                        "value": values[i],
+                    }),
+                ),
+                _ => RpcResponse::error(
+                    waiter.id,
+                    server_error("getMultipleAccounts returned the wrong number of accounts"),
+                ),
+            },
+            Ok(_) => RpcResponse::error(waiter.id, server_error("getMultipleAccounts returned no result")),
+            Err(e) => RpcResponse::error(waiter.id, server_error(&e.to_string())),
+        };
+        // The merged request is internal; only the caller's method's rules apply
+        crate::scrub::scrub(&ctx.config.scrub_rules, "getAccountInfo", &mut response);
+        let _ = waiter.reply.send((response, attempt.clone()));
+    }
+}
+
+/// Whether `key` is a base58 32-byte public key
+fn is_pubkey(key: &str) -> bool {
+    bs58::decode(key).into_vec().is_ok_and(|bytes| bytes.len() == 32)
+}
+
+fn server_error(message: &str) -> RpcError {
+    RpcError {
+        code: -32000,
+        message: message.to_string(),
+        data: None,
+    }
+}
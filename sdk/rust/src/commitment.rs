@@ -0,0 +1,186 @@
+//! Proxy-wide default commitment
+//!
+//! Solana methods that read state take an optional trailing config object
+//! with a `commitment`. With a default configured, requests that leave it out
+//! get the default injected, or every request is overridden when forced. Only
+//! methods known to accept a commitment are touched, and only when the config
+//! object sits where the method expects it.
+
+use crate::RpcRequest;
+use serde::{Deserialize, Serialize};
+
+/// Solana commitment level, ordered from least to most final
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Commitment {
+    Processed,
+    Confirmed,
+    Finalized,
+}
+
+impl Commitment {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Commitment::Processed => "processed",
+            Commitment::Confirmed => "confirmed",
+            Commitment::Finalized => "finalized",
+        }
+    }
+}
+
+/// Methods accepting a commitment, with the index of their config object.
+/// Methods with optional positional params before the config (`getBlocks`,
+/// `getLeaderSchedule`) are left out, since the index isn't fixed.
+const COMMITMENT_METHODS: &[(&str, usize)] = &[
+    ("getSlot", 0),
+    ("getBlockHeight", 0),
+    ("getEpochInfo", 0),
+    ("getLatestBlockhash", 0),
+    ("getSlotLeader", 0),
+    ("getTransactionCount", 0),
+    ("getInflationGovernor", 0),
+    ("getLargestAccounts", 0),
+    ("getSupply", 0),
+    ("getStakeMinimumDelegation", 0),
+    ("getVoteAccounts", 0),
+    ("getBalance", 1),
+    ("getAccountInfo", 1),
+    ("getMultipleAccounts", 1),
+    ("getProgramAccounts", 1),
+    ("getTokenAccountBalance", 1),
+    ("getTokenSupply", 1),
+    ("getTokenLargestAccounts", 1),
+    ("getSignaturesForAddress", 1),
+    ("getTransaction", 1),
+    ("getBlock", 1),
+    ("getInflationReward", 1),
+    ("getMinimumBalanceForRentExemption", 1),
+    ("getFeeForMessage", 1),
+    ("isBlockhashValid", 1),
+    ("simulateTransaction", 1),
+    ("getTokenAccountsByOwner", 2),
+    ("getTokenAccountsByDelegate", 2),
+    ("getBlocksWithLimit", 2),
+    ("requestAirdrop", 2),
+];
+
+/// Methods that reject `processed`
+const NO_PROCESSED: &[&str] = &["getBlock", "getTransaction", "getSignaturesForAddress", "getBlocksWithLimit"];
+
+/// Set `commitment` on `request` if it leaves it out, or always when `force` is set
+pub(crate) fn apply(request: &mut RpcRequest, commitment: Commitment, force: bool) {
+    let Some(&(_, index)) = COMMITMENT_METHODS.iter().find(|(m, _)| *m == request.method) else {
+        return;
+    };
+    if commitment == Commitment::Processed && NO_PROCESSED.contains(&request.method.as_str()) {
+        return;
+    }
+
+    if request.params.is_none() && index == 0 {
+        request.params = Some(serde_json::Value::Array(Vec::new()));
+    }
+    // Named params aren't used by Solana; leave them alone
+    let Some(params) = request.params.as_mut().and_then(|p| p.as_array_mut()) else {
+        return;
+    };
+    let value = serde_json::Value::String(commitment.as_str().to_string());
+    if params.len() == index {
+        params.push(serde_json::json!({ "commitment": value }));
+        return;
+    }
+    match params.get_mut(index) {
+        Some(serde_json::Value::Object(config)) if force || !config.contains_key("commitment") => {
+            config.insert("commitment".to_string(), value);
+        }
+        Some(slot @ serde_json::Value::Null) => *slot = serde_json::json!({ "commitment": value }),
+        // Already set, not a config object, or required params are missing
+        // (upstream reports those)
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str, params: Option<serde_json::Value>) -> RpcRequest {
+        RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(serde_json::json!(1)),
+            method: method.to_string(),
+            params,
+        }
+    }
+
+    fn applied(method: &str, params: Option<serde_json::Value>, force: bool) -> Option<serde_json::Value> {
+        let mut request = request(method, params);
+        apply(&mut request, Commitment::Confirmed, force);
+        request.params
+    }
+
+    #[test]
+    fn test_default_injected_when_absent() {
+        assert_eq!(applied("getSlot", None, false), Some(serde_json::json!([{ "commitment": "confirmed" }])));
+        assert_eq!(
+            applied("getBalance", Some(serde_json::json!(["addr"])), false),
+            Some(serde_json::json!(["addr", { "commitment": "confirmed" }]))
+        );
+        // Other config fields are kept
+        assert_eq!(
+            applied("getAccountInfo", Some(serde_json::json!(["addr", { "encoding": "base64" }])), false),
+            Some(serde_json::json!(["addr", { "encoding": "base64", "commitment": "confirmed" }]))
+        );
+        // The filter object of getTokenAccountsByOwner isn't mistaken for the config
+        assert_eq!(
+            applied("getTokenAccountsByOwner", Some(serde_json::json!(["owner", { "mint": "m" }])), false),
+            Some(serde_json::json!(["owner", { "mint": "m" }, { "commitment": "confirmed" }]))
+        );
+    }
+
+    #[test]
+    fn test_explicit_commitment_kept_unless_forced() {
+        let params = Some(serde_json::json!(["addr", { "commitment": "finalized" }]));
+        assert_eq!(applied("getBalance", params.clone(), false), params);
+        assert_eq!(
+            applied("getBalance", params, true),
+            Some(serde_json::json!(["addr", { "commitment": "confirmed" }]))
+        );
+    }
+
+    #[test]
+    fn test_other_methods_untouched() {
+        assert_eq!(applied("sendTransaction", Some(serde_json::json!(["tx"])), true), Some(serde_json::json!(["tx"])));
+        assert_eq!(applied("getHealth", None, true), None);
+        assert_eq!(applied("getBalance", None, true), None);
+        // Missing required params are passed through for upstream to reject
+        assert_eq!(applied("getBalance", Some(serde_json::json!([])), true), Some(serde_json::json!([])));
+
+        let mut block = request("getBlock", Some(serde_json::json!([100])));
+        apply(&mut block, Commitment::Processed, true);
+        assert_eq!(block.params, Some(serde_json::json!([100])));
+    }
+
+    #[tokio::test]
+    async fn test_proxy_injects_default_commitment() {
+        use crate::{Config, PrivacyRPC};
+
+        // Upstream echoes the params it received
+        let url = crate::tests::mock_upstream(|_, req| {
+            let body = serde_json::json!({ "jsonrpc": "2.0", "id": req["id"], "result": req["params"] });
+            hyper::Response::new(hyper::Body::from(body.to_string()))
+        })
+        .await;
+        let privacy_rpc = PrivacyRPC::new(
+            Config::builder()
+                .primary_rpc(&url)
+                .default_commitment(Commitment::Confirmed)
+                .build(),
+        );
+
+        let response = privacy_rpc
+            .forward_request(request("getBalance", Some(serde_json::json!(["addr"]))))
+            .await
+            .unwrap();
+        assert_eq!(response.result, Some(serde_json::json!(["addr", { "commitment": "confirmed" }])));
+    }
+}
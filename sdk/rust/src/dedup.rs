@@ -0,0 +1,136 @@
+//! `sendTransaction` replay protection
+//!
+//! Wallets and bots often resend a transaction after a timeout, even though
+//! the first broadcast went through. With a window configured, a
+//! `sendTransaction` whose first signature was already broadcast successfully
+//! within the window gets the earlier response back instead of going upstream
+//! again. Only successful sends are remembered, so a failed send can be retried.
+
+use crate::{transaction, RpcRequest, RpcResponse};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Signatures kept before expired entries are pruned
+const MAX_ENTRIES: usize = 4096;
+
+/// Recently broadcast transactions, keyed by first signature
+pub(crate) struct SendDedup {
+    window: Duration,
+    sent: Mutex<HashMap<String, (Instant, RpcResponse)>>,
+}
+
+impl SendDedup {
+    pub(crate) fn new(window: Duration) -> Self {
+        Self {
+            window,
+            sent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Signature identifying `request`, or `None` if it isn't deduplicated
+    fn key(&self, request: &RpcRequest) -> Option<String> {
+        if self.window.is_zero() || request.method != "sendTransaction" {
+            return None;
+        }
+        let params = request.params.as_ref()?.as_array()?;
+        let encoded = params.first()?.as_str()?;
+        // sendTransaction defaults to base58
+        let encoding = params
+            .get(1)
+            .and_then(|config| config.get("encoding"))
+            .and_then(|e| e.as_str())
+            .unwrap_or("base58");
+        transaction::transaction_signature(encoded, encoding).ok()
+    }
+
+    /// The response to an earlier broadcast of the same transaction within the
+    /// window, re-addressed to `request`'s id
+    pub(crate) fn get(&self, request: &RpcRequest) -> Option<RpcResponse> {
+        let key = self.key(request)?;
+        let mut sent = self.sent.lock().unwrap_or_else(|e| e.into_inner());
+        let (at, response) = sent.get(&key)?;
+        if at.elapsed() >= self.window {
+            sent.remove(&key);
+            return None;
+        }
+        Some(RpcResponse {
+            id: request.id.clone(),
+            ..response.clone()
+        })
+    }
+
+    /// Remember `response` to `request` if the broadcast succeeded
+    pub(crate) fn insert(&self, request: &RpcRequest, response: &RpcResponse) {
+        if response.error.is_some() {
+            return;
+        }
+        let Some(key) = self.key(request) else {
+            return;
+        };
+
+        let mut sent = self.sent.lock().unwrap_or_else(|e| e.into_inner());
+        if sent.len() >= MAX_ENTRIES {
+            let window = self.window;
+            sent.retain(|_, (at, _)| at.elapsed() < window);
+        }
+        sent.insert(key, (Instant::now(), response.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn send(id: u64, tx: &str) -> RpcRequest {
+        RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(serde_json::json!(id)),
+            method: "sendTransaction".to_string(),
+            params: Some(serde_json::json!([tx])),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resent_transaction_broadcast_once() {
+        use crate::{Config, PrivacyRPC};
+
+        let broadcasts = Arc::new(AtomicUsize::new(0));
+        let counter = broadcasts.clone();
+        let url = crate::tests::mock_upstream(move |_, req| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let body = serde_json::json!({ "jsonrpc": "2.0", "id": req["id"], "result": "sig" });
+            hyper::Response::new(hyper::Body::from(body.to_string()))
+        })
+        .await;
+        let privacy_rpc = PrivacyRPC::new(
+            Config::builder()
+                .primary_rpc(&url)
+                .dedup_send_transaction(Duration::from_secs(30))
+                .build(),
+        );
+
+        let mut tx = vec![1u8];
+        tx.extend([7u8; 64]);
+        tx.extend([0u8; 40]);
+        let tx = bs58::encode(tx).into_string();
+
+        let first = privacy_rpc.forward_request(send(1, &tx)).await.unwrap();
+        let second = privacy_rpc.forward_request(send(2, &tx)).await.unwrap();
+        assert_eq!(broadcasts.load(Ordering::SeqCst), 1);
+        assert_eq!(second.result, first.result);
+        assert_eq!(second.id, Some(serde_json::json!(2)));
+
+        // A different transaction still goes upstream
+        let mut other = vec![1u8];
+        other.extend([8u8; 64]);
+        other.extend([0u8; 40]);
+        privacy_rpc
+            .forward_request(send(3, &bs58::encode(other).into_string()))
+            .await
+            .unwrap();
+        assert_eq!(broadcasts.load(Ordering::SeqCst), 2);
+    }
+}
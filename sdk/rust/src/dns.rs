@@ -0,0 +1,242 @@
+//! DNS hijacking detection
+//!
+//! Pinned hostnames are resolved through both the system resolver and a
+//! trusted DNS-over-HTTPS resolver. If the two answers share no address, the
+//! local resolver is likely being tampered with and a `DnsHijacking` alert fires.
+
+use crate::{Alert, AlertType, Config, Error, RoutingMode, Severity};
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// Future returned by [`Resolver::resolve`]
+pub type ResolveFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<IpAddr>, String>> + Send + 'a>>;
+
+/// Hostname resolver used by the DNS hijacking check
+pub trait Resolver: Send + Sync {
+    fn resolve<'a>(&'a self, hostname: &'a str) -> ResolveFuture<'a>;
+}
+
+/// Resolves through the operating system (what the proxy actually connects with)
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn resolve<'a>(&'a self, hostname: &'a str) -> ResolveFuture<'a> {
+        Box::pin(async move {
+            let addrs = tokio::net::lookup_host((hostname, 443))
+                .await
+                .map_err(|e| format!("System lookup of {} failed: {}", hostname, e))?;
+            Ok(addrs.map(|addr| addr.ip()).collect())
+        })
+    }
+}
+
+/// Resolves A records through a DNS-over-HTTPS JSON endpoint
+pub struct DohResolver {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl DohResolver {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn cloudflare() -> Self {
+        Self::new("https://cloudflare-dns.com/dns-query")
+    }
+
+    pub fn google() -> Self {
+        Self::new("https://dns.google/resolve")
+    }
+
+    /// Send lookups through `config`'s upstream proxy, if it has one
+    pub(crate) fn routed(mut self, config: &Config) -> Result<Self, Error> {
+        if let Some(proxy) = RoutingMode::for_config(config).proxy()? {
+            self.client = reqwest::Client::builder()
+                .proxy(proxy)
+                .build()
+                .map_err(|e| Error::ConfigError(e.to_string()))?;
+        }
+        Ok(self)
+    }
+}
+
+impl Resolver for DohResolver {
+    fn resolve<'a>(&'a self, hostname: &'a str) -> ResolveFuture<'a> {
+        Box::pin(async move {
+            let json: serde_json::Value = self
+                .client
+                .get(&self.url)
+                .query(&[("name", hostname), ("type", "A")])
+                .header("Accept", "application/dns-json")
+                .timeout(std::time::Duration::from_secs(10))
+                .send()
+                .await
+                .map_err(|e| format!("DoH lookup of {} failed: {}", hostname, e))?
+                .json()
+                .await
+                .map_err(|e| format!("DoH response for {} invalid: {}", hostname, e))?;
+
+            // Keep only A records (type 1); CNAMEs in the chain are skipped
+            Ok(json["Answer"]
+                .as_array()
+                .map(|answers| {
+                    answers
+                        .iter()
+                        .filter(|a| a["type"] == 1)
+                        .filter_map(|a| a["data"].as_str()?.parse().ok())
+                        .collect()
+                })
+                .unwrap_or_default())
+        })
+    }
+}
+
+/// Resolve `hostname` with both resolvers. Returns both answers if their IPv4
+/// addresses don't intersect, `None` if they agree or can't be compared.
+async fn divergent_answers(
+    system: &dyn Resolver,
+    trusted: &dyn Resolver,
+    hostname: &str,
+) -> Result<Option<(Vec<IpAddr>, Vec<IpAddr>)>, String> {
+    let local: Vec<IpAddr> = system
+        .resolve(hostname)
+        .await?
+        .into_iter()
+        .filter(|ip| ip.is_ipv4())
+        .collect();
+    let remote = trusted.resolve(hostname).await?;
+
+    if local.is_empty() || remote.is_empty() || local.iter().any(|ip| remote.contains(ip)) {
+        return Ok(None);
+    }
+    Ok(Some((local, remote)))
+}
+
+/// Check every pinned hostname once, alerting on divergent answers
+pub(crate) async fn check_pinned_hosts(config: &Config) {
+    // Behind a proxy, upstream hostnames are resolved by the proxy: a system
+    // lookup would leak them around it and says nothing about where requests go
+    if config.system_resolver.is_none() && RoutingMode::for_config(config) != RoutingMode::Direct {
+        return;
+    }
+    let system: Arc<dyn Resolver> = config
+        .system_resolver
+        .clone()
+        .unwrap_or_else(|| Arc::new(SystemResolver));
+    let trusted: Arc<dyn Resolver> = match &config.trusted_resolver {
+        Some(resolver) => resolver.clone(),
+        None => match DohResolver::cloudflare().routed(config) {
+            Ok(resolver) => Arc::new(resolver),
+            Err(_) => return,
+        },
+    };
+
+    for hostname in &config.pinned_endpoints {
+        // Lookup failures are not evidence of tampering; try again next interval
+        let Ok(Some((local, remote))) =
+            divergent_answers(system.as_ref(), trusted.as_ref(), hostname).await
+        else {
+            continue;
+        };
+
+        if let Some(handler) = &config.alert_handler {
+            let join = |ips: &[IpAddr]| {
+                ips.iter().map(|ip| ip.to_string()).collect::<Vec<_>>().join(", ")
+            };
+            let mut details = HashMap::new();
+            details.insert("system_resolver".to_string(), join(&local));
+            details.insert("trusted_resolver".to_string(), join(&remote));
+
+            handler(Alert {
+                alert_type: AlertType::DnsHijacking,
+                severity: Severity::High,
+                message: format!(
+                    "DNS answers for {} differ from a trusted resolver - local DNS may be hijacked",
+                    hostname
+                ),
+                hostname: Some(hostname.clone()),
+                details: Some(details),
+                timestamp: chrono::Utc::now().timestamp_millis() as u64,
+            });
+        }
+    }
+}
+
+/// Re-check pinned hostnames every `dns_check_interval` until aborted
+pub(crate) async fn monitor(config: Config) {
+    loop {
+        check_pinned_hosts(&config).await;
+        tokio::time::sleep(config.dns_check_interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Resolver returning a fixed answer
+    struct StubResolver(Vec<IpAddr>);
+
+    impl Resolver for StubResolver {
+        fn resolve<'a>(&'a self, _hostname: &'a str) -> ResolveFuture<'a> {
+            let ips = self.0.clone();
+            Box::pin(async move { Ok(ips) })
+        }
+    }
+
+    fn config_with_answers(local: &str, remote: &str, alerts: Arc<Mutex<Vec<Alert>>>) -> Config {
+        Config::builder()
+            .pin_endpoint("rpc.example.com")
+            .dns_resolvers(
+                Arc::new(StubResolver(vec![local.parse().unwrap()])),
+                Arc::new(StubResolver(vec![remote.parse().unwrap()])),
+            )
+            .on_alert(move |alert| alerts.lock().unwrap().push(alert))
+            .build()
+    }
+
+    #[tokio::test]
+    async fn test_divergent_answers_fire_alert() {
+        let alerts = Arc::new(Mutex::new(Vec::new()));
+        let config = config_with_answers("10.0.0.66", "104.16.0.1", alerts.clone());
+
+        check_pinned_hosts(&config).await;
+
+        let alerts = alerts.lock().unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert!(matches!(alerts[0].alert_type, AlertType::DnsHijacking));
+        assert!(matches!(alerts[0].severity, Severity::High));
+        assert_eq!(alerts[0].hostname.as_deref(), Some("rpc.example.com"));
+    }
+
+    #[tokio::test]
+    async fn test_matching_answers_no_alert() {
+        let alerts = Arc::new(Mutex::new(Vec::new()));
+        let config = config_with_answers("104.16.0.1", "104.16.0.1", alerts.clone());
+
+        check_pinned_hosts(&config).await;
+
+        assert!(alerts.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_proxied_config_skips_system_lookups() {
+        // No resolver overrides: the check must not resolve locally around the proxy
+        let config = Config::builder()
+            .pin_endpoint("rpc.example.com")
+            .socks5_proxy("127.0.0.1:9")
+            .on_alert(|_| panic!("no lookup should happen"))
+            .build();
+        tokio::time::timeout(std::time::Duration::from_millis(100), check_pinned_hosts(&config))
+            .await
+            .expect("returns without looking anything up");
+    }
+}
@@ -0,0 +1,27 @@
+//! Transactions built by hand for tests. Compiled for the SDK's own tests,
+//! and for other crates' tests with the `test-fixtures` feature.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
+/// Legacy transaction with one System transfer of `lamports` from `[1; 32]`
+/// to `[2; 32]`, carrying a placeholder (non-zero) signature
+pub fn sol_transfer_bytes(lamports: u64) -> Vec<u8> {
+    let mut tx = vec![1u8];
+    tx.extend([7u8; 64]); // signature
+    tx.extend([1, 0, 1]); // header
+    tx.push(3); // account keys: payer, recipient, System program
+    tx.extend([1u8; 32]);
+    tx.extend([2u8; 32]);
+    tx.extend([0u8; 32]);
+    tx.extend([9u8; 32]); // recent blockhash
+    tx.push(1); // one instruction
+    tx.extend([2, 2, 0, 1, 12]);
+    tx.extend(2u32.to_le_bytes());
+    tx.extend(lamports.to_le_bytes());
+    tx
+}
+
+/// [`sol_transfer_bytes`] in base64, as `sendTransaction` takes it
+pub fn sol_transfer_base64(lamports: u64) -> String {
+    BASE64.encode(sol_transfer_bytes(lamports))
+}
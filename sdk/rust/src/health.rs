@@ -0,0 +1,474 @@
+//! Per-endpoint health tracking
+//!
+//! Every upstream attempt is recorded here. After `FAILURE_THRESHOLD`
+//! consecutive failures an endpoint's breaker opens and it is skipped for
+//! `OPEN_COOLDOWN`, after which a single trial request is allowed (half-open).
+//! The tracker also orders endpoints for each request according to the
+//! configured [`LoadBalanceStrategy`], and, with an [`ErrorRateAlert`]
+//! configured, raises an alert when an endpoint's error rate over a sliding
+//! window crosses the threshold and again once it drops back below it.
+
+use crate::certs::CertificateTracker;
+use crate::{Alert, AlertType, Severity};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Consecutive failures that open an endpoint's breaker
+const FAILURE_THRESHOLD: u32 = 3;
+/// How long an open breaker skips its endpoint before allowing a trial
+const OPEN_COOLDOWN: Duration = Duration::from_secs(30);
+/// Weight of the newest sample in the latency EWMA
+const EWMA_ALPHA: f64 = 0.3;
+
+/// Circuit breaker state of an endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    /// Requests flow normally
+    Closed,
+    /// Too many consecutive failures; the endpoint is skipped
+    Open,
+    /// Cooldown elapsed; the next request is a trial
+    HalfOpen,
+}
+
+/// Snapshot of an endpoint's health, as returned by `PrivacyRPC::endpoint_states`
+#[derive(Debug, Clone, Serialize)]
+pub struct EndpointState {
+    pub url: String,
+    /// `false` once the most recent request to this endpoint failed
+    pub healthy: bool,
+    pub last_error: Option<String>,
+    pub last_latency_ms: Option<u64>,
+    pub ewma_latency_ms: Option<f64>,
+    pub consecutive_failures: u32,
+    pub breaker_state: BreakerState,
+}
+
+/// How the first endpoint for a request is chosen. The remaining endpoints
+/// follow in failover order (primary, then fallbacks).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum LoadBalanceStrategy {
+    /// Always start with the primary
+    #[default]
+    Failover,
+    /// Start with an endpoint picked in proportion to its weight, among those
+    /// whose breaker is not open. Weights are given primary first, then
+    /// fallbacks; missing weights count as 1 and a weight of 0 is never picked.
+    WeightedRandom(Vec<u32>),
+}
+
+/// Per-endpoint error rate alerting, set with [`ConfigBuilder::error_rate_alert`]
+///
+/// [`ConfigBuilder::error_rate_alert`]: crate::ConfigBuilder::error_rate_alert
+#[derive(Debug, Clone, Copy)]
+pub struct ErrorRateAlert {
+    /// Fraction of failed requests, 0.0 to 1.0, at which the alert fires
+    pub threshold: f64,
+    /// Sliding window the rate is computed over
+    pub window: Duration,
+    /// Requests the window must hold before the rate is judged
+    pub min_requests: usize,
+}
+
+/// An endpoint's windowed error rate crossing the alert threshold
+#[derive(Debug, PartialEq)]
+enum ErrorRateChange {
+    Exceeded { rate: f64, requests: usize },
+    Recovered { rate: f64, requests: usize },
+}
+
+#[derive(Default)]
+struct EndpointHealth {
+    last_error: Option<String>,
+    last_latency_ms: Option<u64>,
+    ewma_latency_ms: Option<f64>,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    /// When each request in the error rate window finished, and whether it failed
+    outcomes: VecDeque<(Instant, bool)>,
+    /// Failed entries in `outcomes`, kept as they are pushed and popped
+    window_failures: usize,
+    error_rate_alerted: bool,
+}
+
+impl EndpointHealth {
+    fn breaker_state(&self) -> BreakerState {
+        match self.opened_at {
+            None => BreakerState::Closed,
+            Some(at) if at.elapsed() < OPEN_COOLDOWN => BreakerState::Open,
+            Some(_) => BreakerState::HalfOpen,
+        }
+    }
+
+    fn record_latency(&mut self, latency: Duration) {
+        let ms = latency.as_millis() as u64;
+        self.last_latency_ms = Some(ms);
+        self.ewma_latency_ms = Some(match self.ewma_latency_ms {
+            Some(avg) => EWMA_ALPHA * ms as f64 + (1.0 - EWMA_ALPHA) * avg,
+            None => ms as f64,
+        });
+    }
+
+    fn record_outcome(&mut self, failed: bool, alert: &ErrorRateAlert) -> Option<ErrorRateChange> {
+        let now = Instant::now();
+        while self.outcomes.front().is_some_and(|(at, _)| now.duration_since(*at) > alert.window) {
+            if let Some((_, true)) = self.outcomes.pop_front() {
+                self.window_failures -= 1;
+            }
+        }
+        self.outcomes.push_back((now, failed));
+        self.window_failures += usize::from(failed);
+
+        let requests = self.outcomes.len();
+        if requests < alert.min_requests.max(1) {
+            return None;
+        }
+        let rate = self.window_failures as f64 / requests as f64;
+        match (self.error_rate_alerted, rate >= alert.threshold) {
+            (false, true) => {
+                self.error_rate_alerted = true;
+                Some(ErrorRateChange::Exceeded { rate, requests })
+            }
+            (true, false) => {
+                self.error_rate_alerted = false;
+                Some(ErrorRateChange::Recovered { rate, requests })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Health of the primary and fallback endpoints, in failover order
+pub(crate) struct EndpointTracker {
+    endpoints: Mutex<Vec<(String, EndpointHealth)>>,
+    rng: Mutex<StdRng>,
+    error_rate_alert: Option<ErrorRateAlert>,
+    alert_handler: Option<Arc<dyn Fn(Alert) + Send + Sync>>,
+    certificates: Option<Arc<CertificateTracker>>,
+}
+
+impl EndpointTracker {
+    pub(crate) fn new<'a>(urls: impl IntoIterator<Item = &'a str>) -> Self {
+        Self::with_rng(urls, StdRng::from_entropy())
+    }
+
+    fn with_rng<'a>(urls: impl IntoIterator<Item = &'a str>, rng: StdRng) -> Self {
+        Self {
+            endpoints: Mutex::new(
+                urls.into_iter()
+                    .map(|url| (url.to_string(), EndpointHealth::default()))
+                    .collect(),
+            ),
+            rng: Mutex::new(rng),
+            error_rate_alert: None,
+            alert_handler: None,
+            certificates: None,
+        }
+    }
+
+    /// Refuse endpoints whose certificate doesn't match its pin in `certificates`
+    pub(crate) fn with_certificates(mut self, certificates: Arc<CertificateTracker>) -> Self {
+        self.certificates = Some(certificates);
+        self
+    }
+
+    /// Whether `url`'s host last presented a certificate other than the one
+    /// it's pinned to. Nothing should be sent there: it's likely intercepted.
+    pub(crate) fn certificate_mismatch(&self, url: &str) -> bool {
+        let Some(certificates) = &self.certificates else {
+            return false;
+        };
+        reqwest::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(|host| certificates.mismatched(host)))
+            .unwrap_or(false)
+    }
+
+    /// Report error rate threshold crossings to `handler`
+    pub(crate) fn with_error_rate_alert(
+        mut self,
+        alert: Option<ErrorRateAlert>,
+        handler: Option<Arc<dyn Fn(Alert) + Send + Sync>>,
+    ) -> Self {
+        self.error_rate_alert = alert;
+        self.alert_handler = handler;
+        self
+    }
+
+    fn record_outcome(&self, url: &str, failed: bool) {
+        let (Some(alert), Some(handler)) = (&self.error_rate_alert, &self.alert_handler) else {
+            return;
+        };
+        let Some(change) = self.with_endpoint(url, |h| h.record_outcome(failed, alert)).flatten() else {
+            return;
+        };
+        let host = reqwest::Url::parse(url).ok().and_then(|u| u.host_str().map(String::from));
+        let name = host.clone().unwrap_or_else(|| "RPC endpoint".to_string());
+        let (severity, message, rate, requests) = match change {
+            ErrorRateChange::Exceeded { rate, requests } => {
+                let severity = match rate {
+                    r if r >= 0.75 => Severity::Critical,
+                    r if r >= 0.5 => Severity::High,
+                    r if r >= 0.25 => Severity::Medium,
+                    _ => Severity::Low,
+                };
+                let message = format!("{} is failing {:.0}% of requests", name, rate * 100.0);
+                (severity, message, rate, requests)
+            }
+            ErrorRateChange::Recovered { rate, requests } => {
+                let message = format!("{} recovered ({:.0}% of requests failing)", name, rate * 100.0);
+                (Severity::Info, message, rate, requests)
+            }
+        };
+        let mut details = HashMap::new();
+        details.insert("error_rate".to_string(), format!("{:.3}", rate));
+        details.insert("window_requests".to_string(), requests.to_string());
+        handler(Alert {
+            alert_type: AlertType::ProxyError,
+            severity,
+            message,
+            hostname: host,
+            details: Some(details),
+            timestamp: chrono::Utc::now().timestamp_millis() as u64,
+        });
+    }
+
+    fn with_endpoint<T>(&self, url: &str, f: impl FnOnce(&mut EndpointHealth) -> T) -> Option<T> {
+        let mut endpoints = self.endpoints.lock().unwrap_or_else(|e| e.into_inner());
+        endpoints.iter_mut().find(|(u, _)| u == url).map(|(_, h)| f(h))
+    }
+
+    /// Whether requests should be sent to `url` (its breaker is not open)
+    pub(crate) fn is_available(&self, url: &str) -> bool {
+        self.with_endpoint(url, |h| h.breaker_state() != BreakerState::Open)
+            .unwrap_or(true)
+    }
+
+    /// Order `urls` (primary first, then fallbacks) for the next request.
+    /// Endpoints with an open breaker are skipped unless that would leave nothing to try.
+    pub(crate) fn attempt_order<'a>(&self, urls: &[&'a str], strategy: &LoadBalanceStrategy) -> Vec<&'a str> {
+        let available: Vec<usize> = (0..urls.len()).filter(|&i| self.is_available(urls[i])).collect();
+
+        let LoadBalanceStrategy::WeightedRandom(weights) = strategy else {
+            return if available.is_empty() {
+                urls.to_vec()
+            } else {
+                available.iter().map(|&i| urls[i]).collect()
+            };
+        };
+
+        if available.is_empty() {
+            // Every breaker is open: start with the one that opened first,
+            // as it will reach half-open soonest
+            let mut order = urls.to_vec();
+            order.sort_by_key(|url| self.with_endpoint(url, |h| h.opened_at).flatten());
+            return order;
+        }
+
+        let weight = |i: usize| u64::from(weights.get(i).copied().unwrap_or(1));
+        let mut order: Vec<&str> = available.iter().map(|&i| urls[i]).collect();
+        let total: u64 = available.iter().map(|&i| weight(i)).sum();
+        if total > 0 {
+            let mut pick = self.rng.lock().unwrap_or_else(|e| e.into_inner()).gen_range(0..total);
+            let chosen = available.iter().position(|&i| {
+                if pick < weight(i) {
+                    return true;
+                }
+                pick -= weight(i);
+                false
+            });
+            if let Some(chosen) = chosen {
+                let first = order.remove(chosen);
+                order.insert(0, first);
+            }
+        }
+        order
+    }
+
+    /// Track `url` as the primary from now on, starting with a clean record
+    pub(crate) fn replace_primary(&self, url: &str) {
+        let mut endpoints = self.endpoints.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(primary) = endpoints.first_mut() {
+            *primary = (url.to_string(), EndpointHealth::default());
+        }
+    }
+
+    pub(crate) fn record_success(&self, url: &str, latency: Duration) {
+        self.with_endpoint(url, |h| {
+            h.record_latency(latency);
+            h.last_error = None;
+            h.consecutive_failures = 0;
+            h.opened_at = None;
+        });
+        self.record_outcome(url, false);
+    }
+
+    pub(crate) fn record_failure(&self, url: &str, error: String, latency: Duration) {
+        self.with_endpoint(url, |h| {
+            h.record_latency(latency);
+            h.last_error = Some(error);
+            h.consecutive_failures += 1;
+            // A failed half-open trial re-opens the breaker for another cooldown
+            if h.consecutive_failures >= FAILURE_THRESHOLD {
+                h.opened_at = Some(Instant::now());
+            }
+        });
+        self.record_outcome(url, true);
+    }
+
+    pub(crate) fn states(&self) -> Vec<EndpointState> {
+        let endpoints = self.endpoints.lock().unwrap_or_else(|e| e.into_inner());
+        endpoints
+            .iter()
+            .map(|(url, h)| EndpointState {
+                url: url.clone(),
+                healthy: h.consecutive_failures == 0,
+                last_error: h.last_error.clone(),
+                last_latency_ms: h.last_latency_ms,
+                ewma_latency_ms: h.ewma_latency_ms,
+                consecutive_failures: h.consecutive_failures,
+                breaker_state: h.breaker_state(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breaker_opens_after_threshold() {
+        let tracker = EndpointTracker::new(["https://a.example.com"]);
+        let url = "https://a.example.com";
+
+        for _ in 0..FAILURE_THRESHOLD - 1 {
+            tracker.record_failure(url, "timeout".into(), Duration::from_millis(5));
+        }
+        assert!(tracker.is_available(url));
+
+        tracker.record_failure(url, "timeout".into(), Duration::from_millis(5));
+        assert!(!tracker.is_available(url));
+        assert_eq!(tracker.states()[0].breaker_state, BreakerState::Open);
+
+        tracker.record_success(url, Duration::from_millis(5));
+        let state = &tracker.states()[0];
+        assert_eq!(state.breaker_state, BreakerState::Closed);
+        assert!(state.healthy);
+    }
+
+    fn trip(tracker: &EndpointTracker, url: &str) {
+        for _ in 0..FAILURE_THRESHOLD {
+            tracker.record_failure(url, "timeout".into(), Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_weighted_random_matches_weights_and_skips_open() {
+        let urls = ["https://a.example.com", "https://b.example.com", "https://c.example.com"];
+        let tracker = EndpointTracker::with_rng(urls, StdRng::seed_from_u64(7));
+        let strategy = LoadBalanceStrategy::WeightedRandom(vec![1, 2, 7]);
+
+        let first_counts = |n: usize| {
+            let mut counts = [0usize; 3];
+            for _ in 0..n {
+                let first = tracker.attempt_order(&urls, &strategy)[0];
+                counts[urls.iter().position(|u| *u == first).unwrap()] += 1;
+            }
+            counts
+        };
+
+        let counts = first_counts(10_000);
+        for (count, expected) in counts.iter().zip([0.1, 0.2, 0.7]) {
+            let share = *count as f64 / 10_000.0;
+            assert!((share - expected).abs() < 0.03, "{:?}", counts);
+        }
+
+        // A tripped endpoint is never picked or retried; the rest keep their ratio
+        trip(&tracker, urls[2]);
+        let counts = first_counts(3_000);
+        assert_eq!(counts[2], 0);
+        assert!((counts[1] as f64 / 3_000.0 - 2.0 / 3.0).abs() < 0.04, "{:?}", counts);
+        assert!(!tracker.attempt_order(&urls, &strategy).contains(&urls[2]));
+    }
+
+    #[test]
+    fn test_all_open_starts_with_earliest_opened() {
+        let urls = ["https://a.example.com", "https://b.example.com"];
+        let tracker = EndpointTracker::with_rng(urls, StdRng::seed_from_u64(7));
+        trip(&tracker, urls[1]);
+        std::thread::sleep(Duration::from_millis(5));
+        trip(&tracker, urls[0]);
+
+        let strategy = LoadBalanceStrategy::WeightedRandom(vec![5, 1]);
+        assert_eq!(tracker.attempt_order(&urls, &strategy), vec![urls[1], urls[0]]);
+        assert_eq!(tracker.attempt_order(&urls, &LoadBalanceStrategy::Failover), urls.to_vec());
+    }
+
+    #[test]
+    fn test_half_failing_window_raises_alert() {
+        let alerts = Arc::new(Mutex::new(Vec::new()));
+        let recorded = alerts.clone();
+        let url = "https://a.example.com/?api-key=secret";
+        let tracker = EndpointTracker::new([url]).with_error_rate_alert(
+            Some(ErrorRateAlert {
+                threshold: 0.4,
+                window: Duration::from_secs(60),
+                min_requests: 10,
+            }),
+            Some(Arc::new(move |alert| recorded.lock().unwrap().push(alert))),
+        );
+
+        // Alternate so the breaker never opens; half of the window fails
+        for _ in 0..5 {
+            tracker.record_failure(url, "timeout".into(), Duration::from_millis(5));
+            tracker.record_success(url, Duration::from_millis(5));
+        }
+        {
+            let alerts = alerts.lock().unwrap();
+            assert_eq!(alerts.len(), 1);
+            assert!(matches!(alerts[0].alert_type, AlertType::ProxyError));
+            assert!(matches!(alerts[0].severity, Severity::High));
+            assert_eq!(alerts[0].hostname.as_deref(), Some("a.example.com"));
+            assert!(!alerts[0].message.contains("secret"));
+            assert_eq!(alerts[0].details.as_ref().unwrap()["error_rate"], "0.500");
+        }
+
+        // Still failing: no repeat alert until it recovers
+        tracker.record_failure(url, "timeout".into(), Duration::from_millis(5));
+        assert_eq!(alerts.lock().unwrap().len(), 1);
+        for _ in 0..5 {
+            tracker.record_success(url, Duration::from_millis(5));
+        }
+        let alerts = alerts.lock().unwrap();
+        assert_eq!(alerts.len(), 2);
+        assert!(matches!(alerts[1].severity, Severity::Info));
+        assert!(alerts[1].message.contains("recovered"));
+    }
+
+    #[test]
+    fn test_window_failures_follow_expiry() {
+        let alert = ErrorRateAlert {
+            threshold: 0.5,
+            window: Duration::from_millis(20),
+            min_requests: 1,
+        };
+        let mut health = EndpointHealth::default();
+        health.record_outcome(true, &alert);
+        health.record_outcome(true, &alert);
+        assert_eq!(health.window_failures, 2);
+
+        // Both failures age out; only the new success is counted
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(matches!(
+            health.record_outcome(false, &alert),
+            Some(ErrorRateChange::Recovered { requests: 1, .. })
+        ));
+        assert_eq!(health.window_failures, 0);
+    }
+}
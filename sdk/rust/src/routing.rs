@@ -0,0 +1,232 @@
+//! Upstream routing: direct, through an HTTP proxy, or through SOCKS5
+//!
+//! A SOCKS5 proxy (e.g. a local Tor client) takes precedence over an HTTP
+//! proxy; the two aren't chained. Hostnames are resolved by the SOCKS proxy
+//! (`socks5h`), so DNS lookups don't leak around it.
+
+use crate::{Config, Error};
+use serde::Serialize;
+
+/// IP echo services tried in order by [`PrivacyRPC::effective_routing`](crate::PrivacyRPC::effective_routing)
+pub const DEFAULT_IP_CHECK_URLS: &[&str] = &[
+    "https://check.torproject.org/api/ip",
+    "https://api.ipify.org?format=json",
+];
+
+/// How upstream requests leave this machine
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "mode", content = "proxy", rename_all = "snake_case")]
+pub enum RoutingMode {
+    Direct,
+    HttpProxy(String),
+    Socks5(String),
+}
+
+impl RoutingMode {
+    pub(crate) fn for_config(config: &Config) -> Self {
+        if let Some(addr) = &config.socks5_proxy {
+            RoutingMode::Socks5(addr.clone())
+        } else if let Some(url) = &config.upstream_proxy {
+            RoutingMode::HttpProxy(url.clone())
+        } else {
+            RoutingMode::Direct
+        }
+    }
+
+    /// reqwest proxy for this route (`None` = direct)
+    pub(crate) fn proxy(&self) -> Result<Option<reqwest::Proxy>, Error> {
+        let url = match self {
+            RoutingMode::Direct => return Ok(None),
+            RoutingMode::HttpProxy(url) => url.clone(),
+            RoutingMode::Socks5(addr) => format!("socks5h://{}", addr),
+        };
+        reqwest::Proxy::all(&url)
+            .map(Some)
+            .map_err(|e| Error::ConfigError(format!("Invalid upstream proxy {}: {}", url, e)))
+    }
+}
+
+/// Check `url` can be used as [`Config::upstream_proxy`]: an http(s) URL
+/// with a host. The URL isn't echoed back, as it may carry credentials.
+pub(crate) fn validate_upstream_proxy(url: &str) -> Result<(), String> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| format!("upstream_proxy is not a valid URL: {}", e))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!(
+            "upstream_proxy must be an http:// or https:// URL, got {}://",
+            parsed.scheme()
+        ));
+    }
+    if parsed.host_str().unwrap_or("").is_empty() {
+        return Err("upstream_proxy has no host".to_string());
+    }
+    reqwest::Proxy::all(url).map_err(|e| format!("upstream_proxy is invalid: {}", e))?;
+    Ok(())
+}
+
+/// Routing in effect, and the public IP upstreams see
+#[derive(Debug, Clone, Serialize)]
+pub struct EffectiveRouting {
+    #[serde(flatten)]
+    pub mode: RoutingMode,
+    /// Public IP reported by an echo service through the route, if one answered
+    pub exit_ip: Option<String>,
+    /// Why no exit IP could be found
+    pub error: Option<String>,
+}
+
+/// Ask each echo service in `config.ip_check_urls` for our IP through the
+/// configured route, returning the first answer
+pub(crate) async fn probe(config: &Config) -> EffectiveRouting {
+    let mode = RoutingMode::for_config(config);
+    let result = match client(&mode) {
+        Ok(client) => detect_ip(&client, &config.ip_check_urls).await,
+        Err(e) => Err(e.to_string()),
+    };
+    let (exit_ip, error) = match result {
+        Ok(ip) => (Some(ip), None),
+        Err(e) => (None, Some(e)),
+    };
+    EffectiveRouting { mode, exit_ip, error }
+}
+
+fn client(mode: &RoutingMode) -> Result<reqwest::Client, Error> {
+    let mut builder = reqwest::Client::builder().timeout(std::time::Duration::from_secs(15));
+    if let Some(proxy) = mode.proxy()? {
+        builder = builder.proxy(proxy);
+    }
+    builder.build().map_err(|e| Error::RpcError(e.to_string()))
+}
+
+async fn detect_ip(client: &reqwest::Client, urls: &[String]) -> Result<String, String> {
+    let mut last_error = "No IP check URLs configured".to_string();
+    for url in urls {
+        match client.get(url).send().await {
+            Ok(resp) => match resp.text().await {
+                Ok(body) => match parse_echo_ip(&body) {
+                    Some(ip) => return Ok(ip),
+                    None => last_error = format!("{} returned no IP address", url),
+                },
+                Err(e) => last_error = format!("{}: {}", url, e),
+            },
+            Err(e) => last_error = format!("{}: {}", url, e),
+        }
+    }
+    Err(last_error)
+}
+
+/// IP address from an echo service reply: JSON with an `ip`/`query`/`IP`
+/// field, or the bare address
+fn parse_echo_ip(body: &str) -> Option<String> {
+    let candidate = match serde_json::from_str::<serde_json::Value>(body) {
+        Ok(json) => ["ip", "query", "IP"]
+            .iter()
+            .find_map(|key| json.get(key)?.as_str())?
+            .to_string(),
+        Err(_) => body.trim().to_string(),
+    };
+    candidate.parse::<std::net::IpAddr>().ok()?;
+    Some(candidate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PrivacyRPC;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Minimal SOCKS5 proxy (no auth) recording each requested `host:port`
+    /// and answering the tunnelled HTTP request with `body`
+    async fn mock_socks5(body: &'static str) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let targets = Arc::new(Mutex::new(Vec::new()));
+        let seen = targets.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let seen = seen.clone();
+                tokio::spawn(async move {
+                    // Greeting: version, method count, methods
+                    let mut head = [0u8; 2];
+                    stream.read_exact(&mut head).await.unwrap();
+                    let mut methods = vec![0u8; head[1] as usize];
+                    stream.read_exact(&mut methods).await.unwrap();
+                    stream.write_all(&[5, 0]).await.unwrap();
+
+                    // CONNECT request with a domain name (socks5h)
+                    let mut request = [0u8; 4];
+                    stream.read_exact(&mut request).await.unwrap();
+                    assert_eq!(request[3], 3, "expected the proxy to resolve the hostname");
+                    let mut len = [0u8; 1];
+                    stream.read_exact(&mut len).await.unwrap();
+                    let mut host = vec![0u8; len[0] as usize];
+                    stream.read_exact(&mut host).await.unwrap();
+                    let mut port = [0u8; 2];
+                    stream.read_exact(&mut port).await.unwrap();
+                    seen.lock().unwrap().push(format!(
+                        "{}:{}",
+                        String::from_utf8_lossy(&host),
+                        u16::from_be_bytes(port)
+                    ));
+                    stream.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).await.unwrap();
+
+                    let mut buf = [0u8; 4096];
+                    let _ = stream.read(&mut buf).await;
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+                        body.len(),
+                        body
+                    );
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        (addr, targets)
+    }
+
+    #[tokio::test]
+    async fn test_forwarding_uses_socks5_proxy() {
+        let (socks, targets) = mock_socks5(r#"{"jsonrpc":"2.0","id":1,"result":"via socks"}"#).await;
+        let privacy_rpc = PrivacyRPC::new(
+            Config::builder()
+                .primary_rpc("http://rpc.invalid:8899")
+                .socks5_proxy(&socks)
+                .build(),
+        );
+
+        let response = privacy_rpc
+            .forward_request(crate::RpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: Some(serde_json::json!(1)),
+                method: "getSlot".to_string(),
+                params: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(response.result, Some(serde_json::json!("via socks")));
+        assert_eq!(targets.lock().unwrap().as_slice(), ["rpc.invalid:8899"]);
+    }
+
+    #[tokio::test]
+    async fn test_effective_routing_reports_exit_ip() {
+        let (socks, _) = mock_socks5(r#"{"IsTor":true,"IP":"185.220.101.1"}"#).await;
+        let privacy_rpc = PrivacyRPC::new(
+            Config::builder()
+                .socks5_proxy(&socks)
+                .ip_check_urls(vec!["http://echo.invalid/".to_string()])
+                .build(),
+        );
+
+        let routing = privacy_rpc.effective_routing().await;
+        assert_eq!(routing.mode, RoutingMode::Socks5(socks));
+        assert_eq!(routing.exit_ip.as_deref(), Some("185.220.101.1"));
+        assert!(routing.error.is_none());
+    }
+
+    #[test]
+    fn test_parse_echo_ip() {
+        assert_eq!(parse_echo_ip(r#"{"ip":"203.0.113.9"}"#).as_deref(), Some("203.0.113.9"));
+        assert_eq!(parse_echo_ip("2001:db8::1\n").as_deref(), Some("2001:db8::1"));
+        assert_eq!(parse_echo_ip("<html>blocked</html>"), None);
+    }
+}
@@ -0,0 +1,136 @@
+//! Response scrubbing
+//!
+//! A proxy shared between users can strip identifying data from responses
+//! before they reach the client. Each [`ScrubRule`] names a method and a path
+//! into its `result`: dot-separated keys, with `*` matching every element of
+//! an array (or every value of an object). Matched values are replaced with
+//! `null` or with a salted hash, which stays the same for the life of the
+//! process so clients can still tell values apart without learning them.
+
+use crate::RpcResponse;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::LazyLock;
+
+/// What to do with a matched value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScrubAction {
+    /// Replace with `null`
+    Redact,
+    /// Replace with a hex SHA-256 of the value, salted per process
+    Hash,
+}
+
+/// Scrub the values at `path` in `method`'s results
+///
+/// ```toml
+/// [[scrub_rules]]
+/// method = "getTokenAccountsByOwner"
+/// path = "value.*.account.data.parsed.info.owner"
+/// action = "redact"
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScrubRule {
+    pub method: String,
+    pub path: String,
+    pub action: ScrubAction,
+}
+
+static SALT: LazyLock<[u8; 16]> = LazyLock::new(rand::random);
+
+/// Apply every rule for `method` to `response`
+pub(crate) fn scrub(rules: &[ScrubRule], method: &str, response: &mut RpcResponse) {
+    let Some(result) = response.result.as_mut() else {
+        return;
+    };
+    for rule in rules.iter().filter(|rule| rule.method == method) {
+        let path: Vec<&str> = rule.path.split('.').filter(|key| !key.is_empty()).collect();
+        scrub_path(result, &path, rule.action);
+    }
+}
+
+fn scrub_path(value: &mut serde_json::Value, path: &[&str], action: ScrubAction) {
+    let Some((key, rest)) = path.split_first() else {
+        *value = match action {
+            ScrubAction::Redact => serde_json::Value::Null,
+            ScrubAction::Hash => {
+                let mut hasher = Sha256::new();
+                hasher.update(*SALT);
+                hasher.update(value.to_string());
+                serde_json::Value::String(hex::encode(hasher.finalize()))
+            }
+        };
+        return;
+    };
+    match (value, *key) {
+        (serde_json::Value::Array(items), "*") => items.iter_mut().for_each(|item| scrub_path(item, rest, action)),
+        (serde_json::Value::Object(map), "*") => map.values_mut().for_each(|item| scrub_path(item, rest, action)),
+        (serde_json::Value::Object(map), key) => {
+            if let Some(item) = map.get_mut(key) {
+                scrub_path(item, rest, action);
+            }
+        }
+        (serde_json::Value::Array(items), index) => {
+            if let Some(item) = index.parse::<usize>().ok().and_then(|i| items.get_mut(i)) {
+                scrub_path(item, rest, action);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, PrivacyRPC, RpcRequest};
+
+    #[tokio::test]
+    async fn test_configured_fields_scrubbed() {
+        let url = crate::tests::mock_upstream(|_, req| {
+            let account = |pubkey: &str| {
+                serde_json::json!({
+                    "pubkey": pubkey,
+                    "account": {
+                        "lamports": 2039280,
+                        "data": { "parsed": { "info": { "owner": "OwnerWallet111", "mint": "Mint111" } } },
+                    },
+                })
+            };
+            let body = serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": req["id"],
+                "result": { "context": { "slot": 1 }, "value": [account("TokenAcct1"), account("TokenAcct2")] },
+            });
+            hyper::Response::new(hyper::Body::from(body.to_string()))
+        })
+        .await;
+        let privacy_rpc = PrivacyRPC::new(
+            Config::builder()
+                .primary_rpc(&url)
+                .scrub_response("getTokenAccountsByOwner", "value.*.account.data.parsed.info.owner", ScrubAction::Redact)
+                .scrub_response("getTokenAccountsByOwner", "value.*.pubkey", ScrubAction::Hash)
+                .build(),
+        );
+
+        let request = |method: &str| RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(serde_json::json!(1)),
+            method: method.to_string(),
+            params: Some(serde_json::json!(["OwnerWallet111"])),
+        };
+        let response = privacy_rpc.forward_request(request("getTokenAccountsByOwner")).await.unwrap();
+        let value = &response.result.unwrap()["value"];
+        let info = &value[0]["account"]["data"]["parsed"]["info"];
+        assert!(info["owner"].is_null());
+        assert_eq!(info["mint"], "Mint111");
+        assert_eq!(value[0]["account"]["lamports"], 2039280);
+        let hashed = value[0]["pubkey"].as_str().unwrap();
+        assert_eq!(hashed.len(), 64);
+        assert_ne!(value[0]["pubkey"], value[1]["pubkey"]);
+
+        // Other methods are untouched
+        let response = privacy_rpc.forward_request(request("getProgramAccounts")).await.unwrap();
+        assert_eq!(response.result.unwrap()["value"][0]["pubkey"], "TokenAcct1");
+    }
+}
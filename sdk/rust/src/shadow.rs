@@ -0,0 +1,145 @@
+//! Shadow endpoint comparison
+//!
+//! Before switching providers, an operator can mirror a sample of read
+//! requests to a candidate "shadow" endpoint. The shadow's answer is only
+//! compared, never returned: the client always gets the real endpoint's
+//! response, and the mirrored request runs in the background. Differing
+//! results are raised as a [`AlertType::ShadowMismatch`] alert; a shadow that
+//! errors or can't be reached is ignored.
+
+use crate::telemetry::endpoint_host;
+use crate::{send_upstream, upstream_client, Alert, AlertType, Config, RpcRequest, RpcResponse, Severity};
+use std::collections::HashMap;
+
+/// Only reads are mirrored; sending a transaction twice is never harmless
+fn is_read_method(method: &str) -> bool {
+    method.starts_with("get") || method == "isBlockhashValid"
+}
+
+/// The part of a result two healthy nodes should agree on. `context` carries
+/// the slot the node answered at, which rarely matches between providers.
+fn comparable(result: &serde_json::Value) -> &serde_json::Value {
+    match result.get("context") {
+        Some(_) => result.get("value").unwrap_or(result),
+        None => result,
+    }
+}
+
+/// Mirror `request` to the shadow endpoint if one is configured and this
+/// request is sampled, comparing its result with `response` in the background
+pub(crate) fn mirror(config: &Config, request: &RpcRequest, response: &RpcResponse) {
+    let Some(shadow) = config.shadow_endpoint.clone() else {
+        return;
+    };
+    let Some(expected) = response.result.clone().filter(|_| response.error.is_none()) else {
+        return;
+    };
+    if !is_read_method(&request.method) || rand::random::<f64>() >= config.shadow_sample_rate {
+        return;
+    }
+
+    // Same route as the real request, so mirroring doesn't leak the client's IP
+    let Ok(client) = upstream_client(config) else {
+        return;
+    };
+
+    let config = config.clone();
+    let request = request.clone();
+    tokio::spawn(async move {
+        let actual = match send_upstream(&client, &config, &shadow, &request, None).await {
+            Ok(resp) => resp.json::<RpcResponse>().await.map_err(|e| e.to_string()),
+            Err(e) => Err(e.message),
+        };
+        let Ok(RpcResponse { result: Some(actual), error: None, .. }) = actual else {
+            return;
+        };
+        if comparable(&actual) == comparable(&expected) {
+            return;
+        }
+
+        if let Some(handler) = &config.alert_handler {
+            let mut details = HashMap::new();
+            details.insert("method".to_string(), request.method.clone());
+            details.insert("expected".to_string(), expected.to_string());
+            details.insert("shadow".to_string(), actual.to_string());
+            handler(Alert {
+                alert_type: AlertType::ShadowMismatch,
+                severity: Severity::Low,
+                // The shadow URL may carry an API key
+                message: format!(
+                    "Shadow endpoint {} returned a different result for {}",
+                    endpoint_host(&shadow),
+                    request.method
+                ),
+                hostname: reqwest::Url::parse(&shadow).ok().and_then(|url| url.host_str().map(String::from)),
+                details: Some(details),
+                timestamp: chrono::Utc::now().timestamp_millis() as u64,
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PrivacyRPC;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_shadow_mismatch_recorded() {
+        let upstream = |balance: u64, calls: Arc<AtomicUsize>| {
+            crate::tests::mock_upstream(move |_, req| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                let body = serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": req["id"],
+                    "result": { "context": { "slot": balance }, "value": balance },
+                });
+                hyper::Response::new(hyper::Body::from(body.to_string()))
+            })
+        };
+        let primary = upstream(100, Arc::default()).await;
+        let shadow_calls = Arc::new(AtomicUsize::new(0));
+        let shadow = format!("{}/v2/shadow-secret", upstream(200, shadow_calls.clone()).await);
+        let alerts = Arc::new(Mutex::new(Vec::new()));
+        let recorded = alerts.clone();
+        let privacy_rpc = PrivacyRPC::new(
+            Config::builder()
+                .primary_rpc(&primary)
+                .shadow_endpoint(&shadow, 1.0)
+                .on_alert(move |alert| recorded.lock().unwrap().push(alert))
+                .build(),
+        );
+
+        let request = |method: &str| RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(serde_json::json!(1)),
+            method: method.to_string(),
+            params: Some(serde_json::json!(["Wallet111"])),
+        };
+        // The client always gets the primary's answer
+        let response = privacy_rpc.forward_request(request("getBalance")).await.unwrap();
+        assert_eq!(response.result.unwrap()["value"], 100);
+
+        for _ in 0..50 {
+            if !alerts.lock().unwrap().is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        {
+            let alerts = alerts.lock().unwrap();
+            assert_eq!(alerts.len(), 1);
+            assert!(matches!(alerts[0].alert_type, AlertType::ShadowMismatch));
+            assert_eq!(alerts[0].details.as_ref().unwrap()["method"], "getBalance");
+            assert!(!alerts[0].message.contains("shadow-secret"), "{}", alerts[0].message);
+        }
+
+        // Writes are never mirrored
+        privacy_rpc.forward_request(request("sendTransaction")).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(shadow_calls.load(Ordering::SeqCst), 1);
+    }
+}
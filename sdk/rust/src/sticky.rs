@@ -0,0 +1,160 @@
+//! Sticky session routing
+//!
+//! A dApp that sends a transaction and then polls for it wants both requests
+//! on the same node: a different node may not have seen the transaction yet.
+//! With a session TTL configured, requests carrying a session id (the
+//! `X-PrivacyRPC-Session` header or a `_session` field) start with the
+//! endpoint that last served that session. Failover still applies, and the
+//! session moves to whichever endpoint answered. When none did, it stays put.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Sessions kept before idle ones are pruned
+const MAX_SESSIONS: usize = 4096;
+
+/// Endpoint each session is pinned to, with when it was last used
+pub(crate) struct StickySessions {
+    ttl: Duration,
+    sessions: Mutex<HashMap<String, (String, Instant)>>,
+}
+
+impl StickySessions {
+    pub(crate) fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        !self.ttl.is_zero()
+    }
+
+    /// Endpoint `session` is pinned to, unless it has been idle past the TTL
+    pub(crate) fn get(&self, session: &str) -> Option<String> {
+        if !self.enabled() {
+            return None;
+        }
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        let (endpoint, used) = sessions.get_mut(session)?;
+        if used.elapsed() >= self.ttl {
+            sessions.remove(session);
+            return None;
+        }
+        *used = Instant::now();
+        Some(endpoint.clone())
+    }
+
+    /// Pin `session` to `endpoint`
+    pub(crate) fn pin(&self, session: &str, endpoint: &str) {
+        if !self.enabled() {
+            return;
+        }
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        if sessions.len() >= MAX_SESSIONS && !sessions.contains_key(session) {
+            let ttl = self.ttl;
+            sessions.retain(|_, (_, used)| used.elapsed() < ttl);
+        }
+        sessions.insert(session.to_string(), (endpoint.to_string(), Instant::now()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, LoadBalanceStrategy};
+
+    #[tokio::test]
+    async fn test_session_sticks_to_one_endpoint() {
+        let upstream = |name: &'static str| {
+            crate::tests::mock_upstream(move |_, req| {
+                let body = serde_json::json!({ "jsonrpc": "2.0", "id": req["id"], "result": name });
+                hyper::Response::new(hyper::Body::from(body.to_string()))
+            })
+        };
+        let a = upstream("a").await;
+        let b = upstream("b").await;
+        let (privacy_rpc, url) = crate::tests::start_proxy(
+            Config::builder()
+                .primary_rpc(&a)
+                .add_fallback(&b)
+                .load_balance(LoadBalanceStrategy::WeightedRandom(vec![1, 1]))
+                .sticky_sessions(Duration::from_secs(60)),
+        )
+        .await;
+
+        let client = reqwest::Client::new();
+        let send = |body: serde_json::Value, session: Option<&str>| {
+            let mut request = client.post(&url).json(&body);
+            if let Some(session) = session {
+                request = request.header("X-PrivacyRPC-Session", session);
+            }
+            async move { request.send().await.unwrap().json::<serde_json::Value>().await.unwrap() }
+        };
+        let send_tx = serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": "sendTransaction" });
+        let status = serde_json::json!({ "jsonrpc": "2.0", "id": 2, "method": "getSignatureStatuses" });
+
+        let first = send(send_tx, Some("session-1")).await["result"].clone();
+        for _ in 0..10 {
+            assert_eq!(send(status.clone(), Some("session-1")).await["result"], first);
+        }
+        // The body field works too, and is stripped before forwarding
+        let mut in_body = status.clone();
+        in_body["_session"] = serde_json::json!("session-1");
+        assert_eq!(send(in_body, None).await["result"], first);
+
+        privacy_rpc.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_session_kept_when_every_endpoint_fails() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+
+        let down = Arc::new(AtomicBool::new(false));
+        let upstream = |name: &'static str| {
+            let down = down.clone();
+            crate::tests::mock_upstream(move |_, req| {
+                if down.load(Ordering::SeqCst) {
+                    return hyper::Response::builder().status(503).body(hyper::Body::empty()).unwrap();
+                }
+                let body = serde_json::json!({ "jsonrpc": "2.0", "id": req["id"], "result": name });
+                hyper::Response::new(hyper::Body::from(body.to_string()))
+            })
+        };
+        let a = upstream("a").await;
+        let b = upstream("b").await;
+        let (privacy_rpc, url) = crate::tests::start_proxy(
+            Config::builder()
+                .primary_rpc(&a)
+                .add_fallback(&b)
+                .sticky_sessions(Duration::from_secs(60)),
+        )
+        .await;
+
+        let client = reqwest::Client::new();
+        let send = || async {
+            client
+                .post(&url)
+                .header("X-PrivacyRPC-Session", "session-1")
+                .json(&serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": "getSlot" }))
+                .send()
+                .await
+                .unwrap()
+                .json::<serde_json::Value>()
+                .await
+                .unwrap()
+        };
+        assert_eq!(send().await["result"], "a");
+
+        // Failing over to b and failing there too doesn't move the session to b
+        down.store(true, Ordering::SeqCst);
+        assert!(send().await["error"].is_object());
+        down.store(false, Ordering::SeqCst);
+        assert_eq!(privacy_rpc.sessions.get("session-1").as_deref(), Some(a.as_str()));
+
+        privacy_rpc.stop().await;
+    }
+}
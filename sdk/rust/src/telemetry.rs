@@ -0,0 +1,185 @@
+//! Optional `tracing` instrumentation
+//!
+//! With the `tracing` feature, each forwarded request runs inside an
+//! `rpc_request` span carrying its method, request id, endpoint host, status
+//! and latency, so logs from one request can be correlated and sampled. tracing's
+//! `log` bridge is enabled, so apps using a plain `log` logger still see the
+//! span events. Without the feature these types compile to nothing.
+
+use std::future::Future;
+
+/// Host of an endpoint URL, with its port unless the scheme's default, for
+/// metrics and span fields. The path, query and credentials are dropped, as
+/// providers put API keys there.
+pub fn endpoint_host(url: &str) -> String {
+    let Ok(url) = reqwest::Url::parse(url) else {
+        return "<redacted>".to_string();
+    };
+    match (url.host_str(), url.port()) {
+        (Some(host), Some(port)) => format!("{}:{}", host, port),
+        (Some(host), None) => host.to_string(),
+        (None, _) => "<redacted>".to_string(),
+    }
+}
+
+/// Span around one upstream request
+#[cfg(feature = "tracing")]
+pub struct RequestSpan {
+    span: tracing::Span,
+    started: std::time::Instant,
+}
+
+/// Span around one upstream request (disabled: built without `tracing`)
+#[cfg(not(feature = "tracing"))]
+pub struct RequestSpan;
+
+#[cfg(feature = "tracing")]
+impl RequestSpan {
+    pub fn new(method: &str, request_id: &str) -> Self {
+        use tracing::field::Empty;
+        Self {
+            span: tracing::info_span!(
+                "rpc_request",
+                method,
+                request_id,
+                endpoint = Empty,
+                status = Empty,
+                success = Empty,
+                latency_ms = Empty,
+            ),
+            started: std::time::Instant::now(),
+        }
+    }
+
+    /// Run `future` inside the span
+    pub fn instrument<F: Future>(&self, future: F) -> impl Future<Output = F::Output> {
+        tracing::Instrument::instrument(future, self.span.clone())
+    }
+
+    /// Record the outcome and latency, and log a completion event in the
+    /// span. Only the host of `endpoint` is recorded; see [`endpoint_host`].
+    pub fn finish(&self, endpoint: Option<&str>, status: Option<u16>, success: bool) {
+        let latency_ms = self.started.elapsed().as_millis() as u64;
+        if let Some(endpoint) = endpoint {
+            self.span.record("endpoint", endpoint_host(endpoint));
+        }
+        if let Some(status) = status {
+            self.span.record("status", status);
+        }
+        self.span.record("success", success);
+        self.span.record("latency_ms", latency_ms);
+        self.span.in_scope(|| tracing::info!(success, latency_ms, "request complete"));
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+impl RequestSpan {
+    pub fn new(_method: &str, _request_id: &str) -> Self {
+        Self
+    }
+
+    pub fn instrument<F: Future>(&self, future: F) -> F {
+        future
+    }
+
+    pub fn finish(&self, _endpoint: Option<&str>, _status: Option<u16>, _success: bool) {}
+}
+
+/// Run the proxy server inside a `privacyrpc_server` span naming its address
+#[cfg(feature = "tracing")]
+pub(crate) fn instrument_server<F: Future>(
+    server: F,
+    address: &str,
+) -> impl Future<Output = F::Output> {
+    tracing::Instrument::instrument(server, tracing::info_span!("privacyrpc_server", address))
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn instrument_server<F: Future>(server: F, _address: &str) -> F {
+    server
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+
+    /// A span's name and recorded fields
+    type CapturedSpan = (&'static str, HashMap<String, String>);
+
+    /// Every span created while installed, in creation order
+    #[derive(Clone, Default)]
+    struct SpanCapture {
+        spans: Arc<Mutex<Vec<CapturedSpan>>>,
+    }
+
+    struct FieldVisitor<'a>(&'a mut HashMap<String, String>);
+
+    impl Visit for FieldVisitor<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0.insert(field.name().to_string(), format!("{:?}", value));
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name().to_string(), value.to_string());
+        }
+    }
+
+    impl tracing::Subscriber for SpanCapture {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, attrs: &Attributes<'_>) -> Id {
+            let mut fields = HashMap::new();
+            attrs.record(&mut FieldVisitor(&mut fields));
+            let mut spans = self.spans.lock().unwrap();
+            spans.push((attrs.metadata().name(), fields));
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, span: &Id, values: &Record<'_>) {
+            let mut spans = self.spans.lock().unwrap();
+            let (_, fields) = &mut spans[span.into_u64() as usize - 1];
+            values.record(&mut FieldVisitor(fields));
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, _: &tracing::Event<'_>) {}
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[tokio::test]
+    async fn test_forward_records_span_fields() {
+        use crate::{Config, PrivacyRPC, RpcRequest};
+
+        let upstream = crate::tests::mock_upstream(|_, _| {
+            hyper::Response::new(hyper::Body::from(r#"{"jsonrpc":"2.0","id":1,"result":5}"#))
+        })
+        .await;
+
+        let capture = SpanCapture::default();
+        let _guard = tracing::subscriber::set_default(capture.clone());
+        // The API key in the path stays out of the span
+        let privacy_rpc = PrivacyRPC::new(Config::builder().primary_rpc(&format!("{}/v2/secret", upstream)).build());
+        let request = RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(serde_json::json!(1)),
+            method: "getSlot".to_string(),
+            params: None,
+        };
+        privacy_rpc.forward_request(request).await.unwrap();
+
+        let spans = capture.spans.lock().unwrap();
+        let (_, fields) = spans.iter().find(|(name, _)| *name == "rpc_request").unwrap();
+        assert_eq!(fields["method"], "getSlot");
+        assert!(!fields["request_id"].is_empty());
+        assert_eq!(fields["endpoint"], upstream.trim_start_matches("http://"));
+        assert_eq!(fields["status"], "200");
+        assert_eq!(fields["success"], "true");
+        assert!(fields.contains_key("latency_ms"));
+    }
+}
@@ -0,0 +1,169 @@
+//! Chain tip polling
+//!
+//! `getSlot` and `getBlockHeight` change every slot, yet latency-sensitive apps
+//! ask for them constantly. With a poll interval configured, a background task
+//! keeps both fresh and plain requests for them are answered from memory,
+//! marked with `_cached_at`. Requests with params other than the default
+//! commitment still go upstream. Each polled slot is also reported to the
+//! response cache, which drops account reads once the slot advances.
+
+use crate::{
+    apply_default_commitment, forward_to_rpc, next_request_id, Config, EndpointTracker, ResponseCache, RpcRequest,
+    RpcResponse, UpstreamAttempt, UpstreamLimiter,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Methods kept fresh by the poller
+const TIP_METHODS: &[&str] = &["getSlot", "getBlockHeight"];
+
+/// Polls missed before cached values are considered too stale to serve
+pub(crate) const MAX_MISSED_POLLS: u32 = 3;
+
+struct TipValue {
+    result: serde_json::Value,
+    fetched: Instant,
+    /// Unix milliseconds, reported as `_cached_at`
+    fetched_at: u64,
+}
+
+/// Latest polled tip values, keyed by method and params
+pub(crate) struct ChainTip {
+    interval: Duration,
+    values: Mutex<HashMap<String, TipValue>>,
+}
+
+impl ChainTip {
+    pub(crate) fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            values: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Key for `request`, or `None` if the poller doesn't cover it
+    fn key(&self, request: &RpcRequest) -> Option<String> {
+        if self.interval.is_zero() || !TIP_METHODS.contains(&request.method.as_str()) {
+            return None;
+        }
+        // `[]` asks the same as no params
+        let params = request
+            .params
+            .as_ref()
+            .filter(|p| p.as_array().is_none_or(|a| !a.is_empty()))
+            .map(|p| p.to_string())
+            .unwrap_or_default();
+        Some(format!("{}:{}", request.method, params))
+    }
+
+    /// The polled value for `request`, unless it has gone stale
+    pub(crate) fn get(&self, request: &RpcRequest) -> Option<RpcResponse> {
+        let key = self.key(request)?;
+        let values = self.values.lock().unwrap_or_else(|e| e.into_inner());
+        let value = values.get(&key)?;
+        if value.fetched.elapsed() > self.interval * MAX_MISSED_POLLS {
+            return None;
+        }
+        Some(RpcResponse {
+            cached_at: Some(value.fetched_at),
+            ..RpcResponse::success(request.id.clone(), value.result.clone())
+        })
+    }
+
+    fn set(&self, request: &RpcRequest, result: serde_json::Value) {
+        let Some(key) = self.key(request) else {
+            return;
+        };
+        let value = TipValue {
+            result,
+            fetched: Instant::now(),
+            fetched_at: chrono::Utc::now().timestamp_millis() as u64,
+        };
+        self.values.lock().unwrap_or_else(|e| e.into_inner()).insert(key, value);
+    }
+}
+
+/// Refresh the tip every `interval` until aborted
+pub(crate) async fn poll(
+    config: Config,
+    endpoints: Arc<EndpointTracker>,
+    upstream: Arc<UpstreamLimiter>,
+    tip: Arc<ChainTip>,
+    cache: Arc<ResponseCache>,
+) {
+    let mut ticker = tokio::time::interval(tip.interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        for method in TIP_METHODS {
+            let mut request = RpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: Some(serde_json::json!(1)),
+                method: method.to_string(),
+                params: None,
+            };
+            // Polled the way a plain client request arrives, so their keys match
+            apply_default_commitment(&config, &mut request);
+            let mut attempt = UpstreamAttempt::default();
+            let response =
+                forward_to_rpc(&config, &endpoints, &upstream, &request, &next_request_id(), &mut attempt).await;
+            if let Ok(RpcResponse { result: Some(result), error: None, .. }) = response {
+                if request.method == "getSlot" {
+                    if let Some(slot) = result.as_u64() {
+                        cache.observe_slot(slot);
+                    }
+                }
+                tip.set(&request, result);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    #[tokio::test]
+    async fn test_slot_served_from_poller() {
+        let polls = Arc::new(AtomicU64::new(0));
+        let counter = polls.clone();
+        let url = crate::tests::mock_upstream(move |_, req| {
+            let result = match req["method"].as_str() {
+                Some("getSlot") => serde_json::json!(counter.fetch_add(1, Ordering::SeqCst) + 1000),
+                _ => serde_json::json!(900),
+            };
+            let body = serde_json::json!({ "jsonrpc": "2.0", "id": req["id"], "result": result });
+            hyper::Response::new(hyper::Body::from(body.to_string()))
+        })
+        .await;
+        let (privacy_rpc, proxy_url) = crate::tests::start_proxy(
+            Config::builder()
+                .primary_rpc(&url)
+                .poll_chain_tip(Duration::from_millis(100)),
+        )
+        .await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let client = reqwest::Client::new();
+        let before = polls.load(Ordering::SeqCst);
+        for _ in 0..10 {
+            let body = serde_json::json!({ "jsonrpc": "2.0", "id": 7, "method": "getSlot", "params": [] });
+            let response: serde_json::Value =
+                client.post(&proxy_url).json(&body).send().await.unwrap().json().await.unwrap();
+            assert!(response["result"].as_u64().unwrap() >= 1000);
+            assert_eq!(response["id"], 7);
+            assert!(response["_cached_at"].is_u64());
+        }
+        // Ten requests, but only the poller reached upstream
+        assert!(polls.load(Ordering::SeqCst) - before < 10);
+
+        // And it keeps polling with no client traffic
+        let before = polls.load(Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(350)).await;
+        assert!(polls.load(Ordering::SeqCst) >= before + 2);
+
+        privacy_rpc.stop().await;
+    }
+}
@@ -0,0 +1,208 @@
+//! TLS for the proxy listener
+//!
+//! With a certificate and key configured ([`ConfigBuilder::tls`]) the proxy
+//! serves HTTPS, so it can be exposed to clients on other machines without
+//! their requests (and API keys) crossing the network in the clear. Handshakes
+//! run off the accept loop, so a slow client can't hold up the others.
+//! [`acceptor`] is public for hosts that run their own accept loop.
+//!
+//! [`ConfigBuilder::tls`]: crate::ConfigBuilder::tls
+
+use crate::Error;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use tokio_rustls::rustls::crypto::{ring, CryptoProvider};
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use tokio_rustls::rustls::{self, ClientConfig, DigitallySignedStruct, ServerConfig, SignatureScheme};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+/// Clients that haven't finished the handshake by then are dropped
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Handshakes completed but not yet picked up by the server
+const ACCEPT_BACKLOG: usize = 128;
+
+fn load_certificates(cert: &Path) -> Result<Vec<CertificateDer<'static>>, Error> {
+    let invalid = |e: rustls::pki_types::pem::Error| {
+        Error::ConfigError(format!("Invalid TLS certificate {}: {}", cert.display(), e))
+    };
+    let certs = CertificateDer::pem_file_iter(cert)
+        .map_err(invalid)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(invalid)?;
+    if certs.is_empty() {
+        return Err(Error::ConfigError(format!("No certificates in {}", cert.display())));
+    }
+    Ok(certs)
+}
+
+/// Build the acceptor for `cert` (PEM, leaf first) and `key` (PEM)
+pub fn acceptor(cert: &Path, key: &Path) -> Result<TlsAcceptor, Error> {
+    let certs = load_certificates(cert)?;
+    let key = PrivateKeyDer::from_pem_file(key)
+        .map_err(|e| Error::ConfigError(format!("Invalid TLS key {}: {}", key.display(), e)))?;
+    let mut config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+        .map_err(|e| Error::ConfigError(format!("TLS configuration rejected: {}", e)))?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Accept connections on `listener` and hand over each once its handshake
+/// completes. Failed handshakes are dropped.
+pub(crate) fn incoming(listener: TcpListener, acceptor: TlsAcceptor) -> mpsc::Receiver<TlsStream<TcpStream>> {
+    let (tx, rx) = mpsc::channel(ACCEPT_BACKLOG);
+    tokio::spawn(async move {
+        loop {
+            let stream = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(_) => continue,
+                },
+                // The server is gone
+                _ = tx.closed() => return,
+            };
+            let (acceptor, tx) = (acceptor.clone(), tx.clone());
+            tokio::spawn(async move {
+                if let Ok(Ok(stream)) = tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    let _ = tx.send(stream).await;
+                }
+            });
+        }
+    });
+    rx
+}
+
+/// Connect to our own listener over TLS, trusting exactly the configured
+/// certificate: it may be self-signed and won't name the loopback address
+pub(crate) async fn connect_own(
+    address: &str,
+    cert: &Path,
+) -> Result<tokio_rustls::client::TlsStream<TcpStream>, Error> {
+    let leaf = load_certificates(cert)?.swap_remove(0);
+    let provider = Arc::new(ring::default_provider());
+    let config = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| Error::ConfigError(e.to_string()))?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(OwnCertificate { leaf, provider }))
+        .with_no_client_auth();
+    let stream = TcpStream::connect(address)
+        .await
+        .map_err(|e| Error::ServerError(e.to_string()))?;
+    let name = ServerName::try_from("localhost").expect("valid server name");
+    TlsConnector::from(Arc::new(config))
+        .connect(name, stream)
+        .await
+        .map_err(|e| Error::ServerError(e.to_string()))
+}
+
+/// Accepts only the one certificate, still checking the handshake signatures
+#[derive(Debug)]
+struct OwnCertificate {
+    leaf: CertificateDer<'static>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for OwnCertificate {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if end_entity.as_ref() == self.leaf.as_ref() {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General("Not the configured proxy certificate".to_string()))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Config, PrivacyRPC};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_rustls::rustls::pki_types::pem::PemObject;
+    use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName};
+    use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+
+    #[tokio::test]
+    async fn test_tls_listener_with_self_signed_cert() {
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let dir = std::env::temp_dir().join(format!("privacyrpc-tls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (cert_path, key_path) = (dir.join("cert.pem"), dir.join("key.pem"));
+        std::fs::write(&cert_path, certified.cert.pem()).unwrap();
+        std::fs::write(&key_path, certified.key_pair.serialize_pem()).unwrap();
+
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let privacy_rpc = Arc::new(PrivacyRPC::new(
+            Config::builder().proxy_port(port).tls(&cert_path, &key_path).build(),
+        ));
+        let server = privacy_rpc.clone();
+        tokio::spawn(async move { server.start().await });
+        let url = privacy_rpc.wait_until_ready(Duration::from_secs(5)).await.unwrap();
+        assert_eq!(url, format!("https://127.0.0.1:{}", port));
+
+        // An ordinary rustls client that trusts the self-signed certificate
+        let mut roots = RootCertStore::empty();
+        roots.add(CertificateDer::from_pem_file(&cert_path).unwrap()).unwrap();
+        let config = ClientConfig::builder().with_root_certificates(roots).with_no_client_auth();
+        let tcp = tokio::net::TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let mut tls = tokio_rustls::TlsConnector::from(Arc::new(config))
+            .connect(ServerName::try_from("localhost").unwrap(), tcp)
+            .await
+            .unwrap();
+        tls.write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        tls.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+
+        // Plain HTTP on the same port gets nowhere
+        let plain = tokio::net::TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        assert!(!crate::health_check(plain).await);
+
+        privacy_rpc.stop().await;
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
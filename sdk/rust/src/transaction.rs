@@ -0,0 +1,1923 @@
+//! Solana Transaction Decoder
+//! Parses base64/base58 encoded transactions and extracts human-readable info
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{LazyLock, Mutex};
+
+// Well-known Solana program IDs
+const SYSTEM_PROGRAM: &str = "11111111111111111111111111111111";
+const TOKEN_PROGRAM: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+const TOKEN_2022_PROGRAM: &str = "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb";
+const ASSOCIATED_TOKEN_PROGRAM: &str = "ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL";
+const MEMO_PROGRAM: &str = "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr";
+const COMPUTE_BUDGET_PROGRAM: &str = "ComputeBudget111111111111111111111111111111";
+
+// Known drainer/scam program patterns (for detection)
+const SUSPICIOUS_PROGRAMS: &[&str] = &[
+    // Add known malicious program IDs here as they're discovered
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DecodedTransaction {
+    pub summary: String,
+    pub instructions: Vec<DecodedInstruction>,
+    pub warnings: Vec<TransactionWarning>,
+    pub accounts_involved: Vec<String>,
+    pub estimated_cost: Option<f64>, // in SOL
+    pub risk_level: RiskLevel,
+    /// Signatures the transaction requires (each pays the base fee)
+    #[serde(default)]
+    pub required_signatures: u8,
+    /// Every signature is still an empty placeholder, as wallets send
+    /// transactions to be signed
+    #[serde(default)]
+    pub unsigned: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DecodedInstruction {
+    pub program: String,
+    pub program_id: String,
+    pub action: String,
+    pub details: InstructionDetails,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type")]
+pub enum InstructionDetails {
+    SolTransfer {
+        from: String,
+        to: String,
+        amount_lamports: u64,
+        amount_sol: f64,
+    },
+    TokenTransfer {
+        from: String,
+        to: String,
+        amount: u64,
+        decimals: Option<u8>,
+        /// Known for `TransferChecked`, which names the mint
+        mint: Option<String>,
+    },
+    /// `MintTo` or `MintToChecked`: new `mint` tokens credited to `account`
+    TokenMintTo {
+        mint: String,
+        account: String,
+        amount: u64,
+        decimals: Option<u8>,
+    },
+    /// `Burn` or `BurnChecked`: `account`'s `mint` tokens destroyed
+    TokenBurn {
+        account: String,
+        mint: String,
+        amount: u64,
+        decimals: Option<u8>,
+    },
+    TokenApprove {
+        source: String,
+        delegate: String,
+        amount: u64,
+    },
+    TokenRevoke {
+        source: String,
+    },
+    /// System `CreateAccount`, or `CreateAccountWithSeed` when `seed` is set
+    CreateAccount {
+        funder: String,
+        new_account: String,
+        lamports: u64,
+        space: u64,
+        owner: String,
+        seed: Option<String>,
+    },
+    /// Associated Token `Create`, or `CreateIdempotent` when `idempotent` is
+    /// set: `funder` pays for `account`, the `owner`'s token account for `mint`
+    CreateAssociatedTokenAccount {
+        funder: String,
+        account: String,
+        owner: String,
+        mint: String,
+        idempotent: bool,
+    },
+    /// System `Assign`: `account` becomes owned by `owner`
+    AssignOwner {
+        account: String,
+        owner: String,
+    },
+    AdvanceNonce {
+        nonce_account: String,
+        authority: String,
+    },
+    SetComputeLimit {
+        units: u32,
+    },
+    SetComputePrice {
+        micro_lamports: u64,
+    },
+    Unknown {
+        data_preview: String,
+        accounts: Vec<String>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TransactionWarning {
+    pub level: WarningLevel,
+    pub title: String,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum WarningLevel {
+    Info,
+    Warning,
+    Danger,
+}
+
+/// Ordered from least to most risky
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RiskLevel {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+// Network fee parameters
+const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+const DEFAULT_UNITS_PER_INSTRUCTION: u64 = 200_000;
+const MAX_COMPUTE_UNITS: u64 = 1_400_000;
+
+impl DecodedTransaction {
+    /// Network fee in lamports: the base fee per signature plus the priority
+    /// fee set by Compute Budget instructions
+    pub fn fee_lamports(&self) -> u64 {
+        let mut units = None;
+        let mut micro_lamports_per_unit = 0;
+        let mut other_instructions = 0;
+        for instruction in &self.instructions {
+            match instruction.details {
+                InstructionDetails::SetComputeLimit { units: u } => units = Some(u as u64),
+                InstructionDetails::SetComputePrice { micro_lamports } => micro_lamports_per_unit = micro_lamports,
+                _ => other_instructions += 1,
+            }
+        }
+        // Without an explicit limit the runtime budgets a default per instruction
+        let units = units
+            .unwrap_or(other_instructions * DEFAULT_UNITS_PER_INSTRUCTION)
+            .min(MAX_COMPUTE_UNITS);
+        let priority_fee = (units as u128 * micro_lamports_per_unit as u128).div_ceil(1_000_000) as u64;
+
+        LAMPORTS_PER_SIGNATURE * self.required_signatures as u64 + priority_fee
+    }
+
+    /// Note that this transaction is being sent unsigned. Only for
+    /// `sendTransaction`: a transaction handed to a wallet is meant to be unsigned.
+    pub fn flag_unsigned_send(&mut self) {
+        if self.unsigned && !self.warnings.iter().any(|w| w.title == "Unsigned Transaction") {
+            self.warnings.push(TransactionWarning {
+                level: WarningLevel::Info,
+                title: "Unsigned Transaction".into(),
+                message: "Every signature is an empty placeholder: this transaction has not been signed yet.".into(),
+            });
+        }
+    }
+}
+
+/// Thresholds the decoder warns at. The defaults suit most wallets; a large
+/// holder may raise them to avoid routine warnings, a cautious user lower them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DecoderConfig {
+    /// SOL sent above which a "High Value Transaction" warning is added and
+    /// the risk is at least Medium
+    pub high_value_sol: f64,
+    /// SOL sent above which the risk is High
+    pub critical_value_sol: f64,
+    /// Token amount (base units) above which an approval is flagged as large
+    pub large_approval_amount: u64,
+}
+
+impl Default for DecoderConfig {
+    fn default() -> Self {
+        Self {
+            high_value_sol: 1.0,
+            critical_value_sol: 10.0,
+            large_approval_amount: 1_000_000_000,
+        }
+    }
+}
+
+/// Decoded transactions kept by `decode_transaction`
+const DECODE_CACHE_SIZE: usize = 256;
+
+/// Recently decoded transactions keyed by their encoded form, so a wallet
+/// re-reading a pending transaction doesn't re-parse it. Least recently used
+/// entries are evicted first.
+#[derive(Default)]
+struct DecodeCache {
+    entries: HashMap<String, (u64, DecodedTransaction)>,
+    /// Bumped on every access; an entry's value is its last use
+    clock: u64,
+}
+
+impl DecodeCache {
+    fn get(&mut self, encoded: &str) -> Option<DecodedTransaction> {
+        self.clock += 1;
+        let (used, decoded) = self.entries.get_mut(encoded)?;
+        *used = self.clock;
+        Some(decoded.clone())
+    }
+
+    fn insert(&mut self, encoded: &str, decoded: &DecodedTransaction) {
+        if self.entries.len() >= DECODE_CACHE_SIZE {
+            let oldest = self.entries.iter().min_by_key(|(_, (used, _))| *used).map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.clock += 1;
+        self.entries.insert(encoded.to_string(), (self.clock, decoded.clone()));
+    }
+}
+
+static DECODE_CACHE: LazyLock<Mutex<DecodeCache>> = LazyLock::new(Default::default);
+
+/// Decode a transaction from base64 or base58 encoding
+pub fn decode_transaction(encoded: &str) -> Result<DecodedTransaction, String> {
+    decode_transaction_with_config(encoded, &DecoderConfig::default())
+}
+
+/// Decode a transaction, warning at the thresholds in `config`
+pub fn decode_transaction_with_config(encoded: &str, config: &DecoderConfig) -> Result<DecodedTransaction, String> {
+    // Only default-threshold decodes are cached; the cache is keyed by transaction alone
+    if *config != DecoderConfig::default() {
+        return decode_uncached(encoded, config);
+    }
+    if let Some(decoded) = DECODE_CACHE.lock().unwrap_or_else(|e| e.into_inner()).get(encoded) {
+        return Ok(decoded);
+    }
+    let decoded = decode_uncached(encoded, config)?;
+    DECODE_CACHE.lock().unwrap_or_else(|e| e.into_inner()).insert(encoded, &decoded);
+    Ok(decoded)
+}
+
+fn decode_uncached(encoded: &str, config: &DecoderConfig) -> Result<DecodedTransaction, String> {
+    // Try base64 first (most common for signTransaction)
+    let tx_bytes = if let Ok(bytes) = BASE64.decode(encoded) {
+        bytes
+    } else if let Ok(bytes) = bs58::decode(encoded).into_vec() {
+        bytes
+    } else {
+        return Err("Failed to decode transaction: not valid base64 or base58".into());
+    };
+
+    parse_transaction_bytes(&tx_bytes, config)
+}
+
+/// Decode a bare base64 message, as passed to `getFeeForMessage`
+pub fn decode_message(message_base64: &str) -> Result<DecodedTransaction, String> {
+    let bytes = BASE64
+        .decode(message_base64)
+        .map_err(|e| format!("Invalid base64: {}", e))?;
+    parse_message(&bytes, None, &DecoderConfig::default())
+}
+
+/// The first signature of a transaction, which Solana uses as its id, in
+/// base58. `encoding` is the `sendTransaction` encoding (`base58` or `base64`).
+pub fn transaction_signature(encoded: &str, encoding: &str) -> Result<String, String> {
+    let bytes = match encoding {
+        "base64" => BASE64.decode(encoded).map_err(|e| format!("Invalid base64: {}", e))?,
+        "base58" => bs58::decode(encoded).into_vec().map_err(|e| format!("Invalid base58: {}", e))?,
+        other => return Err(format!("Unsupported encoding: {}", other)),
+    };
+    let (num_signatures, offset) = read_compact_u16(&bytes, 0)?;
+    if num_signatures == 0 {
+        return Err("Transaction has no signatures".into());
+    }
+    let signature = bytes
+        .get(offset..offset + 64)
+        .ok_or_else(|| "Transaction too short".to_string())?;
+    Ok(bs58::encode(signature).into_string())
+}
+
+/// Parse raw transaction bytes
+fn parse_transaction_bytes(bytes: &[u8], config: &DecoderConfig) -> Result<DecodedTransaction, String> {
+    #[cfg(test)]
+    tests::PARSES.with(|parses| parses.set(parses.get() + 1));
+    if bytes.len() < 4 {
+        return Err("Transaction too short".into());
+    }
+
+    let mut offset = 0;
+
+    // Read number of signatures (compact-u16)
+    let (num_signatures, sig_len) = read_compact_u16(bytes, offset)?;
+    offset += sig_len;
+    check_count(num_signatures, 64, bytes.len() - offset, "signature")?;
+
+    // Signatures (each is 64 bytes)
+    let signatures = &bytes[offset..offset + (num_signatures as usize) * 64];
+    offset += signatures.len();
+
+    if offset >= bytes.len() {
+        return Err("Transaction truncated after signatures".into());
+    }
+
+    // Parse the message
+    let message_bytes = &bytes[offset..];
+    parse_message(message_bytes, Some(signatures), config)
+}
+
+/// Reject a declared `count` of items at least `min_size` bytes each that
+/// can't fit in the `remaining` bytes, before looping over them
+fn check_count(count: u16, min_size: usize, remaining: usize, item: &str) -> Result<(), String> {
+    if count as usize * min_size > remaining {
+        return Err(format!(
+            "Transaction declares {} {}s but only {} bytes remain",
+            count, item, remaining
+        ));
+    }
+    Ok(())
+}
+
+/// Warnings about a transaction's signatures (64 bytes each) that don't
+/// match the `required` count its message header declares
+fn signature_warnings(signatures: &[u8], required: u8) -> Vec<TransactionWarning> {
+    let mut warnings = Vec::new();
+    let count = signatures.len() / 64;
+    if count != required as usize {
+        warnings.push(TransactionWarning {
+            level: WarningLevel::Warning,
+            title: "Signature Count Mismatch".into(),
+            message: format!(
+                "This transaction carries {} signature(s) but its message requires {}; it is malformed and may not be what it appears.",
+                count, required
+            ),
+        });
+    }
+    warnings
+}
+
+/// Parse the transaction message; `signatures` are those that preceded it,
+/// if it came from a full transaction
+fn parse_message(bytes: &[u8], signatures: Option<&[u8]>, config: &DecoderConfig) -> Result<DecodedTransaction, String> {
+    if bytes.is_empty() {
+        return Err("Empty message".into());
+    }
+
+    let mut offset = 0;
+
+    // Message header (3 bytes)
+    if bytes.len() < 3 {
+        return Err("Message header too short".into());
+    }
+    let num_required_signatures = bytes[0];
+    let _num_readonly_signed = bytes[1];
+    let _num_readonly_unsigned = bytes[2];
+    offset += 3;
+
+    // Read account keys
+    let (num_accounts, len) = read_compact_u16(bytes, offset)?;
+    offset += len;
+    check_count(num_accounts, 32, bytes.len() - offset, "account key")?;
+
+    let mut account_keys: Vec<String> = Vec::with_capacity(num_accounts as usize);
+    for _ in 0..num_accounts {
+        if offset + 32 > bytes.len() {
+            return Err("Account keys truncated".into());
+        }
+        let pubkey = bs58::encode(&bytes[offset..offset + 32]).into_string();
+        account_keys.push(pubkey);
+        offset += 32;
+    }
+
+    // Recent blockhash (32 bytes)
+    if offset + 32 > bytes.len() {
+        return Err("Recent blockhash truncated".into());
+    }
+    offset += 32;
+
+    // Read instructions
+    let (num_instructions, len) = read_compact_u16(bytes, offset)?;
+    offset += len;
+    // Program index plus two empty compact-u16 lengths
+    check_count(num_instructions, 3, bytes.len() - offset, "instruction")?;
+
+    let mut instructions: Vec<DecodedInstruction> = Vec::new();
+    let mut warnings = signatures
+        .map(|signatures| signature_warnings(signatures, num_required_signatures))
+        .unwrap_or_default();
+    let mut total_sol_out: f64 = 0.0;
+
+    for _ in 0..num_instructions {
+        if offset >= bytes.len() {
+            break;
+        }
+
+        // Program ID index
+        let program_id_index = bytes[offset] as usize;
+        offset += 1;
+
+        let program_id = account_keys
+            .get(program_id_index)
+            .cloned()
+            .unwrap_or_else(|| "Unknown".to_string());
+
+        // Account indices
+        let (num_accounts, len) = read_compact_u16(bytes, offset)?;
+        offset += len;
+        check_count(num_accounts, 1, bytes.len() - offset, "instruction account")?;
+
+        let mut account_indices: Vec<usize> = Vec::new();
+        for _ in 0..num_accounts {
+            if offset >= bytes.len() {
+                break;
+            }
+            account_indices.push(bytes[offset] as usize);
+            offset += 1;
+        }
+
+        // Instruction data
+        let (data_len, len) = read_compact_u16(bytes, offset)?;
+        offset += len;
+        check_count(data_len, 1, bytes.len() - offset, "instruction data byte")?;
+
+        let instruction_data = if offset + data_len as usize <= bytes.len() {
+            bytes[offset..offset + data_len as usize].to_vec()
+        } else {
+            Vec::new()
+        };
+        offset += data_len as usize;
+
+        // Decode the instruction based on program
+        let decoded = decode_instruction(
+            &program_id,
+            &account_indices,
+            &instruction_data,
+            &account_keys,
+        )?;
+
+        // Track SOL outflows
+        if let InstructionDetails::SolTransfer { amount_sol, .. } = &decoded.details {
+            total_sol_out += amount_sol;
+        }
+
+        // Check for suspicious patterns
+        if SUSPICIOUS_PROGRAMS.contains(&program_id.as_str()) {
+            warnings.push(TransactionWarning {
+                level: WarningLevel::Danger,
+                title: "Known Malicious Program".into(),
+                message: format!("This transaction interacts with a known drainer: {}", program_id),
+            });
+        }
+
+        // Handing a signer's account to another program gives that program
+        // full control of it
+        if let InstructionDetails::AssignOwner { account, owner } = &decoded.details {
+            let signer_index = account_keys
+                .iter()
+                .position(|key| key == account)
+                .filter(|&i| i < num_required_signatures as usize);
+            if let (Some(index), false) = (signer_index, owner == SYSTEM_PROGRAM) {
+                warnings.push(TransactionWarning {
+                    level: WarningLevel::Danger,
+                    title: "Account Ownership Change".into(),
+                    message: format!(
+                        "This reassigns {} {} to program {}, which can then take everything it holds.",
+                        if index == 0 { "your fee-paying account" } else { "signing account" },
+                        shorten_address(account),
+                        owner
+                    ),
+                });
+            }
+        }
+
+        // Paying to set up a token account for someone else is common (sending
+        // them a token they've never held) but is also how some drainers
+        // stage a transfer, so say whose account it is
+        if let InstructionDetails::CreateAssociatedTokenAccount { owner, mint, .. } = &decoded.details {
+            let owner_signs = account_keys
+                .iter()
+                .take(num_required_signatures as usize)
+                .any(|key| key == owner);
+            if !owner_signs {
+                warnings.push(TransactionWarning {
+                    level: WarningLevel::Info,
+                    title: "Token Account For Another Wallet".into(),
+                    message: format!(
+                        "This creates a token account for mint {} owned by {}, which is not a signer of this transaction.",
+                        shorten_address(mint),
+                        owner
+                    ),
+                });
+            }
+        }
+
+        if let InstructionDetails::AdvanceNonce { .. } = &decoded.details {
+            warnings.push(TransactionWarning {
+                level: WarningLevel::Info,
+                title: "Durable Nonce".into(),
+                message: "This transaction uses a durable nonce and never expires; it can be submitted long after signing.".into(),
+            });
+        }
+
+        // Check for token approvals (potential for unlimited drain)
+        if let InstructionDetails::TokenApprove { amount, .. } = &decoded.details {
+            if *amount == u64::MAX {
+                warnings.push(TransactionWarning {
+                    level: WarningLevel::Danger,
+                    title: "Unlimited Token Approval".into(),
+                    message: "This approves UNLIMITED tokens to be spent. This is extremely risky!".into(),
+                });
+            } else if *amount > config.large_approval_amount {
+                warnings.push(TransactionWarning {
+                    level: WarningLevel::Warning,
+                    title: "Large Token Approval".into(),
+                    message: format!("Approving {} tokens - verify this is intended.", amount),
+                });
+            }
+        }
+
+        instructions.push(decoded);
+    }
+
+    // Calculate risk level
+    let risk_level = calculate_risk_level(&instructions, &warnings, total_sol_out, config);
+
+    // Generate summary
+    let summary = generate_summary(&instructions, total_sol_out, num_required_signatures);
+
+    // Add warnings for high-value transactions
+    if total_sol_out > config.high_value_sol {
+        warnings.push(TransactionWarning {
+            level: WarningLevel::Warning,
+            title: "High Value Transaction".into(),
+            message: format!("This transaction sends {:.4} SOL", total_sol_out),
+        });
+    }
+
+    Ok(DecodedTransaction {
+        summary,
+        instructions,
+        warnings,
+        accounts_involved: account_keys,
+        estimated_cost: Some(total_sol_out),
+        risk_level,
+        required_signatures: num_required_signatures,
+        // Wallets send zeroed placeholders for signatures still to be added
+        unsigned: signatures.is_some_and(|s| !s.is_empty() && s.iter().all(|&b| b == 0)),
+    })
+}
+
+/// Decode a single instruction
+fn decode_instruction(
+    program_id: &str,
+    account_indices: &[usize],
+    data: &[u8],
+    account_keys: &[String],
+) -> Result<DecodedInstruction, String> {
+    let get_account = |idx: usize| -> String {
+        account_indices
+            .get(idx)
+            .and_then(|&i| account_keys.get(i))
+            .cloned()
+            .unwrap_or_else(|| "Unknown".to_string())
+    };
+
+    Ok(match program_id {
+        p if p == SYSTEM_PROGRAM => return decode_system_instruction(data, &get_account),
+        p if p == TOKEN_PROGRAM || p == TOKEN_2022_PROGRAM => {
+            decode_token_instruction(data, &get_account, program_id)
+        }
+        p if p == COMPUTE_BUDGET_PROGRAM => decode_compute_budget_instruction(data),
+        p if p == ASSOCIATED_TOKEN_PROGRAM => {
+            let accounts = account_indices
+                .iter()
+                .filter_map(|&i| account_keys.get(i).cloned())
+                .collect();
+            decode_associated_token_instruction(data, &get_account, accounts)
+        }
+        p if p == MEMO_PROGRAM => DecodedInstruction {
+            program: "Memo".into(),
+            program_id: program_id.to_string(),
+            action: "Add Memo".into(),
+            details: InstructionDetails::Unknown {
+                data_preview: String::from_utf8_lossy(data).to_string(),
+                accounts: account_indices
+                    .iter()
+                    .filter_map(|&i| account_keys.get(i).cloned())
+                    .collect(),
+            },
+        },
+        _ => DecodedInstruction {
+            program: shorten_address(program_id),
+            program_id: program_id.to_string(),
+            action: "Unknown Program Call".into(),
+            details: InstructionDetails::Unknown {
+                data_preview: if data.len() > 32 {
+                    format!("{}...", hex::encode(&data[..32]))
+                } else {
+                    hex::encode(data)
+                },
+                accounts: account_indices
+                    .iter()
+                    .filter_map(|&i| account_keys.get(i).cloned())
+                    .collect(),
+            },
+        },
+    })
+}
+
+/// Decode System Program instruction
+fn decode_system_instruction<F: Fn(usize) -> String>(
+    data: &[u8],
+    get_account: &F,
+) -> Result<DecodedInstruction, String> {
+    if data.is_empty() {
+        return Ok(DecodedInstruction {
+            program: "System".into(),
+            program_id: SYSTEM_PROGRAM.to_string(),
+            action: "Unknown".into(),
+            details: InstructionDetails::Unknown {
+                data_preview: "".into(),
+                accounts: vec![],
+            },
+        });
+    }
+
+    // System program instruction discriminator is first 4 bytes (little-endian u32)
+    let instruction_type = if data.len() >= 4 {
+        u32::from_le_bytes([data[0], data[1], data[2], data[3]])
+    } else {
+        data[0] as u32
+    };
+
+    Ok(match instruction_type {
+        2 => {
+            // Transfer
+            let lamports = if data.len() >= 12 {
+                u64::from_le_bytes(data[4..12].try_into().unwrap_or([0; 8]))
+            } else {
+                0
+            };
+            let sol = lamports as f64 / 1_000_000_000.0;
+
+            DecodedInstruction {
+                program: "System".into(),
+                program_id: SYSTEM_PROGRAM.to_string(),
+                action: format!("Transfer {:.6} SOL", sol),
+                details: InstructionDetails::SolTransfer {
+                    from: get_account(0),
+                    to: get_account(1),
+                    amount_lamports: lamports,
+                    amount_sol: sol,
+                },
+            }
+        }
+        0 => {
+            // lamports (u64), space (u64), owner (32 bytes)
+            let lamports = read_u64(data, 4).unwrap_or(0);
+            DecodedInstruction {
+                program: "System".into(),
+                program_id: SYSTEM_PROGRAM.to_string(),
+                action: "Create Account".into(),
+                details: InstructionDetails::CreateAccount {
+                    funder: get_account(0),
+                    new_account: get_account(1),
+                    lamports,
+                    space: read_u64(data, 12).unwrap_or(0),
+                    owner: read_pubkey(data, 20).unwrap_or_else(|| "Unknown".to_string()),
+                    seed: None,
+                },
+            }
+        }
+        1 => {
+            let owner = read_pubkey(data, 4).unwrap_or_else(|| "Unknown".to_string());
+            DecodedInstruction {
+                program: "System".into(),
+                program_id: SYSTEM_PROGRAM.to_string(),
+                action: format!("Assign to {}", shorten_address(&owner)),
+                details: InstructionDetails::AssignOwner {
+                    account: get_account(0),
+                    owner,
+                },
+            }
+        }
+        3 => {
+            // base (32 bytes), seed (u64 length + UTF-8), lamports, space, owner
+            let seed_len = read_u64(data, 36).unwrap_or(0);
+            let rest = usize::try_from(seed_len)
+                .ok()
+                .and_then(|len| 44usize.checked_add(len))
+                .filter(|&rest| rest <= data.len())
+                .ok_or_else(|| format!("Seed length {} exceeds instruction data", seed_len))?;
+            let seed = Some(String::from_utf8_lossy(&data[44..rest]).to_string());
+            DecodedInstruction {
+                program: "System".into(),
+                program_id: SYSTEM_PROGRAM.to_string(),
+                action: "Create Account With Seed".into(),
+                details: InstructionDetails::CreateAccount {
+                    funder: get_account(0),
+                    new_account: get_account(1),
+                    lamports: read_u64(data, rest).unwrap_or(0),
+                    space: read_u64(data, rest + 8).unwrap_or(0),
+                    owner: read_pubkey(data, rest + 16).unwrap_or_else(|| "Unknown".to_string()),
+                    seed,
+                },
+            }
+        }
+        4 => DecodedInstruction {
+            program: "System".into(),
+            program_id: SYSTEM_PROGRAM.to_string(),
+            action: "Advance Nonce".into(),
+            // Accounts: nonce, RecentBlockhashes sysvar, authority
+            details: InstructionDetails::AdvanceNonce {
+                nonce_account: get_account(0),
+                authority: get_account(2),
+            },
+        },
+        _ => DecodedInstruction {
+            program: "System".into(),
+            program_id: SYSTEM_PROGRAM.to_string(),
+            action: format!("System Instruction #{}", instruction_type),
+            details: InstructionDetails::Unknown {
+                data_preview: hex::encode(data),
+                accounts: vec![],
+            },
+        },
+    })
+}
+
+/// Decode Associated Token Account program instruction
+fn decode_associated_token_instruction<F: Fn(usize) -> String>(
+    data: &[u8],
+    get_account: &F,
+    accounts: Vec<String>,
+) -> DecodedInstruction {
+    // Create predates the instruction enum and carries no data at all
+    let idempotent = match data.first() {
+        None | Some(0) => false,
+        Some(1) => true,
+        Some(&kind) => {
+            return DecodedInstruction {
+                program: "Associated Token".into(),
+                program_id: ASSOCIATED_TOKEN_PROGRAM.to_string(),
+                action: if kind == 2 { "Recover Nested Account".into() } else { "Unknown".into() },
+                details: InstructionDetails::Unknown {
+                    data_preview: hex::encode(data),
+                    accounts,
+                },
+            };
+        }
+    };
+
+    // Accounts: funder, associated token account, owner, mint, System, token program
+    let owner = get_account(2);
+    DecodedInstruction {
+        program: "Associated Token".into(),
+        program_id: ASSOCIATED_TOKEN_PROGRAM.to_string(),
+        action: format!(
+            "Create Token Account for {}{}",
+            shorten_address(&owner),
+            if idempotent { " (Idempotent)" } else { "" }
+        ),
+        details: InstructionDetails::CreateAssociatedTokenAccount {
+            funder: get_account(0),
+            account: get_account(1),
+            owner,
+            mint: get_account(3),
+            idempotent,
+        },
+    }
+}
+
+/// Decode Token Program instruction
+fn decode_token_instruction<F: Fn(usize) -> String>(
+    data: &[u8],
+    get_account: &F,
+    program_id: &str,
+) -> DecodedInstruction {
+    if data.is_empty() {
+        return DecodedInstruction {
+            program: "Token".into(),
+            program_id: program_id.to_string(),
+            action: "Unknown".into(),
+            details: InstructionDetails::Unknown {
+                data_preview: "".into(),
+                accounts: vec![],
+            },
+        };
+    }
+
+    let instruction_type = data[0];
+
+    match instruction_type {
+        3 => {
+            // Transfer
+            let amount = if data.len() >= 9 {
+                u64::from_le_bytes(data[1..9].try_into().unwrap_or([0; 8]))
+            } else {
+                0
+            };
+
+            DecodedInstruction {
+                program: "Token".into(),
+                program_id: program_id.to_string(),
+                action: format!("Transfer {} tokens", amount),
+                details: InstructionDetails::TokenTransfer {
+                    from: get_account(0),
+                    to: get_account(1),
+                    amount,
+                    decimals: None,
+                    mint: None,
+                },
+            }
+        }
+        4 => {
+            // Approve
+            let amount = if data.len() >= 9 {
+                u64::from_le_bytes(data[1..9].try_into().unwrap_or([0; 8]))
+            } else {
+                0
+            };
+
+            DecodedInstruction {
+                program: "Token".into(),
+                program_id: program_id.to_string(),
+                action: if amount == u64::MAX {
+                    "Approve UNLIMITED tokens".into()
+                } else {
+                    format!("Approve {} tokens", amount)
+                },
+                details: InstructionDetails::TokenApprove {
+                    source: get_account(0),
+                    delegate: get_account(1),
+                    amount,
+                },
+            }
+        }
+        5 => {
+            // Revoke
+            DecodedInstruction {
+                program: "Token".into(),
+                program_id: program_id.to_string(),
+                action: "Revoke Approval".into(),
+                details: InstructionDetails::TokenRevoke {
+                    source: get_account(0),
+                },
+            }
+        }
+        7 | 14 => {
+            // MintTo / MintToChecked: mint, destination, authority
+            let amount = read_u64(data, 1).unwrap_or(0);
+            let decimals = if instruction_type == 14 { data.get(9).copied() } else { None };
+            DecodedInstruction {
+                program: "Token".into(),
+                program_id: program_id.to_string(),
+                action: format!("Mint {} tokens", amount),
+                details: InstructionDetails::TokenMintTo {
+                    mint: get_account(0),
+                    account: get_account(1),
+                    amount,
+                    decimals,
+                },
+            }
+        }
+        8 | 15 => {
+            // Burn / BurnChecked: account, mint, owner
+            let amount = read_u64(data, 1).unwrap_or(0);
+            let decimals = if instruction_type == 15 { data.get(9).copied() } else { None };
+            DecodedInstruction {
+                program: "Token".into(),
+                program_id: program_id.to_string(),
+                action: format!("Burn {} tokens", amount),
+                details: InstructionDetails::TokenBurn {
+                    account: get_account(0),
+                    mint: get_account(1),
+                    amount,
+                    decimals,
+                },
+            }
+        }
+        12 => {
+            // TransferChecked
+            let amount = if data.len() >= 9 {
+                u64::from_le_bytes(data[1..9].try_into().unwrap_or([0; 8]))
+            } else {
+                0
+            };
+            let decimals = if data.len() >= 10 { Some(data[9]) } else { None };
+
+            DecodedInstruction {
+                program: "Token".into(),
+                program_id: program_id.to_string(),
+                action: format!("Transfer {} tokens (checked)", amount),
+                details: InstructionDetails::TokenTransfer {
+                    from: get_account(0),
+                    to: get_account(2), // TransferChecked has mint at index 1
+                    amount,
+                    decimals,
+                    mint: Some(get_account(1)),
+                },
+            }
+        }
+        _ => DecodedInstruction {
+            program: "Token".into(),
+            program_id: program_id.to_string(),
+            action: format!("Token Instruction #{}", instruction_type),
+            details: InstructionDetails::Unknown {
+                data_preview: hex::encode(data),
+                accounts: vec![],
+            },
+        },
+    }
+}
+
+/// Future returned by [`MintResolver::mint_data`]
+pub type MintDataFuture<'a> = Pin<Box<dyn Future<Output = Result<Option<Vec<u8>>, String>> + Send + 'a>>;
+
+/// Fetches raw account data so Token-2022 extensions can be inspected: mints,
+/// and the token accounts a plain `Transfer` names, whose data holds their mint
+pub trait MintResolver: Send + Sync {
+    fn mint_data<'a>(&'a self, mint: &'a str) -> MintDataFuture<'a>;
+}
+
+/// Fetches mint accounts with `getAccountInfo` from a JSON-RPC endpoint
+pub struct RpcMintResolver {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl RpcMintResolver {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+impl MintResolver for RpcMintResolver {
+    fn mint_data<'a>(&'a self, mint: &'a str) -> MintDataFuture<'a> {
+        Box::pin(async move {
+            let body = serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "getAccountInfo",
+                "params": [mint, { "encoding": "base64" }],
+            });
+            let resp: serde_json::Value = self
+                .client
+                .post(&self.url)
+                .json(&body)
+                .send()
+                .await
+                .map_err(|e| format!("getAccountInfo for {} failed: {}", mint, e))?
+                .json()
+                .await
+                .map_err(|e| format!("getAccountInfo for {} returned invalid JSON: {}", mint, e))?;
+
+            // A null value means the mint account doesn't exist
+            match resp["result"]["value"]["data"][0].as_str() {
+                Some(data) => BASE64
+                    .decode(data)
+                    .map(Some)
+                    .map_err(|e| format!("Mint {} data is not base64: {}", mint, e)),
+                None => Ok(None),
+            }
+        })
+    }
+}
+
+// Token-2022 mint layout: base mint padded to the 165-byte account size,
+// then an account type byte, then TLV extensions (u16 type, u16 length, value)
+const TOKEN_2022_ACCOUNT_TYPE_OFFSET: usize = 165;
+const ACCOUNT_TYPE_MINT: u8 = 1;
+const ACCOUNT_TYPE_ACCOUNT: u8 = 2;
+const EXTENSION_TRANSFER_FEE_CONFIG: u16 = 1;
+const EXTENSION_DEFAULT_ACCOUNT_STATE: u16 = 6;
+const EXTENSION_PERMANENT_DELEGATE: u16 = 12;
+
+/// Mints named by the transaction's Token-2022 instructions. A plain
+/// `Transfer` doesn't name its mint; see [`token_2022_unresolved_sources`].
+pub fn token_2022_mints(decoded: &DecodedTransaction) -> Vec<String> {
+    let mut mints: Vec<String> = Vec::new();
+    for instruction in &decoded.instructions {
+        if instruction.program_id != TOKEN_2022_PROGRAM {
+            continue;
+        }
+        let mint = match &instruction.details {
+            InstructionDetails::TokenTransfer { mint: Some(mint), .. }
+            | InstructionDetails::TokenMintTo { mint, .. }
+            | InstructionDetails::TokenBurn { mint, .. } => mint,
+            _ => continue,
+        };
+        if !mints.contains(mint) {
+            mints.push(mint.clone());
+        }
+    }
+    mints
+}
+
+/// Source token accounts of Token-2022 transfers that don't name their mint
+pub fn token_2022_unresolved_sources(decoded: &DecodedTransaction) -> Vec<String> {
+    let mut sources: Vec<String> = Vec::new();
+    for instruction in &decoded.instructions {
+        if instruction.program_id != TOKEN_2022_PROGRAM {
+            continue;
+        }
+        if let InstructionDetails::TokenTransfer { from, mint: None, .. } = &instruction.details {
+            if !sources.contains(from) {
+                sources.push(from.clone());
+            }
+        }
+    }
+    sources
+}
+
+/// Mint of a token account, from its data (the mint is the first field)
+fn token_account_mint(data: &[u8]) -> Option<String> {
+    // Plain accounts are exactly 165 bytes; extended ones carry their type
+    // after that, which tells them apart from an extended mint
+    let is_account = data.len() == TOKEN_2022_ACCOUNT_TYPE_OFFSET
+        || data.get(TOKEN_2022_ACCOUNT_TYPE_OFFSET) == Some(&ACCOUNT_TYPE_ACCOUNT);
+    if !is_account {
+        return None;
+    }
+    read_pubkey(data, 0)
+}
+
+/// Fetch each Token-2022 mint the transaction touches and add warnings for
+/// risky extensions. Mints that can't be fetched are skipped. The risk is
+/// recalculated with `config`, the thresholds the transaction was decoded with.
+pub async fn check_token_2022_mints(
+    decoded: &mut DecodedTransaction,
+    resolver: &dyn MintResolver,
+    config: &DecoderConfig,
+) {
+    let mut mints = token_2022_mints(decoded);
+    for source in token_2022_unresolved_sources(decoded) {
+        if let Ok(Some(data)) = resolver.mint_data(&source).await {
+            match token_account_mint(&data) {
+                Some(mint) if !mints.contains(&mint) => mints.push(mint),
+                _ => {}
+            }
+        }
+    }
+    for mint in mints {
+        if let Ok(Some(data)) = resolver.mint_data(&mint).await {
+            decoded.warnings.extend(mint_extension_warnings(&mint, &data));
+        }
+    }
+    decoded.risk_level = calculate_risk_level(
+        &decoded.instructions,
+        &decoded.warnings,
+        decoded.estimated_cost.unwrap_or(0.0),
+        config,
+    );
+}
+
+/// Warnings for the extensions set on a Token-2022 mint account
+pub fn mint_extension_warnings(mint: &str, data: &[u8]) -> Vec<TransactionWarning> {
+    let mut warnings = Vec::new();
+    if data.get(TOKEN_2022_ACCOUNT_TYPE_OFFSET) != Some(&ACCOUNT_TYPE_MINT) {
+        return warnings;
+    }
+
+    let mut offset = TOKEN_2022_ACCOUNT_TYPE_OFFSET + 1;
+    while offset + 4 <= data.len() {
+        let ext_type = u16::from_le_bytes([data[offset], data[offset + 1]]);
+        let len = u16::from_le_bytes([data[offset + 2], data[offset + 3]]) as usize;
+        offset += 4;
+        let Some(value) = data.get(offset..offset + len) else {
+            break;
+        };
+        offset += len;
+
+        match ext_type {
+            EXTENSION_PERMANENT_DELEGATE if len == 32 && value.iter().any(|&b| b != 0) => {
+                warnings.push(TransactionWarning {
+                    level: WarningLevel::Warning,
+                    title: "Permanent Delegate".into(),
+                    message: format!(
+                        "Token {} has a permanent delegate ({}) that can transfer or burn your tokens at any time",
+                        shorten_address(mint),
+                        shorten_address(&bs58::encode(value).into_string())
+                    ),
+                });
+            }
+            EXTENSION_TRANSFER_FEE_CONFIG if len == 108 => {
+                // Newer fee: epoch (u64), maximum_fee (u64), basis_points (u16)
+                let maximum_fee = u64::from_le_bytes(value[98..106].try_into().unwrap());
+                let basis_points = u16::from_le_bytes([value[106], value[107]]);
+                if basis_points > 0 {
+                    warnings.push(TransactionWarning {
+                        level: WarningLevel::Warning,
+                        title: "Transfer Fee".into(),
+                        message: format!(
+                            "Token {} charges a {:.2}% fee on every transfer (up to {} tokens)",
+                            shorten_address(mint),
+                            basis_points as f64 / 100.0,
+                            maximum_fee
+                        ),
+                    });
+                }
+            }
+            // Account state 2 = Frozen
+            EXTENSION_DEFAULT_ACCOUNT_STATE if value == [2] => {
+                warnings.push(TransactionWarning {
+                    level: WarningLevel::Info,
+                    title: "Frozen by Default".into(),
+                    message: format!(
+                        "New accounts for token {} start frozen until the issuer thaws them",
+                        shorten_address(mint)
+                    ),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    warnings
+}
+
+/// Decode Compute Budget Program instruction
+fn decode_compute_budget_instruction(data: &[u8]) -> DecodedInstruction {
+    if data.is_empty() {
+        return DecodedInstruction {
+            program: "Compute Budget".into(),
+            program_id: COMPUTE_BUDGET_PROGRAM.to_string(),
+            action: "Unknown".into(),
+            details: InstructionDetails::Unknown {
+                data_preview: "".into(),
+                accounts: vec![],
+            },
+        };
+    }
+
+    match data[0] {
+        2 => {
+            // SetComputeUnitLimit
+            let units = if data.len() >= 5 {
+                u32::from_le_bytes(data[1..5].try_into().unwrap_or([0; 4]))
+            } else {
+                0
+            };
+            DecodedInstruction {
+                program: "Compute Budget".into(),
+                program_id: COMPUTE_BUDGET_PROGRAM.to_string(),
+                action: format!("Set compute limit to {} units", units),
+                details: InstructionDetails::SetComputeLimit { units },
+            }
+        }
+        3 => {
+            // SetComputeUnitPrice
+            let micro_lamports = if data.len() >= 9 {
+                u64::from_le_bytes(data[1..9].try_into().unwrap_or([0; 8]))
+            } else {
+                0
+            };
+            DecodedInstruction {
+                program: "Compute Budget".into(),
+                program_id: COMPUTE_BUDGET_PROGRAM.to_string(),
+                action: format!("Set priority fee to {} micro-lamports/CU", micro_lamports),
+                details: InstructionDetails::SetComputePrice { micro_lamports },
+            }
+        }
+        _ => DecodedInstruction {
+            program: "Compute Budget".into(),
+            program_id: COMPUTE_BUDGET_PROGRAM.to_string(),
+            action: format!("Compute Budget #{}", data[0]),
+            details: InstructionDetails::Unknown {
+                data_preview: hex::encode(data),
+                accounts: vec![],
+            },
+        },
+    }
+}
+
+/// Little-endian u64 at `offset` of instruction data
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset.checked_add(8)?)?;
+    Some(u64::from_le_bytes(bytes.try_into().ok()?))
+}
+
+/// Base58 public key at `offset` of instruction data
+fn read_pubkey(data: &[u8], offset: usize) -> Option<String> {
+    let bytes = data.get(offset..offset.checked_add(32)?)?;
+    Some(bs58::encode(bytes).into_string())
+}
+
+/// Read a compact-u16 (Solana's variable-length encoding): 7 bits per byte,
+/// low bits first, high bit set on every byte but the last. At most three
+/// bytes, and like Solana's `short_vec` only the shortest encoding of a value
+/// is accepted, so each value has exactly one byte representation.
+fn read_compact_u16(bytes: &[u8], offset: usize) -> Result<(u16, usize), String> {
+    if offset >= bytes.len() {
+        return Err("Offset out of bounds".into());
+    }
+
+    let mut value: u32 = 0;
+    for i in 0..3 {
+        let byte = *bytes.get(offset + i).ok_or("Compact-u16 truncated")?;
+        if i == 2 && byte > 0x03 {
+            // The third byte carries bits 14-15 only; anything more overflows a u16
+            return Err("Compact-u16 overflows u16".into());
+        }
+        value |= u32::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            if i > 0 && byte == 0 {
+                return Err("Compact-u16 not minimally encoded".into());
+            }
+            return Ok((value as u16, i + 1));
+        }
+    }
+    // Not reached: a third byte of at most 0x03 ends the value
+    Err("Compact-u16 overflows u16".into())
+}
+
+/// Calculate risk level based on transaction contents
+fn calculate_risk_level(
+    instructions: &[DecodedInstruction],
+    warnings: &[TransactionWarning],
+    total_sol_out: f64,
+    config: &DecoderConfig,
+) -> RiskLevel {
+    // Any danger warnings = Critical
+    if warnings.iter().any(|w| w.level == WarningLevel::Danger) {
+        return RiskLevel::Critical;
+    }
+
+    // High value transfers
+    if total_sol_out > config.critical_value_sol {
+        return RiskLevel::High;
+    }
+    if total_sol_out > config.high_value_sol {
+        return RiskLevel::Medium;
+    }
+
+    // Many unknown program calls
+    let unknown_count = instructions
+        .iter()
+        .filter(|i| matches!(i.details, InstructionDetails::Unknown { .. }))
+        .count();
+
+    if unknown_count > 3 {
+        return RiskLevel::Medium;
+    }
+
+    // Token approvals
+    let has_approval = instructions
+        .iter()
+        .any(|i| matches!(i.details, InstructionDetails::TokenApprove { .. }));
+
+    if has_approval {
+        return RiskLevel::Medium;
+    }
+
+    RiskLevel::Low
+}
+
+/// Generate human-readable summary
+fn generate_summary(instructions: &[DecodedInstruction], total_sol_out: f64, _num_sigs: u8) -> String {
+    let mut parts: Vec<String> = Vec::new();
+
+    // Count instruction types
+    let mut sol_transfers = 0;
+    let mut token_transfers = 0;
+    let mut approvals = 0;
+    let mut unknown = 0;
+
+    for inst in instructions {
+        match &inst.details {
+            InstructionDetails::SolTransfer { .. } => sol_transfers += 1,
+            InstructionDetails::TokenTransfer { .. } => token_transfers += 1,
+            InstructionDetails::TokenApprove { .. } => approvals += 1,
+            InstructionDetails::Unknown { .. } => unknown += 1,
+            _ => {}
+        }
+    }
+
+    if sol_transfers > 0 {
+        parts.push(format!("Transfer {:.4} SOL", total_sol_out));
+    }
+    if token_transfers > 0 {
+        parts.push(format!(
+            "{} token transfer{}",
+            token_transfers,
+            if token_transfers > 1 { "s" } else { "" }
+        ));
+    }
+    if approvals > 0 {
+        parts.push(format!(
+            "{} token approval{}",
+            approvals,
+            if approvals > 1 { "s" } else { "" }
+        ));
+    }
+    if unknown > 0 {
+        parts.push(format!(
+            "{} program call{}",
+            unknown,
+            if unknown > 1 { "s" } else { "" }
+        ));
+    }
+
+    if parts.is_empty() {
+        "Transaction with no detected transfers".into()
+    } else {
+        parts.join(", ")
+    }
+}
+
+/// Shorten an address for display
+fn shorten_address(addr: &str) -> String {
+    if addr.len() > 12 {
+        format!("{}...{}", &addr[..4], &addr[addr.len() - 4..])
+    } else {
+        addr.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::sol_transfer_bytes;
+
+    #[test]
+    fn test_decode_empty_transaction() {
+        let result = decode_transaction("");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_truncated_transactions_rejected() {
+        let bytes = sol_transfer_bytes(1_000_000);
+        for len in 0..bytes.len() {
+            assert!(parse_transaction_bytes(&bytes[..len], &DecoderConfig::default()).is_err(), "prefix of {} bytes parsed", len);
+        }
+        assert!(parse_transaction_bytes(&bytes, &DecoderConfig::default()).is_ok());
+
+        // Random garbage never panics (fixed-seed LCG, no rand dependency)
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        for _ in 0..2_000 {
+            let len = (seed % 300) as usize;
+            let garbage: Vec<u8> = (0..len)
+                .map(|_| {
+                    seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                    (seed >> 56) as u8
+                })
+                .collect();
+            let _ = parse_transaction_bytes(&garbage, &DecoderConfig::default());
+        }
+    }
+
+    fn encode_compact_u16(mut value: u16) -> Vec<u8> {
+        let mut out = Vec::new();
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                out.push(byte);
+                return out;
+            }
+            out.push(byte | 0x80);
+        }
+    }
+
+    #[test]
+    fn test_compact_u16_round_trip_and_boundaries() {
+        for value in 0..=u16::MAX {
+            let mut bytes = encode_compact_u16(value);
+            let len = bytes.len();
+            // Trailing bytes are left alone
+            bytes.push(0xff);
+            assert_eq!(read_compact_u16(&bytes, 0), Ok((value, len)), "{}", value);
+        }
+
+        assert_eq!(read_compact_u16(&[0x7f], 0), Ok((127, 1)));
+        assert_eq!(read_compact_u16(&[0xff, 0x7f], 0), Ok((16383, 2)));
+        assert_eq!(read_compact_u16(&[0x80, 0x80, 0x01], 0), Ok((16384, 3)));
+        assert_eq!(read_compact_u16(&[0xff, 0xff, 0x03], 0), Ok((65535, 3)));
+
+        for malformed in [
+            &[0xff, 0xff, 0x04][..], // 65536
+            &[0x80, 0x80, 0x04],     // would wrap to 0
+            &[0xff, 0xff, 0x83],     // a fourth byte
+            &[0xff, 0xff, 0xff, 0x01],
+            &[0x80, 0x00], // 0 in two bytes
+            &[0xff, 0x80, 0x00],
+            &[0x80],
+            &[0xff, 0xff],
+            &[],
+        ] {
+            assert!(read_compact_u16(malformed, 0).is_err(), "{:02x?}", malformed);
+        }
+    }
+
+    #[test]
+    fn test_compact_u16_every_three_byte_input() {
+        // Whatever the input, a decoded value re-encodes to exactly the bytes read
+        let mut decoded = [0usize; 4];
+        for input in 0..1u32 << 24 {
+            let bytes = input.to_le_bytes();
+            if let Ok((value, len)) = read_compact_u16(&bytes[..3], 0) {
+                assert_eq!(encode_compact_u16(value), bytes[..len], "{:02x?}", &bytes[..3]);
+                decoded[len] += 1;
+            }
+        }
+        // Each value is accepted in one form only, counted once per unread suffix
+        assert_eq!(decoded[1], 128 << 16);
+        assert_eq!(decoded[2], (16384 - 128) << 8);
+        assert_eq!(decoded[3], 65536 - 16384);
+    }
+
+    #[test]
+    fn test_oversized_counts_rejected_early() {
+        // 0xff 0xff 0x03 is the largest compact-u16, 65535
+        let mut signatures = vec![0xff, 0xff, 0x03];
+        signatures.extend([0u8; 100]);
+        let err = parse_transaction_bytes(&signatures, &DecoderConfig::default()).unwrap_err();
+        assert!(err.contains("65535 signatures"), "{}", err);
+
+        let mut accounts = vec![1u8];
+        accounts.extend([0u8; 64]);
+        accounts.extend([1, 0, 0, 0xff, 0xff, 0x03]);
+        accounts.extend([0u8; 64]);
+        let err = parse_transaction_bytes(&accounts, &DecoderConfig::default()).unwrap_err();
+        assert!(err.contains("account keys"), "{}", err);
+
+        let mut bytes = sol_transfer_bytes(1);
+        // Instruction count sits after 3 keys and the blockhash
+        let at = 1 + 64 + 3 + 1 + 32 * 4;
+        assert_eq!(bytes[at], 1);
+        bytes.splice(at..at + 1, [0xff, 0xff, 0x03]);
+        let err = parse_transaction_bytes(&bytes, &DecoderConfig::default()).unwrap_err();
+        assert!(err.contains("instructions"), "{}", err);
+    }
+
+    thread_local! {
+        /// Calls to `parse_transaction_bytes` on this test's thread
+        pub(super) static PARSES: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
+    #[test]
+    fn test_repeat_decode_served_from_cache() {
+        let encoded = BASE64.encode(sol_transfer_bytes(1_234_567));
+        let first = decode_transaction(&encoded).unwrap();
+        let parses = PARSES.with(|p| p.get());
+        let second = decode_transaction(&encoded).unwrap();
+        assert_eq!(PARSES.with(|p| p.get()), parses);
+        assert_eq!(second.estimated_cost, first.estimated_cost);
+
+        // Evicted once enough other transactions have been decoded since
+        for lamports in 0..DECODE_CACHE_SIZE as u64 {
+            decode_transaction(&BASE64.encode(sol_transfer_bytes(lamports))).unwrap();
+        }
+        decode_transaction(&encoded).unwrap();
+        assert!(PARSES.with(|p| p.get()) > parses);
+    }
+
+    #[test]
+    fn test_custom_thresholds_apply_at_boundary() {
+        let config = DecoderConfig {
+            high_value_sol: 5.0,
+            critical_value_sol: 20.0,
+            ..Default::default()
+        };
+        let decode = |lamports: u64| {
+            decode_transaction_with_config(&BASE64.encode(sol_transfer_bytes(lamports)), &config).unwrap()
+        };
+        let high_value = |decoded: &DecodedTransaction| {
+            decoded.warnings.iter().any(|w| w.title == "High Value Transaction")
+        };
+
+        // Exactly at a threshold is still below it
+        let at = decode(5_000_000_000);
+        assert!(!high_value(&at));
+        assert_eq!(at.risk_level, RiskLevel::Low);
+        let over = decode(5_000_000_001);
+        assert!(high_value(&over));
+        assert_eq!(over.risk_level, RiskLevel::Medium);
+
+        assert_eq!(decode(20_000_000_000).risk_level, RiskLevel::Medium);
+        assert_eq!(decode(20_000_000_001).risk_level, RiskLevel::High);
+
+        // The same 5 SOL warns under the defaults
+        let default = decode_transaction(&BASE64.encode(sol_transfer_bytes(5_000_000_000))).unwrap();
+        assert!(high_value(&default));
+    }
+
+    #[test]
+    fn test_decode_sol_transfer() {
+        let encoded = BASE64.encode(sol_transfer_bytes(2_500_000_000));
+        let decoded = decode_transaction(&encoded).unwrap();
+
+        assert_eq!(decoded.instructions.len(), 1);
+        assert_eq!(decoded.instructions[0].program_id, SYSTEM_PROGRAM);
+        assert_eq!(decoded.estimated_cost, Some(2.5));
+        assert_eq!(decoded.risk_level, RiskLevel::Medium);
+        assert!(decoded.warnings.iter().any(|w| w.title == "High Value Transaction"));
+        assert_eq!(decoded.fee_lamports(), 5_000);
+    }
+
+    #[test]
+    fn test_fee_includes_priority_fee() {
+        let mut decoded = decode_transaction(&BASE64.encode(sol_transfer_bytes(1))).unwrap();
+        decoded.instructions.push(decode_compute_budget_instruction(&[2, 0x40, 0x0d, 0x03, 0x00]));
+        let mut price = vec![3];
+        price.extend(1_000_000u64.to_le_bytes());
+        decoded.instructions.push(decode_compute_budget_instruction(&price));
+
+        // 200,000 units at 1 lamport each, plus one signature
+        assert_eq!(decoded.fee_lamports(), 205_000);
+    }
+
+    #[test]
+    fn test_decode_base58_transaction() {
+        let encoded = bs58::encode(sol_transfer_bytes(20_000_000_000)).into_string();
+        let decoded = decode_transaction(&encoded).unwrap();
+        assert_eq!(decoded.risk_level, RiskLevel::High);
+        assert_eq!(decoded.accounts_involved.len(), 3);
+    }
+
+    /// Legacy transaction with one System instruction whose `accounts` index
+    /// into [payer, [2; 32], [3; 32], System], signed by the first `signers` keys
+    fn system_instruction_bytes(signers: u8, accounts: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut tx = vec![signers];
+        tx.extend(vec![7u8; 64 * signers as usize]);
+        tx.extend([signers, 0, 1]);
+        tx.push(4);
+        tx.extend([1u8; 32]);
+        tx.extend([2u8; 32]);
+        tx.extend([3u8; 32]);
+        tx.extend([0u8; 32]); // System program
+        tx.extend([9u8; 32]);
+        tx.extend([1, 3, accounts.len() as u8]);
+        tx.extend(accounts);
+        tx.push(data.len() as u8);
+        tx.extend(data);
+        tx
+    }
+
+    fn assign_data(owner: [u8; 32]) -> Vec<u8> {
+        let mut data = 1u32.to_le_bytes().to_vec();
+        data.extend(owner);
+        data
+    }
+
+    #[test]
+    fn test_assign_of_signer_warns() {
+        let encoded = BASE64.encode(system_instruction_bytes(1, &[0], &assign_data([5u8; 32])));
+        let decoded = decode_transaction(&encoded).unwrap();
+        assert!(matches!(
+            &decoded.instructions[0].details,
+            InstructionDetails::AssignOwner { owner, .. } if *owner == bs58::encode([5u8; 32]).into_string()
+        ));
+        let warning = decoded.warnings.iter().find(|w| w.title == "Account Ownership Change").unwrap();
+        assert_eq!(warning.level, WarningLevel::Danger);
+        assert!(warning.message.contains("fee-paying"));
+        assert_eq!(decoded.risk_level, RiskLevel::Critical);
+
+        // A second signer is flagged too
+        let encoded = BASE64.encode(system_instruction_bytes(2, &[1], &assign_data([5u8; 32])));
+        let decoded = decode_transaction(&encoded).unwrap();
+        assert_eq!(decoded.risk_level, RiskLevel::Critical);
+
+        // Assigning an account the user doesn't sign for, or back to System, is not
+        let encoded = BASE64.encode(system_instruction_bytes(1, &[1], &assign_data([5u8; 32])));
+        assert!(decode_transaction(&encoded).unwrap().warnings.is_empty());
+        let encoded = BASE64.encode(system_instruction_bytes(1, &[0], &assign_data([0u8; 32])));
+        assert!(decode_transaction(&encoded).unwrap().warnings.is_empty());
+    }
+
+    #[test]
+    fn test_decode_advance_nonce() {
+        let encoded = BASE64.encode(system_instruction_bytes(1, &[1, 2, 0], &4u32.to_le_bytes()));
+        let decoded = decode_transaction(&encoded).unwrap();
+        match &decoded.instructions[0].details {
+            InstructionDetails::AdvanceNonce { nonce_account, authority } => {
+                assert_eq!(*nonce_account, bs58::encode([2u8; 32]).into_string());
+                assert_eq!(*authority, bs58::encode([1u8; 32]).into_string());
+            }
+            other => panic!("unexpected details: {:?}", other),
+        }
+        assert_eq!(decoded.warnings.len(), 1);
+        assert_eq!(decoded.warnings[0].level, WarningLevel::Info);
+        assert_eq!(decoded.risk_level, RiskLevel::Low);
+    }
+
+    #[test]
+    fn test_oversized_seed_length_rejected() {
+        let mut data = 3u32.to_le_bytes().to_vec();
+        data.extend([1u8; 32]);
+        data.extend(u64::MAX.to_le_bytes());
+        data.extend(b"seed");
+        let encoded = BASE64.encode(system_instruction_bytes(1, &[0, 1, 0], &data));
+        let err = decode_transaction(&encoded).unwrap_err();
+        assert!(err.contains("Seed length"), "{}", err);
+    }
+
+    #[test]
+    fn test_decode_create_account_with_seed() {
+        let mut data = 3u32.to_le_bytes().to_vec();
+        data.extend([1u8; 32]);
+        data.extend(4u64.to_le_bytes());
+        data.extend(b"seed");
+        data.extend(1_000_000u64.to_le_bytes());
+        data.extend(165u64.to_le_bytes());
+        data.extend([5u8; 32]);
+        let encoded = BASE64.encode(system_instruction_bytes(1, &[0, 1, 0], &data));
+        let decoded = decode_transaction(&encoded).unwrap();
+
+        assert_eq!(decoded.instructions[0].action, "Create Account With Seed");
+        match &decoded.instructions[0].details {
+            InstructionDetails::CreateAccount { new_account, lamports, space, owner, seed, .. } => {
+                assert_eq!(*new_account, bs58::encode([2u8; 32]).into_string());
+                assert_eq!(*lamports, 1_000_000);
+                assert_eq!(*space, 165);
+                assert_eq!(*owner, bs58::encode([5u8; 32]).into_string());
+                assert_eq!(seed.as_deref(), Some("seed"));
+            }
+            other => panic!("unexpected details: {:?}", other),
+        }
+    }
+
+    /// Token-2022 mint account data carrying the given TLV extensions
+    fn token_2022_mint_data(extensions: &[(u16, Vec<u8>)]) -> Vec<u8> {
+        let mut data = vec![0u8; TOKEN_2022_ACCOUNT_TYPE_OFFSET];
+        data.push(ACCOUNT_TYPE_MINT);
+        for (ext_type, value) in extensions {
+            data.extend(ext_type.to_le_bytes());
+            data.extend((value.len() as u16).to_le_bytes());
+            data.extend(value);
+        }
+        data
+    }
+
+    fn transfer_fee_config(basis_points: u16) -> Vec<u8> {
+        let mut value = vec![0u8; 106];
+        value[98..106].copy_from_slice(&5_000u64.to_le_bytes());
+        value.extend(basis_points.to_le_bytes());
+        value
+    }
+
+    /// Resolver returning the same mint data for every mint
+    struct StubMintResolver(Vec<u8>);
+
+    impl MintResolver for StubMintResolver {
+        fn mint_data<'a>(&'a self, _mint: &'a str) -> MintDataFuture<'a> {
+            let data = self.0.clone();
+            Box::pin(async move { Ok(Some(data)) })
+        }
+    }
+
+    /// Resolver serving account data by address
+    struct AccountsResolver(HashMap<String, Vec<u8>>);
+
+    impl MintResolver for AccountsResolver {
+        fn mint_data<'a>(&'a self, address: &'a str) -> MintDataFuture<'a> {
+            let data = self.0.get(address).cloned();
+            Box::pin(async move { Ok(data) })
+        }
+    }
+
+    /// Legacy transaction with one Token-2022 instruction over [owner,
+    /// source, destination, mint] (indices 0 to 3)
+    fn token_2022_instruction_bytes(accounts: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut tx = vec![1u8];
+        tx.extend([7u8; 64]);
+        tx.extend([1, 0, 1]);
+        tx.push(5);
+        tx.extend([1u8; 32]); // owner
+        tx.extend([2u8; 32]); // source
+        tx.extend([3u8; 32]); // destination
+        tx.extend([4u8; 32]); // mint
+        tx.extend(bs58::decode(TOKEN_2022_PROGRAM).into_vec().unwrap());
+        tx.extend([9u8; 32]);
+        tx.push(1);
+        tx.push(4);
+        tx.push(accounts.len() as u8);
+        tx.extend(accounts);
+        tx.push(data.len() as u8);
+        tx.extend(data);
+        tx
+    }
+
+    #[tokio::test]
+    async fn test_token_2022_mint_found_for_transfer_burn_and_mint_to() {
+        let mint = bs58::encode([4u8; 32]).into_string();
+        let source = bs58::encode([2u8; 32]).into_string();
+        let mut token_account = vec![4u8; 32];
+        token_account.resize(TOKEN_2022_ACCOUNT_TYPE_OFFSET, 0);
+        let resolver = AccountsResolver(HashMap::from([
+            (source.clone(), token_account),
+            (mint.clone(), token_2022_mint_data(&[(EXTENSION_PERMANENT_DELEGATE, vec![7u8; 32])])),
+        ]));
+
+        let mut data = vec![3u8];
+        data.extend(1_000u64.to_le_bytes());
+        let transfer = token_2022_instruction_bytes(&[1, 2, 0], &data);
+        data[0] = 8;
+        let burn = token_2022_instruction_bytes(&[1, 3, 0], &data);
+        data[0] = 7;
+        let mint_to = token_2022_instruction_bytes(&[3, 2, 0], &data);
+
+        for (tx, named) in [(transfer, false), (burn, true), (mint_to, true)] {
+            let mut decoded = decode_transaction(&BASE64.encode(tx)).unwrap();
+            if named {
+                assert_eq!(token_2022_mints(&decoded), vec![mint.clone()]);
+            } else {
+                // A plain Transfer only names the source token account
+                assert!(token_2022_mints(&decoded).is_empty());
+                assert_eq!(token_2022_unresolved_sources(&decoded), vec![source.clone()]);
+            }
+            check_token_2022_mints(&mut decoded, &resolver, &DecoderConfig::default()).await;
+            let titles: Vec<_> = decoded.warnings.iter().map(|w| w.title.as_str()).collect();
+            assert!(titles.contains(&"Permanent Delegate"), "{:?}", decoded.instructions[0].action);
+        }
+    }
+
+    /// Legacy transaction with a single Token-2022 TransferChecked
+    fn token_2022_transfer_checked_bytes() -> Vec<u8> {
+        let mut data = vec![12u8];
+        data.extend(1_000u64.to_le_bytes());
+        data.push(6);
+        token_2022_instruction_bytes(&[1, 3, 2, 0], &data)
+    }
+
+    #[test]
+    fn test_permanent_delegate_warns() {
+        let data = token_2022_mint_data(&[(EXTENSION_PERMANENT_DELEGATE, vec![7u8; 32])]);
+        let warnings = mint_extension_warnings("mint", &data);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].level, WarningLevel::Warning);
+        assert_eq!(warnings[0].title, "Permanent Delegate");
+
+        // An unset (all-zero) delegate is not a warning
+        let data = token_2022_mint_data(&[(EXTENSION_PERMANENT_DELEGATE, vec![0u8; 32])]);
+        assert!(mint_extension_warnings("mint", &data).is_empty());
+    }
+
+    #[test]
+    fn test_transfer_fee_warns_only_when_charged() {
+        let data = token_2022_mint_data(&[(EXTENSION_TRANSFER_FEE_CONFIG, transfer_fee_config(250))]);
+        let warnings = mint_extension_warnings("mint", &data);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].title, "Transfer Fee");
+        assert!(warnings[0].message.contains("2.50%"));
+
+        let data = token_2022_mint_data(&[(EXTENSION_TRANSFER_FEE_CONFIG, transfer_fee_config(0))]);
+        assert!(mint_extension_warnings("mint", &data).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_check_token_2022_mints_uses_resolver() {
+        let encoded = BASE64.encode(token_2022_transfer_checked_bytes());
+        let mut decoded = decode_transaction(&encoded).unwrap();
+        let mint = bs58::encode([4u8; 32]).into_string();
+        assert_eq!(token_2022_mints(&decoded), vec![mint]);
+
+        let resolver = StubMintResolver(token_2022_mint_data(&[
+            (EXTENSION_TRANSFER_FEE_CONFIG, transfer_fee_config(100)),
+            (EXTENSION_PERMANENT_DELEGATE, vec![7u8; 32]),
+        ]));
+        check_token_2022_mints(&mut decoded, &resolver, &DecoderConfig::default()).await;
+
+        let titles: Vec<_> = decoded.warnings.iter().map(|w| w.title.as_str()).collect();
+        assert_eq!(titles, vec!["Transfer Fee", "Permanent Delegate"]);
+    }
+
+    #[tokio::test]
+    async fn test_check_token_2022_mints_keeps_decoder_thresholds() {
+        // 20 SOL is High risk by default but Low under raised thresholds; the
+        // mint check must not recalculate it with the defaults
+        let config = DecoderConfig {
+            high_value_sol: 50.0,
+            critical_value_sol: 100.0,
+            ..DecoderConfig::default()
+        };
+        let encoded = BASE64.encode(sol_transfer_bytes(20_000_000_000));
+        let mut decoded = decode_transaction_with_config(&encoded, &config).unwrap();
+        assert_eq!(decoded.risk_level, RiskLevel::Low);
+        let resolver = StubMintResolver(token_2022_mint_data(&[]));
+        check_token_2022_mints(&mut decoded, &resolver, &config).await;
+        assert_eq!(decoded.risk_level, RiskLevel::Low);
+    }
+
+    #[test]
+    fn test_shorten_address() {
+        let addr = "11111111111111111111111111111111";
+        assert_eq!(shorten_address(addr), "1111...1111");
+    }
+
+    /// Legacy transaction with one Associated Token instruction over
+    /// [payer, ata, `owner`, mint, System, Token, ATA program], signed by the
+    /// first `signers` keys
+    fn associated_token_bytes(signers: u8, owner: [u8; 32], data: &[u8]) -> Vec<u8> {
+        let mut tx = vec![signers];
+        tx.extend(vec![7u8; 64 * signers as usize]);
+        tx.extend([signers, 0, 4]);
+        tx.push(7);
+        tx.extend([1u8; 32]); // payer
+        tx.extend(owner);
+        tx.extend([2u8; 32]); // associated token account
+        tx.extend([4u8; 32]); // mint
+        tx.extend([0u8; 32]); // System program
+        tx.extend(bs58::decode(TOKEN_PROGRAM).into_vec().unwrap());
+        tx.extend(bs58::decode(ASSOCIATED_TOKEN_PROGRAM).into_vec().unwrap());
+        tx.extend([9u8; 32]);
+        tx.extend([1, 6, 6, 0, 2, 1, 3, 4, 5]);
+        tx.push(data.len() as u8);
+        tx.extend(data);
+        tx
+    }
+
+    #[test]
+    fn test_decode_associated_token_create() {
+        let stranger = [7u8; 32];
+        for (data, idempotent) in [(&[][..], false), (&[0][..], false), (&[1][..], true)] {
+            let encoded = BASE64.encode(associated_token_bytes(1, stranger, data));
+            let decoded = decode_transaction(&encoded).unwrap();
+            let instruction = &decoded.instructions[0];
+            assert!(instruction.action.contains(&shorten_address(&bs58::encode(stranger).into_string())));
+            match &instruction.details {
+                InstructionDetails::CreateAssociatedTokenAccount {
+                    funder,
+                    account,
+                    owner,
+                    mint,
+                    idempotent: decoded_idempotent,
+                } => {
+                    assert_eq!(*funder, bs58::encode([1u8; 32]).into_string());
+                    assert_eq!(*account, bs58::encode([2u8; 32]).into_string());
+                    assert_eq!(*owner, bs58::encode(stranger).into_string());
+                    assert_eq!(*mint, bs58::encode([4u8; 32]).into_string());
+                    assert_eq!(*decoded_idempotent, idempotent);
+                }
+                other => panic!("unexpected details: {:?}", other),
+            }
+            // The owner isn't signing, so who it is gets called out
+            let warning = decoded.warnings.iter().find(|w| w.title == "Token Account For Another Wallet").unwrap();
+            assert_eq!(warning.level, WarningLevel::Info);
+            assert!(warning.message.contains(&bs58::encode(stranger).into_string()));
+            assert_eq!(decoded.risk_level, RiskLevel::Low);
+        }
+
+        // Creating the payer's own token account is unremarkable
+        let encoded = BASE64.encode(associated_token_bytes(2, stranger, &[1]));
+        assert!(decode_transaction(&encoded).unwrap().warnings.is_empty());
+
+        // RecoverNested keeps the raw view
+        let encoded = BASE64.encode(associated_token_bytes(1, stranger, &[2]));
+        let decoded = decode_transaction(&encoded).unwrap();
+        assert_eq!(decoded.instructions[0].action, "Recover Nested Account");
+        assert!(matches!(decoded.instructions[0].details, InstructionDetails::Unknown { .. }));
+    }
+
+    #[test]
+    fn test_signature_count_mismatch_warns() {
+        // Two signatures for a message that requires one
+        let mut bytes = sol_transfer_bytes(1);
+        bytes[0] = 2;
+        bytes.splice(65..65, [8u8; 64]);
+        let decoded = decode_transaction(&BASE64.encode(&bytes)).unwrap();
+        let warning = decoded.warnings.iter().find(|w| w.title == "Signature Count Mismatch").unwrap();
+        assert_eq!(warning.level, WarningLevel::Warning);
+        assert!(warning.message.contains("2 signature(s) but its message requires 1"));
+
+        // A bare message has no signatures to check
+        let decoded = decode_message(&BASE64.encode(&sol_transfer_bytes(1)[65..])).unwrap();
+        assert!(decoded.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_unsigned_transaction_flagged() {
+        let mut bytes = sol_transfer_bytes(1);
+        bytes[1..65].fill(0);
+        let mut decoded = decode_transaction(&BASE64.encode(&bytes)).unwrap();
+        assert!(decoded.unsigned);
+        // Normal for a transaction a wallet is asked to sign
+        assert!(decoded.warnings.is_empty());
+        // Worth a note when it's being sent
+        decoded.flag_unsigned_send();
+        decoded.flag_unsigned_send();
+        assert_eq!(decoded.warnings.len(), 1);
+        assert_eq!(decoded.warnings[0].title, "Unsigned Transaction");
+        assert_eq!(decoded.warnings[0].level, WarningLevel::Info);
+        assert_eq!(decoded.risk_level, RiskLevel::Low);
+
+        // Partly signed is normal (e.g. a fee payer still to sign)
+        let encoded = BASE64.encode(system_instruction_bytes(2, &[0, 1], &assign_data([0u8; 32])));
+        let mut bytes = BASE64.decode(encoded).unwrap();
+        bytes[1..65].fill(0);
+        let mut decoded = decode_transaction(&BASE64.encode(&bytes)).unwrap();
+        decoded.flag_unsigned_send();
+        assert!(!decoded.unsigned && decoded.warnings.is_empty());
+    }
+}